@@ -0,0 +1,309 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! The isahc interceptor implementation for http-cache.
+//! ```no_run
+//! use http_cache_isahc::{Cache, CacheMode, CACacheManager, HttpCache, HttpCacheOptions};
+//! use isahc::HttpClient;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), isahc::Error> {
+//!     let client = HttpClient::builder()
+//!         .interceptor(Cache(HttpCache {
+//!             mode: CacheMode::Default,
+//!             manager: CACacheManager::default(),
+//!             options: HttpCacheOptions::default(),
+//!         }))
+//!         .build()?;
+//!     client
+//!         .get_async("https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching")
+//!         .await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Limitations
+//!
+//! Interceptors are behind the `unstable-interceptors` feature of isahc,
+//! which this crate enables. Their current shape leads to a few differences
+//! from the other clients:
+//!
+//! - isahc follows redirects without going back through its interceptors, so
+//!   the response stored for a url is the one found at the end of the
+//!   redirects.
+//! - Responses served from the cache don't carry the extensions isahc sets
+//!   on responses, so `ResponseExt::effective_uri` and `ResponseExt::metrics`
+//!   return `None` for them.
+//! - Request extensions, which hold the configuration set through
+//!   `Configurable` on a request, only go along with the first request sent
+//!   upstream.
+//! - Errors returned by isahc are passed on as is, while cache errors reach
+//!   the caller as an `isahc::Error::Curl` holding their message.
+use anyhow::anyhow;
+use std::{convert::TryInto, error::Error, str::FromStr};
+
+use futures::AsyncReadExt;
+use http::{
+    header::{HeaderName, CACHE_CONTROL},
+    request::Parts,
+    HeaderMap, HeaderValue, Method,
+};
+use http_cache::{Bytes, CacheError, CacheManager, Middleware, Result};
+use http_cache_semantics::CachePolicy;
+use isahc::{
+    interceptor::{Context, Interceptor, InterceptorFuture},
+    Body,
+};
+use url::Url;
+
+pub use http_cache::{
+    BreakerState, CacheDecision, CacheEvent, CacheMode, CacheOptions,
+    CacheStatus, CacheToggle, CircuitBreaker, Clock, Coalescing, ContentTypes,
+    DryRun, DryRunLookup, EntryMetadata, ErrorPolicy, EventBroadcast,
+    HealthReport, HitOrMiss, HttpCache, HttpCacheOptions, HttpHeaders,
+    HttpResponse, LeaderFailure, ManagerOverride, NotStoredReason, RecordMode,
+    Redaction, RequestPredicate, ResponsePredicate, RevalidationHook,
+    RevalidationRequest, Spawner, StoreHook, UrlFilter, WarmOutcome,
+    WarmReport, WriteBehind, WriteBehindStats,
+};
+
+#[cfg(feature = "manager-cacache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
+pub use http_cache::CACacheManager;
+
+#[cfg(feature = "manager-moka")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
+pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
+
+/// Wrapper for [`HttpCache`]
+///
+/// Isahc doesn't expose the reason phrase of responses nor lets one be set,
+/// so [`HttpResponse::reason`] is always `None` and responses served from
+/// the cache have the canonical phrase of their status.
+///
+/// Isahc doesn't expose the trailers of responses either, so none are
+/// stored. Trailers stored by another client are only handed over as
+/// headers, see [`HttpCacheOptions::trailers_as_headers`].
+///
+/// Responses the cache never stores, in the [`CacheMode::NoStore`] mode or
+/// answering methods other than `GET` and `HEAD`, are passed on without
+/// their body being read, along with the body of the request, see
+/// [`HttpCache::passes_through`].
+#[derive(Debug)]
+pub struct Cache<T: CacheManager>(pub HttpCache<T>);
+
+impl<T: CacheManager> Cache<T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+}
+
+/// Implements ['Middleware'] for isahc
+pub(crate) struct IsahcMiddleware<'a> {
+    pub parts: Parts,
+    // `None` when the request had no body, which isahc tells apart from an
+    // empty one
+    pub body: Option<Vec<u8>>,
+    pub ctx: Context<'a>,
+}
+
+impl Middleware for IsahcMiddleware<'_> {
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == Method::GET || self.parts.method == Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            http_cache::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
+        Ok(())
+    }
+    fn parts(&self) -> Result<Parts> {
+        let mut converted = http::Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.url()?.as_str())
+            .version(self.parts.version)
+            .body(())?;
+        *converted.headers_mut() = self.parts.headers.clone();
+        Ok(converted.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.parts.uri.to_string())?)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.as_ref().to_string())
+    }
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        self.parts.extensions.get::<ManagerOverride>().cloned()
+    }
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        self.parts.extensions.get::<EntryMetadata>().cloned()
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let body = match &self.body {
+            Some(body) => Body::from(body.clone()),
+            None => Body::empty(),
+        };
+        let req = self.request(body)?;
+        let res = match self.ctx.send(req).await {
+            Ok(r) => r,
+            Err(e) => return Err(CacheError::General(anyhow!(e))),
+        };
+        let (parts, mut body) = res.into_parts();
+        let headers = HttpHeaders::from(&parts.headers);
+        let mut collected = Vec::new();
+        if let Err(e) = body.read_to_end(&mut collected).await {
+            return Err(CacheError::General(anyhow!(e)));
+        }
+        Ok(HttpResponse {
+            body: collected.into(),
+            headers,
+            trailers: None,
+            status: parts.status.into(),
+            reason: None,
+            url: self.url()?,
+            version: parts.version.try_into()?,
+            metadata: Default::default(),
+        })
+    }
+}
+
+impl IsahcMiddleware<'_> {
+    // Builds the request sent upstream, carrying the headers set by the cache
+    fn request(&mut self, body: Body) -> Result<http::Request<Body>> {
+        let mut req = http::Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.parts.uri.clone())
+            .version(self.parts.version)
+            .body(body)?;
+        *req.headers_mut() = self.parts.headers.clone();
+        *req.extensions_mut() = std::mem::take(&mut self.parts.extensions);
+        Ok(req)
+    }
+}
+
+// Sends a request the cache passes through along with its body, leaving the
+// body of the response to be read by the caller
+async fn pass_through<T: CacheManager>(
+    cache: &HttpCache<T>,
+    mut middleware: IsahcMiddleware<'_>,
+    body: Body,
+) -> Result<http::Response<Body>> {
+    let req = middleware.request(body)?;
+    let mut upstream = match middleware.ctx.send(req).await {
+        Ok(r) => r,
+        Err(e) => return Err(CacheError::General(anyhow!(e))),
+    };
+    let mut res = HttpResponse {
+        body: Bytes::new(),
+        headers: HttpHeaders::from(upstream.headers()),
+        trailers: None,
+        status: upstream.status().into(),
+        reason: None,
+        url: middleware.url()?,
+        version: upstream.version().try_into()?,
+        metadata: Default::default(),
+    };
+    cache.pass_through(middleware, &mut res).await?;
+    *upstream.headers_mut() = convert_headers(&res.headers)?;
+    Ok(upstream)
+}
+
+// Hands isahc back its own errors so they aren't turned into strings
+fn convert_error(e: CacheError) -> Box<dyn Error> {
+    match e {
+        CacheError::General(e) => match e.downcast::<isahc::Error>() {
+            Ok(e) => Box::new(e),
+            Err(e) => Box::new(CacheError::General(e)),
+        },
+        e => Box::new(e),
+    }
+}
+
+// Converts an [`HttpResponse`] to an isahc [`Response`](http::Response)
+fn convert_response(
+    response: HttpResponse,
+) -> anyhow::Result<http::Response<Body>> {
+    let mut ret_res = http::Response::builder()
+        .status(response.status)
+        .version(response.version.into())
+        .body(Body::from(Vec::from(response.body)))?;
+    *ret_res.headers_mut() = convert_headers(&response.headers)?;
+    Ok(ret_res)
+}
+
+// Converts the headers of an [`HttpResponse`], keeping repeated values
+fn convert_headers(headers: &HttpHeaders) -> Result<HeaderMap> {
+    let mut converted = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        converted.append(HeaderName::from_str(name)?, value.clone());
+    }
+    Ok(converted)
+}
+
+impl<T: CacheManager> Interceptor for Cache<T> {
+    type Err = Box<dyn Error>;
+
+    fn intercept<'a>(
+        &'a self,
+        req: http::Request<Body>,
+        ctx: Context<'a>,
+    ) -> InterceptorFuture<'a, Self::Err> {
+        Box::pin(async move {
+            let (parts, mut body) = req.into_parts();
+            let mut middleware = IsahcMiddleware { parts, body: None, ctx };
+            if !self.0.can_cache_request(&middleware).map_err(convert_error)? {
+                let IsahcMiddleware { parts, ctx, .. } = middleware;
+                let req = http::Request::from_parts(parts, body);
+                return Ok(ctx.send(req).await?);
+            }
+            if self.0.passes_through(&middleware) {
+                return pass_through(&self.0, middleware, body)
+                    .await
+                    .map_err(convert_error);
+            }
+            if !body.is_empty() {
+                let mut collected = Vec::new();
+                body.read_to_end(&mut collected).await?;
+                middleware.body = Some(collected);
+            }
+            let mut res =
+                self.0.run(middleware).await.map_err(convert_error)?;
+            if self.0.options.trailers_as_headers {
+                res.trailers_into_headers();
+            }
+            Ok(convert_response(res)?)
+        })
+    }
+}
@@ -0,0 +1,287 @@
+use std::{collections::HashMap, io, sync::Mutex};
+
+use anyhow::anyhow;
+use http_cache::{CacheError, Result};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+    sync::Semaphore,
+};
+use url::Url;
+
+const DEFAULT_PORT: u16 = 11211;
+
+// A command of the text protocol, with the replies to several items read
+// in a single round trip
+pub(crate) enum Command<'a> {
+    Get(&'a [String]),
+    // The items along with their expiration time
+    Set(&'a [(String, Vec<u8>)], u64),
+    Delete(&'a [String]),
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Reply {
+    // The values found, by key
+    Values(HashMap<String, Vec<u8>>),
+    Stored,
+    // How many of the items existed
+    Deleted(usize),
+}
+
+// Why a command failed, which tells whether its connection can be reused
+#[derive(Debug)]
+enum Failure {
+    // The connection broke, or the server sent something it shouldn't have
+    Connection(anyhow::Error),
+    // The server answered with an error, the connection is still usable
+    Server(String),
+    // The server rejected the command in a way that leaves the connection
+    // out of step, sending it again would only fail the same way
+    OutOfStep(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<Failure> for CacheError {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Connection(error) => CacheError::General(
+                error.context("memcached connection failed"),
+            ),
+            Failure::Server(message) | Failure::OutOfStep(message) => {
+                CacheError::General(anyhow!("memcached error: {message}"))
+            }
+        }
+    }
+}
+
+fn protocol(reason: &str) -> Failure {
+    Failure::Connection(anyhow!("invalid memcached reply: {reason}"))
+}
+
+// Where the server is, read from a `memcache://` url
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+}
+
+impl Config {
+    pub(crate) fn parse(url: &str) -> Result<Self> {
+        let url = Url::parse(url)?;
+        if url.scheme() != "memcache" {
+            return Err(CacheError::General(anyhow!(
+                "unsupported memcached url scheme: {}",
+                url.scheme()
+            )));
+        }
+        let host = match url.host_str() {
+            Some(host) => host.trim_matches(['[', ']']).to_string(),
+            None => {
+                return Err(CacheError::General(anyhow!(
+                    "memcached url without a host"
+                )))
+            }
+        };
+        Ok(Self { host, port: url.port().unwrap_or(DEFAULT_PORT) })
+    }
+}
+
+struct Connection {
+    stream: BufStream<TcpStream>,
+}
+
+impl Connection {
+    async fn open(config: &Config) -> std::result::Result<Self, Failure> {
+        let stream =
+            TcpStream::connect((config.host.as_str(), config.port)).await?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream: BufStream::new(stream) })
+    }
+
+    async fn run(
+        &mut self,
+        command: &Command<'_>,
+    ) -> std::result::Result<Reply, Failure> {
+        let mut request = Vec::new();
+        match command {
+            Command::Get(keys) => {
+                request.extend_from_slice(b"get");
+                for key in keys.iter() {
+                    request.push(b' ');
+                    request.extend_from_slice(key.as_bytes());
+                }
+                request.extend_from_slice(b"\r\n");
+            }
+            Command::Set(items, exptime) => {
+                for (key, value) in items.iter() {
+                    let line =
+                        format!("set {key} 0 {exptime} {}\r\n", value.len());
+                    request.extend_from_slice(line.as_bytes());
+                    request.extend_from_slice(value);
+                    request.extend_from_slice(b"\r\n");
+                }
+            }
+            Command::Delete(keys) => {
+                for key in keys.iter() {
+                    request.extend_from_slice(b"delete ");
+                    request.extend_from_slice(key.as_bytes());
+                    request.extend_from_slice(b"\r\n");
+                }
+            }
+        }
+        self.stream.write_all(&request).await?;
+        self.stream.flush().await?;
+        match command {
+            Command::Get(_) => self.read_values().await,
+            Command::Set(items, _) => {
+                // Every reply is read, even past an error, so the next
+                // command reads its own
+                let mut failure = None;
+                for _ in items.iter() {
+                    match self.read_line().await?.as_str() {
+                        "STORED" => {}
+                        line => match server(line) {
+                            Failure::Server(message) => {
+                                failure.get_or_insert(Failure::Server(message));
+                            }
+                            out_of_step => return Err(out_of_step),
+                        },
+                    }
+                }
+                failure.map_or(Ok(Reply::Stored), Err)
+            }
+            Command::Delete(keys) => {
+                let mut deleted = 0;
+                let mut failure = None;
+                for _ in keys.iter() {
+                    match self.read_line().await?.as_str() {
+                        "DELETED" => deleted += 1,
+                        "NOT_FOUND" => {}
+                        line => match server(line) {
+                            Failure::Server(message) => {
+                                failure.get_or_insert(Failure::Server(message));
+                            }
+                            out_of_step => return Err(out_of_step),
+                        },
+                    }
+                }
+                failure.map_or(Ok(Reply::Deleted(deleted)), Err)
+            }
+        }
+    }
+
+    async fn read_line(&mut self) -> std::result::Result<String, Failure> {
+        let mut line = Vec::new();
+        self.stream.read_until(b'\n', &mut line).await?;
+        let line = line
+            .strip_suffix(b"\r\n")
+            .ok_or_else(|| protocol("truncated line"))?;
+        String::from_utf8(line.to_vec()).map_err(|_| protocol("not utf-8"))
+    }
+
+    async fn read_values(&mut self) -> std::result::Result<Reply, Failure> {
+        let mut values = HashMap::new();
+        loop {
+            let line = self.read_line().await?;
+            if line == "END" {
+                return Ok(Reply::Values(values));
+            }
+            // `VALUE <key> <flags> <bytes>`
+            let (key, len) = match line.split(' ').collect::<Vec<_>>()[..] {
+                ["VALUE", key, _, len, ..] => (key.to_string(), len),
+                _ => return Err(server(&line)),
+            };
+            let len: usize =
+                len.parse().map_err(|_| protocol("invalid length"))?;
+            let mut data = vec![0; len + 2];
+            self.stream.read_exact(&mut data).await?;
+            if !data.ends_with(b"\r\n") {
+                return Err(protocol("unterminated value"));
+            }
+            data.truncate(len);
+            values.insert(key, data);
+        }
+    }
+}
+
+// Errors the server could answer with in place of the expected reply,
+// which leave the connection usable. The server may have read a command
+// it rejected with `CLIENT_ERROR` out of step, so that connection isn't.
+fn server(line: &str) -> Failure {
+    if line == "ERROR" || line.starts_with("SERVER_ERROR ") {
+        Failure::Server(line.to_string())
+    } else if line.starts_with("CLIENT_ERROR ") {
+        Failure::OutOfStep(line.to_string())
+    } else {
+        protocol(line)
+    }
+}
+
+// Connections to a server, opened as needed and kept open once done with
+// for the next command, with up to `max_connections` of them at once
+pub(crate) struct Pool {
+    config: Config,
+    idle: Mutex<Vec<Connection>>,
+    permits: Semaphore,
+    max_connections: usize,
+}
+
+impl Pool {
+    pub(crate) fn new(config: Config, max_connections: usize) -> Self {
+        let max_connections = max_connections.max(1);
+        Self {
+            config,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(max_connections),
+            max_connections,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    // Runs a command on a connection of the pool. A connection that is
+    // dropped along with the future, which can stop a command halfway, or
+    // that broke isn't put back.
+    pub(crate) async fn run(&self, command: Command<'_>) -> Result<Reply> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|error| CacheError::General(error.into()))?;
+        let idle = self.idle.lock().unwrap().pop();
+        let (conn, reply) = match idle {
+            Some(mut conn) => match conn.run(&command).await {
+                // Idle connections may have been closed by the server since,
+                // the commands the manager runs are safe to run again
+                Err(Failure::Connection(_)) => {
+                    let mut conn = Connection::open(&self.config).await?;
+                    let reply = conn.run(&command).await;
+                    (conn, reply)
+                }
+                reply => (conn, reply),
+            },
+            None => {
+                let mut conn = Connection::open(&self.config).await?;
+                let reply = conn.run(&command).await;
+                (conn, reply)
+            }
+        };
+        if !matches!(reply, Err(Failure::Connection(_) | Failure::OutOfStep(_)))
+        {
+            self.idle.lock().unwrap().push(conn);
+        }
+        Ok(reply?)
+    }
+}
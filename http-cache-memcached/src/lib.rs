@@ -0,0 +1,347 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! A [`CacheManager`] storing records in [memcached](https://memcached.org),
+//! which lets several processes, such as the instances of a service, share
+//! a cache.
+//!
+//! Each record is stored under the [`hash`](CacheKey::hash) of its key
+//! behind a configurable prefix, and set to expire once its response is
+//! stale for longer than [`MemcachedManager::stale_ttl`], so memcached drops
+//! records the cache has no use for anymore by itself. Records too large
+//! for a single item are split across several, see
+//! [`MemcachedManager::chunk_size`]. Connections are opened as needed, kept
+//! open for the next commands and limited in number, see
+//! [`MemcachedManager::with_max_connections`].
+//!
+//! The manager needs a [tokio](https://tokio.rs) runtime.
+//! ```no_run
+//! use http_cache::{CacheMode, HttpCache, HttpCacheOptions};
+//! use http_cache_memcached::MemcachedManager;
+//!
+//! # fn main() -> http_cache::Result<()> {
+//! let manager = MemcachedManager::new("memcache://127.0.0.1:11211")?;
+//! let cache = HttpCache {
+//!     mode: CacheMode::Default,
+//!     manager,
+//!     options: HttpCacheOptions::default(),
+//! };
+//! # drop(cache);
+//! # Ok(())
+//! # }
+//! ```
+mod connection;
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+use http_cache::{
+    decode_record, encode_record, now, BodyChecksum, Bytes, CacheError,
+    CacheKey, CacheManager, DecodedRecord, HttpResponse, Result,
+    SerializationFormat,
+};
+use http_cache_semantics::CachePolicy;
+
+use connection::{Command, Config, Pool, Reply};
+
+/// The number of connections a manager opens at most by default.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+// Expiration times past 30 days are read by memcached as unix timestamps
+const MAX_RELATIVE_EXPTIME: u64 = 60 * 60 * 24 * 30;
+
+// Starts the item standing for a record split across several, followed by
+// the generation of its chunks as a little endian `u64` and their count as
+// a little endian `u32`. Records themselves start with a prefix of their
+// own, see `encode_record`.
+const CHUNKS_PREFIX: &[u8; 8] = b"httpchk\xff";
+
+// Tells apart the chunks of successive writes of the same record
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Implements [`CacheManager`] with [memcached](https://memcached.org) as
+/// the backend.
+///
+/// Records are laid out as the bundled managers store them, see
+/// [`encode_record`], so they carry the
+/// [`CACHE_RECORD_VERSION`](http_cache::CACHE_RECORD_VERSION) of their
+/// layout and the checksum of their body. Records written by a newer version
+/// are treated as misses and removed, and records whose body doesn't match
+/// its checksum are reported as [`CacheError::CorruptEntry`]. A record that
+/// was split is read back only if every chunk of it still is, memcached may
+/// evict them separately, and its checksum is checked once the chunks are
+/// put back together. Chunks of records that were removed or replaced are
+/// left to expire.
+///
+/// As memcached expires and evicts records by itself, pinning a record
+/// doesn't keep it, and tags aren't supported.
+///
+/// Clones share the same connections.
+#[derive(Clone)]
+pub struct MemcachedManager {
+    /// Prepended to the key of every record, so several caches can share a
+    /// server. Memcached keys can't hold spaces nor control characters, and
+    /// with the key of the record and of its chunks they must fit in 250
+    /// bytes, so the prefix should stay short. Defaults to `http-cache:`.
+    pub prefix: String,
+    /// How long records are kept once their response is stale, so it can
+    /// still be revalidated or served stale. Defaults to one day.
+    pub stale_ttl: Duration,
+    /// The most bytes stored in a single item, larger records are split
+    /// across several. Has to stay below the item size limit of the server,
+    /// 1 MiB unless set with `-I`, which also counts the key and the item
+    /// header. Defaults to 1020 KiB.
+    pub chunk_size: usize,
+    /// Format used to serialize newly stored records.
+    pub format: SerializationFormat,
+    /// Checksum stored along with the body of newly stored records and
+    /// checked when records are read back, `None` skips both. Defaults to
+    /// [`BodyChecksum::Crc32`].
+    pub checksum: Option<BodyChecksum>,
+    pool: Arc<Pool>,
+}
+
+impl fmt::Debug for MemcachedManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let config = self.pool.config();
+        f.debug_struct("MemcachedManager")
+            .field("prefix", &self.prefix)
+            .field("stale_ttl", &self.stale_ttl)
+            .field("chunk_size", &self.chunk_size)
+            .field("format", &self.format)
+            .field("checksum", &self.checksum)
+            .field("host", &config.host)
+            .field("port", &config.port)
+            .field("max_connections", &self.pool.max_connections())
+            .finish_non_exhaustive()
+    }
+}
+
+// Where the parts of a record split across several items are
+#[derive(Debug, Clone, Copy)]
+struct Chunks {
+    generation: u64,
+    count: u32,
+}
+
+impl MemcachedManager {
+    /// Creates a manager for the server at `url`, of the form
+    /// `memcache://host[:port]`, opening up to [`DEFAULT_MAX_CONNECTIONS`]
+    /// connections.
+    ///
+    /// No connection is opened until the first command, so a server that
+    /// can't be reached only fails the commands sent to it, see
+    /// [`CacheManager::health_check`] to check it beforehand.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_max_connections(url, DEFAULT_MAX_CONNECTIONS)
+    }
+
+    /// Creates a manager for the server at `url` opening up to
+    /// `max_connections` connections, commands wait for one to be free once
+    /// they are all in use.
+    pub fn with_max_connections(
+        url: &str,
+        max_connections: usize,
+    ) -> Result<Self> {
+        let config = Config::parse(url)?;
+        Ok(Self {
+            prefix: "http-cache:".to_string(),
+            stale_ttl: Duration::from_secs(60 * 60 * 24),
+            chunk_size: 1020 * 1024,
+            format: SerializationFormat::default(),
+            checksum: Some(BodyChecksum::default()),
+            pool: Arc::new(Pool::new(config, max_connections)),
+        })
+    }
+
+    fn key(&self, key: &CacheKey) -> String {
+        format!("{}{}", self.prefix, key.hash())
+    }
+
+    // How long memcached keeps a record, the freshness lifetime left to its
+    // response and `stale_ttl`, as an expiration time
+    fn exptime(&self, policy: &CachePolicy) -> u64 {
+        let now = now();
+        let ttl = policy.time_to_live(now) + self.stale_ttl;
+        // Rounded up, as 0 never expires
+        let secs = ttl.as_secs() + u64::from(ttl.subsec_nanos() > 0);
+        if secs <= MAX_RELATIVE_EXPTIME {
+            return secs.max(1);
+        }
+        let since_epoch = now.duration_since(UNIX_EPOCH).unwrap_or_default();
+        since_epoch.as_secs() + secs
+    }
+
+    async fn values(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        match self.pool.run(Command::Get(keys)).await? {
+            Reply::Values(mut values) => {
+                Ok(keys.iter().map(|key| values.remove(key)).collect())
+            }
+            reply => Err(unexpected(reply)),
+        }
+    }
+}
+
+// Differs between the writes of a process, and most likely from the ones
+// of other processes
+fn generation() -> u64 {
+    let since_epoch = now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    (since_epoch.as_nanos() as u64)
+        .wrapping_add(GENERATION.fetch_add(1, Ordering::Relaxed))
+}
+
+fn chunk_key(key: &str, chunks: Chunks, index: u32) -> String {
+    format!("{key}:{:x}:{index}", chunks.generation)
+}
+
+fn unexpected(reply: Reply) -> CacheError {
+    CacheError::General(anyhow::anyhow!(
+        "unexpected memcached reply: {reply:?}"
+    ))
+}
+
+fn corrupt(
+    error: impl Into<Box<dyn std::error::Error + Send + Sync>>,
+) -> CacheError {
+    CacheError::CorruptEntry(error.into())
+}
+
+impl Chunks {
+    fn encode(self) -> Vec<u8> {
+        let mut item = CHUNKS_PREFIX.to_vec();
+        item.extend_from_slice(&self.generation.to_le_bytes());
+        item.extend_from_slice(&self.count.to_le_bytes());
+        item
+    }
+
+    // Returns `None` for items holding a record themselves
+    fn decode(item: &[u8]) -> Result<Option<Self>> {
+        let data = match item.strip_prefix(CHUNKS_PREFIX) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        if data.len() != 12 {
+            return Err(corrupt("truncated record"));
+        }
+        let (generation, count) = data.split_at(8);
+        Ok(Some(Self {
+            generation: u64::from_le_bytes(
+                generation.try_into().unwrap_or_default(),
+            ),
+            count: u32::from_le_bytes(count.try_into().unwrap_or_default()),
+        }))
+    }
+}
+
+impl CacheManager for MemcachedManager {
+    async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let key = self.key(key);
+        let item = match self
+            .values(std::slice::from_ref(&key))
+            .await?
+            .pop()
+            .flatten()
+        {
+            Some(item) => item,
+            None => return Ok(None),
+        };
+        let record = match Chunks::decode(&item)? {
+            Some(chunks) => {
+                let keys = (0..chunks.count)
+                    .map(|index| chunk_key(&key, chunks, index))
+                    .collect::<Vec<_>>();
+                let mut record = Vec::new();
+                for chunk in self.values(&keys).await? {
+                    match chunk {
+                        Some(chunk) => record.extend_from_slice(&chunk),
+                        // Evicted, the rest of the record is of no use
+                        None => {
+                            self.pool.run(Command::Delete(&[key])).await?;
+                            return Ok(None);
+                        }
+                    }
+                }
+                record
+            }
+            None => item,
+        };
+        // Records of earlier versions are upgraded as they are read, they
+        // are rewritten along with the response once it is stored again
+        match decode_record(&Bytes::from(record), self.checksum.is_some())? {
+            DecodedRecord::Current(res, policy)
+            | DecodedRecord::Upgraded(res, policy) => Ok(Some((res, policy))),
+            DecodedRecord::UnknownVersion => {
+                self.pool.run(Command::Delete(&[key])).await?;
+                Ok(None)
+            }
+            // Another version may understand the format, so leave it be
+            _ => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let key = self.key(key);
+        let exptime = self.exptime(&policy);
+        let chunk_size = self.chunk_size.max(1);
+        let mut item =
+            encode_record(&res, &policy, self.format, self.checksum)?;
+        if item.len() > chunk_size {
+            let count =
+                u32::try_from(item.chunks(chunk_size).len()).map_err(|_| {
+                    CacheError::General(anyhow::anyhow!("record too large"))
+                })?;
+            let chunks = Chunks { generation: generation(), count };
+            let items = item
+                .chunks(chunk_size)
+                .zip(0..)
+                .map(|(chunk, index)| {
+                    (chunk_key(&key, chunks, index), chunk.to_vec())
+                })
+                .collect::<Vec<_>>();
+            // The chunks are stored first, so a record is never read
+            // before its chunks are
+            self.pool.run(Command::Set(&items, exptime)).await?;
+            item = chunks.encode();
+        }
+        match self.pool.run(Command::Set(&[(key, item)], exptime)).await? {
+            Reply::Stored => Ok(res),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        let key = self.key(key);
+        match self.pool.run(Command::Delete(&[key])).await? {
+            Reply::Deleted(_) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
@@ -0,0 +1,247 @@
+use std::{io, sync::Mutex};
+
+use anyhow::anyhow;
+use http_cache::{CacheError, Result};
+use percent_encoding::percent_decode_str;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::TcpStream,
+    sync::Semaphore,
+};
+use url::Url;
+
+const DEFAULT_PORT: u16 = 6379;
+
+// A reply to a command, in the subset of RESP2 the manager needs
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Reply {
+    Status(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+}
+
+// Why a command failed, which tells whether its connection can be reused
+#[derive(Debug)]
+enum Failure {
+    // The connection broke, or the server sent something it shouldn't have
+    Connection(anyhow::Error),
+    // The server answered with an error, the connection is still usable
+    Server(String),
+}
+
+impl From<io::Error> for Failure {
+    fn from(error: io::Error) -> Self {
+        Self::Connection(error.into())
+    }
+}
+
+impl From<Failure> for CacheError {
+    fn from(failure: Failure) -> Self {
+        match failure {
+            Failure::Connection(error) => {
+                CacheError::General(error.context("Redis connection failed"))
+            }
+            Failure::Server(message) => {
+                CacheError::General(anyhow!("Redis error: {message}"))
+            }
+        }
+    }
+}
+
+fn protocol(reason: &str) -> Failure {
+    Failure::Connection(anyhow!("invalid Redis reply: {reason}"))
+}
+
+// Where the server is and how to log in, read from a `redis://` url
+#[derive(Clone)]
+pub(crate) struct Config {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) username: Option<String>,
+    pub(crate) password: Option<String>,
+    pub(crate) database: u32,
+}
+
+impl Config {
+    pub(crate) fn parse(url: &str) -> Result<Self> {
+        let url = Url::parse(url)?;
+        if url.scheme() != "redis" {
+            return Err(CacheError::General(anyhow!(
+                "unsupported Redis url scheme: {}",
+                url.scheme()
+            )));
+        }
+        let host = match url.host_str() {
+            Some(host) => host.trim_matches(['[', ']']).to_string(),
+            None => {
+                return Err(CacheError::General(anyhow!(
+                    "Redis url without a host"
+                )))
+            }
+        };
+        let database = match url.path().trim_start_matches('/') {
+            "" => 0,
+            database => database.parse().map_err(|_| {
+                CacheError::General(anyhow!(
+                    "invalid Redis database: {database}"
+                ))
+            })?,
+        };
+        Ok(Self {
+            host,
+            port: url.port().unwrap_or(DEFAULT_PORT),
+            username: Some(url.username())
+                .filter(|username| !username.is_empty())
+                .map(decode),
+            password: url.password().map(decode),
+            database,
+        })
+    }
+}
+
+// Credentials are percent-encoded in urls
+fn decode(credential: &str) -> String {
+    percent_decode_str(credential).decode_utf8_lossy().into_owned()
+}
+
+struct Connection {
+    stream: BufStream<TcpStream>,
+}
+
+impl Connection {
+    async fn open(config: &Config) -> std::result::Result<Self, Failure> {
+        let stream =
+            TcpStream::connect((config.host.as_str(), config.port)).await?;
+        stream.set_nodelay(true)?;
+        let mut conn = Self { stream: BufStream::new(stream) };
+        let password = config.password.as_deref().map(str::as_bytes);
+        match (config.username.as_deref(), password) {
+            (Some(username), Some(password)) => {
+                conn.query(&[b"AUTH", username.as_bytes(), password]).await?;
+            }
+            (None, Some(password)) => {
+                conn.query(&[b"AUTH", password]).await?;
+            }
+            _ => {}
+        }
+        if config.database != 0 {
+            let database = config.database.to_string();
+            conn.query(&[b"SELECT", database.as_bytes()]).await?;
+        }
+        Ok(conn)
+    }
+
+    async fn query(
+        &mut self,
+        args: &[&[u8]],
+    ) -> std::result::Result<Reply, Failure> {
+        let mut command = format!("*{}\r\n", args.len()).into_bytes();
+        for arg in args {
+            command.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            command.extend_from_slice(arg);
+            command.extend_from_slice(b"\r\n");
+        }
+        self.stream.write_all(&command).await?;
+        self.stream.flush().await?;
+        self.read_reply().await
+    }
+
+    async fn read_reply(&mut self) -> std::result::Result<Reply, Failure> {
+        let mut line = Vec::new();
+        self.stream.read_until(b'\n', &mut line).await?;
+        let line = line
+            .strip_suffix(b"\r\n")
+            .ok_or_else(|| protocol("truncated line"))?;
+        let (kind, rest) =
+            line.split_first().ok_or_else(|| protocol("empty line"))?;
+        let rest =
+            std::str::from_utf8(rest).map_err(|_| protocol("not utf-8"))?;
+        match kind {
+            b'+' => Ok(Reply::Status(rest.to_string())),
+            b'-' => Err(Failure::Server(rest.to_string())),
+            b':' => rest
+                .parse()
+                .map(Reply::Integer)
+                .map_err(|_| protocol("invalid integer")),
+            b'$' => {
+                let len: i64 =
+                    rest.parse().map_err(|_| protocol("invalid length"))?;
+                // A negative length is the null bulk string of a miss
+                let len = match usize::try_from(len) {
+                    Ok(len) => len,
+                    Err(_) => return Ok(Reply::Bulk(None)),
+                };
+                let mut data = vec![0; len + 2];
+                self.stream.read_exact(&mut data).await?;
+                if !data.ends_with(b"\r\n") {
+                    return Err(protocol("unterminated bulk string"));
+                }
+                data.truncate(len);
+                Ok(Reply::Bulk(Some(data)))
+            }
+            _ => Err(protocol("unexpected reply type")),
+        }
+    }
+}
+
+// Connections to a server, opened as needed and kept open once done with
+// for the next command, with up to `max_connections` of them at once
+pub(crate) struct Pool {
+    config: Config,
+    idle: Mutex<Vec<Connection>>,
+    permits: Semaphore,
+    max_connections: usize,
+}
+
+impl Pool {
+    pub(crate) fn new(config: Config, max_connections: usize) -> Self {
+        let max_connections = max_connections.max(1);
+        Self {
+            config,
+            idle: Mutex::new(Vec::new()),
+            permits: Semaphore::new(max_connections),
+            max_connections,
+        }
+    }
+
+    pub(crate) fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub(crate) fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    // Runs a command on a connection of the pool. A connection that is
+    // dropped along with the future, which can stop a command halfway, or
+    // that broke isn't put back.
+    pub(crate) async fn query(&self, args: &[&[u8]]) -> Result<Reply> {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|error| CacheError::General(error.into()))?;
+        let idle = self.idle.lock().unwrap().pop();
+        let (conn, reply) = match idle {
+            Some(mut conn) => match conn.query(args).await {
+                // Idle connections may have been closed by the server since,
+                // the commands the manager runs are safe to run again
+                Err(Failure::Connection(_)) => {
+                    let mut conn = Connection::open(&self.config).await?;
+                    let reply = conn.query(args).await;
+                    (conn, reply)
+                }
+                reply => (conn, reply),
+            },
+            None => {
+                let mut conn = Connection::open(&self.config).await?;
+                let reply = conn.query(args).await;
+                (conn, reply)
+            }
+        };
+        if !matches!(reply, Err(Failure::Connection(_))) {
+            self.idle.lock().unwrap().push(conn);
+        }
+        Ok(reply?)
+    }
+}
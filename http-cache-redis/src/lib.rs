@@ -0,0 +1,204 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! A [`CacheManager`] storing records in [Redis](https://redis.io), which
+//! lets several processes, such as the instances of a service, share a
+//! cache.
+//!
+//! Each record is stored under [`CacheKey::as_str`] behind a configurable
+//! prefix, and set to expire once its response is stale for longer than
+//! [`RedisManager::stale_ttl`], so Redis removes records the cache has no
+//! use for anymore by itself. Connections are opened as needed, kept open
+//! for the next commands and limited in number, see
+//! [`RedisManager::with_max_connections`].
+//!
+//! The manager needs a [tokio](https://tokio.rs) runtime.
+//! ```no_run
+//! use http_cache::{CacheMode, HttpCache, HttpCacheOptions};
+//! use http_cache_redis::RedisManager;
+//!
+//! # fn main() -> http_cache::Result<()> {
+//! let manager = RedisManager::new("redis://127.0.0.1:6379/0")?;
+//! let cache = HttpCache {
+//!     mode: CacheMode::Default,
+//!     manager,
+//!     options: HttpCacheOptions::default(),
+//! };
+//! # drop(cache);
+//! # Ok(())
+//! # }
+//! ```
+mod connection;
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use http_cache::{
+    decode_record, encode_record, now, BodyChecksum, Bytes, CacheError,
+    CacheKey, CacheManager, DecodedRecord, HttpResponse, Result,
+    SerializationFormat,
+};
+use http_cache_semantics::CachePolicy;
+
+use connection::{Config, Pool, Reply};
+
+/// The number of connections a manager opens at most by default.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 16;
+
+/// Implements [`CacheManager`] with [Redis](https://redis.io) as the backend.
+///
+/// Records are laid out as the bundled managers store them, see
+/// [`encode_record`], so they carry the
+/// [`CACHE_RECORD_VERSION`](http_cache::CACHE_RECORD_VERSION) of their
+/// layout and the checksum of their body. Records written by a newer version
+/// are treated as misses and removed, and records whose body doesn't match
+/// its checksum are reported as [`CacheError::CorruptEntry`]. As Redis
+/// expires records by itself, pinning a record doesn't keep it past its TTL,
+/// and tags aren't supported.
+///
+/// Clones share the same connections.
+#[derive(Clone)]
+pub struct RedisManager {
+    /// Prepended to the key of every record, so several caches, or a cache
+    /// and other data, can share a database. Defaults to `http-cache:`.
+    pub prefix: String,
+    /// How long records are kept once their response is stale, so it can
+    /// still be revalidated or served stale. Defaults to one day.
+    pub stale_ttl: Duration,
+    /// Format used to serialize newly stored records.
+    pub format: SerializationFormat,
+    /// Checksum stored along with the body of newly stored records and
+    /// checked when records are read back, `None` skips both. Defaults to
+    /// [`BodyChecksum::Crc32`].
+    pub checksum: Option<BodyChecksum>,
+    pool: Arc<Pool>,
+}
+
+impl fmt::Debug for RedisManager {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // The credentials are left out
+        let config = self.pool.config();
+        f.debug_struct("RedisManager")
+            .field("prefix", &self.prefix)
+            .field("stale_ttl", &self.stale_ttl)
+            .field("format", &self.format)
+            .field("checksum", &self.checksum)
+            .field("host", &config.host)
+            .field("port", &config.port)
+            .field("database", &config.database)
+            .field("max_connections", &self.pool.max_connections())
+            .finish_non_exhaustive()
+    }
+}
+
+impl RedisManager {
+    /// Creates a manager for the server at `url`, of the form
+    /// `redis://[[username]:password@]host[:port][/database]`, opening up to
+    /// [`DEFAULT_MAX_CONNECTIONS`] connections.
+    ///
+    /// No connection is opened until the first command, so a server that
+    /// can't be reached only fails the commands sent to it, see
+    /// [`CacheManager::health_check`] to check it beforehand.
+    pub fn new(url: &str) -> Result<Self> {
+        Self::with_max_connections(url, DEFAULT_MAX_CONNECTIONS)
+    }
+
+    /// Creates a manager for the server at `url` opening up to
+    /// `max_connections` connections, commands wait for one to be free once
+    /// they are all in use.
+    pub fn with_max_connections(
+        url: &str,
+        max_connections: usize,
+    ) -> Result<Self> {
+        let config = Config::parse(url)?;
+        Ok(Self {
+            prefix: "http-cache:".to_string(),
+            stale_ttl: Duration::from_secs(60 * 60 * 24),
+            format: SerializationFormat::default(),
+            checksum: Some(BodyChecksum::default()),
+            pool: Arc::new(Pool::new(config, max_connections)),
+        })
+    }
+
+    fn key(&self, key: &CacheKey) -> Vec<u8> {
+        format!("{}{}", self.prefix, key.as_str()).into_bytes()
+    }
+
+    // How long Redis keeps a record, the freshness lifetime left to its
+    // response and `stale_ttl`, in milliseconds as `PX` takes them
+    fn ttl(&self, policy: &CachePolicy) -> String {
+        let ttl = policy.time_to_live(now()) + self.stale_ttl;
+        // `PX` rejects 0
+        ttl.as_millis().max(1).to_string()
+    }
+}
+
+fn unexpected(reply: Reply) -> CacheError {
+    CacheError::General(anyhow::anyhow!("unexpected Redis reply: {reply:?}"))
+}
+
+impl CacheManager for RedisManager {
+    async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let key = self.key(key);
+        let record = match self.pool.query(&[b"GET", &key]).await? {
+            Reply::Bulk(Some(record)) => record,
+            Reply::Bulk(None) => return Ok(None),
+            reply => return Err(unexpected(reply)),
+        };
+        // Records of earlier versions are upgraded as they are read, they
+        // are rewritten along with the response once it is stored again
+        match decode_record(&Bytes::from(record), self.checksum.is_some())? {
+            DecodedRecord::Current(res, policy)
+            | DecodedRecord::Upgraded(res, policy) => Ok(Some((res, policy))),
+            DecodedRecord::UnknownVersion => {
+                self.pool.query(&[b"DEL", &key]).await?;
+                Ok(None)
+            }
+            // Another version may understand the format, so leave it be
+            _ => Ok(None),
+        }
+    }
+
+    async fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let record = encode_record(&res, &policy, self.format, self.checksum)?;
+        let ttl = self.ttl(&policy);
+        let key = self.key(key);
+        match self
+            .pool
+            .query(&[b"SET", &key, &record, b"PX", ttl.as_bytes()])
+            .await?
+        {
+            Reply::Status(_) => Ok(res),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        let key = self.key(key);
+        match self.pool.query(&[b"DEL", &key]).await? {
+            Reply::Integer(_) => Ok(()),
+            reply => Err(unexpected(reply)),
+        }
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
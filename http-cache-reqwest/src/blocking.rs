@@ -0,0 +1,240 @@
+use anyhow::anyhow;
+use std::convert::TryInto;
+
+use futures::executor::block_on;
+use http::{header::CACHE_CONTROL, request::Parts, HeaderValue, Method};
+use http_cache::{
+    Bytes, CacheError, CacheManager, CacheOptions, CacheStatus, HitOrMiss,
+    HttpCache, HttpHeaders, HttpResponse, Middleware, Result,
+};
+use http_cache_semantics::CachePolicy;
+use reqwest::{
+    blocking::{Client, Request, RequestBuilder, Response},
+    IntoUrl,
+};
+use reqwest_middleware::Error;
+use url::Url;
+
+use crate::{
+    build_response, convert_error, convert_headers, reason, set_reason,
+};
+
+/// Wraps a [`reqwest::blocking::Client`] so its requests go through an
+/// [`HttpCache`].
+///
+/// Requests are keyed and stored the same way as with the [`Cache`](crate::Cache)
+/// middleware, so blocking and async clients sharing a manager see each
+/// other's responses, and the responses look the same as described on
+/// [`Cache`](crate::Cache). The cache runs on the calling thread, which means
+/// that, like the blocking client itself, it must not be used from within
+/// an async runtime.
+///
+/// Blocking requests don't have extensions, so they can't carry a
+/// [`ManagerOverride`](crate::ManagerOverride), use a client built with
+/// another cache instead, nor an [`EntryMetadata`](crate::EntryMetadata),
+/// set it through [`HttpCacheOptions::on_store`](crate::HttpCacheOptions::on_store)
+/// instead.
+///
+/// ```no_run
+/// use http_cache_reqwest::{BlockingClient, CacheMode, CACacheManager, HttpCache, HttpCacheOptions};
+///
+/// fn main() -> reqwest_middleware::Result<()> {
+///     let client = BlockingClient::new(
+///         reqwest::blocking::Client::new(),
+///         HttpCache {
+///             mode: CacheMode::Default,
+///             manager: CACacheManager::default(),
+///             options: HttpCacheOptions::default(),
+///         },
+///     );
+///     client.get("https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching")?;
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BlockingClient<T: CacheManager> {
+    client: Client,
+    cache: HttpCache<T>,
+}
+
+impl<T: CacheManager> BlockingClient<T> {
+    /// Creates a client sending the requests `cache` lets through with
+    /// `client`.
+    pub fn new(client: Client, cache: HttpCache<T>) -> Self {
+        Self { client, cache }
+    }
+
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.cache.manager()
+    }
+
+    /// Starts building a request with the wrapped client, pass the built
+    /// request to [`BlockingClient::send`] to go through the cache.
+    pub fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+        self.client.request(method, url)
+    }
+
+    /// Sends a `GET` request to `url` through the cache.
+    pub fn get(
+        &self,
+        url: impl IntoUrl,
+    ) -> reqwest_middleware::Result<Response> {
+        let req = self.client.get(url).build()?;
+        self.send(req)
+    }
+
+    /// Sends `req` through the cache.
+    pub fn send(&self, req: Request) -> reqwest_middleware::Result<Response> {
+        let url = req.url().clone();
+        let mut upstream = None;
+        let middleware = BlockingMiddleware {
+            req,
+            client: &self.client,
+            upstream: &mut upstream,
+        };
+        let can_cache = match self.cache.can_cache_request(&middleware) {
+            Ok(can_cache) => can_cache,
+            Err(e) => return Err(Error::Middleware(anyhow!(e))),
+        };
+        if !can_cache {
+            return Ok(self.client.execute(middleware.req)?);
+        }
+        if self.cache.passes_through(&middleware) {
+            return self.pass_through(middleware).map_err(convert_error);
+        }
+        let mut res = match block_on(self.cache.run(middleware)) {
+            Ok(r) => r,
+            Err(e) => return Err(convert_error(e)),
+        };
+        if self.cache.options.trailers_as_headers {
+            res.trailers_into_headers();
+        }
+        let status = CacheStatus::from(&res);
+        if status.cache == HitOrMiss::HIT {
+            res.url = url;
+        }
+        let body = std::mem::take(&mut res.body);
+        let reason = res.reason.take();
+        let mut converted = Response::from(build_response(res, body)?);
+        if let Some(extensions) = upstream {
+            *converted.extensions_mut() = extensions;
+        }
+        set_reason(converted.extensions_mut(), reason);
+        converted.extensions_mut().insert(status);
+        Ok(converted)
+    }
+}
+
+impl<T: CacheManager> BlockingClient<T> {
+    // Sends a request the cache passes through, leaving the body of the
+    // response to be read by the caller
+    fn pass_through(
+        &self,
+        middleware: BlockingMiddleware<'_>,
+    ) -> Result<Response> {
+        let copied_req =
+            middleware.req.try_clone().ok_or(CacheError::BadRequest)?;
+        let mut upstream = self
+            .client
+            .execute(copied_req)
+            .map_err(|e| CacheError::General(anyhow!(e)))?;
+        let mut res = HttpResponse {
+            body: Bytes::new(),
+            headers: HttpHeaders::from(upstream.headers()),
+            trailers: None,
+            status: upstream.status().into(),
+            reason: reason(upstream.extensions()),
+            url: upstream.url().clone(),
+            version: upstream.version().try_into()?,
+            metadata: Default::default(),
+        };
+        block_on(self.cache.pass_through(middleware, &mut res))?;
+        *upstream.headers_mut() = convert_headers(&res.headers)?;
+        upstream.extensions_mut().insert(CacheStatus::from(&res));
+        Ok(upstream)
+    }
+}
+
+/// Implements ['Middleware'] for the reqwest blocking client
+pub(crate) struct BlockingMiddleware<'a> {
+    pub req: Request,
+    pub client: &'a Client,
+    // Receives the extensions of the response fetched upstream
+    pub upstream: &'a mut Option<http::Extensions>,
+}
+
+impl Middleware for BlockingMiddleware<'_> {
+    fn is_method_get_head(&self) -> bool {
+        self.req.method() == Method::GET || self.req.method() == Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            http_cache::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.req.headers_mut().insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.req
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
+        Ok(())
+    }
+    fn parts(&self) -> Result<Parts> {
+        let mut converted = http::Request::builder()
+            .method(self.req.method().clone())
+            .uri(self.req.url().as_str())
+            .version(self.req.version())
+            .body(())?;
+        *converted.headers_mut() = self.req.headers().clone();
+        Ok(converted.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(self.req.url().clone())
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.req.method().as_ref().to_string())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let copied_req = self.req.try_clone().ok_or(CacheError::BadRequest)?;
+        let mut res = match self.client.execute(copied_req) {
+            Ok(r) => r,
+            Err(e) => return Err(CacheError::General(anyhow!(e))),
+        };
+        let reason = reason(res.extensions());
+        *self.upstream = Some(std::mem::take(res.extensions_mut()));
+        let headers = HttpHeaders::from(res.headers());
+        let status = res.status().into();
+        let url = res.url().clone();
+        let version = res.version().try_into()?;
+        let body = match res.bytes() {
+            Ok(b) => b,
+            Err(e) => return Err(CacheError::General(anyhow!(e))),
+        };
+        Ok(HttpResponse {
+            body,
+            headers,
+            trailers: None,
+            status,
+            reason,
+            url,
+            version,
+            metadata: Default::default(),
+        })
+    }
+}
@@ -15,7 +15,7 @@
 //! ```no_run
 //! use reqwest::Client;
 //! use reqwest_middleware::{ClientBuilder, Result};
-//! use http_cache_reqwest::{Cache, CacheMode, CACacheManager, HttpCache};
+//! use http_cache_reqwest::{Cache, CacheMode, CACacheManager, HttpCache, HttpCacheOptions};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<()> {
@@ -23,7 +23,7 @@
 //!         .with(Cache(HttpCache {
 //!             mode: CacheMode::Default,
 //!             manager: CACacheManager::default(),
-//!             options: None,
+//!             options: HttpCacheOptions::default(),
 //!         }))
 //!         .build();
 //!     client
@@ -33,54 +33,156 @@
 //!     Ok(())
 //! }
 //! ```
+//!
+//! ## Retries
+//!
+//! Errors from reqwest and from the rest of the middleware chain are passed
+//! on as they were, so retry middleware such as `reqwest-retry` can classify
+//! them wherever it sits in the chain. Errors of the cache itself are
+//! returned as [`Error::Middleware`].
+//!
+//! This ordering is recommended: add the cache first and the retry
+//! middleware after it. Only requests the cache sends upstream are then
+//! retried, and the response of the attempt that succeeds is stored.
+//! Adding the retry middleware first works too. Each attempt then goes
+//! through the cache, and requests the cache answers succeed right away.
 use anyhow::anyhow;
 use std::{
-    collections::HashMap,
     convert::{TryFrom, TryInto},
     str::FromStr,
-    time::SystemTime,
 };
 
+use futures::{StreamExt, TryStreamExt};
 use http::{
     header::{HeaderName, CACHE_CONTROL},
     request::Parts,
-    HeaderValue, Method,
+    HeaderMap, HeaderValue, Method,
+};
+use http_cache::{
+    BodyStream, Bytes, CacheError, CacheManager, Middleware, Result,
+    StreamingCacheManager, StreamingMiddleware,
 };
-use http_cache::{CacheError, CacheManager, Middleware, Result};
 use http_cache_semantics::CachePolicy;
-use reqwest::{Request, Response, ResponseBuilderExt};
-use reqwest_middleware::{Error, Next};
+use hyper::ext::ReasonPhrase;
+use reqwest::{Body, Request, Response, ResponseBuilderExt};
+use reqwest_middleware::{ClientWithMiddleware, Error, Next};
 use task_local_extensions::Extensions;
 use url::Url;
 
-pub use http_cache::{CacheMode, CacheOptions, HttpCache, HttpResponse};
+pub use http_cache::{
+    BreakerState, CacheDecision, CacheEvent, CacheMode, CacheOptions,
+    CacheStatus, CacheToggle, CircuitBreaker, Clock, Coalescing, ContentTypes,
+    DryRun, DryRunLookup, EntryMetadata, ErrorPolicy, EventBroadcast,
+    HealthReport, HitOrMiss, HttpCache, HttpCacheOptions, HttpHeaders,
+    HttpResponse, LeaderFailure, ManagerOverride, NotStoredReason, RecordMode,
+    Redaction, RequestPredicate, ResponsePredicate, RevalidationHook,
+    RevalidationRequest, Spawner, StoreHook, UrlFilter, WarmOutcome,
+    WarmReport, WriteBehind, WriteBehindStats,
+};
 
 #[cfg(feature = "manager-cacache")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
-pub use http_cache::CACacheManager;
+pub use http_cache::{
+    BodyStats, CACacheManager, CACacheReader, CACacheWriter, PruneReport,
+};
 
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
-pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
+pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager, MokaWriter};
+
+#[cfg(feature = "blocking")]
+mod blocking;
+
+#[cfg(feature = "blocking")]
+#[cfg_attr(docsrs, doc(cfg(feature = "blocking")))]
+pub use blocking::BlockingClient;
 
 /// Wrapper for [`HttpCache`]
+///
+/// Responses that went through the cache look the same whether they were
+/// served from the cache or not:
+///
+/// - [`Response::url`] is the url that was requested for responses served
+///   from the cache, and the final url after redirects otherwise.
+/// - [`Response::version`] is the version of the stored response.
+/// - The extensions are those of the response fetched upstream, if any was,
+///   along with the [`CacheStatus`] of the response, which can be read with
+///   `response.extensions().get::<CacheStatus>()`. Responses served from the
+///   cache without going upstream only carry the [`CacheStatus`].
+/// - The reason phrase of HTTP/1 responses that don't use the canonical one
+///   is kept, and can be read with
+///   `response.extensions().get::<hyper::ext::ReasonPhrase>()`. HTTP/2
+///   responses don't have one.
+/// - reqwest drops the trailers of responses, so none are stored. Trailers
+///   stored by another client are only handed over as headers, see
+///   [`HttpCacheOptions::trailers_as_headers`].
+///
+/// A [`ManagerOverride`] in the extensions handed to
+/// `RequestBuilder::send_with_extensions` makes the request use another
+/// manager, and an [`EntryMetadata`] there is stored along with the
+/// response.
+///
+/// When reqwest follows redirects, the response it ends up with is stored
+/// under the url that was requested, as that is the one asked for again, and
+/// later requests to that url are served from it. The url it was redirected
+/// to is recorded in [`HttpResponse::url`] and isn't served from the cache
+/// unless [`HttpCacheOptions::cache_redirect_targets`] is set. Redirects
+/// that aren't followed are returned as they are, only `200` and `204`
+/// responses are stored.
+///
+/// Bodies are read in full before the response is returned, use
+/// [`StreamingCache`] to receive them as they arrive. Responses the cache
+/// never stores, in the [`CacheMode::NoStore`] mode or answering methods
+/// other than `GET` and `HEAD`, are passed on without being read, see
+/// [`HttpCache::passes_through`].
 #[derive(Debug)]
 pub struct Cache<T: CacheManager>(pub HttpCache<T>);
 
+impl<T: CacheManager> Cache<T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+}
+
+/// Wrapper for [`HttpCache`] that passes response bodies through as streams
+/// instead of reading them into memory, see [`HttpCache::run_streaming`].
+///
+/// This lets responses far larger than the available memory be cached, as
+/// long as the manager implements [`StreamingCacheManager`].
+///
+/// Bodies fetched upstream reach the caller chunk by chunk as they arrive,
+/// while being written to the manager, and cached bodies are read back in
+/// chunks, so code reading `Response::bytes_stream` or `Response::chunk`
+/// sees bodies arrive the same way on hits and misses. A body that fails or
+/// is dropped partway through isn't stored. Both bundled managers can be
+/// used, though `MokaManager` keeps records in
+/// memory and so collects the body before storing it.
+#[derive(Debug)]
+pub struct StreamingCache<T: StreamingCacheManager>(pub HttpCache<T>);
+
+impl<T: StreamingCacheManager> StreamingCache<T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+}
+
 /// Implements ['Middleware'] for reqwest
 pub(crate) struct ReqwestMiddleware<'a> {
     pub req: Request,
     pub next: Next<'a>,
     pub extensions: &'a mut Extensions,
+    // Receives the extensions of the response fetched upstream
+    pub upstream: &'a mut Option<http::Extensions>,
 }
 
-#[async_trait::async_trait]
 impl Middleware for ReqwestMiddleware<'_> {
     fn is_method_get_head(&self) -> bool {
         self.req.method() == Method::GET || self.req.method() == Method::HEAD
     }
     fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
-        Ok(CachePolicy::new(&self.parts()?, &response.parts()?))
+        self.policy_with_options(response, CacheOptions::default())
     }
     fn policy_with_options(
         &self,
@@ -90,7 +192,7 @@ impl Middleware for ReqwestMiddleware<'_> {
         Ok(CachePolicy::new_options(
             &self.parts()?,
             &response.parts()?,
-            SystemTime::now(),
+            http_cache::now(),
             options,
         ))
     }
@@ -107,11 +209,13 @@ impl Middleware for ReqwestMiddleware<'_> {
         Ok(())
     }
     fn parts(&self) -> Result<Parts> {
-        let copied_req = self.req.try_clone().ok_or(CacheError::BadRequest)?;
-        let converted = match http::Request::try_from(copied_req) {
-            Ok(r) => r,
-            Err(e) => return Err(CacheError::General(anyhow!(e))),
-        };
+        // Built without the body so streaming requests still have parts
+        let mut converted = http::Request::builder()
+            .method(self.req.method().clone())
+            .uri(self.req.url().as_str())
+            .version(self.req.version())
+            .body(())?;
+        *converted.headers_mut() = self.req.headers().clone();
         Ok(converted.into_parts().0)
     }
     fn url(&self) -> Result<Url> {
@@ -120,52 +224,197 @@ impl Middleware for ReqwestMiddleware<'_> {
     fn method(&self) -> Result<String> {
         Ok(self.req.method().as_ref().to_string())
     }
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        self.extensions.get::<ManagerOverride>().cloned()
+    }
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        self.extensions.get::<EntryMetadata>().cloned()
+    }
     async fn remote_fetch(&mut self) -> Result<HttpResponse> {
-        let copied_req = self.req.try_clone().ok_or(CacheError::BadRequest)?;
-        let res = match self.next.clone().run(copied_req, self.extensions).await
-        {
-            Ok(r) => r,
-            Err(e) => return Err(CacheError::General(anyhow!(e))),
-        };
-        let mut headers = HashMap::new();
-        for header in res.headers() {
-            headers.insert(
-                header.0.as_str().to_owned(),
-                header.1.to_str()?.to_owned(),
-            );
-        }
-        let url = res.url().clone();
-        let status = res.status().into();
-        let version = res.version();
-        let body: Vec<u8> = match res.bytes().await {
+        let mut res = self.fetch().await?;
+        let mut converted = convert_parts(&res)?;
+        *self.upstream = Some(std::mem::take(res.extensions_mut()));
+        converted.body = match res.bytes().await {
             Ok(b) => b,
             Err(e) => return Err(CacheError::General(anyhow!(e))),
-        }
-        .to_vec();
-        Ok(HttpResponse {
-            body,
-            headers,
-            status,
-            url,
-            version: version.try_into()?,
+        };
+        Ok(converted)
+    }
+}
+
+impl StreamingMiddleware for ReqwestMiddleware<'_> {
+    async fn remote_fetch_stream(
+        &mut self,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let mut res = self.fetch().await?;
+        let converted = convert_parts(&res)?;
+        *self.upstream = Some(std::mem::take(res.extensions_mut()));
+        let body = res
+            .bytes_stream()
+            .map_err(|e| CacheError::General(anyhow!(e)))
+            .boxed();
+        Ok((converted, body))
+    }
+}
+
+impl ReqwestMiddleware<'_> {
+    // The extensions of the response are left for the caller to take once
+    // the reason phrase was read from them
+    async fn fetch(&mut self) -> Result<Response> {
+        let copied_req = self.req.try_clone().ok_or(CacheError::BadRequest)?;
+        self.next
+            .clone()
+            .run(copied_req, self.extensions)
+            .await
+            .map_err(|e| CacheError::General(anyhow!(e)))
+    }
+}
+
+// Sends a request the cache passes through, leaving the body of the
+// response to be read by the caller
+async fn pass_through<T: CacheManager>(
+    cache: &HttpCache<T>,
+    middleware: ReqwestMiddleware<'_>,
+) -> Result<Response> {
+    let copied_req =
+        middleware.req.try_clone().ok_or(CacheError::BadRequest)?;
+    let mut upstream = middleware
+        .next
+        .clone()
+        .run(copied_req, middleware.extensions)
+        .await
+        .map_err(|e| CacheError::General(anyhow!(e)))?;
+    let mut res = convert_parts(&upstream)?;
+    cache.pass_through(middleware, &mut res).await?;
+    *upstream.headers_mut() = convert_headers(&res.headers)?;
+    upstream.extensions_mut().insert(CacheStatus::from(&res));
+    Ok(upstream)
+}
+
+// Converts everything but the body of a reqwest [`Response`]
+fn convert_parts(res: &Response) -> Result<HttpResponse> {
+    let headers = HttpHeaders::from(res.headers());
+    Ok(HttpResponse {
+        body: Bytes::new(),
+        headers,
+        trailers: None,
+        status: res.status().into(),
+        reason: reason(res.extensions()),
+        url: res.url().clone(),
+        version: res.version().try_into()?,
+        metadata: Default::default(),
+    })
+}
+
+/// Prefetches the provided urls through `client` so later requests can be
+/// served from cache, see [`HttpCache::warm`] for details.
+///
+/// The `cache` should share its manager with the [`Cache`] middleware that
+/// was installed on the client. Responses with an error status are reported
+/// as failures.
+pub async fn warm_cache<T: CacheManager>(
+    client: &ClientWithMiddleware,
+    cache: &HttpCache<T>,
+    urls: impl IntoIterator<Item = Url>,
+    concurrency: usize,
+) -> WarmReport {
+    cache
+        .warm(urls, concurrency, |url| async move {
+            match client.get(url).send().await {
+                Ok(res) => match res.error_for_status() {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(CacheError::General(anyhow!(e))),
+                },
+                Err(e) => Err(CacheError::General(anyhow!(e))),
+            }
         })
+        .await
+}
+
+// Converts an [`HttpResponse`] to a reqwest [`Response`] with the given body
+fn convert_response(
+    response: HttpResponse,
+    body: impl Into<Body>,
+) -> anyhow::Result<Response> {
+    Ok(Response::from(build_response(response, body.into())?))
+}
+
+// Hands back the errors of the client and the rest of the middleware chain
+// as they were, so middleware further up can still tell them apart
+pub(crate) fn convert_error(e: CacheError) -> Error {
+    match e {
+        CacheError::General(e) => match e.downcast::<Error>() {
+            Ok(e) => e,
+            Err(e) => match e.downcast::<reqwest::Error>() {
+                Ok(e) => Error::Reqwest(e),
+                Err(e) => Error::Middleware(e),
+            },
+        },
+        e => Error::Middleware(anyhow!(e)),
     }
 }
 
-// Converts an [`HttpResponse`] to a reqwest [`Response`]
-fn convert_response(response: HttpResponse) -> anyhow::Result<Response> {
+// Gives the responses of the cache the same shape, as documented on [`Cache`]
+fn finish_response(
+    mut response: HttpResponse,
+    body: impl Into<Body>,
+    request_url: &Url,
+    upstream: Option<http::Extensions>,
+) -> anyhow::Result<Response> {
+    let status = CacheStatus::from(&response);
+    if status.cache == HitOrMiss::HIT {
+        response.url = request_url.clone();
+    }
+    let reason = response.reason.take();
+    let mut converted = convert_response(response, body)?;
+    if let Some(extensions) = upstream {
+        *converted.extensions_mut() = extensions;
+    }
+    set_reason(converted.extensions_mut(), reason);
+    converted.extensions_mut().insert(status);
+    Ok(converted)
+}
+
+// Reads the reason phrase hyper keeps for HTTP/1 responses that don't use
+// the canonical one
+pub(crate) fn reason(extensions: &http::Extensions) -> Option<String> {
+    let reason = extensions.get::<ReasonPhrase>()?;
+    Some(String::from_utf8_lossy(reason.as_bytes()).into_owned())
+}
+
+// Replaces the reason phrase of a response, where hyper would have put it
+pub(crate) fn set_reason(
+    extensions: &mut http::Extensions,
+    reason: Option<String>,
+) {
+    extensions.remove::<ReasonPhrase>();
+    if let Some(reason) = reason.and_then(|r| ReasonPhrase::try_from(r).ok()) {
+        extensions.insert(reason);
+    }
+}
+
+// Converts the headers of an [`HttpResponse`], keeping repeated values
+pub(crate) fn convert_headers(headers: &HttpHeaders) -> Result<HeaderMap> {
+    let mut converted = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        converted.append(HeaderName::from_str(name)?, value.clone());
+    }
+    Ok(converted)
+}
+
+// Builds an http [`Response`](http::Response) reqwest can convert, for both
+// the async and blocking clients
+pub(crate) fn build_response<B>(
+    response: HttpResponse,
+    body: B,
+) -> anyhow::Result<http::Response<B>> {
     let mut ret_res = http::Response::builder()
         .status(response.status)
         .url(response.url)
-        .version(response.version.try_into()?)
-        .body(response.body)?;
-    for header in response.headers {
-        ret_res.headers_mut().insert(
-            HeaderName::from_str(header.0.clone().as_str())?,
-            HeaderValue::from_str(header.1.clone().as_str())?,
-        );
-    }
-    Ok(Response::from(ret_res))
+        .version(response.version.into())
+        .body(body)?;
+    *ret_res.headers_mut() = convert_headers(&response.headers)?;
+    Ok(ret_res)
 }
 
 #[async_trait::async_trait]
@@ -176,12 +425,72 @@ impl<T: CacheManager> reqwest_middleware::Middleware for Cache<T> {
         extensions: &mut Extensions,
         next: Next<'_>,
     ) -> std::result::Result<Response, Error> {
-        let middleware = ReqwestMiddleware { req, next, extensions };
-        let res = match self.0.run(middleware).await {
+        let url = req.url().clone();
+        let mut upstream = None;
+        let middleware = ReqwestMiddleware {
+            req,
+            next,
+            extensions,
+            upstream: &mut upstream,
+        };
+        let can_cache = match self.0.can_cache_request(&middleware) {
+            Ok(can_cache) => can_cache,
+            Err(e) => return Err(Error::Middleware(anyhow::anyhow!(e))),
+        };
+        if !can_cache {
+            let ReqwestMiddleware { req, next, extensions, .. } = middleware;
+            return next.run(req, extensions).await;
+        }
+        if self.0.passes_through(&middleware) {
+            return pass_through(&self.0, middleware)
+                .await
+                .map_err(convert_error);
+        }
+        let mut res = match self.0.run(middleware).await {
             Ok(r) => r,
+            Err(e) => return Err(convert_error(e)),
+        };
+        if self.0.options.trailers_as_headers {
+            res.trailers_into_headers();
+        }
+        let body = std::mem::take(&mut res.body);
+        Ok(finish_response(res, body, &url, upstream)?)
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: StreamingCacheManager> reqwest_middleware::Middleware
+    for StreamingCache<T>
+{
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut Extensions,
+        next: Next<'_>,
+    ) -> std::result::Result<Response, Error> {
+        let url = req.url().clone();
+        let mut upstream = None;
+        let middleware = ReqwestMiddleware {
+            req,
+            next,
+            extensions,
+            upstream: &mut upstream,
+        };
+        let can_cache = match self.0.can_cache_request(&middleware) {
+            Ok(can_cache) => can_cache,
             Err(e) => return Err(Error::Middleware(anyhow::anyhow!(e))),
         };
-        let converted = convert_response(res)?;
-        Ok(converted)
+        if !can_cache {
+            let ReqwestMiddleware { req, next, extensions, .. } = middleware;
+            return next.run(req, extensions).await;
+        }
+        let (mut res, body) = match self.0.run_streaming(middleware).await {
+            Ok(r) => r,
+            Err(e) => return Err(convert_error(e)),
+        };
+        if self.0.options.trailers_as_headers {
+            res.trailers_into_headers();
+        }
+        Ok(finish_response(res, Body::wrap_stream(body), &url, upstream)?)
     }
 }
@@ -13,7 +13,7 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 //! The surf middleware implementation for http-cache.
 //! ```no_run
-//! use http_cache_surf::{Cache, CacheMode, CACacheManager, HttpCache};
+//! use http_cache_surf::{Cache, CacheMode, CACacheManager, HttpCache, HttpCacheOptions};
 //!
 //! #[async_std::main]
 //! async fn main() -> surf::Result<()> {
@@ -22,7 +22,7 @@
 //!         .with(Cache(HttpCache {
 //!             mode: CacheMode::Default,
 //!             manager: CACacheManager::default(),
-//!             options: None,
+//!             options: HttpCacheOptions::default(),
 //!         }))
 //!         .send(req)
 //!         .await?;
@@ -31,30 +31,118 @@
 //! ```
 use anyhow::anyhow;
 use std::{
-    collections::HashMap, convert::TryInto, str::FromStr, time::SystemTime,
+    convert::TryInto,
+    pin::Pin,
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+    task::{Context, Poll},
 };
 
+use bytes::BytesMut;
+use futures::{
+    io::{AsyncBufRead, AsyncRead},
+    AsyncReadExt, StreamExt, TryStreamExt,
+};
 use http::{header::CACHE_CONTROL, request, request::Parts};
-use http_cache::{CacheError, CacheManager, Middleware, Result};
+use http_cache::{
+    BodyStream, Bytes, CacheError, CacheManager, Middleware, Result,
+    StreamingCacheManager, StreamingMiddleware,
+};
 use http_cache_semantics::CachePolicy;
-use http_types::{headers::HeaderValue, Method, Response, StatusCode, Version};
+use http_types::{
+    headers::HeaderValue, Body, Method, Response, StatusCode, Version,
+};
 use surf::{middleware::Next, Client, Request};
 use url::Url;
 
-pub use http_cache::{CacheMode, CacheOptions, HttpCache, HttpResponse};
+pub use http_cache::{
+    BreakerState, CacheDecision, CacheEvent, CacheMode, CacheOptions,
+    CacheStatus, CacheToggle, CircuitBreaker, Clock, Coalescing, ContentTypes,
+    DryRun, DryRunLookup, EntryMetadata, ErrorPolicy, EventBroadcast,
+    HealthReport, HitOrMiss, HttpCache, HttpCacheOptions, HttpHeaders,
+    HttpResponse, LeaderFailure, ManagerOverride, NotStoredReason, RecordMode,
+    Redaction, RequestPredicate, ResponsePredicate, RevalidationHook,
+    RevalidationRequest, Spawner, StoreHook, UrlFilter, WarmOutcome,
+    WarmReport, WriteBehind, WriteBehindStats,
+};
 
 #[cfg(feature = "manager-cacache")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
-pub use http_cache::CACacheManager;
+pub use http_cache::{
+    BodyStats, CACacheManager, CACacheReader, CACacheWriter, PruneReport,
+};
 
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
-pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
+pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager, MokaWriter};
 
 /// Wrapper for [`HttpCache`]
+///
+/// Responses that went through the cache carry their [`CacheStatus`], which
+/// can be read with `response.ext::<CacheStatus>()`.
+///
+/// Surf doesn't expose the reason phrase of responses nor lets one be set,
+/// so [`HttpResponse::reason`] is always `None` and responses served from
+/// the cache have the canonical phrase of their status.
+///
+/// Trailers aren't read from surf responses either, so none are stored.
+/// Trailers stored by another client are only handed over as headers, see
+/// [`HttpCacheOptions::trailers_as_headers`].
+///
+/// Surf doesn't follow redirects on its own. With surf's `Redirect`
+/// middleware installed before the cache, each request it makes goes
+/// through the cache on its own. Installed after the cache, the response it
+/// was redirected to is stored under the url that was requested, and as surf
+/// doesn't report the url it ended up at, that is also the url recorded in
+/// [`HttpResponse::url`].
+///
+/// Responses the cache never stores, in the [`CacheMode::NoStore`] mode or
+/// answering methods other than `GET` and `HEAD`, are passed on without
+/// their body being read, see [`HttpCache::passes_through`].
 #[derive(Debug)]
 pub struct Cache<T: CacheManager>(pub HttpCache<T>);
 
+impl<T: CacheManager> Cache<T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+}
+
+/// Wrapper for [`HttpCache`] that passes response bodies through as streams
+/// instead of reading them into memory, see [`HttpCache::run_streaming`].
+///
+/// Cached bodies are only read from the manager as the body of the response
+/// is, so a hit whose body is never read, such as when only the status and
+/// headers are looked at, doesn't read the body stored by a
+/// `CACacheManager` at all. Bodies fetched upstream are passed on as they
+/// arrive while being written to the manager, and a body that fails or is
+/// dropped partway through isn't stored.
+///
+/// ```no_run
+/// use http_cache_surf::{CacheMode, CACacheManager, HttpCache, StreamingCache};
+///
+/// #[async_std::main]
+/// async fn main() -> surf::Result<()> {
+///     let client = surf::client().with(StreamingCache(HttpCache::new(
+///         CacheMode::Default,
+///         CACacheManager::default(),
+///     )));
+///     let res = client.get("https://example.com/health").await?;
+///     assert!(res.status().is_success());
+///     Ok(())
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StreamingCache<T: StreamingCacheManager>(pub HttpCache<T>);
+
+impl<T: StreamingCacheManager> StreamingCache<T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.0.manager()
+    }
+}
+
 /// Implements ['Middleware'] for surf
 pub(crate) struct SurfMiddleware<'a> {
     pub req: Request,
@@ -62,13 +150,12 @@ pub(crate) struct SurfMiddleware<'a> {
     pub next: Next<'a>,
 }
 
-#[async_trait::async_trait]
 impl Middleware for SurfMiddleware<'_> {
     fn is_method_get_head(&self) -> bool {
         self.req.method() == Method::Get || self.req.method() == Method::Head
     }
     fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
-        Ok(CachePolicy::new(&self.parts()?, &response.parts()?))
+        self.policy_with_options(response, CacheOptions::default())
     }
     fn policy_with_options(
         &self,
@@ -78,7 +165,7 @@ impl Middleware for SurfMiddleware<'_> {
         Ok(CachePolicy::new_options(
             &self.parts()?,
             &response.parts()?,
-            SystemTime::now(),
+            http_cache::now(),
             options,
         ))
     }
@@ -118,36 +205,183 @@ impl Middleware for SurfMiddleware<'_> {
     fn method(&self) -> Result<String> {
         Ok(self.req.method().as_ref().to_string())
     }
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        self.req.ext::<ManagerOverride>().cloned()
+    }
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        self.req.ext::<EntryMetadata>().cloned()
+    }
     async fn remote_fetch(&mut self) -> Result<HttpResponse> {
-        let url = self.req.url().clone();
-        let mut res =
-            match self.next.run(self.req.clone(), self.client.clone()).await {
-                Ok(r) => r,
-                Err(e) => return Err(CacheError::General(anyhow!(e))),
-            };
-        let mut headers = HashMap::new();
-        for header in res.iter() {
-            headers.insert(
-                header.0.as_str().to_owned(),
-                header.1.as_str().to_owned(),
-            );
-        }
-        let status = res.status().into();
-        let version = res.version().unwrap_or(Version::Http1_1);
-        let body: Vec<u8> = match res.body_bytes().await {
-            Ok(b) => b,
+        let mut res = self.fetch().await?;
+        let mut converted = convert_parts(&res, self.req.url())?;
+        converted.body = match res.body_bytes().await {
+            Ok(b) => b.into(),
             Err(e) => return Err(CacheError::General(anyhow!(e))),
         };
-        Ok(HttpResponse {
-            body,
-            headers,
-            status,
-            url,
-            version: version.try_into()?,
-        })
+        Ok(converted)
     }
 }
 
+impl StreamingMiddleware for SurfMiddleware<'_> {
+    async fn remote_fetch_stream(
+        &mut self,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let mut res = self.fetch().await?;
+        let converted = convert_parts(&res, self.req.url())?;
+        let reader = res.take_body().into_reader();
+        let body = futures::stream::unfold(
+            (reader, BytesMut::new()),
+            |(mut reader, mut buf)| async move {
+                buf.resize(64 * 1024, 0);
+                match reader.read(&mut buf).await {
+                    Ok(0) => None,
+                    Ok(read) => {
+                        buf.truncate(read);
+                        Some((Ok(buf.split().freeze()), (reader, buf)))
+                    }
+                    Err(e) => Some((Err(e.into()), (reader, buf))),
+                }
+            },
+        )
+        .boxed();
+        Ok((converted, body))
+    }
+}
+
+impl SurfMiddleware<'_> {
+    async fn fetch(&mut self) -> Result<surf::Response> {
+        match self.next.run(self.req.clone(), self.client.clone()).await {
+            Ok(r) => Ok(r),
+            Err(e) => Err(CacheError::General(anyhow!(e))),
+        }
+    }
+}
+
+// Sends a request the cache passes through, leaving the body of the
+// response to be read by the caller
+async fn pass_through<T: CacheManager>(
+    cache: &HttpCache<T>,
+    mut middleware: SurfMiddleware<'_>,
+) -> std::result::Result<surf::Response, http_types::Error> {
+    let mut upstream = middleware.fetch().await?;
+    let mut res = convert_parts(&upstream, middleware.req.url())?;
+    cache.pass_through(middleware, &mut res).await?;
+    convert_response(res, upstream.take_body())
+}
+
+// Converts everything but the body of a surf [`Response`](surf::Response)
+fn convert_parts(res: &surf::Response, url: &Url) -> Result<HttpResponse> {
+    let mut headers = HttpHeaders::new();
+    for (name, values) in res.iter() {
+        for value in values {
+            headers.append(
+                name.as_str(),
+                http::HeaderValue::from_str(value.as_str())?,
+            );
+        }
+    }
+    let version = res.version().unwrap_or(Version::Http1_1);
+    Ok(HttpResponse {
+        body: Bytes::new(),
+        headers,
+        trailers: None,
+        status: res.status().into(),
+        reason: None,
+        url: url.clone(),
+        version: version.try_into()?,
+        metadata: Default::default(),
+    })
+}
+
+// Builds the surf response handed back for a response that went through the
+// cache
+fn convert_response(
+    res: HttpResponse,
+    body: Body,
+) -> std::result::Result<surf::Response, http_types::Error> {
+    let mut converted = Response::new(StatusCode::Ok);
+    // Surf only holds ASCII header values, others fail the conversion
+    for (name, value) in &res.headers {
+        let val = HeaderValue::from_bytes(value.as_bytes().to_vec())?;
+        converted.append_header(name, val);
+    }
+    converted.set_status(res.status.try_into()?);
+    converted.set_version(Some(res.version.into()));
+    converted.ext_mut().insert(CacheStatus::from(&res));
+    converted.set_body(body);
+    Ok(surf::Response::from(converted))
+}
+
+// Reads a [`BodyStream`] as a surf body. Surf wants body readers to be
+// `Sync`, which the stream isn't, the mutex is only there to make it so and
+// never actually locked.
+struct BodyReader(Mutex<Pin<Box<dyn AsyncBufRead + Send>>>);
+
+impl BodyReader {
+    fn new(body: BodyStream) -> Self {
+        let reader = body.map_err(std::io::Error::other).into_async_read();
+        Self(Mutex::new(Box::pin(reader)))
+    }
+
+    fn reader(&mut self) -> Pin<&mut (dyn AsyncBufRead + Send)> {
+        self.0.get_mut().unwrap_or_else(PoisonError::into_inner).as_mut()
+    }
+}
+
+impl AsyncRead for BodyReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.get_mut().reader().poll_read(cx, buf)
+    }
+}
+
+impl AsyncBufRead for BodyReader {
+    fn poll_fill_buf(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<&[u8]>> {
+        self.get_mut().reader().poll_fill_buf(cx)
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().reader().consume(amt)
+    }
+}
+
+/// Prefetches the provided urls through `client` so later requests can be
+/// served from cache, see [`HttpCache::warm`] for details.
+///
+/// The `cache` should share its manager with the [`Cache`] middleware that
+/// was installed on the client. Responses with an error status are reported
+/// as failures.
+pub async fn warm_cache<T: CacheManager>(
+    client: &Client,
+    cache: &HttpCache<T>,
+    urls: impl IntoIterator<Item = Url>,
+    concurrency: usize,
+) -> WarmReport {
+    cache
+        .warm(urls, concurrency, |url| async move {
+            match client.get(url).await {
+                Ok(res)
+                    if res.status().is_client_error()
+                        || res.status().is_server_error() =>
+                {
+                    Err(CacheError::General(anyhow!(
+                        "Unexpected status code {}",
+                        res.status()
+                    )))
+                }
+                Ok(_) => Ok(()),
+                Err(e) => Err(CacheError::General(anyhow!(e))),
+            }
+        })
+        .await
+}
+
 #[surf::utils::async_trait]
 impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
     async fn handle(
@@ -157,15 +391,44 @@ impl<T: CacheManager> surf::middleware::Middleware for Cache<T> {
         next: Next<'_>,
     ) -> std::result::Result<surf::Response, http_types::Error> {
         let middleware = SurfMiddleware { req, client, next };
-        let res = self.0.run(middleware).await?;
-        let mut converted = Response::new(StatusCode::Ok);
-        for header in &res.headers {
-            let val = HeaderValue::from_bytes(header.1.as_bytes().to_vec())?;
-            converted.insert_header(header.0.as_str(), val);
+        if !self.0.can_cache_request(&middleware)? {
+            let SurfMiddleware { req, client, next } = middleware;
+            return next.run(req, client).await;
+        }
+        if self.0.passes_through(&middleware) {
+            return pass_through(&self.0, middleware).await;
+        }
+        let mut res = self.0.run(middleware).await?;
+        if self.0.options.trailers_as_headers {
+            res.trailers_into_headers();
+        }
+        let body = Body::from(Vec::from(std::mem::take(&mut res.body)));
+        convert_response(res, body)
+    }
+}
+
+#[surf::utils::async_trait]
+impl<T: StreamingCacheManager> surf::middleware::Middleware
+    for StreamingCache<T>
+{
+    async fn handle(
+        &self,
+        req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> std::result::Result<surf::Response, http_types::Error> {
+        let middleware = SurfMiddleware { req, client, next };
+        if !self.0.can_cache_request(&middleware)? {
+            let SurfMiddleware { req, client, next } = middleware;
+            return next.run(req, client).await;
+        }
+        let (mut res, body) = self.0.run_streaming(middleware).await?;
+        if self.0.options.trailers_as_headers {
+            res.trailers_into_headers();
         }
-        converted.set_status(res.status.try_into()?);
-        converted.set_version(Some(res.version.try_into()?));
-        converted.set_body(res.body.clone());
-        Ok(surf::Response::from(converted))
+        // The length is left unknown, a stored `content-length` header
+        // still goes along with the other headers
+        let body = Body::from_reader(BodyReader::new(body), None);
+        convert_response(res, body)
     }
 }
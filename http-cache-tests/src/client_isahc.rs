@@ -0,0 +1,564 @@
+use crate::*;
+
+use futures::AsyncReadExt;
+use http_cache_isahc::Cache;
+use isahc::{
+    http::{Request, Response},
+    Body, HttpClient,
+};
+
+fn client<T: CacheManager>(cache: HttpCache<T>) -> anyhow::Result<HttpClient> {
+    Ok(HttpClient::builder().interceptor(Cache(cache)).build()?)
+}
+
+async fn body(mut res: Response<Body>) -> anyhow::Result<Vec<u8>> {
+    let mut body = Vec::new();
+    res.body_mut().read_to_end(&mut body).await?;
+    Ok(body)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode_with_options() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PRIVATE, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache options override
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            cache_options: Some(CacheOptions {
+                shared: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    })?;
+
+    // Cold pass to load cache
+    client.get_async(&url).await?;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = client.get_async(&url).await?;
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode_no_cache_response() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("no-cache", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_store_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::NoStore,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Remote request but should not cache
+    client.get_async(&url).await?;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_cache_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::NoCache,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Remote request and should cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn force_cache_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::ForceCache,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Should result in a cache miss and a remote request
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Should result in a cache hit and no remote request
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn delete_after_non_get_head_method_request() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_get = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_post = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201).set_body_bytes(b"created"))
+        .expect(1);
+    let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
+    let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Post request to make sure the cache object at the same resource was deleted
+    let req_post = Request::post(&url).body("payload")?;
+    let res = client.send_async(req_post).await?;
+    assert_eq!(body(res).await?, b"created");
+
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn request_body_is_sent() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string("payload"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client =
+        client(HttpCache::new(CacheMode::Default, MokaManager::default()))?;
+
+    let req = Request::post(&url).body("payload")?;
+    let res = client.send_async(req).await?;
+    assert_eq!(res.status(), 201);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_304() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_304 = Mock::given(method(GET))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_304).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_200() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_200 = build_mock(MUST_REVALIDATE, b"updated", 200, 1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_200).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(body(res).await?, b"updated");
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_500() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_500 = Mock::given(method(GET))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct isahc client with cache defaults
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    // Cold pass to load cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_500).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = client.get_async(&url).await?;
+    assert!(res.headers().get("warning").is_some());
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(test)]
+mod only_if_cached_mode {
+    use super::*;
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn miss() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 0);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct isahc client with cache defaults
+        let client = client(HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        })?;
+
+        // Should result in a cache miss and no remote request
+        let res = client.get_async(&url).await?;
+        assert_eq!(res.status(), 504);
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+        // Try to load cached object
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn hit() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct isahc client with cache defaults
+        let client = client(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        })?;
+
+        // Cold pass to load the cache
+        let res = client.get_async(&url).await?;
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+        // Try to load cached object
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Construct isahc client with cache defaults
+        let client = super::client(HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        })?;
+
+        // Should result in a cache hit and no remote request
+        let res = client.get_async(&url).await?;
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+        assert_eq!(body(res).await?, TEST_BODY);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn should_cache_request_bypass() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = CountingManager::default();
+
+    // Construct isahc client that bypasses the cache for debug requests
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            should_cache_request: Some(Arc::new(
+                |parts: &http::request::Parts| {
+                    !parts.headers.contains_key("x-debug-live")
+                },
+            )),
+            ..Default::default()
+        },
+    })?;
+
+    // Bypassed requests go to the origin without touching the manager
+    for _ in 0..2 {
+        let req = Request::get(&url).header("x-debug-live", "1").body(())?;
+        let res = client.send_async(req).await?;
+        assert!(res.headers().get(XCACHE).is_none());
+        assert!(res.headers().get(XCACHELOOKUP).is_none());
+        assert_eq!(body(res).await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), 0);
+
+    // Other requests still use the cache
+    let res = client.get_async(&url).await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert!(manager.calls() > 0);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn normalizes_keys() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    })?;
+
+    let res = client.get_async(format!("{}/a/../", &mock_server.uri())).await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // The same resource spelled without the dot-segments
+    let res = client.get_async(format!("{}/", &mock_server.uri())).await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
+
+// Isahc decodes gzip bodies and keeps the headers of the encoded one, which
+// are reconciled before the response is stored
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn reconciles_decoded_encoding() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guard = mock_server.register_as_scoped(build_gzip_mock(1)).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client =
+        client(HttpCache::new(CacheMode::Default, MokaManager::default()))?;
+    let len = TEST_BODY.len().to_string();
+
+    for cache in [MISS, HIT] {
+        let res = client.get_async(&url).await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), cache);
+        assert!(res.headers().get("content-encoding").is_none());
+        assert_eq!(res.headers().get("content-length").unwrap(), len.as_str());
+        assert_eq!(body(res).await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_store_mode_passes_bodies_through() -> anyhow::Result<()> {
+    let (url, sent, release) = held_body_server()?;
+    let client =
+        client(HttpCache::new(CacheMode::NoStore, MokaManager::default()))?;
+
+    // The response is returned before its body was sent
+    let res = client.get_async(&url).await?;
+    assert!(!sent.load(Ordering::SeqCst));
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    drop(release);
+    assert_eq!(body(res).await?, TEST_BODY);
+    Ok(())
+}
@@ -1,54 +1,357 @@
 use crate::*;
-use std::sync::Arc;
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
 
-use http_cache_reqwest::Cache;
+use http_cache_reqwest::{Cache, StreamingCache};
+use hyper::ext::ReasonPhrase;
 use reqwest::{Client, Request, ResponseBuilderExt};
 use reqwest_middleware::ClientBuilder;
 
+#[cfg(feature = "manager-moka")]
 #[tokio::test]
 async fn default_mode() -> anyhow::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
 
     // Construct reqwest client with cache defaults
     let client = ClientBuilder::new(Client::new())
         .with(Cache(HttpCache {
             mode: CacheMode::Default,
-            manager: Arc::clone(&manager),
-            options: None,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
         }))
         .build();
 
     // Cold pass to load cache
-    client.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(
+        res.extensions().get::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure the expect response was returned
     let res = client.get(url).send().await?;
+    assert_eq!(
+        res.extensions().get::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::HIT,
+            lookup: HitOrMiss::HIT,
+            overridden: false,
+        })
+    );
     assert_eq!(res.bytes().await?, TEST_BODY);
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_repeated_headers() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guard =
+        mock_server.register_as_scoped(build_cookie_mock(1)).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    for cache in [HitOrMiss::MISS, HitOrMiss::HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.extensions().get::<CacheStatus>().unwrap().cache, cache);
+        let cookies = res
+            .headers()
+            .get_all("set-cookie")
+            .iter()
+            .map(|value| value.to_str())
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        assert_eq!(cookies, SET_COOKIES);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_opaque_header_values() -> anyhow::Result<()> {
+    let mut response = b"HTTP/1.1 200 OK\r\n".to_vec();
+    response.extend_from_slice(b"cache-control: max-age=86400, public\r\n");
+    response.extend_from_slice(b"x-latin1: ");
+    response.extend_from_slice(LATIN1_VALUE);
+    response.extend_from_slice(b"\r\nx-token: ");
+    response.extend_from_slice(OPAQUE_TOKEN.as_bytes());
+    response.extend_from_slice(b"\r\ncontent-length: 4\r\n");
+    response.extend_from_slice(b"connection: close\r\n\r\ntest");
+    let (url, requests) = raw_server(response)?;
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    for cache in [HitOrMiss::MISS, HitOrMiss::HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.extensions().get::<CacheStatus>().unwrap().cache, cache);
+        assert_eq!(res.headers()["x-latin1"].as_bytes(), LATIN1_VALUE);
+        assert_eq!(res.headers()["x-token"], OPAQUE_TOKEN);
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_the_reason_phrase() -> anyhow::Result<()> {
+    let (url, requests) = reason_server()?;
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .build();
+
+    for cache in [HitOrMiss::MISS, HitOrMiss::HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.extensions().get::<CacheStatus>().unwrap().cache, cache);
+        let reason = res.extensions().get::<ReasonPhrase>().unwrap();
+        assert_eq!(reason.as_bytes(), REASON_PHRASE.as_bytes());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn trailers_as_headers() -> anyhow::Result<()> {
+    let url = Url::parse("http://example.com/trailers")?;
+    let manager = MokaManager::default();
+    let mut headers = HttpHeaders::new();
+    headers.insert(CACHE_CONTROL.as_str(), CACHEABLE_PUBLIC.parse()?);
+    let res = HttpResponse {
+        body: Bytes::from_static(TEST_BODY),
+        headers,
+        trailers: Some(HttpHeaders::from(&test_trailers())),
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(())?;
+    let policy = CachePolicy::new(&req, &res.parts()?);
+    manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+
+    for trailers_as_headers in [false, true] {
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::OnlyIfCached,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    trailers_as_headers,
+                    ..Default::default()
+                },
+            }))
+            .build();
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+        let headers = res.headers();
+        if trailers_as_headers {
+            assert_eq!(headers.get("trailer-grpc-status").unwrap(), "0");
+            assert_eq!(headers.get("trailer-grpc-message").unwrap(), "done");
+        } else {
+            assert!(!headers.contains_key("trailer-grpc-status"));
+        }
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn manager_override() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let shared = MokaManager::default();
+    let artifacts = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, shared.clone())))
+        .build();
+    let page = Url::parse(&format!("{}/page", &mock_server.uri()))?;
+    let artifact = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+
+    client.get(page.clone()).send().await?;
+    let mut overridden = task_local_extensions::Extensions::new();
+    overridden.insert(ManagerOverride::new(artifacts.clone()));
+    for cache in [HitOrMiss::MISS, HitOrMiss::HIT] {
+        let res = client
+            .get(artifact.clone())
+            .send_with_extensions(&mut overridden)
+            .await?;
+        assert_eq!(res.extensions().get::<CacheStatus>().unwrap().cache, cache);
+    }
+    assert!(shared.get(&CacheKey::new(GET, &page)).await?.is_some());
+    assert!(shared.get(&CacheKey::new(GET, &artifact)).await?.is_none());
+    assert!(artifacts.get(&CacheKey::new(GET, &artifact)).await?.is_some());
+    assert!(artifacts.get(&CacheKey::new(GET, &page)).await?.is_none());
+
+    // Invalidations reach the same manager through the override
+    let override_cache = HttpCache::new(CacheMode::Default, shared.clone())
+        .with_manager(ManagerOverride::new(artifacts.clone()));
+    override_cache.manager().delete(&CacheKey::new(GET, &artifact)).await?;
+    assert!(artifacts.get(&CacheKey::new(GET, &artifact)).await?.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn entry_metadata() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guards = validated_mocks(&mock_server, "\"v1\"", 1).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let on_store: StoreHook =
+        Arc::new(|res: &HttpResponse, metadata: &mut EntryMetadata| {
+            metadata.insert("status", res.status.to_string()).unwrap();
+        });
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            on_store: Some(on_store),
+            ..Default::default()
+        },
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    let mut metadata = EntryMetadata::new();
+    metadata.insert("tenant", "acme")?;
+    let mut extensions = task_local_extensions::Extensions::new();
+    extensions.insert(metadata);
+    client.get(url.clone()).send_with_extensions(&mut extensions).await?;
+    let inspection = cache.inspect(GET, &url).await?.unwrap();
+    assert_eq!(inspection.metadata().get("tenant"), Some("acme"));
+    assert_eq!(inspection.metadata().get("status"), Some("200"));
+
+    // A 304 refreshes the headers and keeps the metadata, even though the
+    // request carries none
+    client.get(url.clone()).send().await?;
+    let (res, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(res.headers.get("x-revision").unwrap(), "2");
+    assert_eq!(res.metadata.get("tenant"), Some("acme"));
+    assert_eq!(res.metadata.len(), 2);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_the_version() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    let cold = client.get(url.clone()).send().await?;
+    let hot = client.get(url).send().await?;
+    assert_eq!(
+        hot.extensions().get::<CacheStatus>().unwrap().cache,
+        HitOrMiss::HIT
+    );
+    assert_eq!(cold.version(), reqwest::Version::HTTP_11);
+    assert_eq!(hot.version(), cold.version());
+    Ok(())
+}
+
+// Runs `methods` in order against a url answering both GET and HEAD, returning
+// the bodies of the responses
+#[cfg(feature = "manager-moka")]
+async fn head_and_get(methods: [&str; 3]) -> anyhow::Result<Vec<Bytes>> {
+    let mock_server = MockServer::start().await;
+    let get = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let head = Mock::given(method("HEAD"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC),
+        )
+        .expect(1);
+    let _get_guard = mock_server.register_as_scoped(get).await;
+    let _head_guard = mock_server.register_as_scoped(head).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .build();
+    let mut bodies = Vec::new();
+    for method in methods {
+        let method = reqwest::Method::from_bytes(method.as_bytes())?;
+        let res = client.request(method, url.clone()).send().await?;
+        bodies.push(res.bytes().await?);
+    }
+    Ok(bodies)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn head_then_get() -> anyhow::Result<()> {
+    let bodies = head_and_get(["HEAD", "GET", "GET"]).await?;
+    assert_eq!(bodies, [&b""[..], TEST_BODY, TEST_BODY]);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn get_then_head() -> anyhow::Result<()> {
+    let bodies = head_and_get(["GET", "HEAD", "GET"]).await?;
+    assert_eq!(bodies, [TEST_BODY, &b""[..], TEST_BODY]);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
 #[tokio::test]
 async fn default_mode_with_options() -> anyhow::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
 
     // Construct reqwest client with cache options override
     let client = ClientBuilder::new(Client::new())
         .with(Cache(HttpCache {
             mode: CacheMode::Default,
-            manager: Arc::clone(&manager),
-            options: Some(CacheOptions { shared: false, ..Default::default() }),
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                cache_options: Some(CacheOptions {
+                    shared: false,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
         }))
         .build();
 
@@ -56,25 +359,26 @@ async fn default_mode_with_options() -> anyhow::Result<()> {
     client.get(url.clone()).send().await?;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[tokio::test]
 async fn no_cache_mode() -> anyhow::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
 
     // Construct reqwest client with cache defaults
     let client = ClientBuilder::new(Client::new())
         .with(Cache(HttpCache {
             mode: CacheMode::NoCache,
-            manager: Arc::clone(&manager),
-            options: None,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
         }))
         .build();
 
@@ -82,10 +386,2737 @@ async fn no_cache_mode() -> anyhow::Result<()> {
     client.get(url.clone()).send().await?;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // To verify our endpoint receives the request rather than a cache hit
     client.get(url).send().await?;
     Ok(())
 }
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn warm_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_fresh =
+        build_path_mock("/fresh", CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_cold = build_path_mock("/cold", CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_missing =
+        build_path_mock("/missing", CACHEABLE_PUBLIC, TEST_BODY, 404, 1);
+    let _mock_guard_fresh = mock_server.register_as_scoped(m_fresh).await;
+    let _mock_guard_cold = mock_server.register_as_scoped(m_cold).await;
+    let _mock_guard_missing = mock_server.register_as_scoped(m_missing).await;
+    let fresh = Url::parse(&format!("{}/fresh", &mock_server.uri()))?;
+    let cold = Url::parse(&format!("{}/cold", &mock_server.uri()))?;
+    let missing = Url::parse(&format!("{}/missing", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    };
+
+    // Construct reqwest client with cache defaults
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // Load one of the urls ahead of time
+    client.get(fresh.clone()).send().await?;
+
+    let report = http_cache_reqwest::warm_cache(
+        &client,
+        &cache,
+        vec![fresh.clone(), cold.clone(), missing.clone()],
+        2,
+    )
+    .await;
+    assert_eq!(report.entries.len(), 3);
+    assert_eq!(report.entries[0].0, fresh);
+    assert!(matches!(report.entries[0].1, WarmOutcome::AlreadyFresh));
+    assert!(matches!(report.entries[1].1, WarmOutcome::Fetched));
+    assert!(matches!(report.entries[2].1, WarmOutcome::Failed(_)));
+    assert_eq!(report.already_fresh(), 1);
+    assert_eq!(report.fetched(), 1);
+    assert_eq!(report.failed(), 1);
+
+    // The cold url should now be cached
+    let data = manager.get(&CacheKey::new(GET, &cold)).await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn corrupt_entry_is_refetched() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Write garbage directly into the store under the request's key
+    manager
+        .cache
+        .insert(format!("{}:{}", GET, &url), Bytes::from_static(b"garbage"))
+        .await;
+
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    // The corrupt record is treated as a miss and overwritten
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert_eq!(data.unwrap().0.body, TEST_BODY);
+
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    Ok(())
+}
+
+// Without its `gzip` feature reqwest hands over the encoded body, which is
+// stored as is along with its headers
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_encoded_body() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guard = mock_server.register_as_scoped(build_gzip_mock(1)).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .build();
+    let encoded = gzip(TEST_BODY);
+    let len = encoded.len().to_string();
+
+    for cache in [MISS, HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), cache);
+        assert_eq!(res.headers().get("content-encoding").unwrap(), "gzip");
+        assert_eq!(res.headers().get("content-length").unwrap(), len.as_str());
+        assert_eq!(res.bytes().await?, encoded);
+    }
+    Ok(())
+}
+
+// Mounts `/old` redirecting to `/new` with `status` and a cacheable `/new`,
+// along with a client following redirects unless `follow` is false
+async fn redirect(
+    mock_server: &MockServer,
+    status: u16,
+    follow: bool,
+    cache_redirect_targets: bool,
+) -> anyhow::Result<(reqwest_middleware::ClientWithMiddleware, MokaManager)> {
+    Mock::given(method(GET))
+        .and(path("/old"))
+        .respond_with(
+            ResponseTemplate::new(status).insert_header("location", "/new"),
+        )
+        .mount(mock_server)
+        .await;
+    Mock::given(method(GET))
+        .and(path("/new"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY),
+        )
+        .mount(mock_server)
+        .await;
+    let policy = if follow {
+        reqwest::redirect::Policy::default()
+    } else {
+        reqwest::redirect::Policy::none()
+    };
+    let manager = MokaManager::default();
+    let client =
+        ClientBuilder::new(Client::builder().redirect(policy).build()?)
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    cache_redirect_targets,
+                    ..Default::default()
+                },
+            }))
+            .build();
+    Ok((client, manager))
+}
+
+// The number of requests the mock server received for `path`
+async fn requests_to(mock_server: &MockServer, path: &str) -> usize {
+    let requests = mock_server.received_requests().await.unwrap_or_default();
+    requests.iter().filter(|req| req.url.path() == path).count()
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn followed_redirects_are_keyed_by_request_url() -> anyhow::Result<()> {
+    for status in [301, 302] {
+        let mock_server = MockServer::start().await;
+        let (client, manager) =
+            redirect(&mock_server, status, true, false).await?;
+        let old = Url::parse(&format!("{}/old", &mock_server.uri()))?;
+        let new = Url::parse(&format!("{}/new", &mock_server.uri()))?;
+
+        let res = client.get(old.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+        assert_eq!(res.url(), &new);
+        let (cached, _) =
+            manager.get(&CacheKey::new(GET, &old)).await?.unwrap();
+        assert_eq!(cached.url, new);
+        assert!(manager.get(&CacheKey::new(GET, &new)).await?.is_none());
+
+        // The requested url is served from the cache, the final one isn't
+        let res = client.get(old.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT, "{}", status);
+        assert_eq!(res.url(), &old);
+        assert_eq!(res.bytes().await?, TEST_BODY);
+        let res = client.get(new.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+        assert_eq!(requests_to(&mock_server, "/old").await, 1);
+        assert_eq!(requests_to(&mock_server, "/new").await, 2);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn followed_redirects_cache_their_target() -> anyhow::Result<()> {
+    for status in [301, 302] {
+        let mock_server = MockServer::start().await;
+        let (client, manager) =
+            redirect(&mock_server, status, true, true).await?;
+        let old = Url::parse(&format!("{}/old", &mock_server.uri()))?;
+        let new = Url::parse(&format!("{}/new", &mock_server.uri()))?;
+
+        client.get(old.clone()).send().await?;
+        assert!(manager.get(&CacheKey::new(GET, &new)).await?.is_some());
+        for url in [old, new] {
+            let res = client.get(url.clone()).send().await?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), HIT, "{}", url);
+            assert_eq!(res.bytes().await?, TEST_BODY);
+        }
+        assert_eq!(requests_to(&mock_server, "/old").await, 1);
+        assert_eq!(requests_to(&mock_server, "/new").await, 1);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn unfollowed_redirects_are_returned() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let (client, manager) = redirect(&mock_server, 302, false, true).await?;
+    let old = Url::parse(&format!("{}/old", &mock_server.uri()))?;
+    let new = Url::parse(&format!("{}/new", &mock_server.uri()))?;
+
+    for _ in 0..2 {
+        let res = client.get(old.clone()).send().await?;
+        assert_eq!(res.status(), 302);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    }
+    assert!(manager.get(&CacheKey::new(GET, &old)).await?.is_none());
+    assert!(manager.get(&CacheKey::new(GET, &new)).await?.is_none());
+    assert_eq!(requests_to(&mock_server, "/old").await, 2);
+    assert_eq!(requests_to(&mock_server, "/new").await, 0);
+    Ok(())
+}
+
+// The status, headers and body of a response, leaving out the headers telling
+// a hit from a miss. Headers are sorted by name, as the mock server sends
+// them in any order.
+type Shape = (StatusCode, Vec<(String, Vec<u8>)>, Bytes);
+
+async fn shape(res: reqwest::Response) -> anyhow::Result<Shape> {
+    let status = res.status();
+    let mut headers: Vec<_> = res
+        .headers()
+        .iter()
+        .filter(|(name, _)| {
+            ![XCACHE, XCACHELOOKUP, "age", "date"].contains(&name.as_str())
+        })
+        .map(|(name, value)| (name.to_string(), value.as_bytes().to_vec()))
+        .collect();
+    headers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((status, headers, res.bytes().await?))
+}
+
+// Sends `status` responses without a body through every adapter and
+// bundled manager, returning how the cold and hot passes looked
+async fn empty_responses(
+    status: u16,
+    expect: u64,
+) -> anyhow::Result<Vec<[(HitOrMiss, Shape); 2]>> {
+    let cacache =
+        |path: &str| CACacheManager { path: path.into(), ..Default::default() };
+    let clients = [
+        ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache::new(
+                CacheMode::Default,
+                MokaManager::default(),
+            )))
+            .build(),
+        ClientBuilder::new(Client::new())
+            .with(StreamingCache(HttpCache::new(
+                CacheMode::Default,
+                MokaManager::default(),
+            )))
+            .build(),
+        ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache::new(
+                CacheMode::Default,
+                cacache("./http-cacache-empty"),
+            )))
+            .build(),
+        ClientBuilder::new(Client::new())
+            .with(StreamingCache(HttpCache::new(
+                CacheMode::Default,
+                cacache("./http-cacache-empty-streaming"),
+            )))
+            .build(),
+    ];
+    let mut passes = Vec::new();
+    for client in clients {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("etag", "\"empty\""),
+            )
+            .expect(expect);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let mut pass = Vec::new();
+        for _ in 0..2 {
+            let res = client.get(&url).send().await?;
+            let cache = res.extensions().get::<CacheStatus>().unwrap().cache;
+            pass.push((cache, shape(res).await?));
+        }
+        passes.push(pass.try_into().unwrap());
+    }
+    for path in ["./http-cacache-empty", "./http-cacache-empty-streaming"] {
+        cacache(path).clear(true).await?;
+    }
+    Ok(passes)
+}
+
+#[cfg(all(feature = "manager-cacache", feature = "manager-moka"))]
+#[tokio::test]
+async fn no_content_round_trips() -> anyhow::Result<()> {
+    for [(cold_cache, cold), (hot_cache, hot)] in
+        empty_responses(204, 1).await?
+    {
+        assert_eq!(cold_cache, HitOrMiss::MISS);
+        assert_eq!(hot_cache, HitOrMiss::HIT);
+        assert_eq!(cold.0, StatusCode::NO_CONTENT);
+        assert!(cold.2.is_empty());
+        assert_eq!(hot, cold);
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "manager-cacache", feature = "manager-moka"))]
+#[tokio::test]
+async fn reset_content_is_not_stored() -> anyhow::Result<()> {
+    for [(cold_cache, cold), (hot_cache, hot)] in
+        empty_responses(205, 2).await?
+    {
+        assert_eq!(cold_cache, HitOrMiss::MISS);
+        assert_eq!(hot_cache, HitOrMiss::MISS);
+        assert_eq!(cold.0, StatusCode::RESET_CONTENT);
+        assert!(cold.2.is_empty());
+        assert_eq!(hot, cold);
+    }
+    Ok(())
+}
+
+// Sends a request storing a response, one revalidating it answered with a
+// 500 and one once the origin recovered, returning the status, cache status
+// and body of each response along with whether a response was stored
+// after it
+#[cfg(feature = "manager-moka")]
+async fn through_server_error(
+    options: HttpCacheOptions,
+    streaming: bool,
+) -> anyhow::Result<Vec<(u16, String, Bytes, bool)>> {
+    const STALE: &str = "public, max-age=0";
+    let mock_server = MockServer::start().await;
+    let m_good = build_mock(STALE, TEST_BODY, 200, 1).up_to_n_times(1);
+    let m_500 = build_mock(STALE, b"oops", 500, 1).up_to_n_times(1);
+    let m_recovered = build_mock(STALE, TEST_BODY, 200, 1);
+    let _mock_guard_good = mock_server.register_as_scoped(m_good).await;
+    let _mock_guard_500 = mock_server.register_as_scoped(m_500).await;
+    let _mock_guard_recovered =
+        mock_server.register_as_scoped(m_recovered).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options,
+    };
+    let client = if streaming {
+        ClientBuilder::new(Client::new()).with(StreamingCache(cache)).build()
+    } else {
+        ClientBuilder::new(Client::new()).with(Cache(cache)).build()
+    };
+
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        let res = client.get(url.clone()).send().await?;
+        let status = res.status().as_u16();
+        let x_cache = res.headers().get(XCACHE).unwrap().to_str()?.to_string();
+        let body = res.bytes().await?;
+        let stored = manager.get(&CacheKey::new(GET, &url)).await?.is_some();
+        responses.push((status, x_cache, body, stored));
+    }
+    Ok(responses)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn server_errors_keep_stored_response() -> anyhow::Result<()> {
+    for streaming in [false, true] {
+        let responses =
+            through_server_error(HttpCacheOptions::default(), streaming)
+                .await?;
+        // The good response is served in place of the 500
+        let body = Bytes::from_static(TEST_BODY);
+        assert_eq!(
+            responses,
+            [
+                (200, MISS.into(), body.clone(), true),
+                (200, HIT.into(), body.clone(), true),
+                (200, MISS.into(), body, true),
+            ]
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn server_errors_replace_stored_response() -> anyhow::Result<()> {
+    let options = HttpCacheOptions {
+        server_errors_replace_stored: true,
+        ..Default::default()
+    };
+    for streaming in [false, true] {
+        let responses =
+            through_server_error(options.clone(), streaming).await?;
+        let body = Bytes::from_static(TEST_BODY);
+        assert_eq!(
+            responses,
+            [
+                (200, MISS.into(), body.clone(), true),
+                (500, MISS.into(), Bytes::from_static(b"oops"), false),
+                (200, MISS.into(), body, true),
+            ]
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn server_errors_are_not_stored() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, b"oops", 500, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.status(), 500);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    }
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn should_store_predicate() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_error = build_mock(CACHEABLE_PUBLIC, br#"{"ok":false}"#, 200, 1)
+        .up_to_n_times(1);
+    let m_success = build_mock(CACHEABLE_PUBLIC, br#"{"ok":true}"#, 200, 1);
+    let _mock_guard_error = mock_server.register_as_scoped(m_error).await;
+    let _mock_guard_success = mock_server.register_as_scoped(m_success).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct reqwest client that refuses to store error envelopes
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                should_store: Some(Arc::new(|res: &HttpResponse| {
+                    !res.body.starts_with(br#"{"ok":false"#)
+                })),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // The error envelope is returned but not stored
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.bytes().await?, r#"{"ok":false}"#);
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // The success envelope is stored
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.bytes().await?, r#"{"ok":true}"#);
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // And served from cache afterwards
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.bytes().await?, r#"{"ok":true}"#);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn runtime_toggle() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = CountingManager::default();
+    let cache = HttpCache::new(CacheMode::Default, manager.clone());
+    // Kept by the admin endpoint while the client holds the cache
+    let admin = cache.clone();
+    let client = ClientBuilder::new(Client::new()).with(Cache(cache)).build();
+
+    for expected in [MISS, HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+    }
+
+    // Requests go to the origin without touching the manager
+    admin.set_enabled(false);
+    assert!(!admin.is_enabled());
+    let calls = manager.calls();
+    for _ in 0..2 {
+        let res = client.get(url.clone()).send().await?;
+        assert!(res.headers().get(XCACHE).is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), calls);
+
+    // The stored response is still there once enabled again
+    admin.set_enabled(true);
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn url_filter() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 4);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let host = Url::parse(&mock_server.uri())?;
+    let host = host.host_str().unwrap();
+    let manager = CountingManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                url_filter: Some(
+                    UrlFilter::new()
+                        .allow(&format!("http://{}/catalog/**", host))
+                        .deny(&format!("{}/catalog/drafts/*", host)),
+                ),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Excluded urls go to the origin without touching the manager
+    for route in ["/account", "/catalog/drafts/1", "/catalog/drafts/1"] {
+        let url = format!("{}{}", &mock_server.uri(), route);
+        let res = client.get(url).send().await?;
+        assert!(res.headers().get(XCACHELOOKUP).is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), 0);
+
+    // Included ones use the cache as usual
+    let url = format!("{}/catalog/items/1", &mock_server.uri());
+    for expected in [MISS, HIT] {
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+    }
+    assert!(manager.calls() > 0);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn event_subscription() -> anyhow::Result<()> {
+    use futures::StreamExt;
+
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions {
+            event_broadcast: Some(EventBroadcast::new(16)),
+            ..Default::default()
+        },
+    };
+    let mut events = cache.subscribe();
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    client.get(url.clone()).send().await?;
+    client.get(url.clone()).send().await?;
+    client.post(url.clone()).send().await?;
+    cache.purge_tag("catalog").await?;
+
+    assert!(matches!(
+        events.next().await,
+        Some(CacheEvent::Stored { method, url: stored, status: 200 })
+            if method == GET && stored == url
+    ));
+    assert!(matches!(
+        events.next().await,
+        Some(CacheEvent::Miss { status: 200, .. })
+    ));
+    assert!(matches!(
+        events.next().await,
+        Some(CacheEvent::Hit { status: 200, .. })
+    ));
+    assert!(matches!(
+        events.next().await,
+        Some(CacheEvent::Evicted { url: evicted, .. }) if evicted == url
+    ));
+    assert!(matches!(
+        events.next().await,
+        Some(CacheEvent::PurgedTag { tag, removed: 0 }) if tag == "catalog"
+    ));
+
+    // The stream ends along with the cache
+    drop((cache, client));
+    assert!(events.next().await.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn ignores_fragments() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+
+    let res = client.get(format!("{}#intro", url)).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    let res = client.get(format!("{}#usage", url)).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(manager.keys().await?, [CacheKey::new(GET, &Url::parse(&url)?)]);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn key_headers() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 5);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                key_headers: vec![
+                    http::header::ACCEPT,
+                    http::HeaderName::from_static("x-tenant-id"),
+                ],
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Requests differing only in a keyed header are stored apart, a missing
+    // header being different from an empty one
+    for (accept, tenant, expected) in [
+        ("application/json", Some("a"), MISS),
+        ("application/json", Some(" a "), HIT),
+        ("application/json", Some("b"), MISS),
+        ("application/json", Some(""), MISS),
+        ("application/json", None, MISS),
+        ("application/json", None, HIT),
+        ("text/plain", Some("a"), MISS),
+        ("text/plain", Some("a"), HIT),
+    ] {
+        let mut req = client.get(url.clone()).header("accept", accept);
+        if let Some(tenant) = tenant {
+            req = req.header("x-tenant-id", tenant);
+        }
+        let res = req.send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), expected);
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+
+    // Other request headers are left out of the key
+    let res = client
+        .get(url.clone())
+        .header("accept", "text/plain")
+        .header("x-tenant-id", "a")
+        .header("user-agent", "other")
+        .send()
+        .await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+
+    // The values are kept in the fragment of the url responses are stored
+    // under
+    let keyed = Url::parse(&format!(
+        "{}#accept=application%2Fjson&x-tenant-id=a",
+        url
+    ))?;
+    assert!(manager.get(&CacheKey::new(GET, &keyed)).await?.is_some());
+    let missing =
+        Url::parse(&format!("{}#accept=application%2Fjson&x-tenant-id", url))?;
+    assert!(manager.get(&CacheKey::new(GET, &missing)).await?.is_some());
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn record_and_replay() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    Mock::given(method(GET))
+        .and(path("/missing"))
+        .respond_with(
+            ResponseTemplate::new(404)
+                .insert_header("cache-control", "no-store")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path("/items"))
+        .respond_with(ResponseTemplate::new(201).set_body_bytes(TEST_BODY))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    let manager = MokaManager::default();
+    let client = |record_mode| {
+        ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    record_mode: Some(record_mode),
+                    ..Default::default()
+                },
+            }))
+            .build()
+    };
+    let missing = format!("{}/missing", &mock_server.uri());
+    let items = format!("{}/items", &mock_server.uri());
+
+    // Responses that wouldn't be cached are recorded, and never expire
+    let recorder = client(RecordMode::Record);
+    let res = recorder.get(missing.clone()).send().await?;
+    assert_eq!(res.status(), 404);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    let res = recorder.post(items.clone()).send().await?;
+    assert_eq!(res.status(), 201);
+    let cache = HttpCache::new(CacheMode::Default, manager.clone());
+    let freshness = cache
+        .freshness(GET, &Url::parse(&missing)?)
+        .await?
+        .expect("the response was recorded");
+    assert!(freshness.time_to_live > Duration::from_secs(50 * 365 * 86400));
+
+    // Recorded responses are replayed without going to the network
+    let replayer = client(RecordMode::Replay);
+    let res = replayer.get(missing.clone()).send().await?;
+    assert_eq!(res.status(), 404);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert!(res.headers().get("warning").is_none());
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    let res = replayer.post(items.clone()).send().await?;
+    assert_eq!(res.status(), 201);
+
+    // Anything else fails, listing the key that wasn't recorded
+    let unknown = format!("{}/unknown", &mock_server.uri());
+    let err = replayer.get(unknown.clone()).send().await.unwrap_err();
+    assert!(format!("{:?}", err)
+        .contains(&format!("No recorded response for GET:{}", unknown)));
+    let err = replayer.head(missing.clone()).send().await.unwrap_err();
+    assert!(format!("{:?}", err).contains(&format!("HEAD:{}", missing)));
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn received_age() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "public, max-age=300")
+                .insert_header("age", "290")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let clock = MockClock::default();
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions {
+            clock: Some(clock.clock()),
+            ..Default::default()
+        },
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    // The response used up most of its lifetime before it arrived
+    client.get(url.clone()).send().await?;
+    let freshness = cache.freshness(GET, &Url::parse(&url)?).await?.unwrap();
+    assert_eq!(freshness.time_to_live, Duration::from_secs(10));
+
+    clock.advance(Duration::from_secs(5));
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.headers().get("age").unwrap(), "295");
+
+    // Stale ten seconds after it was stored, not five minutes
+    clock.advance(Duration::from_secs(6));
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn qualified_private_and_no_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    for (route, cache_control, field, expect) in [
+        ("/private", "private=\"set-cookie\", max-age=600", "set-cookie", 1),
+        (
+            "/no-cache",
+            "no-cache=\"authorization-info, x-nonce\", max-age=600",
+            "authorization-info",
+            1,
+        ),
+        // Taken as plain `private` and `no-cache`
+        ("/unterminated", "private=\"set-cookie, max-age=600", "set-cookie", 2),
+        ("/bad-name", "no-cache=\"x nonce\", max-age=600", "x-nonce", 2),
+    ] {
+        Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", cache_control)
+                    .insert_header(field, "secret")
+                    .insert_header("x-kept", "1")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect)
+            .mount(&mock_server)
+            .await;
+    }
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+
+    for route in ["/private", "/no-cache"] {
+        let url = format!("{}{}", &mock_server.uri(), route);
+        // The caller gets the whole response, the listed field isn't stored
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+        let field = if route == "/private" {
+            "set-cookie"
+        } else {
+            "authorization-info"
+        };
+        assert_eq!(res.headers().get(field).unwrap(), "secret");
+        assert!(res.headers()["cache-control"]
+            .to_str()?
+            .contains(&format!("{}=", &route[1..])));
+        let (stored, _) = manager
+            .get(&CacheKey::new(GET, &Url::parse(&url)?))
+            .await?
+            .unwrap();
+        assert!(stored.headers.get(field).is_none());
+        assert_eq!(stored.headers.get("cache-control").unwrap(), "max-age=600");
+
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+        assert!(res.headers().get(field).is_none());
+        assert_eq!(res.headers().get("x-kept").unwrap(), "1");
+    }
+
+    for route in ["/unterminated", "/bad-name"] {
+        let url = format!("{}{}", &mock_server.uri(), route);
+        for _ in 0..2 {
+            let res = client.get(url.clone()).send().await?;
+            assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn must_understand() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    // Each route is requested twice, the ones stored are fetched once
+    let routes = [
+        ("/understood", 200, "must-understand, max-age=600", 1),
+        (
+            "/understood-no-store",
+            200,
+            "no-store, must-understand, max-age=600",
+            1,
+        ),
+        ("/no-store", 200, "no-store, max-age=600", 2),
+        ("/not-understood", 404, "must-understand, max-age=600", 2),
+        ("/not-understood-no-store", 404, "must-understand, no-store", 2),
+    ];
+    for (route, status, cache_control, expect) in routes {
+        Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(status)
+                    .insert_header("cache-control", cache_control)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect)
+            .mount(&mock_server)
+            .await;
+    }
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+
+    for (route, _, cache_control, expect) in routes {
+        let url = format!("{}{}", &mock_server.uri(), route);
+        let res = client.get(url.clone()).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+        // The caller gets the directives as they were sent
+        assert_eq!(res.headers()["cache-control"], cache_control);
+        let res = client.get(url.clone()).send().await?;
+        let status = if expect == 1 { HIT } else { MISS };
+        assert_eq!(res.headers().get(XCACHE).unwrap(), status, "{}", route);
+    }
+    let url =
+        Url::parse(&format!("{}/understood-no-store", mock_server.uri()))?;
+    let (stored, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(
+        stored.headers.get("cache-control").unwrap(),
+        "must-understand, max-age=600"
+    );
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_honors_no_store() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    // The directives the stored response is revalidated with, and whether
+    // the new response replaces it
+    let routes = [
+        ("/no-store", "no-store, max-age=600", false),
+        ("/understood", "must-understand, max-age=600", true),
+        (
+            "/understood-no-store",
+            "no-store, must-understand, max-age=600",
+            true,
+        ),
+    ];
+    for (route, cache_control, _) in routes {
+        Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", "public, max-age=0")
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_bytes(TEST_BODY),
+            )
+            .mount(&mock_server)
+            .await;
+        Mock::given(method(GET))
+            .and(path(route))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", cache_control)
+                    .set_body_bytes(&b"changed"[..]),
+            )
+            .with_priority(1)
+            .mount(&mock_server)
+            .await;
+    }
+
+    for streaming in [false, true] {
+        let manager = MokaManager::default();
+        let cache = HttpCache::new(CacheMode::Default, manager.clone());
+        let client = if streaming {
+            ClientBuilder::new(Client::new()).with(StreamingCache(cache))
+        } else {
+            ClientBuilder::new(Client::new()).with(Cache(cache))
+        }
+        .build();
+        for (route, _, stored) in routes {
+            let url = Url::parse(&format!("{}{}", &mock_server.uri(), route))?;
+            client.get(url.clone()).send().await?.bytes().await?;
+            let res = client.get(url.clone()).send().await?;
+            assert_eq!(res.bytes().await?, &b"changed"[..]);
+            let record = manager.get(&CacheKey::new(GET, &url)).await?;
+            match record {
+                Some((res, _)) => {
+                    assert!(stored, "{route}");
+                    assert_eq!(res.body, &b"changed"[..]);
+                }
+                // The outdated response is gone as well
+                None => assert!(!stored, "{route}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Builds a client caching in `mode`, overriding `no-store` for `ttl`
+fn overriding_client(
+    mode: CacheMode,
+    manager: &MokaManager,
+    ttl: Option<Duration>,
+) -> reqwest_middleware::ClientWithMiddleware {
+    ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                force_cache_overrides_no_store: ttl,
+                ..Default::default()
+            },
+        }))
+        .build()
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn force_cache_overrides_no_store() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-store")
+                .set_body_bytes(TEST_BODY),
+        )
+        .mount(&mock_server)
+        .await;
+    let url = format!("{}/", &mock_server.uri());
+    let ttl = Some(Duration::from_secs(600));
+    let status = |res: &reqwest::Response| {
+        *res.extensions().get::<CacheStatus>().unwrap()
+    };
+
+    // Only the `ForceCache` mode with the override set stores the response
+    let modes = [
+        CacheMode::Default,
+        CacheMode::NoStore,
+        CacheMode::Reload,
+        CacheMode::NoCache,
+        CacheMode::ForceCache,
+    ];
+    for mode in modes {
+        for ttl in [None, ttl] {
+            let client = overriding_client(mode, &MokaManager::default(), ttl);
+            let stored = mode == CacheMode::ForceCache && ttl.is_some();
+            let res = client.get(&url).send().await?;
+            assert_eq!(status(&res).overridden, stored, "{:?} {:?}", mode, ttl);
+            assert_eq!(res.headers()["cache-control"], "no-store");
+            let res = client.get(&url).send().await?;
+            let cache = if stored { HitOrMiss::HIT } else { HitOrMiss::MISS };
+            assert_eq!(status(&res).cache, cache, "{:?} {:?}", mode, ttl);
+            assert_eq!(status(&res).overridden, stored);
+        }
+    }
+
+    // The stored response is only served in the `ForceCache` and
+    // `OnlyIfCached` modes with the override set
+    let manager = MokaManager::default();
+    overriding_client(CacheMode::ForceCache, &manager, ttl)
+        .get(&url)
+        .send()
+        .await?;
+    for (mode, ttl, cache) in [
+        (CacheMode::Default, ttl, HitOrMiss::MISS),
+        (CacheMode::NoCache, ttl, HitOrMiss::MISS),
+        (CacheMode::ForceCache, None, HitOrMiss::MISS),
+        (CacheMode::OnlyIfCached, None, HitOrMiss::MISS),
+        (CacheMode::OnlyIfCached, ttl, HitOrMiss::HIT),
+    ] {
+        let res =
+            overriding_client(mode, &manager, ttl).get(&url).send().await?;
+        assert_eq!(status(&res).cache, cache, "{:?} {:?}", mode, ttl);
+    }
+
+    // Requests with `no-store` of their own are never stored
+    let manager = MokaManager::default();
+    let client = overriding_client(CacheMode::ForceCache, &manager, ttl);
+    let res =
+        client.get(&url).header("cache-control", "no-store").send().await?;
+    assert!(!status(&res).overridden);
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_none());
+
+    // Stored responses are fetched again once they expire
+    let client = overriding_client(
+        CacheMode::ForceCache,
+        &manager,
+        Some(Duration::ZERO),
+    );
+    for _ in 0..2 {
+        let res = client.get(&url).send().await?;
+        assert_eq!(status(&res).cache, HitOrMiss::MISS);
+        assert!(status(&res).overridden);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_keeps_the_no_store_override_to_force_cache(
+) -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "public, max-age=0")
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-store")
+                .set_body_bytes(TEST_BODY),
+        )
+        .with_priority(1)
+        .mount(&mock_server)
+        .await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let ttl = Some(Duration::from_secs(600));
+
+    // Only the `Default` mode revalidates, where the override never applies
+    for streaming in [false, true] {
+        let manager = MokaManager::default();
+        let cache = HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                force_cache_overrides_no_store: ttl,
+                ..Default::default()
+            },
+        };
+        let client = if streaming {
+            ClientBuilder::new(Client::new()).with(StreamingCache(cache))
+        } else {
+            ClientBuilder::new(Client::new()).with(Cache(cache))
+        }
+        .build();
+        client.get(url.clone()).send().await?.bytes().await?;
+        let res = client.get(url.clone()).send().await?;
+        let status = *res.extensions().get::<CacheStatus>().unwrap();
+        assert_eq!(status.lookup, HitOrMiss::HIT);
+        assert!(!status.overridden);
+        res.bytes().await?;
+        assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn cacheable_content_types() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    for (route, content_type) in [
+        ("/data", "application/json; charset=utf-8"),
+        ("/logo", "image/png"),
+        ("/banner", "IMAGE/webp"),
+        ("/page", "text/html"),
+        ("/private", "application/json"),
+    ] {
+        Mock::given(method(GET))
+            .and(path(route))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .set_body_raw(TEST_BODY, content_type),
+            )
+            .mount(&mock_server)
+            .await;
+    }
+    // The mock server always sends a `Content-Type`
+    let (raw_url, _) = raw_server(
+        format!(
+            "HTTP/1.1 200 OK\r\ncache-control: {}\r\n\
+             content-length: {}\r\n\r\n{}",
+            CACHEABLE_PUBLIC,
+            TEST_BODY.len(),
+            std::str::from_utf8(TEST_BODY)?,
+        )
+        .into_bytes(),
+    )?;
+    let raw_url = Url::parse(&raw_url)?;
+    let manager = MokaManager::default();
+    let cache = |allow_missing| HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            cacheable_content_types: Some(
+                ContentTypes::new(["application/json", "image/*"])
+                    .allow_missing(allow_missing),
+            ),
+            // Both have to allow a response for it to be stored
+            should_store: Some(Arc::new(|res: &HttpResponse| {
+                res.url.path() != "/private"
+            })),
+            ..Default::default()
+        },
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache(false))).build();
+
+    for (route, stored) in [
+        ("/data", true),
+        ("/logo", true),
+        ("/banner", true),
+        ("/page", false),
+        ("/private", false),
+    ] {
+        let url = Url::parse(&format!("{}{}", &mock_server.uri(), route))?;
+        client.get(url.clone()).send().await?;
+        assert_eq!(
+            manager.get(&CacheKey::new(GET, &url)).await?.is_some(),
+            stored,
+            "{}",
+            route
+        );
+    }
+    client.get(raw_url.clone()).send().await?;
+    assert!(manager.get(&CacheKey::new(GET, &raw_url)).await?.is_none());
+
+    // Responses without a `Content-Type` header can be allowed
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache(true))).build();
+    client.get(raw_url.clone()).send().await?;
+    assert!(manager.get(&CacheKey::new(GET, &raw_url)).await?.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn should_cache_request_bypass() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = CountingManager::default();
+
+    // Construct reqwest client that bypasses the cache for debug requests
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                should_cache_request: Some(Arc::new(
+                    |parts: &http::request::Parts| {
+                        !parts.headers.contains_key("x-debug-live")
+                    },
+                )),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Bypassed requests go to the origin without touching the manager
+    for _ in 0..2 {
+        let res =
+            client.get(url.clone()).header("x-debug-live", "1").send().await?;
+        assert!(res.headers().get(XCACHE).is_none());
+        assert!(res.headers().get(XCACHELOOKUP).is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), 0);
+
+    // Other requests still use the cache
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert!(manager.calls() > 0);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn purge_tag() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let tagged = |p: &str, tags: &str, expect: u64| {
+        Mock::given(method(GET))
+            .and(path(p))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC)
+                    .insert_header("surrogate-key", tags)
+                    .set_body_bytes(TEST_BODY),
+            )
+            .expect(expect)
+    };
+    let _a =
+        mock_server.register_as_scoped(tagged("/a", "product-42", 2)).await;
+    let _b = mock_server.register_as_scoped(tagged("/b", "listing", 1)).await;
+    let a = format!("{}/a", &mock_server.uri());
+    let b = format!("{}/b", &mock_server.uri());
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions {
+            tag_header: Some("Surrogate-Key".into()),
+            ..Default::default()
+        },
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    client.get(a.clone()).send().await?;
+    client.get(b.clone()).send().await?;
+    assert_eq!(cache.purge_tag("product-42").await?, 1);
+
+    // The purged entry is fetched again, the other one is still cached
+    let res = client.get(a).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    let res = client.get(b).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn soft_purge() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_full = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let m_revalidate = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304).insert_header("etag", "\"v1\""),
+        )
+        .with_priority(1)
+        .expect(1);
+    let _full_guard = mock_server.register_as_scoped(m_full).await;
+    let _revalidate_guard = mock_server.register_as_scoped(m_revalidate).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    client.get(url.clone()).send().await?;
+    assert!(cache.soft_purge(GET, &url).await?);
+
+    // The body is kept and the next request revalidates it
+    assert!(cache.manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+
+    // Revalidating takes back the purge, the one after is served as is
+    assert!(cache.inspect(GET, &url).await?.unwrap().metadata().is_empty());
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+
+    // Nothing to purge for uncached urls
+    assert!(!cache.soft_purge(GET, &url.join("missing")?).await?);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn soft_purge_keeps_varied_requests() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_full = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("vary", "accept-language")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let m_revalidate = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .and(header("accept-language", "fr"))
+        .respond_with(ResponseTemplate::new(304))
+        .with_priority(1)
+        .expect(1);
+    let _full_guard = mock_server.register_as_scoped(m_full).await;
+    let _revalidate_guard = mock_server.register_as_scoped(m_revalidate).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    client.get(url.clone()).header("accept-language", "fr").send().await?;
+    assert!(cache.soft_purge(GET, &url).await?);
+
+    // The entry still matches the request it was stored for
+    let res =
+        client.get(url.clone()).header("accept-language", "fr").send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn inspect() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("vary", "Accept-Encoding, Accept-Language")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    assert!(cache.inspect(GET, &url).await?.is_none());
+    client.get(url.clone()).send().await?;
+
+    let inspection = cache.inspect("get", &url).await?.unwrap();
+    assert!(inspection.is_fresh_now());
+    assert!(inspection.is_storable());
+    assert!(inspection.time_to_live() > Duration::from_secs(0));
+    assert!(inspection.has_etag());
+    assert!(!inspection.has_last_modified());
+    assert_eq!(
+        inspection.vary_headers(),
+        vec!["accept-encoding", "accept-language"]
+    );
+    assert_eq!(inspection.headers().get("etag").unwrap(), "\"v1\"");
+
+    // Soft purged responses are reported as stale
+    cache.soft_purge(GET, &url).await?;
+    let inspection = cache.inspect(GET, &url).await?.unwrap();
+    assert!(!inspection.is_fresh_now());
+    assert_eq!(inspection.time_to_live(), Duration::from_secs(0));
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn freshness() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header(
+                    "cache-control",
+                    "max-age=60, stale-while-revalidate=30, stale-if-error=300",
+                )
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+
+    assert!(cache.freshness(GET, &url).await?.is_none());
+    client.get(url.clone()).send().await?;
+
+    let freshness = cache.freshness("get", &url).await?.unwrap();
+    assert!(!freshness.is_stale);
+    assert!(freshness.time_to_live <= Duration::from_secs(60));
+    assert!(freshness.time_to_live > Duration::from_secs(0));
+    // Neither an `ETag` nor a `Last-Modified` date to revalidate with
+    assert!(!freshness.can_revalidate);
+    assert_eq!(freshness.stale_while_revalidate, Some(Duration::from_secs(30)));
+    assert_eq!(freshness.stale_if_error, Some(Duration::from_secs(300)));
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn clear_site_data_purges_origin() -> anyhow::Result<()> {
+    let api = MockServer::start().await;
+    let other = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .and(path("/logout"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-store")
+                .insert_header("clear-site-data", "\"cache\", \"cookies\""),
+        )
+        .expect(1);
+    let _logout_guard = api.register_as_scoped(m).await;
+    let _api_guard = api
+        .register_as_scoped(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2))
+        .await;
+    let _other_guard = other
+        .register_as_scoped(build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1))
+        .await;
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                clear_site_data: true,
+                ..Default::default()
+            },
+        }))
+        .build();
+    let api_urls = [
+        Url::parse(&format!("{}/a", &api.uri()))?,
+        Url::parse(&format!("{}/b", &api.uri()))?,
+    ];
+    let other_url = Url::parse(&format!("{}/a", &other.uri()))?;
+    for url in api_urls.iter().chain([&other_url]) {
+        client.get(url.clone()).send().await?;
+    }
+    assert_eq!(manager.keys().await?.len(), 3);
+
+    client.get(format!("{}/logout", &api.uri())).send().await?;
+    for url in &api_urls {
+        assert!(manager.get(&CacheKey::new(GET, url)).await?.is_none());
+    }
+    assert!(manager.get(&CacheKey::new(GET, &other_url)).await?.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn dry_run_reports_decisions() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .and(path("/session"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-store")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let _session_guard = mock_server.register_as_scoped(m).await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("set-cookie", "id=1")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let manager = MokaManager::default();
+    let decisions = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&decisions);
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions {
+                on_event: Some(Arc::new(move |event: &CacheEvent| {
+                    if let CacheEvent::DryRun(decision) = event {
+                        recorded.lock().unwrap().push(decision.clone());
+                    }
+                })),
+                dry_run: Some(DryRun::new()),
+                ..Default::default()
+            },
+        }))
+        .build();
+    let url = format!("{}/", &mock_server.uri());
+
+    // Both requests go to the network and nothing is stored
+    client.get(url.clone()).send().await?;
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    client.get(format!("{}/session", &mock_server.uri())).send().await?;
+    assert!(manager.keys().await?.is_empty());
+
+    let decisions = decisions.lock().unwrap();
+    assert_eq!(decisions.len(), 3);
+    assert_eq!(decisions[0].lookup, DryRunLookup::Miss);
+    assert_eq!(decisions[0].not_stored, None);
+    assert!(decisions[0].time_to_live > Duration::from_secs(0));
+    assert!(decisions[0].sets_cookie);
+    // The second request would have been served from cache
+    assert_eq!(decisions[1].lookup, DryRunLookup::Fresh);
+    assert_eq!(decisions[2].lookup, DryRunLookup::Miss);
+    assert_eq!(decisions[2].not_stored, Some(NotStoredReason::Policy));
+    assert!(!decisions[2].sets_cookie);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn dyn_and_concrete_managers() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+
+    // A manager picked at runtime
+    let manager: Arc<dyn DynCacheManager> = Arc::new(MokaManager::default());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+    client.get(url.clone()).send().await?;
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_some());
+
+    // A concrete manager owned by the cache, without an Arc
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+    client.get(url.clone()).send().await?;
+    assert_eq!(cache.manager.keys().await?.len(), 1);
+    Ok(())
+}
+
+// Lists every file in the content store of a cacache directory along with
+// when it was last written
+#[cfg(feature = "manager-cacache")]
+fn content_files(
+    dir: &std::path::Path,
+) -> anyhow::Result<Vec<(std::path::PathBuf, std::time::SystemTime)>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            files.extend(content_files(&entry.path())?);
+        } else {
+            files.push((entry.path(), entry.metadata()?.modified()?));
+        }
+    }
+    Ok(files)
+}
+
+#[cfg(feature = "manager-cacache")]
+#[tokio::test]
+async fn revalidation_keeps_body_blob() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let body = vec![7; 1024 * 1024];
+    let m = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "2"),
+        )
+        .expect(1);
+    let _revalidate_guard = mock_server.register_as_scoped(m).await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "1")
+                .set_body_bytes(body.clone()),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let manager = CACacheManager {
+        path: "./http-cacache-revalidate".into(),
+        ..Default::default()
+    };
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+    let content = std::path::Path::new(&manager.path).join("content-v2");
+
+    client.get(url.clone()).send().await?;
+    let blob = content_files(&content)?
+        .into_iter()
+        .find(|(path, _)| std::fs::read(path).is_ok_and(|data| data == body))
+        .unwrap();
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.headers().get("x-revision").unwrap(), "2");
+    assert_eq!(res.bytes().await?, body);
+    // Only the record was rewritten, the body is where it was
+    assert!(content_files(&content)?.contains(&blob));
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.headers.get("x-revision").unwrap(), "2");
+    assert_eq!(cached.body, body);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+// Mounts a stored response tagged `"v1"` and the 304 answering a request
+// that validates it with exactly `if_none_match`
+async fn validated_mocks(
+    mock_server: &MockServer,
+    if_none_match: &str,
+    expect: u64,
+) -> [wiremock::MockGuard; 2] {
+    let m_304 = Mock::given(method(GET))
+        .and(header("if-none-match", if_none_match))
+        .respond_with(
+            ResponseTemplate::new(304)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "2"),
+        )
+        .expect(1);
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "1")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(expect);
+    [
+        mock_server.register_as_scoped(m_304).await,
+        mock_server.register_as_scoped(m).await,
+    ]
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_request_hook() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_304 = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .and(header("x-signature", "signed"))
+        .respond_with(
+            ResponseTemplate::new(304)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "2"),
+        )
+        .expect(1);
+    let _revalidate_guard = mock_server.register_as_scoped(m_304).await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", MUST_REVALIDATE)
+                .insert_header("etag", "\"v1\"")
+                .insert_header("x-revision", "1")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let drop_validators = Arc::new(AtomicBool::new(false));
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let hook: RevalidationHook = {
+        let drop_validators = drop_validators.clone();
+        let seen = seen.clone();
+        Arc::new(move |req: &mut RevalidationRequest<'_>| {
+            seen.lock()
+                .unwrap()
+                .push(req.headers().get("if-none-match").cloned());
+            req.headers_mut().insert(
+                "x-signature",
+                http::HeaderValue::from_static("signed"),
+            );
+            // Removing the validator by accident leaves it in place
+            req.headers_mut().remove("if-none-match");
+            if drop_validators.load(Ordering::SeqCst) {
+                req.drop_validators();
+            }
+        })
+    };
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions {
+                on_revalidation_request: Some(hook),
+                on_event: Some({
+                    let events = events.clone();
+                    Arc::new(move |event: &CacheEvent| {
+                        if let CacheEvent::ValidatorsDropped { url, .. } = event
+                        {
+                            events.lock().unwrap().push(url.clone());
+                        }
+                    })
+                }),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // The hook isn't called for the request that stores the response
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get("x-revision").unwrap(), "1");
+    assert!(seen.lock().unwrap().is_empty());
+
+    // The revalidation carries both the validator and the signature
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.headers().get("x-revision").unwrap(), "2");
+    assert_eq!(*seen.lock().unwrap(), [Some("\"v1\"".parse()?)]);
+    assert!(events.lock().unwrap().is_empty());
+
+    // Dropping the validators sends an unconditional request, reported
+    drop_validators.store(true, Ordering::SeqCst);
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.headers().get("x-revision").unwrap(), "1");
+    assert_eq!(*events.lock().unwrap(), [Url::parse(&url)?]);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn forwards_not_modified_with_entry() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guards = validated_mocks(&mock_server, "\"v1\"", 1).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+
+    client.get(url.clone()).send().await?;
+
+    // The validator of the caller is sent alone and the 304 handed back
+    let res = client
+        .get(url.clone())
+        .header("if-none-match", "\"v1\"")
+        .send()
+        .await?;
+    assert_eq!(res.status(), 304);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert!(res.bytes().await?.is_empty());
+
+    // The stored response was refreshed all the same
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.status, 200);
+    assert_eq!(cached.headers.get("x-revision").unwrap(), "2");
+    assert_eq!(cached.body, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn forwards_not_modified_without_entry() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guards = validated_mocks(&mock_server, "\"v1\"", 0).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+
+    let res = client
+        .get(url.clone())
+        .header("if-none-match", "\"v1\"")
+        .send()
+        .await?;
+    assert_eq!(res.status(), 304);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn streaming_forwards_not_modified() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guards = validated_mocks(&mock_server, "\"v1\"", 1).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            manager.clone(),
+        )))
+        .build();
+
+    client.get(url.clone()).send().await?.bytes().await?;
+
+    let res = client
+        .get(url.clone())
+        .header("if-none-match", "\"v1\"")
+        .send()
+        .await?;
+    assert_eq!(res.status(), 304);
+    assert!(res.bytes().await?.is_empty());
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.headers.get("x-revision").unwrap(), "2");
+    assert_eq!(cached.body, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-cacache")]
+#[tokio::test]
+async fn streaming_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let body = vec![7; 4 * 1024 * 1024];
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(body.clone()),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let manager = CACacheManager {
+        path: "./http-cacache-streaming".into(),
+        ..Default::default()
+    };
+    let client = ClientBuilder::new(Client::new())
+        .with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            manager.clone(),
+        )))
+        .build();
+
+    // Cold pass stores the body once it has been read to the end
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    assert_eq!(res.bytes().await?, body);
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.body, body);
+
+    // Hot pass streams the body back from the cache
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.bytes().await?, body);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+#[cfg(feature = "manager-cacache")]
+#[tokio::test]
+async fn streaming_cache_aborted_download() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(vec![7; 4 * 1024 * 1024]),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let manager = CACacheManager {
+        path: "./http-cacache-aborted".into(),
+        ..Default::default()
+    };
+    let client = ClientBuilder::new(Client::new())
+        .with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            manager.clone(),
+        )))
+        .build();
+
+    // Dropping the response partway through discards what was written
+    let mut res = client.get(url.clone()).send().await?;
+    assert!(res.chunk().await?.is_some());
+    drop(res);
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    let tmp = std::fs::read_dir("./http-cacache-aborted/tmp")?;
+    assert_eq!(tmp.count(), 0);
+
+    // So the next request goes back to the network
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+// Reads a response body chunk by chunk, returning the chunks
+async fn read_chunks(
+    mut res: reqwest::Response,
+) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut chunks = Vec::new();
+    while let Some(chunk) = res.chunk().await? {
+        chunks.push(chunk.to_vec());
+    }
+    Ok(chunks)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn streaming_cache_delivers_chunks() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let body = vec![7; 1024 * 1024];
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(body.clone()),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            manager.clone(),
+        )))
+        .build();
+
+    // The body fetched upstream is passed on as it arrives
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    let chunks = read_chunks(res).await?;
+    assert!(chunks.len() > 1);
+    assert_eq!(chunks.concat(), body);
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.body, body);
+
+    // And the cached one is served in chunks as well
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    let chunks = read_chunks(res).await?;
+    assert!(chunks.len() > 1);
+    assert_eq!(chunks.concat(), body);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn streaming_cache_upstream_failure() -> anyhow::Result<()> {
+    // Announces a body larger than what is sent before hanging up
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = Url::parse(&format!("http://{}/", listener.local_addr()?))?;
+    let server = std::thread::spawn(move || -> std::io::Result<()> {
+        use std::io::{Read, Write};
+        let (mut stream, _) = listener.accept()?;
+        let _request = stream.read(&mut [0; 4096])?;
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\ncache-control: max-age=86400, public\r\n\
+              content-length: 1048576\r\n\r\n",
+        )?;
+        stream.write_all(&[7; 64 * 1024])?;
+        Ok(())
+    });
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            manager.clone(),
+        )))
+        .build();
+
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert!(read_chunks(res).await.is_err());
+    server.join().unwrap()?;
+
+    // The partial body wasn't stored
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn coalesces_concurrent_misses() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY)
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions {
+                coalescing: Some(Coalescing::default()),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    let requests = (0..20)
+        .map(|_| {
+            let client = client.clone();
+            let url = url.clone();
+            tokio::spawn(async move {
+                let res = client.get(url).send().await?;
+                anyhow::Ok(res.bytes().await?)
+            })
+        })
+        .collect::<Vec<_>>();
+    for request in requests {
+        assert_eq!(request.await??, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn coalesced_followers_skip_overridden_responses() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", "no-store")
+                .set_body_bytes(TEST_BODY)
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let coalescing = Coalescing::default();
+    let client = |mode, ttl| {
+        ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    force_cache_overrides_no_store: ttl,
+                    coalescing: Some(coalescing.clone()),
+                    ..Default::default()
+                },
+            }))
+            .build()
+    };
+    // The leader stores the `no-store` response, which the follower in the
+    // default mode must not be served
+    let leader = client(CacheMode::ForceCache, Some(Duration::from_secs(600)));
+    let follower = client(CacheMode::Default, None);
+    let leading = tokio::spawn({
+        let url = url.clone();
+        async move { leader.get(url).send().await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let res = follower.get(&url).send().await?;
+    let status = *res.extensions().get::<CacheStatus>().unwrap();
+    assert_eq!(status.cache, HitOrMiss::MISS);
+    assert!(!status.overridden);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    let res = leading.await??;
+    assert!(res.extensions().get::<CacheStatus>().unwrap().overridden);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn coalesced_leader_failure_propagates() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_delay(Duration::from_secs(2)),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    // The leader times out, the requests waiting on it fail along with it
+    let client = ClientBuilder::new(
+        Client::builder().timeout(Duration::from_millis(200)).build()?,
+    )
+    .with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions {
+            coalescing: Some(Coalescing::new(LeaderFailure::Propagate)),
+            ..Default::default()
+        },
+    }))
+    .build();
+
+    let requests = (0..5)
+        .map(|_| {
+            let client = client.clone();
+            let url = url.clone();
+            tokio::spawn(async move { client.get(url).send().await })
+        })
+        .collect::<Vec<_>>();
+    for request in requests {
+        assert!(request.await?.is_err());
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn coalescing_within_one_task() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1..=2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: MokaManager::default(),
+            options: HttpCacheOptions {
+                coalescing: Some(Coalescing::default()),
+                ..Default::default()
+            },
+        }))
+        .build();
+
+    // Waiting on a request driven by the same task would never end
+    let (first, second) =
+        futures::join!(client.get(url.clone()).send(), client.get(url).send());
+    assert_eq!(first?.bytes().await?, TEST_BODY);
+    assert_eq!(second?.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+async fn concurrent_revalidations(
+    cache_control: &str,
+) -> anyhow::Result<Vec<reqwest::Response>> {
+    let mock_server = MockServer::start().await;
+    let m_full = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", cache_control)
+                .insert_header("etag", "\"v1\"")
+                .set_body_bytes(TEST_BODY),
+        )
+        .expect(1);
+    let m_revalidate = Mock::given(method(GET))
+        .and(header("if-none-match", "\"v1\""))
+        .respond_with(
+            ResponseTemplate::new(304)
+                .insert_header("etag", "\"v1\"")
+                .set_delay(Duration::from_millis(300)),
+        )
+        .with_priority(1)
+        .expect(1);
+    let _full_guard = mock_server.register_as_scoped(m_full).await;
+    let _revalidate_guard = mock_server.register_as_scoped(m_revalidate).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let mut coalescing = Coalescing::default();
+    coalescing.revalidations = true;
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions {
+            coalescing: Some(coalescing),
+            ..Default::default()
+        },
+    };
+    let client =
+        ClientBuilder::new(Client::new()).with(Cache(cache.clone())).build();
+    client.get(url.clone()).send().await?;
+    cache.soft_purge(GET, &url).await?;
+
+    let requests = (0..10)
+        .map(|_| {
+            let client = client.clone();
+            let url = url.clone();
+            tokio::spawn(async move { client.get(url).send().await })
+        })
+        .collect::<Vec<_>>();
+    let mut responses = Vec::new();
+    for request in requests {
+        responses.push(request.await??);
+    }
+    Ok(responses)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn coalesces_concurrent_revalidations() -> anyhow::Result<()> {
+    for res in concurrent_revalidations(CACHEABLE_PUBLIC).await? {
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+        assert!(res.headers().get("warning").is_none());
+        assert_eq!(res.bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn serves_stale_while_revalidating() -> anyhow::Result<()> {
+    let responses = concurrent_revalidations(
+        "max-age=86400, public, stale-while-revalidate=60",
+    )
+    .await?;
+    // Everyone but the request doing the revalidation gets the stale response
+    let stale = responses
+        .iter()
+        .filter(|res| match res.headers().get("warning") {
+            Some(warning) => warning.to_str().unwrap().starts_with("110"),
+            None => false,
+        })
+        .count();
+    assert_eq!(stale, responses.len() - 1);
+    Ok(())
+}
+
+// Marks the responses it passes on, like a middleware down the chain would
+struct Marker;
+
+fn mark_responses<'a>(
+    req: Request,
+    extensions: &'a mut task_local_extensions::Extensions,
+    next: reqwest_middleware::Next<'a>,
+) -> futures::future::BoxFuture<'a, reqwest_middleware::Result<reqwest::Response>>
+{
+    Box::pin(async move {
+        let mut res = next.run(req, extensions).await?;
+        res.extensions_mut().insert(Marker);
+        Ok(res)
+    })
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn hits_keep_the_shape_of_responses() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_redirect = Mock::given(method(GET))
+        .and(path("/moved"))
+        .respond_with(
+            ResponseTemplate::new(301).insert_header("location", "/target"),
+        )
+        .expect(1);
+    let m_target =
+        build_path_mock("/target", CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _redirect_guard = mock_server.register_as_scoped(m_redirect).await;
+    let _target_guard = mock_server.register_as_scoped(m_target).await;
+    let url = Url::parse(&format!("{}/moved", &mock_server.uri()))?;
+
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .with(mark_responses)
+        .build();
+
+    // The response fetched upstream keeps its url and extensions
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.url().path(), "/target");
+    assert_eq!(res.version(), http::Version::HTTP_11);
+    assert!(res.extensions().get::<Marker>().is_some());
+    assert_eq!(
+        res.extensions().get::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
+
+    // The hit has the requested url and only the cache status
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.url(), &url);
+    assert_eq!(res.version(), http::Version::HTTP_11);
+    assert!(res.extensions().get::<Marker>().is_none());
+    assert_eq!(
+        res.extensions().get::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::HIT,
+            lookup: HitOrMiss::HIT,
+            overridden: false,
+        })
+    );
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+// Retries server errors and failed connections, like reqwest-retry does
+#[derive(Clone, Default)]
+struct RetryServerErrors {
+    attempts: Arc<AtomicUsize>,
+}
+
+#[async_trait::async_trait]
+impl reqwest_middleware::Middleware for RetryServerErrors {
+    async fn handle(
+        &self,
+        req: Request,
+        extensions: &mut task_local_extensions::Extensions,
+        next: reqwest_middleware::Next<'_>,
+    ) -> reqwest_middleware::Result<reqwest::Response> {
+        let mut remaining = 2;
+        loop {
+            self.attempts.fetch_add(1, Ordering::SeqCst);
+            let copied = req.try_clone().unwrap();
+            let transient = match next.clone().run(copied, extensions).await {
+                Ok(res) if res.status().is_server_error() => Ok(res),
+                Err(reqwest_middleware::Error::Reqwest(e))
+                    if e.is_connect() =>
+                {
+                    Err(reqwest_middleware::Error::Reqwest(e))
+                }
+                other => return other,
+            };
+            if remaining == 0 {
+                return transient;
+            }
+            remaining -= 1;
+        }
+    }
+}
+
+// Fails twice with a server error before answering with a cacheable response
+async fn mount_flaky(mock_server: &MockServer) {
+    Mock::given(method(GET))
+        .respond_with(ResponseTemplate::new(503))
+        .up_to_n_times(2)
+        .with_priority(1)
+        .expect(2)
+        .mount(mock_server)
+        .await;
+    build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1).mount(mock_server).await;
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn retries_inside_the_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    mount_flaky(&mock_server).await;
+    let url = format!("{}/", &mock_server.uri());
+    let retry = RetryServerErrors::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .with(retry.clone())
+        .build();
+
+    // The retried success is stored
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(retry.attempts.load(Ordering::SeqCst), 3);
+
+    // The hit never reaches the retry middleware
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(retry.attempts.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn retries_outside_the_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    mount_flaky(&mock_server).await;
+    let url = format!("{}/", &mock_server.uri());
+    let retry = RetryServerErrors::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(retry.clone())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .build();
+
+    // Server errors come back through the cache for the retry to see
+    let res = client.get(url.clone()).send().await?;
+    assert_eq!(res.status(), 200);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(retry.attempts.load(Ordering::SeqCst), 3);
+
+    // The hit succeeds on the first attempt
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(retry.attempts.load(Ordering::SeqCst), 4);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn passes_on_client_errors() -> anyhow::Result<()> {
+    // Nothing listens on the port, so connecting fails
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    drop(listener);
+    let retry = RetryServerErrors::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(retry.clone())
+        .with(Cache(HttpCache::new(CacheMode::Default, MokaManager::default())))
+        .build();
+
+    let err = client.get(url).send().await.unwrap_err();
+    assert!(
+        matches!(&err, reqwest_middleware::Error::Reqwest(e) if e.is_connect())
+    );
+    assert_eq!(retry.attempts.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_store_mode_passes_bodies_through() -> anyhow::Result<()> {
+    let (url, sent, release) = held_body_server()?;
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::NoStore, MokaManager::default())))
+        .build();
+
+    // The response is returned before its body was sent
+    let res = client.get(&url).send().await?;
+    assert!(!sent.load(Ordering::SeqCst));
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(
+        res.extensions().get::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
+    drop(release);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn delete_after_non_get_head_method_request() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_get = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_post = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201).set_body_bytes("created"))
+        .expect(1);
+    let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
+    let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .build();
+
+    // Cold pass to load cache
+    client.get(&url).send().await?;
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_some());
+
+    // The response to the post is passed on and removes the stored one
+    let res = client.post(&url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.status(), 201);
+    assert_eq!(res.bytes().await?, "created");
+    assert!(manager
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_none());
+    Ok(())
+}
+
+#[cfg(test)]
+mod blocking {
+    use super::*;
+    use http_cache_reqwest::BlockingClient;
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn default_mode() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+        let moved = manager.clone();
+        let cloned_url = url.clone();
+
+        // The blocking client can't be used on the runtime's thread
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let client = BlockingClient::new(
+                reqwest::blocking::Client::new(),
+                HttpCache::new(CacheMode::Default, moved),
+            );
+
+            // Cold pass to load cache
+            let res = client.get(&cloned_url)?;
+            assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+            // Hot pass to make sure the expect response was returned
+            let res = client.get(&cloned_url)?;
+            assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+            assert_eq!(res.bytes()?, TEST_BODY);
+            Ok(())
+        })
+        .await??;
+
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+        Ok(())
+    }
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn no_store_mode_passes_bodies_through() -> anyhow::Result<()> {
+        let (url, sent, release) = held_body_server()?;
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let client = BlockingClient::new(
+                reqwest::blocking::Client::new(),
+                HttpCache::new(CacheMode::NoStore, MokaManager::default()),
+            );
+
+            // The response is returned before its body was sent
+            let res = client.get(&url)?;
+            assert!(!sent.load(Ordering::SeqCst));
+            assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+            drop(release);
+            assert_eq!(res.bytes()?, TEST_BODY);
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn keeps_the_reason_phrase() -> anyhow::Result<()> {
+        let (url, requests) = reason_server()?;
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let client = BlockingClient::new(
+                reqwest::blocking::Client::new(),
+                HttpCache::new(CacheMode::Default, MokaManager::default()),
+            );
+            for cache in [HitOrMiss::MISS, HitOrMiss::HIT] {
+                let res = client.get(&url)?;
+                let status = res.extensions().get::<CacheStatus>().unwrap();
+                assert_eq!(status.cache, cache);
+                let reason = res.extensions().get::<ReasonPhrase>().unwrap();
+                assert_eq!(reason.as_bytes(), REASON_PHRASE.as_bytes());
+            }
+            Ok(())
+        })
+        .await??;
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+        Ok(())
+    }
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn shares_the_cache_with_async_clients() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Store the response with the async client
+        let client = ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+            .build();
+        let res = client.get(&url).send().await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+        // The blocking client should be served the stored response
+        tokio::task::spawn_blocking(move || -> anyhow::Result<()> {
+            let client = BlockingClient::new(
+                reqwest::blocking::Client::new(),
+                HttpCache::new(CacheMode::Default, manager),
+            );
+            let res = client.get(&url)?;
+            assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+            assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+            assert_eq!(res.bytes()?, TEST_BODY);
+            Ok(())
+        })
+        .await??;
+        Ok(())
+    }
+}
@@ -1,33 +1,42 @@
 use crate::*;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
-use http_cache_surf::Cache;
+use http_cache_surf::{Cache, StreamingCache};
 
 use surf::{middleware::Next, Client, Request};
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn default_mode() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
     let res = client.send(req.clone()).await?;
     assert_eq!(res.header(XCACHELOOKUP).unwrap(), MISS);
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert_eq!(
+        res.ext::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure the expect response was returned
@@ -35,30 +44,145 @@ async fn default_mode() -> surf::Result<()> {
     assert_eq!(res.body_bytes().await?, TEST_BODY);
     assert_eq!(res.header(XCACHELOOKUP).unwrap(), HIT);
     assert_eq!(res.header(XCACHE).unwrap(), HIT);
+    assert_eq!(
+        res.ext::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::HIT,
+            lookup: HitOrMiss::HIT,
+            overridden: false,
+        })
+    );
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn keeps_repeated_headers() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guard =
+        mock_server.register_as_scoped(build_cookie_mock(1)).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    }));
+
+    for cache in [MISS, HIT] {
+        let res = client.send(Request::new(Method::Get, url.clone())).await?;
+        assert_eq!(res.header(XCACHE).unwrap(), cache);
+        let cookies = res.header("set-cookie").unwrap();
+        assert_eq!(
+            cookies.iter().map(|value| value.as_str()).collect::<Vec<_>>(),
+            SET_COOKIES
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn keeps_the_version() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    }));
+
+    let cold = client.send(Request::new(Method::Get, url.clone())).await?;
+    let hot = client.send(Request::new(Method::Get, url)).await?;
+    assert_eq!(hot.header(XCACHE).unwrap(), HIT);
+    assert_eq!(cold.version(), Some(Version::Http1_1));
+    assert_eq!(hot.version(), cold.version());
+    Ok(())
+}
+
+// The curl client decodes gzip bodies and keeps the headers of the encoded
+// one, which are reconciled before the response is stored
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn reconciles_decoded_encoding() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let _mock_guard = mock_server.register_as_scoped(build_gzip_mock(1)).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    }));
+    let len = TEST_BODY.len().to_string();
+
+    for cache in [MISS, HIT] {
+        let mut res =
+            client.send(Request::new(Method::Get, url.clone())).await?;
+        assert_eq!(res.header(XCACHE).unwrap(), cache);
+        assert!(res.header("content-encoding").is_none());
+        assert_eq!(res.header("content-length").unwrap(), len.as_str());
+        assert_eq!(res.body_bytes().await?, TEST_BODY);
+    }
+    Ok(())
+}
+
+// Surf only holds ASCII header values, so opaque tokens are all it can get
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn keeps_opaque_header_values() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("x-token", OPAQUE_TOKEN),
+        )
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/", &mock_server.uri()))?;
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: MokaManager::default(),
+        options: HttpCacheOptions::default(),
+    }));
+
+    for cache in [MISS, HIT] {
+        let res = client.send(Request::new(Method::Get, url.clone())).await?;
+        assert_eq!(res.header(XCACHE).unwrap(), cache);
+        assert_eq!(res.header("x-token").unwrap(), OPAQUE_TOKEN);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn default_mode_with_options() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PRIVATE, TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache options override
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: Some(CacheOptions { shared: false, ..Default::default() }),
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            cache_options: Some(CacheOptions {
+                shared: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
     }));
 
     // Cold pass to load cache
     client.send(req.clone()).await?;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure the expect response was returned
@@ -67,20 +191,21 @@ async fn default_mode_with_options() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn default_mode_no_cache_response() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock("no-cache", TEST_BODY, 200, 2);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -89,7 +214,7 @@ async fn default_mode_no_cache_response() -> surf::Result<()> {
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure the expect response was returned
@@ -100,6 +225,7 @@ async fn default_mode_no_cache_response() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn removes_warning() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
@@ -113,14 +239,14 @@ async fn removes_warning() -> surf::Result<()> {
         .expect(1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -129,7 +255,7 @@ async fn removes_warning() -> surf::Result<()> {
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure the expect response was returned
@@ -141,50 +267,60 @@ async fn removes_warning() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn no_store_mode() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::NoStore,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Remote request but should not cache
     client.send(req.clone()).await?;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_none());
 
     // To verify our endpoint receives the request rather than a cache hit
     let res = client.send(req).await?;
     assert_eq!(res.header(XCACHELOOKUP).unwrap(), MISS);
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert_eq!(
+        res.ext::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn no_cache_mode() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::NoCache,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Remote request and should cache
@@ -193,7 +329,7 @@ async fn no_cache_mode() -> surf::Result<()> {
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // To verify our endpoint receives the request rather than a cache hit
@@ -203,20 +339,21 @@ async fn no_cache_mode() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn force_cache_mode() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
     let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
     let _mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::ForceCache,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Should result in a cache miss and a remote request
@@ -225,7 +362,7 @@ async fn force_cache_mode() -> surf::Result<()> {
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Should result in a cache hit and no remote request
@@ -235,6 +372,7 @@ async fn force_cache_mode() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn delete_after_non_get_head_method_request() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
@@ -245,15 +383,15 @@ async fn delete_after_non_get_head_method_request() -> surf::Result<()> {
     let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
     let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req_get = Request::new(Method::Get, Url::parse(&url)?);
     let req_post = Request::new(Method::Post, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -262,18 +400,19 @@ async fn delete_after_non_get_head_method_request() -> surf::Result<()> {
     assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Post request to make sure the cache object at the same resource was deleted
     client.send(req_post).await?;
 
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_none());
 
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn revalidation_304() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
@@ -283,14 +422,14 @@ async fn revalidation_304() -> surf::Result<()> {
         .expect(1);
     let mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -303,7 +442,7 @@ async fn revalidation_304() -> surf::Result<()> {
     let _mock_guard = mock_server.register_as_scoped(m_304).await;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure revalidation request was sent
@@ -314,6 +453,7 @@ async fn revalidation_304() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn revalidation_200() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
@@ -321,14 +461,14 @@ async fn revalidation_200() -> surf::Result<()> {
     let m_200 = build_mock(MUST_REVALIDATE, b"updated", 200, 1);
     let mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -341,7 +481,7 @@ async fn revalidation_200() -> surf::Result<()> {
     let _mock_guard = mock_server.register_as_scoped(m_200).await;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure revalidation request was sent
@@ -352,6 +492,7 @@ async fn revalidation_200() -> surf::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "manager-moka")]
 #[async_std::test]
 async fn revalidation_500() -> surf::Result<()> {
     let mock_server = MockServer::start().await;
@@ -361,14 +502,14 @@ async fn revalidation_500() -> surf::Result<()> {
         .expect(1);
     let mock_guard = mock_server.register_as_scoped(m).await;
     let url = format!("{}/", &mock_server.uri());
-    let manager = Arc::new(MokaManager::default());
+    let manager = MokaManager::default();
     let req = Request::new(Method::Get, Url::parse(&url)?);
 
     // Construct Surf client with cache defaults
     let client = Client::new().with(Cache(HttpCache {
         mode: CacheMode::Default,
-        manager: Arc::clone(&manager),
-        options: None,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
     }));
 
     // Cold pass to load cache
@@ -381,7 +522,7 @@ async fn revalidation_500() -> surf::Result<()> {
     let _mock_guard = mock_server.register_as_scoped(m_500).await;
 
     // Try to load cached object
-    let data = manager.get(GET, &Url::parse(&url)?).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
     assert!(data.is_some());
 
     // Hot pass to make sure revalidation request was sent
@@ -397,20 +538,21 @@ async fn revalidation_500() -> surf::Result<()> {
 mod only_if_cached_mode {
     use super::*;
 
+    #[cfg(feature = "manager-moka")]
     #[async_std::test]
     async fn miss() -> surf::Result<()> {
         let mock_server = MockServer::start().await;
         let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 0);
         let _mock_guard = mock_server.register_as_scoped(m).await;
         let url = format!("{}/", &mock_server.uri());
-        let manager = Arc::new(MokaManager::default());
+        let manager = MokaManager::default();
         let req = Request::new(Method::Get, Url::parse(&url)?);
 
         // Construct Surf client with cache defaults
         let client = Client::new().with(Cache(HttpCache {
             mode: CacheMode::OnlyIfCached,
-            manager: Arc::clone(&manager),
-            options: None,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
         }));
 
         // Should result in a cache miss and no remote request
@@ -419,25 +561,26 @@ mod only_if_cached_mode {
         assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
         // Try to load cached object
-        let data = manager.get(GET, &Url::parse(&url)?).await?;
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
         assert!(data.is_none());
         Ok(())
     }
 
+    #[cfg(feature = "manager-moka")]
     #[async_std::test]
     async fn hit() -> surf::Result<()> {
         let mock_server = MockServer::start().await;
         let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
         let _mock_guard = mock_server.register_as_scoped(m).await;
         let url = format!("{}/", &mock_server.uri());
-        let manager = Arc::new(MokaManager::default());
+        let manager = MokaManager::default();
         let req = Request::new(Method::Get, Url::parse(&url)?);
 
         // Construct Surf client with cache defaults
         let client = Client::new().with(Cache(HttpCache {
             mode: CacheMode::Default,
-            manager: Arc::clone(&manager),
-            options: None,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
         }));
 
         // Cold pass to load the cache
@@ -446,14 +589,14 @@ mod only_if_cached_mode {
         assert_eq!(res.header(XCACHE).unwrap(), MISS);
 
         // Try to load cached object
-        let data = manager.get(GET, &Url::parse(&url)?).await?;
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
         assert!(data.is_some());
 
         // Construct Surf client with cache defaults
         let client = Client::new().with(Cache(HttpCache {
             mode: CacheMode::OnlyIfCached,
-            manager: Arc::clone(&manager),
-            options: None,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
         }));
 
         // Should result in a cache hit and no remote request
@@ -464,3 +607,419 @@ mod only_if_cached_mode {
         Ok(())
     }
 }
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn warm_cache() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_fresh =
+        build_path_mock("/fresh", CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_cold = build_path_mock("/cold", CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_missing =
+        build_path_mock("/missing", CACHEABLE_PUBLIC, TEST_BODY, 404, 1);
+    let _mock_guard_fresh = mock_server.register_as_scoped(m_fresh).await;
+    let _mock_guard_cold = mock_server.register_as_scoped(m_cold).await;
+    let _mock_guard_missing = mock_server.register_as_scoped(m_missing).await;
+    let fresh = Url::parse(&format!("{}/fresh", &mock_server.uri()))?;
+    let cold = Url::parse(&format!("{}/cold", &mock_server.uri()))?;
+    let missing = Url::parse(&format!("{}/missing", &mock_server.uri()))?;
+    let manager = MokaManager::default();
+    let cache = HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    };
+
+    // Construct Surf client with cache defaults
+    let client = Client::new().with(Cache(cache.clone()));
+
+    // Load one of the urls ahead of time
+    client.get(fresh.clone()).await?;
+
+    let report = http_cache_surf::warm_cache(
+        &client,
+        &cache,
+        vec![fresh.clone(), cold.clone(), missing.clone()],
+        2,
+    )
+    .await;
+    assert_eq!(report.entries.len(), 3);
+    assert!(matches!(report.entries[0].1, WarmOutcome::AlreadyFresh));
+    assert!(matches!(report.entries[1].1, WarmOutcome::Fetched));
+    assert!(matches!(report.entries[2].1, WarmOutcome::Failed(_)));
+
+    // The cold url should now be cached
+    let data = manager.get(&CacheKey::new(GET, &cold)).await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-cacache")]
+#[async_std::test]
+async fn corrupt_entry_is_refetched() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = CACacheManager {
+        path: "./http-cacache-corrupt".into(),
+        ..Default::default()
+    };
+    let req = Request::new(Method::Get, Url::parse(&url)?);
+
+    // Write garbage directly into the store under the request's key
+    cacache::write(&manager.path, format!("{}:{}", GET, &url), b"garbage")
+        .await?;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&events);
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            on_event: Some(Arc::new(move |event: &CacheEvent| {
+                recorded.lock().unwrap().push(event.clone())
+            })),
+            ..Default::default()
+        },
+    }));
+
+    // The corrupt record is treated as a miss
+    let mut res = client.send(req.clone()).await?;
+    assert_eq!(res.body_bytes().await?, TEST_BODY);
+    assert_eq!(res.header(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert!(matches!(
+        events.lock().unwrap().as_slice(),
+        [
+            CacheEvent::CorruptEntry { .. },
+            CacheEvent::Stored { .. },
+            CacheEvent::Miss { .. }
+        ]
+    ));
+
+    // And has been overwritten with the fresh response
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert_eq!(data.unwrap().0.body, TEST_BODY);
+    let res = client.send(req).await?;
+    assert_eq!(res.header(XCACHE).unwrap(), HIT);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn corrupt_entry_can_be_propagated() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 0);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+    let req = Request::new(Method::Get, Url::parse(&url)?);
+    manager
+        .cache
+        .insert(format!("{}:{}", GET, &url), Bytes::from_static(b"garbage"))
+        .await;
+
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            propagate_corrupt_entries: true,
+            ..Default::default()
+        },
+    }));
+
+    assert!(client.send(req).await.is_err());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn should_cache_request_bypass() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = CountingManager::default();
+    let mut bypass_req = Request::new(Method::Get, Url::parse(&url)?);
+    bypass_req.insert_header("x-debug-live", "1");
+
+    // Construct Surf client that bypasses the cache for debug requests
+    let client = Client::new().with(Cache(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            should_cache_request: Some(Arc::new(
+                |parts: &http::request::Parts| {
+                    !parts.headers.contains_key("x-debug-live")
+                },
+            )),
+            ..Default::default()
+        },
+    }));
+
+    // Bypassed requests go to the origin without touching the manager
+    for _ in 0..2 {
+        let mut res = client.send(bypass_req.clone()).await?;
+        assert!(res.header(XCACHE).is_none());
+        assert!(res.header(XCACHELOOKUP).is_none());
+        assert_eq!(res.body_bytes().await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), 0);
+
+    // Other requests still use the cache
+    let req = Request::new(Method::Get, Url::parse(&url)?);
+    let res = client.send(req).await?;
+    assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert!(manager.calls() > 0);
+    Ok(())
+}
+
+// Answers `data:` requests itself, standing in for a middleware resolving
+// schemes other than http and https
+#[derive(Default)]
+struct DataUrls {
+    calls: Arc<AtomicUsize>,
+}
+
+#[surf::utils::async_trait]
+impl surf::middleware::Middleware for DataUrls {
+    async fn handle(
+        &self,
+        req: Request,
+        client: Client,
+        next: Next<'_>,
+    ) -> surf::Result<surf::Response> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if req.url().scheme() != "data" {
+            return next.run(req, client).await;
+        }
+        let mut res = http_types::Response::new(200);
+        res.insert_header("cache-control", CACHEABLE_PUBLIC);
+        res.set_body(TEST_BODY);
+        Ok(res.into())
+    }
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn other_schemes_bypass() -> surf::Result<()> {
+    let manager = CountingManager::default();
+    let data_urls = DataUrls::default();
+    let inner_calls = data_urls.calls.clone();
+    let client = Client::new()
+        .with(Cache(HttpCache::new(CacheMode::Default, manager.clone())))
+        .with(data_urls);
+
+    let url = Url::parse("data:text/plain,test")?;
+    for _ in 0..2 {
+        let mut res =
+            client.send(Request::new(Method::Get, url.clone())).await?;
+        assert_eq!(res.status(), 200);
+        assert!(res.header(XCACHE).is_none());
+        assert!(res.header(XCACHELOOKUP).is_none());
+        assert_eq!(res.body_bytes().await?, TEST_BODY);
+    }
+    assert_eq!(inner_calls.load(Ordering::SeqCst), 2);
+    assert_eq!(manager.calls(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn manager_accessor() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let req = Request::new(Method::Get, Url::parse(&url)?);
+
+    // The manager is reached through the wrapper instead of being kept aside
+    let cache =
+        Cache(HttpCache::new(CacheMode::Default, MokaManager::default()));
+    let manager = cache.manager().clone();
+    let client = Client::new().with(cache);
+
+    client.send(req).await?;
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-cacache")]
+#[async_std::test]
+async fn streaming_cache() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let body = vec![7; 1024 * 1024];
+    let m = build_mock(CACHEABLE_PUBLIC, &body, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let manager = CACacheManager {
+        path: "./http-cacache-surf-streaming".into(),
+        ..Default::default()
+    };
+    let client = Client::new().with(StreamingCache(HttpCache::new(
+        CacheMode::Default,
+        manager.clone(),
+    )));
+
+    // Cold pass stores the body once it has been read to the end
+    let mut res = client.get(url.clone()).await?;
+    assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+    assert_eq!(res.body_bytes().await?, body);
+    let (cached, _) = manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+    assert_eq!(cached.body, body);
+
+    // Hot pass streams the body back from the cache
+    let mut res = client.get(url).await?;
+    assert_eq!(res.header(XCACHE).unwrap(), HIT);
+    assert_eq!(
+        res.ext::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::HIT,
+            lookup: HitOrMiss::HIT,
+            overridden: false,
+        })
+    );
+    assert_eq!(res.body_bytes().await?, body);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+#[cfg(feature = "manager-cacache")]
+#[async_std::test]
+async fn streaming_cache_reads_bodies_lazily() -> surf::Result<()> {
+    let mock_server = MockServer::start().await;
+    let body = vec![7; 1024 * 1024];
+    let m = build_mock(CACHEABLE_PUBLIC, &body, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = Url::parse(&format!("{}/artifact", &mock_server.uri()))?;
+    let path = "./http-cacache-surf-lazy";
+    let manager = CACacheManager { path: path.into(), ..Default::default() };
+    let client = Client::new().with(StreamingCache(HttpCache::new(
+        CacheMode::Default,
+        manager.clone(),
+    )));
+    client.get(url.clone()).recv_bytes().await?;
+
+    // Only the status and headers of a hit are read up front
+    let mut res = client.get(url).await?;
+    assert_eq!(res.status(), surf::StatusCode::Ok);
+    assert_eq!(res.header(XCACHE).unwrap(), HIT);
+
+    // So rewriting the stored body in place, it being the only content of
+    // its size, changes what is read afterwards
+    let mut dirs = vec![std::path::PathBuf::from(path).join("content-v2")];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else if entry.metadata()?.len() == body.len() as u64 {
+                std::fs::write(entry.path(), vec![0; body.len()])?;
+            }
+        }
+    }
+    assert_eq!(res.body_bytes().await?, vec![0; body.len()]);
+    manager.clear(true).await?;
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[async_std::test]
+async fn no_store_mode_passes_bodies_through() -> surf::Result<()> {
+    let (url, sent, release) = held_body_server()?;
+    let client = Client::new().with(Cache(HttpCache::new(
+        CacheMode::NoStore,
+        MokaManager::default(),
+    )));
+
+    // The response is returned before its body was sent
+    let mut res = client.get(&url).await?;
+    assert!(!sent.load(Ordering::SeqCst));
+    assert_eq!(res.header(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.header(XCACHE).unwrap(), MISS);
+    assert_eq!(
+        res.ext::<CacheStatus>(),
+        Some(&CacheStatus {
+            cache: HitOrMiss::MISS,
+            lookup: HitOrMiss::MISS,
+            overridden: false,
+        })
+    );
+    drop(release);
+    assert_eq!(res.body_bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+// The status, headers and body of a response, leaving out the headers telling
+// a hit from a miss, sorted by name
+type Shape = (u16, Vec<(String, String)>, Vec<u8>);
+
+async fn shape(mut res: surf::Response) -> surf::Result<Shape> {
+    let mut headers: Vec<_> = res
+        .iter()
+        .filter(|(name, _)| {
+            ![XCACHE, XCACHELOOKUP, "age", "date"].contains(&name.as_str())
+        })
+        .flat_map(|(name, values)| {
+            values.iter().map(|value| (name.to_string(), value.to_string()))
+        })
+        .collect();
+    headers.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok((res.status().into(), headers, res.body_bytes().await?))
+}
+
+#[cfg(all(feature = "manager-cacache", feature = "manager-moka"))]
+#[async_std::test]
+async fn no_content_round_trips() -> surf::Result<()> {
+    let cacache =
+        |path: &str| CACacheManager { path: path.into(), ..Default::default() };
+    let clients = [
+        Client::new().with(Cache(HttpCache::new(
+            CacheMode::Default,
+            MokaManager::default(),
+        ))),
+        Client::new().with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            MokaManager::default(),
+        ))),
+        Client::new().with(Cache(HttpCache::new(
+            CacheMode::Default,
+            cacache("./http-cacache-surf-empty"),
+        ))),
+        Client::new().with(StreamingCache(HttpCache::new(
+            CacheMode::Default,
+            cacache("./http-cacache-surf-empty-streaming"),
+        ))),
+    ];
+    for client in clients {
+        let mock_server = MockServer::start().await;
+        let m = Mock::given(method(GET))
+            .respond_with(
+                ResponseTemplate::new(204)
+                    .insert_header("cache-control", CACHEABLE_PUBLIC),
+            )
+            .expect(1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+
+        let cold = client.get(&url).await?;
+        assert_eq!(cold.header(XCACHE).unwrap(), MISS);
+        let cold = shape(cold).await?;
+        assert_eq!(cold.0, 204);
+        assert!(cold.2.is_empty());
+
+        let hot = client.get(&url).await?;
+        assert_eq!(hot.header(XCACHE).unwrap(), HIT);
+        assert_eq!(shape(hot).await?, cold);
+    }
+    for path in
+        ["./http-cacache-surf-empty", "./http-cacache-surf-empty-streaming"]
+    {
+        cacache(path).clear(true).await?;
+    }
+    Ok(())
+}
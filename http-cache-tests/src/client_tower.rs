@@ -0,0 +1,614 @@
+use crate::*;
+use std::future::poll_fn;
+
+use http_cache_tower::{CacheLayer, CacheService};
+use hyper::{
+    body::to_bytes, client::HttpConnector, ext::ReasonPhrase,
+    service::service_fn, Body, Client, Request,
+};
+use tower_service::Service;
+
+type TowerClient<T> = CacheService<Client<HttpConnector>, T>;
+
+fn client<T: CacheManager>(cache: HttpCache<T>) -> TowerClient<T> {
+    CacheLayer::new(cache).layer(Client::new())
+}
+
+fn get(url: &str) -> anyhow::Result<Request<Body>> {
+    Ok(Request::get(url).body(Body::empty())?)
+}
+
+// Sends the request once the service is ready, like `tower::ServiceExt::oneshot`
+async fn send<T: CacheManager>(
+    client: &mut TowerClient<T>,
+    req: Request<Body>,
+) -> anyhow::Result<http::Response<Body>> {
+    poll_fn(|cx| client.poll_ready(cx))
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    client.call(req).await.map_err(|e| anyhow::anyhow!(e))
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode_with_options() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PRIVATE, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache options override
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            cache_options: Some(CacheOptions {
+                shared: false,
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+    });
+
+    // Cold pass to load cache
+    send(&mut client, get(&url)?).await?;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn default_mode_no_cache_response() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock("no-cache", TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure the expect response was returned
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_store_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::NoStore,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Remote request but should not cache
+    send(&mut client, get(&url)?).await?;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_cache_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::NoCache,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Remote request and should cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // To verify our endpoint receives the request rather than a cache hit
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn force_cache_mode() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::ForceCache,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Should result in a cache miss and a remote request
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Should result in a cache hit and no remote request
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn delete_after_non_get_head_method_request() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m_get = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let m_post = Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(201).set_body_bytes("created"))
+        .expect(1);
+    let _mock_guard_get = mock_server.register_as_scoped(m_get).await;
+    let _mock_guard_post = mock_server.register_as_scoped(m_post).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Post request to make sure the cache object at the same resource was deleted
+    let req_post = Request::post(&url).body(Body::from("payload"))?;
+    let res = send(&mut client, req_post).await?;
+    assert_eq!(to_bytes(res.into_body()).await?, "created");
+
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_none());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn request_body_is_sent() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = Mock::given(method("POST"))
+        .and(wiremock::matchers::body_string("payload"))
+        .respond_with(ResponseTemplate::new(201))
+        .expect(1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let mut client =
+        client(HttpCache::new(CacheMode::Default, MokaManager::default()));
+
+    let req = Request::post(&url).body(Body::from("payload"))?;
+    let res = send(&mut client, req).await?;
+    assert_eq!(res.status(), 201);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_304() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_304 = Mock::given(method(GET))
+        .respond_with(ResponseTemplate::new(304))
+        .expect(1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_304).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_200() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_200 = build_mock(MUST_REVALIDATE, b"updated", 200, 1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_200).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(to_bytes(res.into_body()).await?, "updated");
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn revalidation_500() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(MUST_REVALIDATE, TEST_BODY, 200, 1);
+    let m_500 = Mock::given(method(GET))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1);
+    let mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = MokaManager::default();
+
+    // Construct tower service with cache defaults
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions::default(),
+    });
+
+    // Cold pass to load cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    drop(mock_guard);
+
+    let _mock_guard = mock_server.register_as_scoped(m_500).await;
+
+    // Try to load cached object
+    let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+    assert!(data.is_some());
+
+    // Hot pass to make sure revalidation request was sent
+    let res = send(&mut client, get(&url)?).await?;
+    assert!(res.headers().get("warning").is_some());
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(test)]
+mod only_if_cached_mode {
+    use super::*;
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn miss() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 0);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct tower service with cache defaults
+        let mut client = client(HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Should result in a cache miss and no remote request
+        let res = send(&mut client, get(&url)?).await?;
+        assert_eq!(res.status(), 504);
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+        // Try to load cached object
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "manager-moka")]
+    #[tokio::test]
+    async fn hit() -> anyhow::Result<()> {
+        let mock_server = MockServer::start().await;
+        let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+        let _mock_guard = mock_server.register_as_scoped(m).await;
+        let url = format!("{}/", &mock_server.uri());
+        let manager = MokaManager::default();
+
+        // Construct tower service with cache defaults
+        let mut client = client(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Cold pass to load the cache
+        let res = send(&mut client, get(&url)?).await?;
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+        // Try to load cached object
+        let data = manager.get(&CacheKey::new(GET, &Url::parse(&url)?)).await?;
+        assert!(data.is_some());
+
+        // Construct tower service with cache defaults
+        let mut client = super::client(HttpCache {
+            mode: CacheMode::OnlyIfCached,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        });
+
+        // Should result in a cache hit and no remote request
+        let res = send(&mut client, get(&url)?).await?;
+        assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), HIT);
+        assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+        assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn should_cache_request_bypass() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 3);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let manager = CountingManager::default();
+
+    // Construct tower service that bypasses the cache for debug requests
+    let mut client = client(HttpCache {
+        mode: CacheMode::Default,
+        manager: manager.clone(),
+        options: HttpCacheOptions {
+            should_cache_request: Some(Arc::new(
+                |parts: &http::request::Parts| {
+                    !parts.headers.contains_key("x-debug-live")
+                },
+            )),
+            ..Default::default()
+        },
+    });
+
+    // Bypassed requests go to the origin without touching the manager
+    for _ in 0..2 {
+        let req = Request::get(&url)
+            .header("x-debug-live", "1")
+            .body(Body::empty())?;
+        let res = send(&mut client, req).await?;
+        assert!(res.headers().get(XCACHE).is_none());
+        assert!(res.headers().get(XCACHELOOKUP).is_none());
+        assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    }
+    assert_eq!(manager.calls(), 0);
+
+    // Other requests still use the cache
+    let res = send(&mut client, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert!(manager.calls() > 0);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn clones_share_the_cache() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let layer = CacheLayer::new(HttpCache::new(
+        CacheMode::Default,
+        MokaManager::default(),
+    ));
+    let mut client = layer.layer(Client::new());
+    let mut cloned = client.clone();
+
+    send(&mut client, get(&url)?).await?;
+    let res = send(&mut cloned, get(&url)?).await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert!(layer
+        .manager()
+        .get(&CacheKey::new(GET, &Url::parse(&url)?))
+        .await?
+        .is_some());
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn no_store_mode_passes_bodies_through() -> anyhow::Result<()> {
+    let (url, sent, release) = held_body_server()?;
+    let mut client =
+        client(HttpCache::new(CacheMode::NoStore, MokaManager::default()));
+
+    // The response is returned before its body was sent
+    let res = send(&mut client, get(&url)?).await?;
+    assert!(!sent.load(Ordering::SeqCst));
+    assert_eq!(res.headers().get(XCACHELOOKUP).unwrap(), MISS);
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    drop(release);
+    assert_eq!(to_bytes(res.into_body()).await?, TEST_BODY);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_trailers() -> anyhow::Result<()> {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&calls);
+    // hyper's HTTP/1 client drops trailers, so the origin answers in process
+    let origin = service_fn(move |_req: Request<Body>| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        async {
+            let res = http::Response::builder()
+                .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                .body(trailed_body().await?)?;
+            Ok::<_, anyhow::Error>(res)
+        }
+    });
+    let mut client = CacheLayer::new(HttpCache::new(
+        CacheMode::Default,
+        MokaManager::default(),
+    ))
+    .layer(origin);
+
+    for cache in [MISS, HIT] {
+        poll_fn(|cx| client.poll_ready(cx))
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        let res = client
+            .call(get("http://example.com/trailers")?)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), cache);
+        let (body, trailers) = read_trailed(res.into_body()).await?;
+        assert_eq!(body, TEST_BODY);
+        assert_eq!(trailers, Some(test_trailers()));
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_the_reason_phrase() -> anyhow::Result<()> {
+    let (url, requests) = reason_server()?;
+    let mut client =
+        client(HttpCache::new(CacheMode::Default, MokaManager::default()));
+
+    for cache in [MISS, HIT] {
+        let res = send(&mut client, get(&url)?).await?;
+        assert_eq!(res.headers().get(XCACHE).unwrap(), cache);
+        let reason = res.extensions().get::<ReasonPhrase>().unwrap();
+        assert_eq!(reason.as_bytes(), REASON_PHRASE.as_bytes());
+    }
+    assert_eq!(requests.load(Ordering::SeqCst), 1);
+    Ok(())
+}
@@ -1,17 +1,43 @@
 #![allow(unused_imports, dead_code)]
 
+#[cfg(test)]
+mod client_isahc;
+
 #[cfg(test)]
 mod client_surf;
 
 #[cfg(test)]
 mod client_reqwest;
 
+#[cfg(test)]
+mod client_tower;
+
+#[cfg(test)]
+mod manager_memcached;
+
+#[cfg(test)]
+mod manager_redis;
+
+#[cfg(test)]
+mod server_tower;
+
 use http::{header::CACHE_CONTROL, StatusCode};
 use http_cache::*;
+use http_cache_semantics::CachePolicy;
 use http_types::{headers::HeaderValue, Method, Version};
-use std::{collections::HashMap, convert::TryInto, str::FromStr};
+use std::{
+    convert::TryInto,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
 use url::Url;
-use wiremock::{matchers::method, Mock, MockServer, ResponseTemplate};
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
 
 pub(crate) fn build_mock(
     cache_control_val: &str,
@@ -28,6 +54,269 @@ pub(crate) fn build_mock(
         .expect(expect)
 }
 
+pub(crate) fn build_path_mock(
+    path_val: &str,
+    cache_control_val: &str,
+    body: &[u8],
+    status: u16,
+    expect: u64,
+) -> Mock {
+    Mock::given(method(GET))
+        .and(path(path_val))
+        .respond_with(
+            ResponseTemplate::new(status)
+                .insert_header("cache-control", cache_control_val)
+                .set_body_bytes(body),
+        )
+        .expect(expect)
+}
+
+// Answers with a cacheable response setting every one of `SET_COOKIES`
+pub(crate) fn build_cookie_mock(expect: u64) -> Mock {
+    let mut template = ResponseTemplate::new(200)
+        .insert_header("cache-control", CACHEABLE_PUBLIC)
+        .set_body_bytes(TEST_BODY);
+    for cookie in SET_COOKIES {
+        template = template.append_header("set-cookie", cookie);
+    }
+    Mock::given(method(GET)).respond_with(template).expect(expect)
+}
+
+// Answers with a cacheable gzip encoded `TEST_BODY`
+pub(crate) fn build_gzip_mock(expect: u64) -> Mock {
+    Mock::given(method(GET))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("cache-control", CACHEABLE_PUBLIC)
+                .insert_header("content-encoding", "gzip")
+                .set_body_bytes(gzip(TEST_BODY)),
+        )
+        .expect(expect)
+}
+
+// Wraps `data` in a gzip member holding a single stored deflate block
+pub(crate) fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    let len = data.len() as u16;
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff, 1];
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(&(!len).to_le_bytes());
+    out.extend_from_slice(data);
+    out.extend_from_slice(&(!crc).to_le_bytes());
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out
+}
+
+// Serves `response` as is on every connection, for responses a mock server
+// can't build such as ones with header values that aren't UTF-8
+pub(crate) fn raw_server(
+    response: Vec<u8>,
+) -> std::io::Result<(String, Arc<AtomicUsize>)> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            counter.fetch_add(1, Ordering::SeqCst);
+            stream.write_all(&response).ok();
+        }
+    });
+    Ok((url, requests))
+}
+
+// The reason phrase sent by `reason_server`, which isn't the canonical one
+pub(crate) const REASON_PHRASE: &str = "Fine By Me";
+
+// Serves a cacheable `TEST_BODY` whose status line carries `REASON_PHRASE`
+pub(crate) fn reason_server() -> std::io::Result<(String, Arc<AtomicUsize>)> {
+    let mut response = format!(
+        "HTTP/1.1 200 {}\r\ncache-control: {}\r\ncontent-length: {}\r\n\
+         connection: close\r\n\r\n",
+        REASON_PHRASE,
+        CACHEABLE_PUBLIC,
+        TEST_BODY.len()
+    )
+    .into_bytes();
+    response.extend_from_slice(TEST_BODY);
+    raw_server(response)
+}
+
+// The trailers `trailed_body` sends after the body
+pub(crate) fn test_trailers() -> http::HeaderMap {
+    let mut trailers = http::HeaderMap::new();
+    trailers.insert("grpc-status", http::HeaderValue::from_static("0"));
+    trailers.insert("grpc-message", http::HeaderValue::from_static("done"));
+    trailers
+}
+
+// A hyper body yielding `TEST_BODY` followed by `test_trailers`
+pub(crate) async fn trailed_body() -> anyhow::Result<hyper::Body> {
+    let (mut sender, body) = hyper::Body::channel();
+    sender.send_data(Bytes::from_static(TEST_BODY)).await?;
+    sender.send_trailers(test_trailers()).await?;
+    Ok(body)
+}
+
+// Reads a body to the end along with the trailers following it
+pub(crate) async fn read_trailed<B>(
+    body: B,
+) -> anyhow::Result<(Vec<u8>, Option<http::HeaderMap>)>
+where
+    B: hyper::body::HttpBody<Data = Bytes>,
+    B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    use hyper::body::HttpBody;
+
+    let mut body = std::pin::pin!(body);
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        data.extend_from_slice(&chunk.map_err(|e| anyhow::anyhow!(e.into()))?);
+    }
+    let trailers =
+        body.trailers().await.map_err(|e| anyhow::anyhow!(e.into()))?;
+    Ok((data, trailers))
+}
+
+// Answers a single request with `TEST_BODY`, sending the headers and the
+// first byte of the body right away, as some clients wait for the body to
+// start, but holding the rest back until something is sent to the returned
+// sender or it is dropped, or for five seconds. Tests can tell that way
+// whether a client read the body before returning the response, the flag is
+// set once the rest of the body was sent.
+pub(crate) fn held_body_server(
+) -> std::io::Result<(String, Arc<AtomicBool>, std::sync::mpsc::Sender<()>)> {
+    use std::io::{Read, Write};
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    let sent = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&sent);
+    let (release, released) = std::sync::mpsc::channel();
+    std::thread::spawn(move || -> std::io::Result<()> {
+        let (mut stream, _) = listener.accept()?;
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            match stream.read(&mut buf)? {
+                0 => return Ok(()),
+                n => request.extend_from_slice(&buf[..n]),
+            }
+        }
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\ncache-control: {}\r\n\
+             content-length: {}\r\nconnection: close\r\n\r\n",
+            CACHEABLE_PUBLIC,
+            TEST_BODY.len()
+        )?;
+        stream.write_all(&TEST_BODY[..1])?;
+        stream.flush()?;
+        released.recv_timeout(std::time::Duration::from_secs(5)).ok();
+        flag.store(true, Ordering::SeqCst);
+        stream.write_all(&TEST_BODY[1..])
+    });
+    Ok((url, sent, release))
+}
+
+// Wraps a MokaManager and counts every call made to it
+#[cfg(feature = "manager-moka")]
+#[derive(Debug, Clone, Default)]
+pub(crate) struct CountingManager {
+    inner: MokaManager,
+    calls: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "manager-moka")]
+impl CountingManager {
+    pub(crate) fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[cfg(feature = "manager-moka")]
+impl CacheManager for CountingManager {
+    async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.inner.get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.inner.put(key, res, policy).await
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.inner.delete(key).await
+    }
+}
+
+// Counts the bytes allocated by each thread, so tests can check how many
+// copies of a body get made
+struct CountingAllocator;
+
+thread_local! {
+    static ALLOCATED: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+fn count_allocation(size: usize) {
+    ALLOCATED.try_with(|allocated| allocated.set(allocated.get() + size)).ok();
+}
+
+// Returns the number of bytes allocated by the current thread so far
+fn allocated() -> usize {
+    ALLOCATED.with(|allocated| allocated.get())
+}
+
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        count_allocation(layout.size());
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(
+        &self,
+        ptr: *mut u8,
+        layout: std::alloc::Layout,
+        new_size: usize,
+    ) -> *mut u8 {
+        count_allocation(new_size);
+        std::alloc::System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
 const GET: &str = "GET";
 
 const TEST_BODY: &[u8] = b"test";
@@ -38,6 +327,20 @@ const CACHEABLE_PRIVATE: &str = "max-age=86400, private";
 
 const MUST_REVALIDATE: &str = "public, must-revalidate";
 
+// Cookies set by the same response, the comma of the expiry date must not
+// split the last one
+const SET_COOKIES: [&str; 3] = [
+    "session=abc; Path=/",
+    "theme=dark",
+    "seen=1; Expires=Wed, 21 Oct 2015 07:28:00 GMT",
+];
+
+// A header value holding latin-1 text, which isn't valid UTF-8
+const LATIN1_VALUE: &[u8] = b"caf\xe9; na\xefve";
+
+// A header value carrying an opaque binary token
+const OPAQUE_TOKEN: &str = "tok+3q2+7w/AAE=";
+
 const HIT: &str = "HIT";
 
 const MISS: &str = "MISS";
@@ -50,11 +353,14 @@ mod http_cache_tests {
     fn response_methods_work() -> anyhow::Result<()> {
         let url = Url::from_str("http://example.com")?;
         let mut res = HttpResponse {
-            body: TEST_BODY.to_vec(),
-            headers: HashMap::default(),
+            body: Bytes::from_static(TEST_BODY),
+            headers: HttpHeaders::default(),
+            trailers: None,
             status: 200,
+            reason: None,
             url: url.clone(),
             version: HttpVersion::Http11,
+            metadata: Default::default(),
         };
         res.add_warning(&url, 112, "Test Warning");
         let code = res.warning_code();
@@ -139,39 +445,61 @@ mod http_cache_tests {
     mod managers {
         use crate::*;
         use http_cache_semantics::CachePolicy;
-        use std::sync::Arc;
+        use std::{sync::Arc, time::Duration};
 
+        #[cfg(feature = "manager-cacache")]
         #[async_std::test]
         async fn cacache() -> anyhow::Result<()> {
             let url = Url::parse("http://example.com")?;
             let manager = CACacheManager::default();
             let http_res = HttpResponse {
-                body: TEST_BODY.to_vec(),
+                body: Bytes::from_static(TEST_BODY),
                 headers: Default::default(),
+                trailers: None,
                 status: 200,
+                reason: None,
                 url: url.clone(),
                 version: HttpVersion::Http11,
+                metadata: Default::default(),
             };
             let req = http::Request::get("http://example.com").body(())?;
             let res = http::Response::builder()
                 .status(200)
                 .body(TEST_BODY.to_vec())?;
             let policy = CachePolicy::new(&req, &res);
-            manager.put(GET, &url, http_res.clone(), policy.clone()).await?;
-            let data = manager.get(GET, &url).await?;
+            manager
+                .put(
+                    &CacheKey::new(GET, &url),
+                    http_res.clone(),
+                    policy.clone(),
+                )
+                .await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_some());
             assert_eq!(data.unwrap().0.body, TEST_BODY);
-            manager.delete(GET, &url).await?;
-            let data = manager.get(GET, &url).await?;
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_none());
 
-            manager.put(GET, &url, http_res, policy).await?;
-            manager.clear().await?;
-            let data = manager.get(GET, &url).await?;
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            manager.clear(true).await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_none());
             Ok(())
         }
 
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_takes_at_least_one_write_lock() -> anyhow::Result<()> {
+            let manager = MokaManager::with_write_locks(MokaCache::new(42), 0);
+            let url = Url::parse("http://example.com")?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
         #[async_std::test]
         async fn moka() -> anyhow::Result<()> {
             // Added to test custom Debug impl
@@ -180,32 +508,3576 @@ mod http_cache_tests {
                 "MokaManager { .. }",
             );
             let url = Url::parse("http://example.com")?;
-            let manager = Arc::new(MokaManager::default());
+            let manager = MokaManager::default();
             let http_res = HttpResponse {
-                body: TEST_BODY.to_vec(),
+                body: Bytes::from_static(TEST_BODY),
                 headers: Default::default(),
+                trailers: None,
                 status: 200,
+                reason: None,
                 url: url.clone(),
                 version: HttpVersion::Http11,
+                metadata: Default::default(),
             };
             let req = http::Request::get("http://example.com").body(())?;
             let res = http::Response::builder()
                 .status(200)
                 .body(TEST_BODY.to_vec())?;
             let policy = CachePolicy::new(&req, &res);
-            manager.put(GET, &url, http_res.clone(), policy.clone()).await?;
-            let data = manager.get(GET, &url).await?;
+            manager
+                .put(
+                    &CacheKey::new(GET, &url),
+                    http_res.clone(),
+                    policy.clone(),
+                )
+                .await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_some());
             assert_eq!(data.unwrap().0.body, TEST_BODY);
-            manager.delete(GET, &url).await?;
-            let data = manager.get(GET, &url).await?;
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_none());
 
-            manager.put(GET, &url, http_res, policy).await?;
-            manager.clear().await?;
-            let data = manager.get(GET, &url).await?;
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            manager.clear(true).await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
             assert!(data.is_none());
             Ok(())
         }
+
+        // Builds a response with a 4 MiB body along with its policy
+        fn large_response(
+            url: &Url,
+        ) -> anyhow::Result<(HttpResponse, CachePolicy)> {
+            let http_res = HttpResponse {
+                body: vec![7; 4 * 1024 * 1024].into(),
+                headers: Default::default(),
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            };
+            let req = http::Request::get(url.as_str()).body(())?;
+            let policy = CachePolicy::new(&req, &http_res.parts()?);
+            Ok((http_res, policy))
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_updates_dont_race_writes() -> anyhow::Result<()> {
+            use futures::executor::block_on;
+            use std::thread;
+
+            let url = Url::parse("http://example.com")?;
+            let key = CacheKey::new(GET, &url);
+            let manager = MokaManager::default();
+            let (res, policy) = super::cacheable_record(&url)?;
+            // Updating the large record takes long enough for the put of
+            // the small one, on another thread, to land meanwhile
+            let large = Bytes::from(vec![0; 4 * 1024 * 1024]);
+            let old = HttpResponse { body: large, ..res.clone() };
+            for _ in 0..20 {
+                manager.put(&key, old.clone(), policy.clone()).await?;
+                // The put is kept, the update either goes first or is
+                // applied on top of it
+                let update = thread::spawn({
+                    let (manager, key, policy) =
+                        (manager.clone(), key.clone(), policy.clone());
+                    move || {
+                        let headers = HttpHeaders::default();
+                        block_on(manager.update(&key, headers, policy))
+                    }
+                });
+                let put = thread::spawn({
+                    let (manager, key, policy) =
+                        (manager.clone(), key.clone(), policy.clone());
+                    let new = res.clone();
+                    move || block_on(manager.put(&key, new, policy))
+                });
+                assert!(update.join().unwrap()?);
+                put.join().unwrap()?;
+                let (stored, _) = manager.get(&key).await?.unwrap();
+                assert_eq!(stored.body, TEST_BODY);
+
+                // A removed record isn't brought back
+                manager.put(&key, old.clone(), policy.clone()).await?;
+                let update = thread::spawn({
+                    let (manager, key, policy) =
+                        (manager.clone(), key.clone(), policy.clone());
+                    move || block_on(manager.update_policy(&key, policy))
+                });
+                let delete = thread::spawn({
+                    let (manager, key) = (manager.clone(), key.clone());
+                    move || block_on(manager.delete(&key))
+                });
+                update.join().unwrap()?;
+                delete.join().unwrap()?;
+                assert!(manager.get(&key).await?.is_none());
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_stores_a_single_copy() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com/large")?;
+            let manager = MokaManager::default();
+            let (http_res, policy) = large_response(&url)?;
+            let size = http_res.body.len();
+            let before = allocated();
+            let res = manager
+                .put(&CacheKey::new(GET, &url), http_res, policy)
+                .await?;
+            // The serialized record is the only copy of the body
+            assert!(allocated() - before < size + size / 2);
+            assert_eq!(res.body.len(), size);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_hits_share_the_stored_body() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com/large")?;
+            let manager = MokaManager::default();
+            let (http_res, policy) = large_response(&url)?;
+            let size = http_res.body.len();
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            let before = allocated();
+            let (first, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            let (second, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            // Both bodies point into the stored record
+            assert!(allocated() - before < size / 4);
+            assert_eq!(first.body.as_ptr(), second.body.as_ptr());
+            assert_eq!(second.body.len(), size);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_writes_the_body_in_chunks() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com/large")?;
+            let manager = CACacheManager {
+                path: "./http-cacache-copies".into(),
+                ..Default::default()
+            };
+            let (http_res, policy) = large_response(&url)?;
+            let size = http_res.body.len();
+            let before = allocated();
+            let res = manager
+                .put(&CacheKey::new(GET, &url), http_res, policy)
+                .await?;
+            // The body is handed to cacache's writer in chunks, so the copy
+            // it makes of each write stays small
+            assert!(allocated() - before < size / 4);
+            assert_eq!(res.body.len(), size);
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_shares_identical_bodies() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-shared-bodies".into(),
+                prune_grace: Duration::ZERO,
+                ..Default::default()
+            };
+            let first = Url::parse("http://cdn-1.example.com/asset")?;
+            let second = Url::parse("http://cdn-2.example.com/asset")?;
+            for url in [&first, &second] {
+                let (http_res, policy) = large_response(url)?;
+                manager.put(&CacheKey::new(GET, url), http_res, policy).await?;
+            }
+            let size = 4 * 1024 * 1024;
+            let stats = manager.body_stats().await?;
+            assert_eq!((stats.records, stats.bodies), (2, 1));
+            assert_eq!(stats.referenced_bytes, 2 * size);
+            assert_eq!(stats.saved_bytes(), size);
+
+            // The body is still referenced by the second record
+            manager.delete(&CacheKey::new(GET, &first)).await?;
+            let report = manager.prune().await?;
+            assert_eq!(report.removed, 1);
+            assert!(report.removed_bytes < size);
+            let (res, _) =
+                manager.get(&CacheKey::new(GET, &second)).await?.unwrap();
+            assert_eq!(res.body.len(), size as usize);
+
+            manager.delete(&CacheKey::new(GET, &second)).await?;
+            let report = manager.prune().await?;
+            assert_eq!(report.removed, 2);
+            assert!(report.removed_bytes > size);
+            assert_eq!(manager.body_stats().await?, BodyStats::default());
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_prune_keeps_bodies_being_stored() -> anyhow::Result<()>
+        {
+            let manager = CACacheManager {
+                path: "./http-cacache-prune-grace".into(),
+                ..Default::default()
+            };
+            let url = Url::parse("http://example.com/asset")?;
+            let (http_res, policy) = large_response(&url)?;
+            // A put writes the body first, a prune running before the record
+            // is written finds it referenced by nothing
+            let sri =
+                cacache::write_hash(&manager.path, &http_res.body).await?;
+            let report = manager.prune().await?;
+            assert_eq!(report.removed, 0);
+            assert!(cacache::read_hash(&manager.path, &sri).await.is_ok());
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            let (res, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body.len(), 4 * 1024 * 1024);
+
+            // Past the grace period the body goes once nothing references it
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+            assert_eq!(manager.prune().await?.removed, 0);
+            let manager =
+                CACacheManager { prune_grace: Duration::ZERO, ..manager };
+            assert_eq!(manager.prune().await?.removed, 2);
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        // A url with a 10 KB query, far longer than a record key may be
+        fn long_url() -> anyhow::Result<Url> {
+            let query = "q".repeat(10 * 1024);
+            Ok(Url::parse(&format!("http://example.com/search?{}", query))?)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_long_keys() -> anyhow::Result<()> {
+            let url = long_url()?;
+            let manager = MokaManager::default();
+            let (http_res, policy) = large_response(&url)?;
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            let (res, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body.len(), 4 * 1024 * 1024);
+            // The record is stored under a digest but listed by its url
+            assert!(manager.cache.iter().all(|(key, _)| key.len() < 128));
+            assert_eq!(manager.keys().await?, [CacheKey::new(GET, &url)]);
+            // The full key stays with the record as it is rewritten
+            let (_, policy) = large_response(&url)?;
+            assert!(
+                manager
+                    .update_policy(&CacheKey::new(GET, &url), policy)
+                    .await?
+            );
+            assert_eq!(manager.keys().await?, [CacheKey::new(GET, &url)]);
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+            assert!(manager.keys().await?.is_empty());
+
+            // And goes along with it when moka evicts it
+            let manager = MokaManager::new(MokaCache::new(1));
+            let (http_res, policy) = large_response(&url)?;
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            let other = Url::parse("http://example.com/other")?;
+            for _ in 0..4 {
+                let (http_res, policy) = large_response(&other)?;
+                manager
+                    .put(&CacheKey::new(GET, &other), http_res, policy)
+                    .await?;
+                manager.get(&CacheKey::new(GET, &other)).await?;
+            }
+            manager.flush().await?;
+            assert_eq!(manager.keys().await?, [CacheKey::new(GET, &other)]);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_long_keys() -> anyhow::Result<()> {
+            let url = long_url()?;
+            let manager = CACacheManager {
+                path: "./http-cacache-long-keys".into(),
+                ..Default::default()
+            };
+            let (http_res, policy) = large_response(&url)?;
+            manager.put(&CacheKey::new(GET, &url), http_res, policy).await?;
+            let (res, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body.len(), 4 * 1024 * 1024);
+            // The record is stored under a digest but listed by its url
+            for entry in cacache::list_sync(&manager.path) {
+                assert!(entry?.key.len() < 128);
+            }
+            assert_eq!(manager.keys().await?, [CacheKey::new(GET, &url)]);
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        #[test]
+        fn cache_keys() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com:80/search?q=rust")?;
+            let key = CacheKey::new("get", &url);
+            assert_eq!(key.method(), GET);
+            assert_eq!(key.as_str(), "GET:http://example.com/search?q=rust");
+            assert_eq!(key.to_string(), key.as_str());
+            assert_eq!(key.hash().len(), 64);
+            assert!(!key.is_digest());
+            assert_eq!(CacheKey::parse(key.as_str()), Some(key));
+
+            let mut url = Url::parse("http://example.com/")?;
+            url.set_fragment(Some("accept-language=en"));
+            let key = CacheKey::new(GET, &url);
+            assert_eq!(key.variant(), Some("accept-language=en"));
+            assert_ne!(
+                key,
+                CacheKey::new(GET, &Url::parse("http://example.com/")?)
+            );
+
+            // Long keys are replaced by their digest but keep their url
+            let key = CacheKey::new(GET, &long_url()?);
+            assert!(key.is_digest());
+            assert_eq!(key.as_str(), format!("GET:sha256-{}", key.hash()));
+            assert_eq!(CacheKey::parse(&key.to_string()), Some(key));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_health_check() -> anyhow::Result<()> {
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            cache.health_check().await?;
+            // The sentinel record is removed once read back
+            assert!(cache.manager.keys().await?.is_empty());
+            let manager: Arc<dyn DynCacheManager> =
+                Arc::new(MokaManager::default());
+            manager.health_check().await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_health_check() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-health".into(),
+                ..Default::default()
+            };
+            manager.health_check().await?;
+            assert!(std::fs::read_dir(&manager.path)?.next().is_none());
+            std::fs::remove_dir_all(&manager.path)?;
+            // The directory can't be created under a file
+            let broken = CACacheManager {
+                path: "./Cargo.toml/http-cacache".into(),
+                ..Default::default()
+            };
+            assert!(broken.health_check().await.is_err());
+            Ok(())
+        }
+
+        // Accepts every record without keeping any
+        #[derive(Debug)]
+        struct Forgetful;
+
+        impl CacheManager for Forgetful {
+            async fn get(
+                &self,
+                _key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                Ok(None)
+            }
+
+            async fn put(
+                &self,
+                _key: &CacheKey,
+                res: HttpResponse,
+                _policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                Ok(res)
+            }
+
+            async fn delete(&self, _key: &CacheKey) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        #[async_std::test]
+        async fn lost_writes_fail_the_health_check() {
+            let cache = HttpCache::new(CacheMode::Default, Forgetful);
+            assert!(matches!(
+                cache.health_check().await,
+                Err(CacheError::Unhealthy(_))
+            ));
+        }
+    }
+
+    #[cfg(test)]
+    mod formats {
+        use crate::*;
+        use http_cache_semantics::CachePolicy;
+        use serde::Serialize;
+        use std::sync::Arc;
+
+        // The record layout written before records were tagged with a format
+        #[derive(Serialize)]
+        struct LegacyStore {
+            response: LegacyResponse,
+            policy: CachePolicy,
+        }
+
+        #[derive(Serialize)]
+        struct LegacyResponse {
+            body: Vec<u8>,
+            headers: HttpHeaders,
+            status: u16,
+            url: Url,
+            version: HttpVersion,
+        }
+
+        impl From<HttpResponse> for LegacyResponse {
+            fn from(res: HttpResponse) -> Self {
+                Self {
+                    body: res.body.to_vec(),
+                    headers: res.headers,
+                    status: res.status,
+                    url: res.url,
+                    version: res.version,
+                }
+            }
+        }
+
+        fn record(url: &Url) -> anyhow::Result<(HttpResponse, CachePolicy)> {
+            let http_res = HttpResponse {
+                body: Bytes::from_static(TEST_BODY),
+                headers: Default::default(),
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            };
+            let req = http::Request::get(url.as_str()).body(())?;
+            let res = http::Response::builder()
+                .status(200)
+                .body(TEST_BODY.to_vec())?;
+            Ok((http_res, CachePolicy::new(&req, &res)))
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn round_trip_each_format() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            for format in [
+                SerializationFormat::Bincode,
+                SerializationFormat::Json,
+                SerializationFormat::Cbor,
+            ] {
+                let mut manager = MokaManager::default();
+                manager.format = format;
+                let (res, policy) = record(&url)?;
+                manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+                let data = manager.get(&CacheKey::new(GET, &url)).await?;
+                assert_eq!(data.unwrap().0.body, TEST_BODY);
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keeps_opaque_header_values() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let latin1 = http::HeaderValue::from_bytes(LATIN1_VALUE)?;
+            let token = http::HeaderValue::from_static(OPAQUE_TOKEN);
+            for format in [
+                SerializationFormat::Bincode,
+                SerializationFormat::Json,
+                SerializationFormat::Cbor,
+            ] {
+                let mut manager = MokaManager::default();
+                manager.format = format;
+                let (mut res, policy) = record(&url)?;
+                res.headers.append("x-latin1", latin1.clone());
+                res.headers.append("x-token", token.clone());
+                manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+                let (res, _) =
+                    manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+                assert_eq!(res.headers.get("x-latin1"), Some(&latin1));
+                assert_eq!(res.headers.get("x-token"), Some(&token));
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keeps_the_version() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            for format in [
+                SerializationFormat::Bincode,
+                SerializationFormat::Json,
+                SerializationFormat::Cbor,
+            ] {
+                let mut manager = MokaManager::default();
+                manager.format = format;
+                let (mut res, policy) = record(&url)?;
+                res.version = HttpVersion::H2;
+                manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+                let (res, _) =
+                    manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+                assert_eq!(res.version, HttpVersion::H2);
+            }
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn reads_records_written_in_other_formats() -> anyhow::Result<()>
+        {
+            let url = Url::parse("http://example.com")?;
+            let path = "./http-cacache-formats";
+            let json = CACacheManager {
+                path: path.into(),
+                format: SerializationFormat::Json,
+                ..Default::default()
+            };
+            let (res, policy) = record(&url)?;
+            json.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let bincode = CACacheManager {
+                path: path.into(),
+                format: SerializationFormat::Bincode,
+                ..Default::default()
+            };
+            let data = bincode.get(&CacheKey::new(GET, &url)).await?;
+            assert_eq!(data.unwrap().0.body, TEST_BODY);
+            bincode.clear(true).await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn reads_untagged_legacy_records() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let manager = CACacheManager {
+                path: "./http-cacache-legacy".into(),
+                ..Default::default()
+            };
+            let (response, policy) = record(&url)?;
+            let response = response.into();
+            let bytes = bincode::serialize(&LegacyStore { response, policy })?;
+            cacache::write(&manager.path, format!("{}:{}", GET, url), bytes)
+                .await?;
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
+            assert_eq!(data.unwrap().0.body, TEST_BODY);
+            // The record is rewritten using the current version
+            let bytes =
+                cacache::read(&manager.path, format!("{}:{}", GET, url))
+                    .await?;
+            assert!(bytes.starts_with(b"httpcac\xff\x06"));
+            let data = manager.get(&CacheKey::new(GET, &url)).await?;
+            assert_eq!(data.unwrap().0.body, TEST_BODY);
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn unknown_formats_are_misses() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let key = format!("{}:{}", GET, url);
+            let manager = MokaManager::default();
+            let mut bytes = b"httpcac\xff".to_vec();
+            bytes.push(CACHE_RECORD_VERSION);
+            bytes.push(0x7f);
+            bytes.extend_from_slice(b"from the future");
+            manager.cache.insert(key.clone(), Bytes::from(bytes)).await;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+            // Another version may understand the format, so leave it be
+            assert!(manager.cache.get(&key).is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn unknown_versions_are_removed() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let key = format!("{}:{}", GET, url);
+            let manager = MokaManager::default();
+            let mut bytes = b"httpcac\xff".to_vec();
+            bytes.push(CACHE_RECORD_VERSION + 1);
+            bytes.push(1);
+            bytes.extend_from_slice(b"from the future");
+            manager.cache.insert(key.clone(), Bytes::from(bytes)).await;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+            assert!(manager.cache.get(&key).is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_1_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v1.bin");
+            let url = Url::parse("http://example.com/fixture")?;
+            let manager = MokaManager::default();
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.status, 200);
+            assert_eq!(res.url, url);
+            assert_eq!(res.version, HttpVersion::Http11);
+            assert_eq!(res.headers.get("content-type").unwrap(), "text/plain");
+            assert!(policy.is_storable());
+            // The record is rewritten using the current version
+            let bytes = manager.cache.get(&format!("{}:{}", GET, url)).unwrap();
+            assert!(bytes.starts_with(b"httpcac\xff\x06"));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_2_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v2.bin");
+            assert!(fixture.ends_with(TEST_BODY));
+            let url = Url::parse("http://example.com/fixture")?;
+            let manager = MokaManager::default();
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.status, 200);
+            assert_eq!(res.reason, None);
+            assert_eq!(res.url, url);
+            assert_eq!(res.headers.get("content-type").unwrap(), "text/plain");
+            assert!(policy.is_storable());
+            // The record is rewritten using the current version
+            let bytes = manager.cache.get(&format!("{}:{}", GET, url)).unwrap();
+            assert!(bytes.starts_with(b"httpcac\xff\x06"));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_3_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v3.bin");
+            assert!(fixture.ends_with(TEST_BODY));
+            let url = Url::parse("http://example.com/fixture")?;
+            let manager = MokaManager::default();
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.status, 200);
+            assert_eq!(res.reason.as_deref(), Some("Fine By Me"));
+            assert_eq!(res.trailers, None);
+            assert_eq!(res.url, url);
+            assert_eq!(res.headers.get("content-type").unwrap(), "text/plain");
+            assert!(policy.is_storable());
+            // The record is rewritten using the current version
+            let bytes = manager.cache.get(&format!("{}:{}", GET, url)).unwrap();
+            assert!(bytes.starts_with(b"httpcac\xff\x06"));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_4_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v4.bin");
+            assert!(fixture.ends_with(TEST_BODY));
+            let url = Url::parse("http://example.com/fixture")?;
+            let manager = MokaManager::default();
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.status, 200);
+            assert_eq!(res.reason.as_deref(), Some("Fine By Me"));
+            assert_eq!(res.trailers, Some(HttpHeaders::from(&test_trailers())));
+            assert_eq!(res.url, url);
+            assert_eq!(res.headers.get("content-type").unwrap(), "text/plain");
+            assert!(policy.is_storable());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_5_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v5.bin");
+            assert!(fixture.ends_with(TEST_BODY));
+            let url = Url::parse("http://example.com/fixture")?;
+            let mut manager = MokaManager::default();
+            manager.checksum = Some(BodyChecksum::Crc32);
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.reason.as_deref(), Some("Fine By Me"));
+            assert_eq!(res.trailers, Some(HttpHeaders::from(&test_trailers())));
+            assert!(res.metadata.is_empty());
+            assert!(policy.is_storable());
+            let bytes = manager.cache.get(&format!("{}:{}", GET, url)).unwrap();
+            assert!(bytes.starts_with(b"httpcac\xff\x06"));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn reads_version_6_fixture() -> anyhow::Result<()> {
+            // Records written by this version must stay readable, do not
+            // regenerate this fixture when the layout changes
+            let fixture = include_bytes!("../fixtures/record-v6.bin");
+            assert!(fixture.ends_with(TEST_BODY));
+            let url = Url::parse("http://example.com/fixture")?;
+            let mut manager = MokaManager::default();
+            manager.checksum = Some(BodyChecksum::Crc32);
+            manager
+                .cache
+                .insert(format!("{}:{}", GET, url), Bytes::from_static(fixture))
+                .await;
+            let (res, policy) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, TEST_BODY);
+            assert_eq!(res.reason.as_deref(), Some("Fine By Me"));
+            assert_eq!(res.trailers, Some(HttpHeaders::from(&test_trailers())));
+            assert_eq!(res.metadata.get("tenant"), Some("acme"));
+            assert_eq!(res.metadata.get("origin-region"), Some("eu-west"));
+            assert!(policy.is_storable());
+            // The record is current, so it is left as it was
+            let bytes = manager.cache.get(&format!("{}:{}", GET, url)).unwrap();
+            assert_eq!(bytes, &fixture[..]);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn checks_version_5_fixture_checksum() -> anyhow::Result<()> {
+            // Version 5 records carry the checksum of their body, which is
+            // still checked when they are upgraded
+            let fixture = include_bytes!("../fixtures/record-v5.bin");
+            let url = Url::parse("http://example.com/fixture")?;
+            let key = format!("{}:{}", GET, url);
+            let mut damaged = fixture.to_vec();
+            *damaged.last_mut().unwrap() ^= 0xff;
+            let manager = MokaManager::default();
+            manager.cache.insert(key.clone(), Bytes::from(damaged)).await;
+            assert!(matches!(
+                manager.get(&CacheKey::new(GET, &url)).await,
+                Err(CacheError::CorruptEntry(_))
+            ));
+            // The damaged record is left for the cache to remove, not
+            // rewritten with a checksum of the damaged body
+            let bytes = manager.cache.get(&key).unwrap();
+            assert!(bytes.starts_with(b"httpcac\xff\x05"));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn stores_binary_bodies_as_is() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let key = format!("{}:{}", GET, url);
+            let mut manager = MokaManager::default();
+            manager.format = SerializationFormat::Json;
+            let (mut res, policy) = record(&url)?;
+            res.body = (0..=255).cycle().take(64 * 1024).collect();
+            let body = res.body.clone();
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let bytes = manager.cache.get(&key).unwrap();
+            assert!(bytes.ends_with(&body));
+            assert!(bytes.len() < body.len() + 1024);
+            let (res, _) =
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap();
+            assert_eq!(res.body, body);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn truncated_records_are_corrupt() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let key = format!("{}:{}", GET, url);
+            let manager = MokaManager::default();
+            let (res, policy) = record(&url)?;
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let bytes = manager.cache.get(&key).unwrap();
+            let truncated = bytes[..20].to_vec();
+            manager.cache.insert(key.clone(), Bytes::from(truncated)).await;
+            assert!(matches!(
+                manager.get(&CacheKey::new(GET, &url)).await,
+                Err(CacheError::CorruptEntry(_))
+            ));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn checksums_catch_damaged_bodies() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let key = format!("{}:{}", GET, url);
+            for checksum in [BodyChecksum::Crc32, BodyChecksum::Sha256] {
+                let mut manager = MokaManager::default();
+                manager.checksum = Some(checksum);
+                let (res, policy) = record(&url)?;
+                manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+                let bytes = manager.cache.get(&key).unwrap();
+                // The body lost its last byte on the way to the backend
+                let damaged = bytes.slice(..bytes.len() - 1);
+                manager.cache.insert(key.clone(), damaged.clone()).await;
+                assert!(matches!(
+                    manager.get(&CacheKey::new(GET, &url)).await,
+                    Err(CacheError::CorruptEntry(_))
+                ));
+
+                // Unless the check is skipped
+                let mut unchecked = manager.clone();
+                unchecked.checksum = None;
+                let (res, _) =
+                    unchecked.get(&CacheKey::new(GET, &url)).await?.unwrap();
+                assert_eq!(res.body, TEST_BODY[..TEST_BODY.len() - 1]);
+
+                // Damaged records are removed rather than refreshed
+                let (_, policy) = record(&url)?;
+                let cache_key = CacheKey::new(GET, &url);
+                assert!(!manager.update_policy(&cache_key, policy).await?);
+                assert!(manager.cache.get(&key).is_none());
+            }
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod archive {
+        use crate::*;
+        use futures::io::Cursor;
+        use http_cache_semantics::CachePolicy;
+        use std::sync::Arc;
+
+        fn record(
+            url: &Url,
+            body: &[u8],
+        ) -> anyhow::Result<(HttpResponse, CachePolicy)> {
+            let http_res = HttpResponse {
+                body: body.to_vec().into(),
+                headers: Default::default(),
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            };
+            let req = http::Request::get(url.as_str()).body(())?;
+            let res = http::Response::builder()
+                .status(200)
+                .header(CACHE_CONTROL.as_str(), CACHEABLE_PUBLIC)
+                .body(body.to_vec())?;
+            Ok((http_res, CachePolicy::new(&req, &res)))
+        }
+
+        #[cfg(all(feature = "manager-moka", feature = "manager-cacache"))]
+        #[async_std::test]
+        async fn round_trip_between_managers() -> anyhow::Result<()> {
+            let binary: Vec<u8> = (0..=255).collect();
+            let url_text = Url::parse("http://example.com/text")?;
+            let url_binary = Url::parse("http://example.com/binary")?;
+            let moka = MokaManager::default();
+            let (res, policy) = record(&url_text, TEST_BODY)?;
+            moka.put(&CacheKey::new(GET, &url_text), res, policy).await?;
+            let (res, policy) = record(&url_binary, &binary)?;
+            moka.put(&CacheKey::new(GET, &url_binary), res, policy).await?;
+
+            // Moka -> cacache
+            let mut archive = Cursor::new(Vec::new());
+            let report = export(&moka, &mut archive).await?;
+            assert_eq!(report.records, 2);
+            assert_eq!(report.bytes, archive.get_ref().len() as u64);
+            let cacache = CACacheManager {
+                path: "./http-cacache-archive".into(),
+                ..Default::default()
+            };
+            cacache.clear(true).await.ok();
+            archive.set_position(0);
+            let report =
+                import(&cacache, &mut archive, ConflictPolicy::Overwrite)
+                    .await?;
+            assert_eq!(report.imported, 2);
+            assert_eq!(report.rejected, 0);
+            let data =
+                cacache.get(&CacheKey::new(GET, &url_binary)).await?.unwrap();
+            assert_eq!(data.0.body, binary);
+            let data =
+                cacache.get(&CacheKey::new(GET, &url_text)).await?.unwrap();
+            assert_eq!(data.0.body, TEST_BODY);
+
+            // cacache -> Moka
+            let mut archive = Cursor::new(Vec::new());
+            let report = export(&cacache, &mut archive).await?;
+            assert_eq!(report.records, 2);
+            let moka = MokaManager::default();
+            archive.set_position(0);
+            import(&moka, &mut archive, ConflictPolicy::Overwrite).await?;
+            let data =
+                moka.get(&CacheKey::new(GET, &url_binary)).await?.unwrap();
+            assert_eq!(data.0.body, binary);
+            let data = moka.get(&CacheKey::new(GET, &url_text)).await?.unwrap();
+            assert_eq!(data.0.body, TEST_BODY);
+            cacache.clear(true).await?;
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn rejects_corrupt_records() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let source = MokaManager::default();
+            let (res, policy) = record(&url, TEST_BODY)?;
+            source.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let mut archive = Cursor::new(Vec::new());
+            export(&source, &mut archive).await?;
+            let mut bytes = archive.into_inner();
+            let last = bytes.len() - 1;
+            bytes[last] ^= 0xff;
+
+            let manager = MokaManager::default();
+            let report = import(
+                &manager,
+                Cursor::new(bytes.clone()),
+                ConflictPolicy::Overwrite,
+            )
+            .await?;
+            assert_eq!(report.imported, 0);
+            assert_eq!(report.rejected, 1);
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+
+            // Truncated archives stop at the last complete record
+            bytes.truncate(last - 4);
+            let report =
+                import(&manager, Cursor::new(bytes), ConflictPolicy::Overwrite)
+                    .await?;
+            assert_eq!(report.rejected, 1);
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+
+            // Anything that isn't an archive is refused outright
+            let res = import(
+                &manager,
+                Cursor::new(b"not an archive".to_vec()),
+                ConflictPolicy::Overwrite,
+            )
+            .await;
+            assert!(matches!(res, Err(CacheError::BadArchive(_))));
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn conflict_policies() -> anyhow::Result<()> {
+            let url = Url::parse("http://example.com")?;
+            let source = MokaManager::default();
+            let (res, policy) = record(&url, b"incoming")?;
+            source.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let mut archive = Cursor::new(Vec::new());
+            export(&source, &mut archive).await?;
+            let bytes = archive.into_inner();
+
+            let manager = MokaManager::default();
+            let (res, policy) = record(&url, b"existing")?;
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            let report = import(
+                &manager,
+                Cursor::new(bytes.clone()),
+                ConflictPolicy::SkipExisting,
+            )
+            .await?;
+            assert_eq!(report.skipped, 1);
+            assert_eq!(
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap().0.body,
+                &b"existing"[..]
+            );
+
+            // Both records have the same lifetime, the newer one is slightly
+            // older by the time it is compared so the existing one is kept
+            let report = import(
+                &manager,
+                Cursor::new(bytes.clone()),
+                ConflictPolicy::KeepFreshest,
+            )
+            .await?;
+            assert_eq!(report.skipped, 1);
+
+            let report =
+                import(&manager, Cursor::new(bytes), ConflictPolicy::Overwrite)
+                    .await?;
+            assert_eq!(report.imported, 1);
+            assert_eq!(
+                manager.get(&CacheKey::new(GET, &url)).await?.unwrap().0.body,
+                &b"incoming"[..]
+            );
+            Ok(())
+        }
+    }
+
+    // A stored response the cache policy considers fresh
+    fn cacheable_record(
+        url: &Url,
+    ) -> anyhow::Result<(HttpResponse, http_cache_semantics::CachePolicy)> {
+        let http_res = HttpResponse {
+            body: Bytes::from_static(TEST_BODY),
+            headers: HttpHeaders::default(),
+            trailers: None,
+            status: 200,
+            reason: None,
+            url: url.clone(),
+            version: HttpVersion::Http11,
+            metadata: Default::default(),
+        };
+        let req = http::Request::get(url.as_str()).body(())?;
+        let res = http::Response::builder()
+            .status(200)
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(TEST_BODY.to_vec())?;
+        Ok((http_res, http_cache_semantics::CachePolicy::new(&req, &res)))
+    }
+
+    #[cfg(feature = "manager-moka")]
+    mod har {
+        use crate::*;
+        use futures::io::Cursor;
+        use http_cache_semantics::CachePolicy;
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        // A recording holding an entry for each case the import handles
+        const RECORDING: &str = r#"{
+          "log": {
+            "version": "1.2",
+            "creator": { "name": "test", "version": "1.0" },
+            "entries": [
+              {
+                "startedDateTime": "2023-11-15T00:13:20.000+02:00",
+                "request": { "method": "GET", "url": "http://example.com/app.json", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/2.0",
+                  "headers": [
+                    { "name": ":status", "value": "200" },
+                    { "name": "cache-control", "value": "max-age=3600" },
+                    { "name": "content-encoding", "value": "gzip" },
+                    { "name": "content-length", "value": "9" }
+                  ],
+                  "content": { "size": 12, "mimeType": "application/json", "text": "{\"ok\":true}" }
+                }
+              },
+              {
+                "request": { "method": "GET", "url": "http://example.com/logo.png", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/1.1",
+                  "headers": [{ "name": "cache-control", "value": "max-age=3600" }],
+                  "content": { "size": 4, "mimeType": "image/png", "text": "iVBORw==", "encoding": "base64" }
+                }
+              },
+              {
+                "request": { "method": "POST", "url": "http://example.com/submit", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/1.1",
+                  "headers": [{ "name": "cache-control", "value": "max-age=3600" }],
+                  "content": { "size": 2, "text": "ok" }
+                }
+              },
+              {
+                "request": { "method": "GET", "url": "http://example.com/video.mp4", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/1.1",
+                  "headers": [{ "name": "cache-control", "value": "max-age=3600" }],
+                  "content": { "size": 1048576, "mimeType": "video/mp4" }
+                }
+              },
+              {
+                "request": { "method": "GET", "url": "http://example.com/session", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/1.1",
+                  "headers": [{ "name": "cache-control", "value": "no-store" }],
+                  "content": { "size": 5, "text": "hello" }
+                }
+              },
+              {
+                "request": { "method": "GET", "url": "http://example.com/revalidated", "headers": [] },
+                "response": {
+                  "status": 304, "statusText": "Not Modified", "httpVersion": "HTTP/1.1",
+                  "headers": [], "content": { "size": 0 }
+                }
+              },
+              {
+                "request": { "method": "GET", "url": "http://example.com/broken", "headers": [] },
+                "response": {
+                  "status": 200, "statusText": "OK", "httpVersion": "HTTP/1.1",
+                  "headers": [{ "name": "cache-control", "value": "max-age=3600" }],
+                  "content": { "size": 3, "text": "!!!", "encoding": "base64" }
+                }
+              }
+            ]
+          }
+        }"#;
+
+        fn url(path: &str) -> anyhow::Result<Url> {
+            Ok(Url::parse("http://example.com")?.join(path)?)
+        }
+
+        #[async_std::test]
+        async fn imports_cacheable_entries() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let report = import_har(
+                &manager,
+                Cursor::new(RECORDING),
+                HarImportOptions::default(),
+            )
+            .await?;
+            assert_eq!(
+                report,
+                ImportReport { imported: 2, skipped: 4, rejected: 1 }
+            );
+
+            let (res, policy) = manager
+                .get(&CacheKey::new(GET, &url("/app.json")?))
+                .await?
+                .unwrap();
+            assert_eq!(res.body, r#"{"ok":true}"#);
+            assert_eq!(res.version, HttpVersion::H2);
+            assert_eq!(res.reason.as_deref(), Some("OK"));
+            // The recorded body was already decoded
+            assert!(res.headers.get("content-encoding").is_none());
+            assert!(res.headers.get(":status").is_none());
+            assert_eq!(res.headers.get_str("content-length"), Some("11"));
+            assert!(!policy.is_stale(now()));
+
+            let (res, _) = manager
+                .get(&CacheKey::new(GET, &url("/logo.png")?))
+                .await?
+                .unwrap();
+            assert_eq!(res.body, [0x89, b'P', b'N', b'G'].as_slice());
+            for path in ["/submit", "/video.mp4", "/session", "/broken"] {
+                assert!(manager
+                    .get(&CacheKey::new(GET, &url(path)?))
+                    .await?
+                    .is_none());
+            }
+
+            // Aged from when it was recorded, long past its max-age
+            let options =
+                HarImportOptions { recorded_times: true, ..Default::default() };
+            import_har(&manager, Cursor::new(RECORDING), options).await?;
+            let (_, policy) = manager
+                .get(&CacheKey::new(GET, &url("/app.json")?))
+                .await?
+                .unwrap();
+            let recorded_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            let age = policy.age(now());
+            let expected = now().duration_since(recorded_at)?;
+            assert!(age <= expected && expected - age < Duration::from_secs(5));
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn fallback_ttl() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let options = HarImportOptions {
+                fallback_ttl: Some(Duration::from_secs(60)),
+                ..Default::default()
+            };
+            let report =
+                import_har(&manager, Cursor::new(RECORDING), options).await?;
+            assert_eq!(report.imported, 3);
+            let (res, policy) = manager
+                .get(&CacheKey::new(GET, &url("/session")?))
+                .await?
+                .unwrap();
+            assert_eq!(res.body, "hello");
+            // The recorded headers are kept as they are
+            assert_eq!(res.headers.get_str("cache-control"), Some("no-store"));
+            let ttl = policy.time_to_live(now());
+            assert!(ttl > Duration::ZERO && ttl <= Duration::from_secs(60));
+            Ok(())
+        }
+
+        // Stores a response holding `body` and `headers` at `url`, with a
+        // policy created at `stored_at`
+        async fn store(
+            manager: &MokaManager,
+            url: &Url,
+            body: &[u8],
+            headers: &[(&str, &str)],
+            stored_at: SystemTime,
+        ) -> anyhow::Result<()> {
+            let res = HttpResponse {
+                body: body.to_vec().into(),
+                headers: headers
+                    .iter()
+                    .map(|(name, value)| {
+                        (*name, http::HeaderValue::from_str(value).unwrap())
+                    })
+                    .collect(),
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: url.clone(),
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            };
+            let req = http::Request::get(url.as_str()).body(())?;
+            let policy = CachePolicy::new_options(
+                &req,
+                &res.parts()?,
+                stored_at,
+                Default::default(),
+            );
+            manager.put(&CacheKey::new(GET, url), res, policy).await?;
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn export_round_trip() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let cache_control = ("cache-control", "max-age=3600");
+            let text = url("/app.json")?;
+            let binary = url("/logo.png")?;
+            let stale = url("/old")?;
+            store(&manager, &text, b"{}", &[cache_control], now()).await?;
+            store(&manager, &binary, &[0x89, 0xff], &[cache_control], now())
+                .await?;
+            // Stored two hours ago, past its max-age
+            let stored_at = now() - Duration::from_secs(2 * 3600);
+            store(&manager, &stale, TEST_BODY, &[cache_control], stored_at)
+                .await?;
+
+            let mut har = Vec::new();
+            let report =
+                export_har(&manager, &mut har, HarExportOptions::default())
+                    .await?;
+            assert_eq!(report.records, 3);
+            assert_eq!(report.bytes, har.len() as u64);
+
+            let imported = MokaManager::default();
+            let options =
+                HarImportOptions { recorded_times: true, ..Default::default() };
+            let report =
+                import_har(&imported, Cursor::new(&har), options).await?;
+            assert_eq!(report.imported, 3);
+            for url in [&text, &binary, &stale] {
+                let (original, policy) =
+                    manager.get(&CacheKey::new(GET, url)).await?.unwrap();
+                let (res, imported_policy) =
+                    imported.get(&CacheKey::new(GET, url)).await?.unwrap();
+                assert_eq!(res.body, original.body);
+                assert_eq!(res.status, original.status);
+                assert_eq!(res.headers, original.headers);
+                assert_eq!(
+                    imported_policy.is_stale(now()),
+                    policy.is_stale(now()),
+                    "{}",
+                    url
+                );
+            }
+            assert!(imported
+                .get(&CacheKey::new(GET, &stale))
+                .await?
+                .unwrap()
+                .1
+                .is_stale(now()));
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn export_format() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let stored_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+            let headers = [
+                ("cache-control", "max-age=3600"),
+                ("set-cookie", "session=secret"),
+                ("content-type", "image/png"),
+            ];
+            store(
+                &manager,
+                &url("/logo.png")?,
+                &[0x89, 0xff],
+                &headers,
+                stored_at,
+            )
+            .await?;
+            store(&manager, &url("/other")?, b"", &headers, stored_at).await?;
+
+            let mut har = Vec::new();
+            let options = HarExportOptions {
+                filter: Some(UrlFilter::new().allow("example.com/*.png")),
+                ..Default::default()
+            };
+            export_har(&manager, &mut har, options).await?;
+            let har: serde_json::Value = serde_json::from_slice(&har)?;
+            assert_eq!(har["log"]["version"], "1.2");
+            let entries = har["log"]["entries"].as_array().unwrap();
+            assert_eq!(entries.len(), 1);
+            let entry = &entries[0];
+            assert_eq!(entry["startedDateTime"], "2023-11-14T22:13:20.000Z");
+            assert_eq!(entry["request"]["url"], "http://example.com/logo.png");
+            let response = &entry["response"];
+            assert_eq!(response["status"], 200);
+            assert_eq!(response["statusText"], "OK");
+            assert_eq!(response["headers"][1]["value"], "[redacted]");
+            assert_eq!(response["content"]["mimeType"], "image/png");
+            assert_eq!(response["content"]["text"], "if8=");
+            assert_eq!(response["content"]["encoding"], "base64");
+
+            // Redaction can be turned off
+            let mut har = Vec::new();
+            let options =
+                HarExportOptions { redact: false, ..Default::default() };
+            export_har(&manager, &mut har, options).await?;
+            let har: serde_json::Value = serde_json::from_slice(&har)?;
+            let response = &har["log"]["entries"][0]["response"];
+            assert_eq!(response["headers"][1]["value"], "session=secret");
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn rejects_invalid_files() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let res = import_har(
+                &manager,
+                Cursor::new("not a HAR file"),
+                HarImportOptions::default(),
+            )
+            .await;
+            assert!(matches!(res, Err(CacheError::BadArchive(_))));
+            Ok(())
+        }
+    }
+
+    mod report {
+        use crate::*;
+
+        async fn reports_records(
+            manager: impl EnumerableCacheManager,
+        ) -> anyhow::Result<CacheReport> {
+            let fresh = Url::parse("http://example.com/fresh")?;
+            let (mut res, policy) = super::cacheable_record(&fresh)?;
+            res.headers
+                .insert("etag", http::HeaderValue::from_static("\"v1\""));
+            manager
+                .put_tagged(
+                    &CacheKey::new(GET, &fresh),
+                    res,
+                    policy,
+                    &["docs".into()],
+                )
+                .await?;
+
+            let stale = Url::parse("http://example.com/stale")?;
+            let req = http::Request::get(stale.as_str()).body(())?;
+            let res = http::Response::builder()
+                .status(200)
+                .header(CACHE_CONTROL, "public, max-age=0")
+                .body(())?;
+            let policy = CachePolicy::new(&req, &res);
+            let res = HttpResponse {
+                body: Bytes::from_static(b"stale body"),
+                headers: HttpHeaders::default(),
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: stale.clone(),
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            };
+            manager.put(&CacheKey::new(GET, &stale), res, policy).await?;
+
+            let report = inspect(&manager).await?;
+            let entry = |url: &Url| {
+                report.entries.iter().find(|entry| &entry.url == url).unwrap()
+            };
+            let fresh = entry(&fresh);
+            assert_eq!(fresh.method, GET);
+            assert_eq!(fresh.status, 200);
+            assert!(!fresh.is_stale);
+            assert!(fresh.expires_at > fresh.stored_at);
+            assert_eq!(fresh.body_size, TEST_BODY.len());
+            assert!(fresh.has_etag);
+            assert!(!fresh.has_last_modified);
+            assert_eq!(fresh.tags, vec!["docs"]);
+            let stale = entry(&stale);
+            assert!(stale.is_stale);
+            assert!(stale.tags.is_empty());
+            assert_eq!(report.totals.stale, 1);
+            assert_eq!(
+                report.totals.body_bytes,
+                TEST_BODY.len() + b"stale body".len()
+            );
+
+            // The report can be dumped as is
+            let json = serde_json::to_value(&report)?;
+            assert_eq!(json["entries"].as_array().unwrap().len(), 2);
+            assert_eq!(json["totals"]["entries"], 2);
+            Ok(report)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_report() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let garbage = Url::parse("http://example.com/garbage")?;
+            manager
+                .cache
+                .insert(
+                    format!("{}:{}", GET, garbage),
+                    Bytes::from_static(b"garbage"),
+                )
+                .await;
+            let report = reports_records(manager).await?;
+            assert_eq!(report.totals.entries, 2);
+            assert_eq!(report.totals.unreadable, 1);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_report() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-report".into(),
+                ..Default::default()
+            };
+            let report = reports_records(manager.clone()).await?;
+            assert_eq!(report.totals.unreadable, 0);
+            manager.clear(true).await?;
+            Ok(())
+        }
+    }
+
+    mod batched {
+        use crate::*;
+
+        // Looks up a fresh and a stale record along with a missing one and
+        // `garbage`, which the caller filled with an unreadable record
+        async fn looks_up_many(
+            manager: impl CacheManager,
+            garbage: &Url,
+        ) -> anyhow::Result<()> {
+            let fresh = Url::parse("http://example.com/fresh")?;
+            let (res, policy) = super::cacheable_record(&fresh)?;
+            manager.put(&CacheKey::new(GET, &fresh), res, policy).await?;
+            let stale = Url::parse("http://example.com/stale")?;
+            let (res, _) = super::cacheable_record(&stale)?;
+            let req = http::Request::get(stale.as_str()).body(())?;
+            let policy = CachePolicy::new(
+                &req,
+                &http::Response::builder()
+                    .header(CACHE_CONTROL, "public, max-age=0")
+                    .body(())?,
+            );
+            manager.put(&CacheKey::new(GET, &stale), res, policy).await?;
+            let missing = Url::parse("http://example.com/missing")?;
+            let urls = [fresh, garbage.clone(), missing, stale];
+
+            // Results come back in order, with errors kept to their record
+            let keys = urls
+                .iter()
+                .map(|url| CacheKey::new(GET, url))
+                .collect::<Vec<_>>();
+            let records = manager.get_many(&keys).await;
+            assert_eq!(records.len(), 4);
+            let url = |i: usize| {
+                records[i].as_ref().unwrap().as_ref().map(|(res, _)| &res.url)
+            };
+            assert_eq!(url(0), Some(&urls[0]));
+            assert!(matches!(records[1], Err(CacheError::CorruptEntry(_))));
+            assert_eq!(url(2), None);
+            assert_eq!(url(3), Some(&urls[3]));
+
+            // Unreadable records are removed and treated as missing
+            let cache = HttpCache::new(CacheMode::Default, manager);
+            let fresh = cache
+                .lookup_many("get", &urls)
+                .await
+                .into_iter()
+                .map(|res| Ok(res?.map(|found| found.is_fresh_now())))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(fresh, [Some(true), None, None, Some(false)]);
+            assert!(cache
+                .manager()
+                .get(&CacheKey::new(GET, garbage))
+                .await?
+                .is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_get_many() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let garbage = Url::parse("http://example.com/garbage")?;
+            manager
+                .cache
+                .insert(
+                    format!("{}:{}", GET, garbage),
+                    Bytes::from_static(b"garbage"),
+                )
+                .await;
+            looks_up_many(manager, &garbage).await
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_get_many() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-get-many".into(),
+                ..Default::default()
+            };
+            let garbage = Url::parse("http://example.com/garbage")?;
+            cacache::write(
+                &manager.path,
+                format!("{}:{}", GET, garbage),
+                b"garbage",
+            )
+            .await?;
+            looks_up_many(manager.clone(), &garbage).await?;
+            manager.clear(true).await?;
+            Ok(())
+        }
+    }
+
+    mod tags {
+        use crate::*;
+
+        async fn purges_tagged_records(
+            manager: impl CacheManager,
+        ) -> anyhow::Result<()> {
+            let a = Url::parse("http://example.com/a")?;
+            let b = Url::parse("http://example.com/b")?;
+            let c = Url::parse("http://example.com/c")?;
+            let tags = |t: &[&str]| -> Vec<String> {
+                t.iter().map(|t| t.to_string()).collect()
+            };
+            let (res, policy) = super::cacheable_record(&a)?;
+            manager
+                .put_tagged(
+                    &CacheKey::new(GET, &a),
+                    res,
+                    policy,
+                    &tags(&["product-42"]),
+                )
+                .await?;
+            let (res, policy) = super::cacheable_record(&b)?;
+            manager
+                .put_tagged(
+                    &CacheKey::new(GET, &b),
+                    res,
+                    policy,
+                    &tags(&["product-42", "listing"]),
+                )
+                .await?;
+            let (res, policy) = super::cacheable_record(&c)?;
+            manager.put(&CacheKey::new(GET, &c), res, policy).await?;
+
+            // Overwriting with a different tag set drops the old tags
+            let (res, policy) = super::cacheable_record(&a)?;
+            manager
+                .put_tagged(
+                    &CacheKey::new(GET, &a),
+                    res,
+                    policy,
+                    &tags(&["product-7"]),
+                )
+                .await?;
+
+            assert_eq!(manager.purge_tag("product-42").await?, 1);
+            assert!(manager.get(&CacheKey::new(GET, &a)).await?.is_some());
+            assert!(manager.get(&CacheKey::new(GET, &b)).await?.is_none());
+            assert!(manager.get(&CacheKey::new(GET, &c)).await?.is_some());
+
+            // Purged records are dropped from their other tags too
+            assert_eq!(manager.purge_tag("listing").await?, 0);
+            assert_eq!(manager.purge_tag("product-7").await?, 1);
+            assert!(manager.get(&CacheKey::new(GET, &a)).await?.is_none());
+            assert!(manager.get(&CacheKey::new(GET, &c)).await?.is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_tag_index() -> anyhow::Result<()> {
+            purges_tagged_records(MokaManager::default()).await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_forgets_tags_of_evicted_records() -> anyhow::Result<()> {
+            let manager = MokaManager::new(MokaCache::new(4));
+            let mut keys = Vec::new();
+            for i in 0..100 {
+                let url = Url::parse(&format!("http://example.com/{}", i))?;
+                let (res, policy) = super::cacheable_record(&url)?;
+                let key = CacheKey::new(GET, &url);
+                let tags = ["listing".to_string()];
+                manager.put_tagged(&key, res, policy, &tags).await?;
+                keys.push(key);
+            }
+            manager.flush().await?;
+            let mut live = 0;
+            for key in &keys {
+                let cached = manager.get(key).await?.is_some();
+                assert_eq!(manager.tags(key).await?.is_empty(), !cached);
+                live += usize::from(cached);
+            }
+            assert!(live <= 4);
+            assert_eq!(manager.purge_tag("listing").await?, live);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_tag_index() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-tags".into(),
+                ..Default::default()
+            };
+            manager.clear(true).await.ok();
+            purges_tagged_records(manager.clone()).await?;
+            manager.clear(true).await?;
+            Ok(())
+        }
+    }
+
+    mod entry_metadata {
+        use crate::*;
+        use std::collections::HashMap;
+
+        fn metadata(pairs: &[(&str, &str)]) -> anyhow::Result<EntryMetadata> {
+            let mut metadata = EntryMetadata::new();
+            for (key, value) in pairs {
+                metadata.insert(*key, *value)?;
+            }
+            Ok(metadata)
+        }
+
+        #[test]
+        fn limits_the_size() -> anyhow::Result<()> {
+            let mut metadata = metadata(&[("tenant", "acme")])?;
+            assert_eq!(metadata.size(), 10);
+            let large = "x".repeat(EntryMetadata::MAX_SIZE);
+            assert!(matches!(
+                metadata.insert("build", large.clone()),
+                Err(CacheError::MetadataTooLarge(_))
+            ));
+            assert_eq!(metadata.len(), 1);
+            // Replacing a value only counts the new one
+            assert_eq!(
+                metadata.insert("tenant", "initech")?.as_deref(),
+                Some("acme")
+            );
+            assert_eq!(metadata.get("tenant"), Some("initech"));
+            let map = HashMap::from([("build".to_string(), large)]);
+            assert!(EntryMetadata::try_from(map).is_err());
+            Ok(())
+        }
+
+        // Stores a record for each tenant, refreshes one of them and purges
+        // the records of a tenant
+        async fn keeps_and_purges_by_metadata<T>(
+            manager: T,
+        ) -> anyhow::Result<()>
+        where
+            T: EnumerableCacheManager + Clone,
+        {
+            let a = Url::parse("http://example.com/a")?;
+            let b = Url::parse("http://example.com/b")?;
+            let c = Url::parse("http://example.com/c")?;
+            for (url, tenant) in [(&a, "acme"), (&b, "initech"), (&c, "acme")] {
+                let (mut res, policy) = super::cacheable_record(url)?;
+                res.metadata = metadata(&[("tenant", tenant)])?;
+                manager.put(&CacheKey::new(GET, url), res, policy).await?;
+            }
+            let key = CacheKey::new(GET, &a);
+            let (res, policy) = manager.get(&key).await?.unwrap();
+            assert_eq!(res.metadata.get("tenant"), Some("acme"));
+            let (_, _, stored) = manager.get_metadata(&key).await?.unwrap();
+            assert_eq!(stored, res.metadata);
+
+            // Refreshing the headers keeps the metadata
+            let mut headers = res.headers.clone();
+            headers.insert("x-revision", http::HeaderValue::from_static("2"));
+            assert!(manager.update(&key, headers, policy).await?);
+            let (res, _) = manager.get(&key).await?.unwrap();
+            assert_eq!(res.headers.get("x-revision").unwrap(), "2");
+            assert_eq!(res.metadata.get("tenant"), Some("acme"));
+
+            let cache = HttpCache::new(CacheMode::Default, manager.clone());
+            let inspection = cache.inspect(GET, &b).await?.unwrap();
+            assert_eq!(inspection.metadata().get("tenant"), Some("initech"));
+            let purged = cache
+                .purge_where(|metadata| metadata.get("tenant") == Some("acme"))
+                .await?;
+            assert_eq!(purged, 2);
+            assert!(manager.get(&CacheKey::new(GET, &a)).await?.is_none());
+            assert!(manager.get(&CacheKey::new(GET, &b)).await?.is_some());
+            assert!(manager.get(&CacheKey::new(GET, &c)).await?.is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn moka_keeps_metadata() -> anyhow::Result<()> {
+            keeps_and_purges_by_metadata(MokaManager::default()).await
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_keeps_metadata() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-metadata".into(),
+                ..Default::default()
+            };
+            manager.clear(true).await.ok();
+            keeps_and_purges_by_metadata(manager.clone()).await?;
+            manager.clear(true).await?;
+            Ok(())
+        }
+    }
+
+    mod pinning {
+        use crate::*;
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn pinned_records_survive_eviction() -> anyhow::Result<()> {
+            let manager = MokaManager::new(MokaCache::new(2));
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions::default(),
+            };
+            let pinned = Url::parse("http://example.com/config")?;
+            cache.pin(&pinned).await?;
+            let (res, policy) = super::cacheable_record(&pinned)?;
+            manager.put(&CacheKey::new(GET, &pinned), res, policy).await?;
+
+            let mut urls = Vec::new();
+            for i in 0..20 {
+                let url = Url::parse(&format!("http://example.com/{}", i))?;
+                let (res, policy) = super::cacheable_record(&url)?;
+                manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+                urls.push(url);
+            }
+            let mut cached = 0;
+            for url in &urls {
+                if manager.get(&CacheKey::new(GET, url)).await?.is_some() {
+                    cached += 1;
+                }
+            }
+            assert!(cached <= 2);
+            assert!(manager.get(&CacheKey::new(GET, &pinned)).await?.is_some());
+
+            // Pins are kept unless clear is told to remove them
+            manager.clear(false).await?;
+            assert!(manager.get(&CacheKey::new(GET, &pinned)).await?.is_some());
+            manager.clear(true).await?;
+            assert!(manager.get(&CacheKey::new(GET, &pinned)).await?.is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn unpinned_records_can_be_evicted() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let url = Url::parse("http://example.com/config")?;
+            manager.pin(&CacheKey::new(GET, &url)).await?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            manager.unpin(&CacheKey::new(GET, &url)).await?;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+            manager.clear(false).await?;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn unpinning_keeps_the_other_pins() -> anyhow::Result<()> {
+            let manager = MokaManager::default();
+            let kept = Url::parse("http://example.com/config")?;
+            let dropped = Url::parse("http://example.com/flags")?;
+            for url in [&kept, &dropped] {
+                manager.pin(&CacheKey::new(GET, url)).await?;
+                let (res, policy) = super::cacheable_record(url)?;
+                manager.put(&CacheKey::new(GET, url), res, policy).await?;
+            }
+            manager.unpin(&CacheKey::new(GET, &dropped)).await?;
+            manager.clear(false).await?;
+            assert!(manager.get(&CacheKey::new(GET, &kept)).await?.is_some());
+            assert!(manager
+                .get(&CacheKey::new(GET, &dropped))
+                .await?
+                .is_none());
+
+            // Pinning again after the last pin was removed
+            manager.unpin(&CacheKey::new(GET, &kept)).await?;
+            manager.pin(&CacheKey::new(GET, &dropped)).await?;
+            let (res, policy) = super::cacheable_record(&dropped)?;
+            manager.put(&CacheKey::new(GET, &dropped), res, policy).await?;
+            manager.clear(false).await?;
+            assert!(manager
+                .get(&CacheKey::new(GET, &dropped))
+                .await?
+                .is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn cacache_pins_survive_clear() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-pins".into(),
+                ..Default::default()
+            };
+            manager.clear(true).await.ok();
+            let pinned = Url::parse("http://example.com/config")?;
+            let other = Url::parse("http://example.com/other")?;
+            manager.pin(&CacheKey::new(GET, &pinned)).await?;
+            let (res, policy) = super::cacheable_record(&pinned)?;
+            manager
+                .put_tagged(
+                    &CacheKey::new(GET, &pinned),
+                    res,
+                    policy,
+                    &["config".into()],
+                )
+                .await?;
+            let (res, policy) = super::cacheable_record(&other)?;
+            manager.put(&CacheKey::new(GET, &other), res, policy).await?;
+
+            manager.clear(false).await?;
+            assert!(manager.get(&CacheKey::new(GET, &pinned)).await?.is_some());
+            assert!(manager.get(&CacheKey::new(GET, &other)).await?.is_none());
+            assert_eq!(manager.keys().await?.len(), 1);
+            // The tags of the kept record are preserved too
+            assert_eq!(manager.purge_tag("config").await?, 1);
+            manager.clear(true).await?;
+            Ok(())
+        }
+    }
+
+    mod spawner {
+        use crate::*;
+        use std::{sync::Mutex, time::Duration};
+
+        // A spawner that queues every task until the test runs them
+        pub(super) fn queued() -> (Spawner, Arc<Mutex<Vec<BackgroundTask>>>) {
+            let tasks = Arc::new(Mutex::new(Vec::new()));
+            let queue = tasks.clone();
+            let spawner =
+                Spawner::new(move |task| queue.lock().unwrap().push(task));
+            (spawner, tasks)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn runs_work_in_the_background() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let manager = MokaManager::default();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions { spawner, ..Default::default() },
+            };
+            let url = Url::parse("http://example.com/background")?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            let key = url.clone();
+            cache
+                .spawn(move |manager| {
+                    Box::pin(async move {
+                        manager
+                            .put(&CacheKey::new(GET, &key), res, policy)
+                            .await
+                            .ok();
+                    })
+                })
+                .await;
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_none());
+
+            let queued: Vec<_> = tasks.lock().unwrap().drain(..).collect();
+            assert_eq!(queued.len(), 1);
+            for task in queued {
+                task.await;
+            }
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn runs_work_inline_without_a_handle() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let manager = CountingManager::default();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions { spawner, ..Default::default() },
+            };
+            let url = Url::parse("http://example.com/inline")?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            let key = url.clone();
+            cache
+                .spawn(move |manager| {
+                    Box::pin(async move {
+                        manager
+                            .put(&CacheKey::new(GET, &key), res, policy)
+                            .await
+                            .ok();
+                    })
+                })
+                .await;
+            assert!(tasks.lock().unwrap().is_empty());
+            assert_eq!(manager.calls(), 1);
+            assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn shutdown_waits_for_background_writes() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-shutdown".into(),
+                ..Default::default()
+            };
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: manager.clone(),
+                options: HttpCacheOptions {
+                    spawner: Spawner::new(|task| {
+                        async_std::task::spawn(task);
+                    }),
+                    ..Default::default()
+                },
+            };
+            let url = Url::parse("http://example.com/shutdown")?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            let key = url.clone();
+            cache
+                .spawn(move |manager| {
+                    Box::pin(async move {
+                        async_std::task::sleep(Duration::from_millis(100))
+                            .await;
+                        manager
+                            .put(&CacheKey::new(GET, &key), res, policy)
+                            .await
+                            .ok();
+                    })
+                })
+                .await;
+            assert_eq!(cache.shutdown(Duration::from_secs(5)).await?, 0);
+            drop(cache);
+            // Read back by a manager of its own over the same directory
+            let reopened = CACacheManager {
+                path: manager.path.clone(),
+                ..Default::default()
+            };
+            let read = reopened.get(&CacheKey::new(GET, &url)).await?;
+            reopened.clear(true).await?;
+            assert!(read.is_some());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn shutdown_runs_later_work_inline() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: MokaManager::default(),
+                options: HttpCacheOptions { spawner, ..Default::default() },
+            };
+            let calls = Arc::new(AtomicUsize::new(0));
+            for _ in 0..2 {
+                let calls = calls.clone();
+                cache
+                    .spawn(move |_| {
+                        Box::pin(async move {
+                            calls.fetch_add(1, Ordering::SeqCst);
+                        })
+                    })
+                    .await;
+            }
+            // The queued tasks never run, so the wait runs out
+            let timeout = Duration::from_millis(10);
+            assert_eq!(cache.shutdown(timeout).await?, 2);
+            let counted = calls.clone();
+            cache
+                .spawn(move |_| {
+                    Box::pin(async move {
+                        counted.fetch_add(1, Ordering::SeqCst);
+                    })
+                })
+                .await;
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            assert_eq!(tasks.lock().unwrap().len(), 2);
+            // Dropped by the executor, which no longer counts them
+            tasks.lock().unwrap().clear();
+            assert_eq!(cache.shutdown(timeout).await?, 0);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "manager-moka")]
+    mod write_behind {
+        use super::spawner::queued;
+        use crate::*;
+        use std::time::Duration;
+
+        fn cache(
+            spawner: Spawner,
+            capacity: usize,
+        ) -> (HttpCache<MokaManager>, WriteBehind) {
+            let write_behind = WriteBehind::new(capacity);
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: MokaManager::default(),
+                options: HttpCacheOptions {
+                    spawner,
+                    write_behind: Some(write_behind.clone()),
+                    ..Default::default()
+                },
+            };
+            (cache, write_behind)
+        }
+
+        async fn send<T: CacheManager>(
+            cache: &HttpCache<T>,
+            method: &str,
+            path: &str,
+        ) -> anyhow::Result<HitOrMiss> {
+            let (parts, body) = http::Request::builder()
+                .method(method)
+                .uri(format!("http://example.com/{}", path))
+                .body(Bytes::new())?
+                .into_parts();
+            let (res, status) =
+                Box::pin(cache.run_http(parts, body, |_| async {
+                    Ok(http::Response::builder()
+                        .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                        .body(Bytes::from_static(TEST_BODY))?)
+                }))
+                .await?;
+            assert_eq!(res.body(), TEST_BODY);
+            Ok(status.cache)
+        }
+
+        fn key(path: &str) -> anyhow::Result<CacheKey> {
+            let url = Url::parse(&format!("http://example.com/{}", path))?;
+            Ok(CacheKey::new(GET, &url))
+        }
+
+        #[async_std::test]
+        async fn stores_in_the_background() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let (cache, write_behind) = cache(spawner, 8);
+            for path in ["a", "b"] {
+                assert_eq!(send(&cache, GET, path).await?, HitOrMiss::MISS);
+            }
+            assert!(cache.manager.get(&key("a")?).await?.is_none());
+            assert_eq!(write_behind.stats().queued, 2);
+            // A single task drains the queue
+            let queued: Vec<_> = tasks.lock().unwrap().drain(..).collect();
+            assert_eq!(queued.len(), 1);
+            for task in queued {
+                task.await;
+            }
+            assert_eq!(write_behind.stats().queued, 0);
+            assert_eq!(send(&cache, GET, "a").await?, HitOrMiss::HIT);
+            assert_eq!(send(&cache, GET, "b").await?, HitOrMiss::HIT);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn drops_stores_when_full() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let (cache, write_behind) = cache(spawner, 2);
+            for path in ["a", "b", "c", "d"] {
+                send(&cache, GET, path).await?;
+            }
+            let stats = write_behind.stats();
+            assert_eq!((stats.queued, stats.dropped), (2, 2));
+            // A newer response for a queued record takes its place
+            send(&cache, GET, "a").await?;
+            assert_eq!(write_behind.stats().dropped, 2);
+            let queued: Vec<_> = tasks.lock().unwrap().drain(..).collect();
+            for task in queued {
+                task.await;
+            }
+            assert!(cache.manager.get(&key("b")?).await?.is_some());
+            assert!(cache.manager.get(&key("c")?).await?.is_none());
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn removals_drop_queued_stores() -> anyhow::Result<()> {
+            let (spawner, tasks) = queued();
+            let (cache, write_behind) = cache(spawner, 8);
+            send(&cache, GET, "a").await?;
+            send(&cache, "POST", "a").await?;
+            assert_eq!(write_behind.stats().queued, 0);
+            let queued: Vec<_> = tasks.lock().unwrap().drain(..).collect();
+            for task in queued {
+                task.await;
+            }
+            assert!(cache.manager.get(&key("a")?).await?.is_none());
+            Ok(())
+        }
+
+        // Takes a while to store each response
+        #[derive(Debug, Clone, Default)]
+        struct SlowPuts(MokaManager);
+
+        impl CacheManager for SlowPuts {
+            async fn get(
+                &self,
+                key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                self.0.get(key).await
+            }
+
+            async fn put(
+                &self,
+                key: &CacheKey,
+                res: HttpResponse,
+                policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                async_std::task::sleep(Duration::from_millis(200)).await;
+                self.0.put(key, res, policy).await
+            }
+
+            async fn delete(&self, key: &CacheKey) -> Result<()> {
+                self.0.delete(key).await
+            }
+
+            fn background_handle(&self) -> Option<Self> {
+                Some(self.clone())
+            }
+        }
+
+        #[async_std::test]
+        async fn removals_wait_for_the_store_in_progress() -> anyhow::Result<()>
+        {
+            let write_behind = WriteBehind::default();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: SlowPuts::default(),
+                options: HttpCacheOptions {
+                    spawner: Spawner::new(|task| {
+                        async_std::task::spawn(task);
+                    }),
+                    write_behind: Some(write_behind.clone()),
+                    ..Default::default()
+                },
+            };
+            send(&cache, GET, "a").await?;
+            // Removed while the response is being stored
+            async_std::task::sleep(Duration::from_millis(50)).await;
+            send(&cache, "POST", "a").await?;
+            cache.flush().await?;
+            assert!(cache.manager.get(&key("a")?).await?.is_none());
+            assert_eq!(write_behind.stats(), WriteBehindStats::default());
+            Ok(())
+        }
+    }
+
+    mod maintenance {
+        use crate::*;
+        use futures::{channel::oneshot, task::noop_waker, FutureExt};
+        use std::{
+            sync::Mutex,
+            task::{Context, Poll},
+            time::Duration,
+        };
+
+        type Cycle = oneshot::Sender<std::result::Result<PruneReport, String>>;
+
+        // Holds every prune cycle until the test finishes it
+        #[derive(Clone, Default)]
+        struct HeldManager {
+            cycles: Arc<Mutex<Vec<Cycle>>>,
+            running: Arc<AtomicUsize>,
+            most_running: Arc<AtomicUsize>,
+        }
+
+        impl CacheManager for HeldManager {
+            async fn get(
+                &self,
+                _key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                Ok(None)
+            }
+
+            async fn put(
+                &self,
+                _key: &CacheKey,
+                res: HttpResponse,
+                _policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                Ok(res)
+            }
+
+            async fn delete(&self, _key: &CacheKey) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        impl PrunableCacheManager for HeldManager {
+            async fn prune(&self) -> Result<PruneReport> {
+                let running = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+                self.most_running.fetch_max(running, Ordering::SeqCst);
+                let (cycle, finished) = oneshot::channel();
+                self.cycles.lock().unwrap().push(cycle);
+                let res = finished.await.unwrap();
+                self.running.fetch_sub(1, Ordering::SeqCst);
+                res.map_err(|e| CacheError::General(anyhow::anyhow!(e)))
+            }
+        }
+
+        impl HeldManager {
+            fn finish(&self, res: std::result::Result<PruneReport, String>) {
+                let cycle = self.cycles.lock().unwrap().remove(0);
+                cycle.send(res).unwrap();
+            }
+
+            fn held(&self) -> usize {
+                self.cycles.lock().unwrap().len()
+            }
+        }
+
+        type Wait = (Duration, oneshot::Sender<()>);
+
+        // A timer whose waits are over once the test says so
+        #[derive(Clone, Default)]
+        struct ManualTimer {
+            waits: Arc<Mutex<Vec<Wait>>>,
+        }
+
+        impl ManualTimer {
+            fn timer(&self) -> Timer {
+                let waits = self.waits.clone();
+                Arc::new(move |duration| {
+                    let (over, wait) = oneshot::channel();
+                    waits.lock().unwrap().push((duration, over));
+                    Box::pin(async move {
+                        wait.await.ok();
+                    })
+                })
+            }
+
+            fn pending(&self) -> Vec<Duration> {
+                let waits = self.waits.lock().unwrap();
+                waits.iter().map(|(duration, _)| *duration).collect()
+            }
+
+            fn elapse(&self) {
+                let (_, over) = self.waits.lock().unwrap().remove(0);
+                over.send(()).unwrap();
+            }
+        }
+
+        // Starts the cycles on a spawner queuing the task, which the test
+        // then polls by hand
+        fn start(
+            manager: &HeldManager,
+            mut options: MaintenanceOptions,
+        ) -> (MaintenanceHandle, ManualTimer, BackgroundTask) {
+            let timer = ManualTimer::default();
+            options.timer = Some(timer.timer());
+            let tasks = Arc::new(Mutex::new(Vec::new()));
+            let queue = tasks.clone();
+            let spawner =
+                Spawner::new(move |task| queue.lock().unwrap().push(task));
+            let handle =
+                maintenance(manager.clone(), options, &spawner).unwrap();
+            let task = tasks.lock().unwrap().pop().unwrap();
+            (handle, timer, task)
+        }
+
+        // Returns true once the task is done
+        fn run(task: &mut BackgroundTask) -> bool {
+            let waker = noop_waker();
+            let mut cx = Context::from_waker(&waker);
+            matches!(task.poll_unpin(&mut cx), Poll::Ready(()))
+        }
+
+        fn report(removed: usize) -> PruneReport {
+            let mut report = PruneReport::default();
+            report.removed = removed;
+            report.removed_bytes = removed as u64 * 100;
+            report
+        }
+
+        #[test]
+        fn runs_cycles_on_schedule_without_overlap() {
+            let manager = HeldManager::default();
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = events.clone();
+            let options = MaintenanceOptions {
+                on_event: Some(Arc::new(move |event: &CacheEvent| {
+                    recorded.lock().unwrap().push(event.clone())
+                })),
+                ..MaintenanceOptions::new(Duration::from_secs(60))
+            };
+            let (handle, timer, mut task) = start(&manager, options);
+
+            // Nothing is pruned before the interval is over
+            assert!(!run(&mut task));
+            assert_eq!(timer.pending(), [Duration::from_secs(60)]);
+            assert_eq!(manager.held(), 0);
+
+            for removed in [2, 3] {
+                timer.elapse();
+                assert!(!run(&mut task));
+                // The next wait only starts once the cycle is over, however
+                // long it takes
+                assert_eq!(manager.held(), 1);
+                assert!(!run(&mut task));
+                assert!(timer.pending().is_empty());
+                manager.finish(Ok(report(removed)));
+                assert!(!run(&mut task));
+                assert_eq!(timer.pending(), [Duration::from_secs(60)]);
+            }
+            assert_eq!(manager.most_running.load(Ordering::SeqCst), 1);
+
+            let stats = handle.stats();
+            assert_eq!(stats.cycles, 2);
+            assert_eq!(stats.failures, 0);
+            assert_eq!(stats.removed, 5);
+            assert_eq!(stats.removed_bytes, 500);
+            assert!(stats.last_cycle.is_some());
+            let events = events.lock().unwrap();
+            assert_eq!(events.len(), 2);
+            assert!(matches!(
+                events[1],
+                CacheEvent::Pruned { report } if report.removed == 3
+            ));
+        }
+
+        #[test]
+        fn backs_off_after_failed_cycles() {
+            let manager = HeldManager::default();
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = events.clone();
+            let options = MaintenanceOptions {
+                max_backoff: Duration::from_secs(35),
+                on_event: Some(Arc::new(move |event: &CacheEvent| {
+                    recorded.lock().unwrap().push(event.clone())
+                })),
+                ..MaintenanceOptions::new(Duration::from_secs(10))
+            };
+            let (handle, timer, mut task) = start(&manager, options);
+            assert!(!run(&mut task));
+
+            for (res, next) in [
+                (Err("disk full".to_string()), 20),
+                (Err("disk full".to_string()), 35),
+                (Err("disk full".to_string()), 35),
+                (Ok(report(1)), 10),
+            ] {
+                timer.elapse();
+                assert!(!run(&mut task));
+                manager.finish(res);
+                assert!(!run(&mut task));
+                assert_eq!(timer.pending(), [Duration::from_secs(next)]);
+            }
+
+            let stats = handle.stats();
+            assert_eq!(stats.cycles, 4);
+            assert_eq!(stats.failures, 3);
+            assert_eq!(stats.consecutive_failures, 0);
+            assert_eq!(stats.removed, 1);
+            let events = events.lock().unwrap();
+            assert!(matches!(
+                &events[0],
+                CacheEvent::PruneFailed { error, retry_in }
+                    if error == "disk full"
+                        && *retry_in == Duration::from_secs(20)
+            ));
+        }
+
+        #[test]
+        fn stops() {
+            let interval = Duration::from_secs(60);
+
+            // While waiting for the next cycle
+            let manager = HeldManager::default();
+            let (handle, timer, mut task) =
+                start(&manager, MaintenanceOptions::new(interval));
+            assert!(!run(&mut task));
+            handle.stop();
+            assert!(run(&mut task));
+            assert!(handle.is_finished());
+            assert_eq!(manager.held(), 0);
+            drop(timer);
+
+            // While a cycle runs, which finishes first
+            let (handle, timer, mut task) =
+                start(&manager, MaintenanceOptions::new(interval));
+            assert!(!run(&mut task));
+            timer.elapse();
+            assert!(!run(&mut task));
+            handle.stop();
+            assert!(!run(&mut task));
+            assert!(!handle.is_finished());
+            manager.finish(Ok(report(1)));
+            assert!(run(&mut task));
+            assert!(handle.is_finished());
+            assert!(timer.pending().is_empty());
+            assert_eq!(handle.stats().cycles, 1);
+
+            // Along with the handle
+            let (handle, _timer, mut task) =
+                start(&manager, MaintenanceOptions::new(interval));
+            assert!(!run(&mut task));
+            drop(handle);
+            assert!(run(&mut task));
+        }
+
+        #[cfg(feature = "manager-cacache")]
+        #[async_std::test]
+        async fn prunes_cacache_on_the_runtime_timer() -> anyhow::Result<()> {
+            let manager = CACacheManager {
+                path: "./http-cacache-maintenance".into(),
+                prune_grace: Duration::ZERO,
+                ..Default::default()
+            };
+            let url = Url::parse("http://example.com/removed")?;
+            let (res, policy) = super::cacheable_record(&url)?;
+            manager.put(&CacheKey::new(GET, &url), res, policy).await?;
+            manager.delete(&CacheKey::new(GET, &url)).await?;
+
+            let spawner = Spawner::new(|task| {
+                async_std::task::spawn(task);
+            });
+            let options = MaintenanceOptions::new(Duration::from_millis(10));
+            let handle = maintenance(manager.clone(), options, &spawner)?;
+            for _ in 0..5000 {
+                if handle.stats().removed > 0 {
+                    break;
+                }
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            // The content of the removed record and its body
+            assert_eq!(handle.stats().removed, 2);
+            handle.stop();
+            for _ in 0..5000 {
+                if handle.is_finished() {
+                    break;
+                }
+                async_std::task::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(handle.is_finished());
+            manager.clear(true).await?;
+            Ok(())
+        }
+
+        #[test]
+        fn needs_a_background_spawner() {
+            let res = maintenance(
+                HeldManager::default(),
+                MaintenanceOptions::new(Duration::from_secs(60)),
+                &Spawner::inline(),
+            );
+            assert!(matches!(res, Err(CacheError::InlineSpawner)));
+        }
+    }
+
+    mod event_broadcast {
+        use crate::*;
+        use futures::{executor::block_on, StreamExt};
+
+        fn tagged(removed: usize) -> CacheEvent {
+            CacheEvent::PurgedTag { tag: "tag".to_string(), removed }
+        }
+
+        fn removed(event: Option<CacheEvent>) -> Option<usize> {
+            match event {
+                Some(CacheEvent::PurgedTag { removed, .. }) => Some(removed),
+                _ => None,
+            }
+        }
+
+        #[test]
+        fn slow_subscribers_lag_behind() {
+            let broadcast = EventBroadcast::new(2);
+            let mut slow = broadcast.subscribe();
+            let mut fast = broadcast.subscribe();
+            for n in 0..5 {
+                broadcast.send(tagged(n));
+                assert_eq!(removed(block_on(fast.next())), Some(n));
+            }
+
+            // Sending never waits, the slow subscriber misses the oldest
+            // events instead
+            assert!(matches!(
+                block_on(slow.next()),
+                Some(CacheEvent::Lagged { missed: 3 })
+            ));
+            assert_eq!(removed(block_on(slow.next())), Some(3));
+            assert_eq!(removed(block_on(slow.next())), Some(4));
+
+            drop(broadcast);
+            assert!(block_on(slow.next()).is_none());
+            assert!(block_on(fast.next()).is_none());
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[test]
+        fn subscribers_only_get_later_events() {
+            let broadcast = EventBroadcast::new(4);
+            assert!(!broadcast.has_subscribers());
+            broadcast.send(tagged(0));
+            let mut events = broadcast.subscribe();
+            assert!(broadcast.has_subscribers());
+            broadcast.send(tagged(1));
+            assert_eq!(removed(block_on(events.next())), Some(1));
+            drop(events);
+            assert!(!broadcast.has_subscribers());
+
+            // Without a broadcast the stream ends right away
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            assert!(block_on(cache.subscribe().next()).is_none());
+        }
+    }
+
+    mod backend_timeout {
+        use crate::*;
+        use std::{
+            sync::Mutex,
+            time::{Duration, Instant},
+        };
+
+        // Wraps a MokaManager, taking `delay` to answer every call
+        #[cfg(feature = "manager-moka")]
+        #[derive(Debug, Clone, Default)]
+        struct SlowManager {
+            inner: MokaManager,
+            delay: Duration,
+        }
+
+        #[cfg(feature = "manager-moka")]
+        impl CacheManager for SlowManager {
+            async fn get(
+                &self,
+                key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                async_std::task::sleep(self.delay).await;
+                self.inner.get(key).await
+            }
+
+            async fn put(
+                &self,
+                key: &CacheKey,
+                res: HttpResponse,
+                policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                async_std::task::sleep(self.delay).await;
+                self.inner.put(key, res, policy).await
+            }
+
+            async fn delete(&self, key: &CacheKey) -> Result<()> {
+                async_std::task::sleep(self.delay).await;
+                self.inner.delete(key).await
+            }
+        }
+
+        #[cfg(feature = "manager-moka")]
+        fn cache(
+            delay: Duration,
+        ) -> (HttpCache<SlowManager>, Arc<Mutex<Vec<CacheEvent>>>) {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = events.clone();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: SlowManager { inner: MokaManager::default(), delay },
+                options: HttpCacheOptions {
+                    backend_timeout: Some(Duration::from_millis(100)),
+                    on_event: Some(Arc::new(move |event: &CacheEvent| {
+                        recorded.lock().unwrap().push(event.clone())
+                    })),
+                    ..Default::default()
+                },
+            };
+            (cache, events)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        async fn send(
+            cache: &HttpCache<SlowManager>,
+        ) -> anyhow::Result<(http::Response<Bytes>, CacheStatus)> {
+            let (parts, body) = http::Request::get("http://example.com/")
+                .body(Bytes::new())?
+                .into_parts();
+            // Boxed, as debug builds of the request future otherwise
+            // overflow the stack of test threads
+            Ok(Box::pin(cache.run_http(parts, body, |_| async {
+                Ok(http::Response::builder()
+                    .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                    .body(Bytes::from_static(TEST_BODY))?)
+            }))
+            .await?)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn gives_up_on_a_slow_manager() -> anyhow::Result<()> {
+            let (cache, events) = cache(Duration::from_secs(30));
+            let started = Instant::now();
+            let (res, status) = send(&cache).await?;
+            assert!(started.elapsed() < Duration::from_secs(5));
+            assert_eq!(status.lookup, HitOrMiss::MISS);
+            assert_eq!(res.body(), TEST_BODY);
+            let operations: Vec<_> = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|event| match event {
+                    CacheEvent::BackendTimeout { operation, url, .. } => {
+                        assert_eq!(url.as_str(), "http://example.com/");
+                        Some(*operation)
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(operations, ["get", "put"]);
+            // Giving up on storing the response cancels it
+            let url = Url::parse("http://example.com/")?;
+            assert!(cache
+                .manager
+                .inner
+                .get(&CacheKey::new(GET, &url))
+                .await?
+                .is_none());
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keeps_fast_operations() -> anyhow::Result<()> {
+            let (cache, events) = cache(Duration::from_millis(1));
+            send(&cache).await?;
+            let (_, status) = send(&cache).await?;
+            assert_eq!(status.cache, HitOrMiss::HIT);
+            let events = events.lock().unwrap();
+            assert!(!events.iter().any(|event| matches!(
+                event,
+                CacheEvent::BackendTimeout { .. }
+            )));
+            Ok(())
+        }
+    }
+
+    mod error_policy {
+        use crate::*;
+        use std::{sync::Mutex, time::Duration};
+
+        // Fails every call, after `delay`, counting them
+        #[derive(Debug, Clone, Default)]
+        struct FailingManager {
+            calls: Arc<AtomicUsize>,
+            delay: Duration,
+        }
+
+        impl FailingManager {
+            async fn fail<T>(&self) -> Result<T> {
+                self.calls.fetch_add(1, Ordering::SeqCst);
+                if !self.delay.is_zero() {
+                    async_std::task::sleep(self.delay).await;
+                }
+                Err(CacheError::General(anyhow::anyhow!("backend down")))
+            }
+        }
+
+        impl CacheManager for FailingManager {
+            async fn get(
+                &self,
+                _key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                self.fail().await
+            }
+
+            async fn put(
+                &self,
+                _key: &CacheKey,
+                _res: HttpResponse,
+                _policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                self.fail().await
+            }
+
+            async fn delete(&self, _key: &CacheKey) -> Result<()> {
+                self.fail().await
+            }
+        }
+
+        fn cache(
+            options: HttpCacheOptions,
+        ) -> (HttpCache<FailingManager>, Arc<Mutex<Vec<CacheEvent>>>) {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = events.clone();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: FailingManager::default(),
+                options: HttpCacheOptions {
+                    on_event: Some(Arc::new(move |event: &CacheEvent| {
+                        recorded.lock().unwrap().push(event.clone())
+                    })),
+                    ..options
+                },
+            };
+            (cache, events)
+        }
+
+        async fn send(
+            cache: &HttpCache<FailingManager>,
+        ) -> Result<(http::Response<Bytes>, CacheStatus)> {
+            let (parts, body) = http::Request::get("http://example.com/")
+                .body(Bytes::new())?
+                .into_parts();
+            // Boxed, as debug builds of the request future otherwise
+            // overflow the stack of test threads
+            Box::pin(cache.run_http(parts, body, |_| async {
+                Ok(http::Response::builder()
+                    .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                    .body(Bytes::from_static(TEST_BODY))?)
+            }))
+            .await
+        }
+
+        #[async_std::test]
+        async fn fails_open_by_default() -> anyhow::Result<()> {
+            let (cache, events) = cache(HttpCacheOptions::default());
+            let (res, status) = send(&cache).await?;
+            assert_eq!(res.body(), TEST_BODY);
+            assert_eq!(status.lookup, HitOrMiss::MISS);
+            let operations: Vec<_> = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter_map(|event| match event {
+                    CacheEvent::BackendError { operation, error, .. } => {
+                        assert_eq!(error, "backend down");
+                        Some(*operation)
+                    }
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(operations, ["get", "put"]);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn fails_closed() -> anyhow::Result<()> {
+            let (cache, _) = cache(HttpCacheOptions {
+                error_policy: ErrorPolicy::FailClosed,
+                ..Default::default()
+            });
+            let err = send(&cache).await.unwrap_err();
+            assert_eq!(err.to_string(), "backend down");
+            Ok(())
+        }
+
+        // Finds nothing and fails to store anything
+        #[derive(Debug, Clone, Default)]
+        struct FailingPuts;
+
+        impl CacheManager for FailingPuts {
+            async fn get(
+                &self,
+                _key: &CacheKey,
+            ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+                Ok(None)
+            }
+
+            async fn put(
+                &self,
+                _key: &CacheKey,
+                _res: HttpResponse,
+                _policy: CachePolicy,
+            ) -> Result<HttpResponse> {
+                Err(CacheError::General(anyhow::anyhow!("disk full")))
+            }
+
+            async fn delete(&self, _key: &CacheKey) -> Result<()> {
+                Ok(())
+            }
+        }
+
+        #[async_std::test]
+        async fn failed_puts_return_the_response() -> anyhow::Result<()> {
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let recorded = events.clone();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: FailingPuts,
+                options: HttpCacheOptions {
+                    on_event: Some(Arc::new(move |event: &CacheEvent| {
+                        if let CacheEvent::BackendError { operation, .. } =
+                            event
+                        {
+                            recorded.lock().unwrap().push(*operation);
+                        }
+                    })),
+                    ..Default::default()
+                },
+            };
+            for _ in 0..2 {
+                let (parts, body) = http::Request::get("http://example.com/")
+                    .body(Bytes::new())?
+                    .into_parts();
+                let (res, status) =
+                    Box::pin(cache.run_http(parts, body, |_| async {
+                        Ok(http::Response::builder()
+                            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                            .body(Bytes::from_static(TEST_BODY))?)
+                    }))
+                    .await?;
+                assert_eq!(res.status(), 200);
+                assert_eq!(res.body(), TEST_BODY);
+                assert_eq!(status.cache, HitOrMiss::MISS);
+            }
+            assert_eq!(*events.lock().unwrap(), ["put", "put"]);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn breaker_stops_calling_the_manager() -> anyhow::Result<()> {
+            let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+            let (cache, events) = cache(HttpCacheOptions {
+                circuit_breaker: Some(breaker.clone()),
+                ..Default::default()
+            });
+            for _ in 0..10 {
+                let (res, _) = send(&cache).await?;
+                assert_eq!(res.body(), TEST_BODY);
+            }
+            // The get and put of the first request and the get of the
+            // second one, the breaker is open from then on
+            assert_eq!(cache.manager.calls.load(Ordering::SeqCst), 3);
+            assert!(matches!(breaker.state(), BreakerState::Open { .. }));
+            let opened = events
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|event| {
+                    matches!(event, CacheEvent::CircuitOpened { .. })
+                })
+                .count();
+            assert_eq!(opened, 1);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn breaker_lets_a_call_through_after_cooling_down(
+        ) -> anyhow::Result<()> {
+            let breaker = CircuitBreaker::new(1, Duration::ZERO);
+            let (cache, _) = cache(HttpCacheOptions {
+                circuit_breaker: Some(breaker.clone()),
+                error_policy: ErrorPolicy::FailClosed,
+                ..Default::default()
+            });
+            assert!(matches!(send(&cache).await, Err(CacheError::General(_))));
+            assert_eq!(breaker.state(), BreakerState::HalfOpen);
+            send(&cache).await.unwrap_err();
+            assert_eq!(cache.manager.calls.load(Ordering::SeqCst), 2);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn half_open_breaker_lets_a_single_call_through(
+        ) -> anyhow::Result<()> {
+            let breaker = CircuitBreaker::new(1, Duration::from_millis(100));
+            let (mut cache, _) = cache(HttpCacheOptions {
+                circuit_breaker: Some(breaker.clone()),
+                ..Default::default()
+            });
+            cache.manager.delay = Duration::from_millis(20);
+            send(&cache).await?;
+            async_std::task::sleep(Duration::from_millis(150)).await;
+            assert_eq!(breaker.state(), BreakerState::HalfOpen);
+
+            // The other requests skip the manager while the first one
+            // finds out whether it is back
+            let sends = (0..5).map(|_| send(&cache));
+            for res in futures::future::join_all(sends).await {
+                assert_eq!(res?.0.body(), TEST_BODY);
+            }
+            assert_eq!(cache.manager.calls.load(Ordering::SeqCst), 2);
+            assert!(matches!(breaker.state(), BreakerState::Open { .. }));
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn open_breaker_fails_closed() -> anyhow::Result<()> {
+            let (cache, _) = cache(HttpCacheOptions {
+                circuit_breaker: Some(CircuitBreaker::new(
+                    1,
+                    Duration::from_secs(60),
+                )),
+                error_policy: ErrorPolicy::FailClosed,
+                ..Default::default()
+            });
+            send(&cache).await.unwrap_err();
+            assert!(matches!(
+                send(&cache).await,
+                Err(CacheError::BackendUnavailable)
+            ));
+            assert_eq!(cache.manager.calls.load(Ordering::SeqCst), 1);
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "manager-moka")]
+    mod clock {
+        use crate::*;
+        use std::time::{Duration, SystemTime};
+
+        const DAY: u64 = 24 * 60 * 60;
+
+        // A cache reading the time from a mock clock
+        fn cache() -> (HttpCache<MokaManager>, MockClock) {
+            let clock = MockClock::default();
+            let cache = HttpCache {
+                mode: CacheMode::Default,
+                manager: MokaManager::default(),
+                options: HttpCacheOptions {
+                    clock: Some(clock.clock()),
+                    ..Default::default()
+                },
+            };
+            (cache, clock)
+        }
+
+        // Moves the clock `step` seconds away from `start`
+        fn step(clock: &MockClock, start: SystemTime, step: i64) {
+            let by = Duration::from_secs(step.unsigned_abs());
+            clock.set(start);
+            if step < 0 {
+                clock.rewind(by);
+            } else {
+                clock.advance(by);
+            }
+        }
+
+        async fn send_with(
+            cache: &HttpCache<MokaManager>,
+            calls: &AtomicUsize,
+            cache_control: Option<&str>,
+            last_modified: &str,
+        ) -> Result<HitOrMiss> {
+            let (parts, body) = http::Request::get("http://example.com/")
+                .body(Bytes::new())?
+                .into_parts();
+            let (_, status) =
+                Box::pin(cache.run_http(parts, body, |_| async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let mut res = http::Response::builder()
+                        .header("last-modified", last_modified);
+                    if let Some(cache_control) = cache_control {
+                        res = res.header(CACHE_CONTROL, cache_control);
+                    }
+                    Ok(res.body(Bytes::from_static(TEST_BODY))?)
+                }))
+                .await?;
+            Ok(status.cache)
+        }
+
+        async fn send(
+            cache: &HttpCache<MokaManager>,
+            calls: &AtomicUsize,
+        ) -> Result<HitOrMiss> {
+            let last_modified = "Mon, 01 Jan 2024 00:00:00 GMT";
+            send_with(cache, calls, Some(CACHEABLE_PUBLIC), last_modified).await
+        }
+
+        #[async_std::test]
+        async fn clock_stepping_backwards() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let start = clock.now();
+            let calls = AtomicUsize::new(0);
+            assert_eq!(send(&cache, &calls).await?, HitOrMiss::MISS);
+            // Earlier than the response was stored, which is no age at all
+            for by in [60, DAY, 400 * DAY] {
+                clock.set(start);
+                clock.rewind(Duration::from_secs(by));
+                assert_eq!(send(&cache, &calls).await?, HitOrMiss::HIT);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn clock_stepping_forwards() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let calls = AtomicUsize::new(0);
+            assert_eq!(send(&cache, &calls).await?, HitOrMiss::MISS);
+            clock.advance(Duration::from_secs(DAY / 2));
+            assert_eq!(send(&cache, &calls).await?, HitOrMiss::HIT);
+            // Past the max-age, the response is fetched and stored again
+            clock.advance(Duration::from_secs(DAY));
+            assert_eq!(send(&cache, &calls).await?, HitOrMiss::MISS);
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn freshness_follows_the_clock() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let calls = AtomicUsize::new(0);
+            let url = Url::parse("http://example.com/")?;
+            assert!(cache.freshness(GET, &url).await?.is_none());
+            send(&cache, &calls).await?;
+            let freshness = cache.freshness(GET, &url).await?.unwrap();
+            assert!(!freshness.is_stale);
+            assert!(freshness.can_revalidate);
+            assert_eq!(freshness.time_to_live, Duration::from_secs(DAY));
+            clock.advance(Duration::from_secs(DAY / 4));
+            let inspection = cache.inspect(GET, &url).await?.unwrap();
+            assert_eq!(
+                inspection.time_to_live(),
+                Duration::from_secs(3 * DAY / 4)
+            );
+            clock.advance(Duration::from_secs(2 * DAY));
+            let freshness = cache.freshness(GET, &url).await?.unwrap();
+            assert!(freshness.is_stale);
+            assert_eq!(freshness.time_to_live, Duration::ZERO);
+            assert!(!cache.inspect(GET, &url).await?.unwrap().is_fresh_now());
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn heuristic_freshness_follows_the_clock() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let calls = AtomicUsize::new(0);
+            // Modified ten days ago, which makes it fresh for a tenth of that
+            let last_modified = httpdate::fmt_http_date(
+                clock.now() - Duration::from_secs(10 * DAY),
+            );
+            for (by, expected) in [
+                (0, HitOrMiss::MISS),
+                (DAY / 2, HitOrMiss::HIT),
+                (DAY / 2 - 60, HitOrMiss::HIT),
+                (120, HitOrMiss::MISS),
+            ] {
+                clock.advance(Duration::from_secs(by));
+                assert_eq!(
+                    send_with(&cache, &calls, None, &last_modified).await?,
+                    expected,
+                    "{}",
+                    by
+                );
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn response_delay_adds_to_the_age() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let (parts, body) = http::Request::get("http://example.com/")
+                .body(Bytes::new())?
+                .into_parts();
+            // The origin takes five seconds to answer
+            let (res, _) = Box::pin(cache.run_http(parts, body, |_| async {
+                clock.advance(Duration::from_secs(5));
+                Ok(http::Response::builder()
+                    .header(CACHE_CONTROL, "public, max-age=300")
+                    .header("age", "290")
+                    .body(Bytes::from_static(TEST_BODY))?)
+            }))
+            .await?;
+            assert_eq!(res.headers()["age"], "295");
+            let url = Url::parse("http://example.com/")?;
+            let freshness = cache.freshness(GET, &url).await?.unwrap();
+            assert_eq!(freshness.time_to_live, Duration::from_secs(5));
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn clock_stepping_back_and_forth() -> anyhow::Result<()> {
+            let (cache, clock) = cache();
+            let start = clock.now();
+            let calls = AtomicUsize::new(0);
+            let day = DAY as i64;
+            for (by, expected) in [
+                (0, HitOrMiss::MISS),
+                (-day, HitOrMiss::HIT),
+                (day / 2, HitOrMiss::HIT),
+                (-400 * day, HitOrMiss::HIT),
+                (2 * day, HitOrMiss::MISS),
+                (-day, HitOrMiss::HIT),
+            ] {
+                step(&clock, start, by);
+                assert_eq!(send(&cache, &calls).await?, expected, "{}", by);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            Ok(())
+        }
+
+        // Stores responses for `count` urls at the same time, returning the
+        // second each one was first revalidated at from then on, fewer than
+        // the default capacity of the manager
+        async fn refresh_times(
+            jitter: f64,
+            count: usize,
+        ) -> anyhow::Result<Vec<u64>> {
+            let (mut cache, clock) = cache();
+            cache.options.refresh_jitter = Some(jitter);
+            let cache = &cache;
+            let calls = &AtomicUsize::new(0);
+            let send = |path: usize| async move {
+                let url = format!("http://example.com/{}", path);
+                let (parts, body) =
+                    http::Request::get(url).body(Bytes::new())?.into_parts();
+                Box::pin(cache.run_http(parts, body, |_| async {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    Ok(http::Response::builder()
+                        .header(CACHE_CONTROL, "public, max-age=100")
+                        .body(Bytes::from_static(TEST_BODY))?)
+                }))
+                .await?;
+                anyhow::Ok(())
+            };
+            for path in 0..count {
+                send(path).await?;
+            }
+            let mut refreshed = vec![None; count];
+            for second in 1..=100 {
+                clock.advance(Duration::from_secs(1));
+                for (path, refreshed) in refreshed.iter_mut().enumerate() {
+                    let before = calls.load(Ordering::SeqCst);
+                    send(path).await?;
+                    if calls.load(Ordering::SeqCst) > before {
+                        refreshed.get_or_insert(second);
+                    }
+                }
+            }
+            Ok(refreshed.into_iter().map(Option::unwrap).collect())
+        }
+
+        #[async_std::test]
+        async fn refresh_jitter_spreads_revalidations() -> anyhow::Result<()> {
+            let seconds = refresh_times(0.5, 40).await?;
+            // Never past the freshness lifetime, nor before half of it is
+            // gone, and not all at once
+            assert!(
+                seconds.iter().all(|s| (50..=100).contains(s)),
+                "{:?}",
+                seconds
+            );
+            let mut distinct = seconds.clone();
+            distinct.sort_unstable();
+            distinct.dedup();
+            assert!(distinct.len() > 15, "{:?}", seconds);
+            assert!(distinct[0] < 60 && distinct[distinct.len() - 1] > 90);
+            // Each key is given the same share every time
+            assert_eq!(refresh_times(0.5, 40).await?, seconds);
+            // Without jitter every response is revalidated once it is stale
+            assert!(refresh_times(0.0, 10).await?.iter().all(|s| *s == 100));
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "manager-moka")]
+    mod malformed_dates {
+        use crate::*;
+
+        // Values origins send in place of an HTTP-date
+        const MALFORMED: &[&[u8]] = &[
+            b"-1",
+            b"0",
+            b"",
+            b" ",
+            b"never",
+            b"2024-01-01T00:00:00Z",
+            b"Mon, 32 Jan 2024 00:00:00 GMT",
+            b"Mon, 01 Foo 2024 00:00:00 GMT",
+            b"Mon, 01 Jan 2024 25:61:61 GMT",
+            b"Mon, 01 Jan 10000 00:00:00 GMT",
+            b"Mon, 01 Jan 0001 00:00:00 GMT",
+            b"Mon, 01 Jan 1800 00:00:00 GMT",
+            b"Sunday, 06-Nov-94 08:49:37 GMT",
+            b"Sun Nov  6 08:49:37 1994",
+            b"Mon, 01 Jan 2024 00:00:00 GMT, Tue, 02 Jan 2024 00:00:00 GMT",
+            b"\xff\xfe garbage",
+            b"18446744073709551616",
+        ];
+
+        // Sends two requests answered with `headers`, returning how the
+        // second one was handled and how many reached the origin
+        async fn twice(
+            headers: &[(&str, &[u8])],
+        ) -> anyhow::Result<(HitOrMiss, usize)> {
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            let calls = &AtomicUsize::new(0);
+            let mut status = HitOrMiss::MISS;
+            for _ in 0..2 {
+                let (parts, body) = http::Request::get("http://example.com/")
+                    .body(Bytes::new())?
+                    .into_parts();
+                let (res, cache_status) =
+                    Box::pin(cache.run_http(parts, body, |_| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        let mut res = http::Response::builder();
+                        for (name, value) in headers {
+                            res = res.header(
+                                *name,
+                                http::HeaderValue::from_bytes(value)?,
+                            );
+                        }
+                        Ok(res.body(Bytes::from_static(TEST_BODY))?)
+                    }))
+                    .await?;
+                assert_eq!(res.body(), TEST_BODY);
+                status = cache_status.cache;
+            }
+            Ok((status, calls.load(Ordering::SeqCst)))
+        }
+
+        #[async_std::test]
+        async fn malformed_expires_is_expired() -> anyhow::Result<()> {
+            for value in MALFORMED {
+                let headers: &[(&str, &[u8])] = &[
+                    ("expires", value),
+                    ("last-modified", b"Mon, 01 Jan 2024 00:00:00 GMT"),
+                ];
+                assert_eq!(
+                    twice(headers).await?,
+                    (HitOrMiss::MISS, 2),
+                    "{:?}",
+                    String::from_utf8_lossy(value)
+                );
+            }
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn malformed_expires_is_ignored_with_max_age(
+        ) -> anyhow::Result<()> {
+            for value in MALFORMED {
+                let headers: &[(&str, &[u8])] = &[
+                    ("cache-control", CACHEABLE_PUBLIC.as_bytes()),
+                    ("expires", value),
+                ];
+                assert_eq!(
+                    twice(headers).await?,
+                    (HitOrMiss::HIT, 1),
+                    "{:?}",
+                    String::from_utf8_lossy(value)
+                );
+            }
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn malformed_date_is_absent() -> anyhow::Result<()> {
+            for value in MALFORMED {
+                let headers: &[(&str, &[u8])] = &[
+                    ("cache-control", CACHEABLE_PUBLIC.as_bytes()),
+                    ("date", value),
+                ];
+                assert_eq!(
+                    twice(headers).await?,
+                    (HitOrMiss::HIT, 1),
+                    "{:?}",
+                    String::from_utf8_lossy(value)
+                );
+            }
+            Ok(())
+        }
+
+        #[async_std::test]
+        async fn malformed_dates_with_revalidation() -> anyhow::Result<()> {
+            for value in MALFORMED {
+                let headers: &[(&str, &[u8])] = &[
+                    ("cache-control", MUST_REVALIDATE.as_bytes()),
+                    ("date", value),
+                    ("expires", value),
+                    ("last-modified", value),
+                ];
+                assert_eq!(
+                    twice(headers).await?,
+                    (HitOrMiss::MISS, 2),
+                    "{:?}",
+                    String::from_utf8_lossy(value)
+                );
+            }
+            Ok(())
+        }
+    }
+
+    mod redaction {
+        use crate::*;
+        use http_cache_semantics::CachePolicy;
+        use std::sync::Mutex;
+
+        // The redaction applies to the whole process, so the tests changing
+        // it don't run alongside those relying on the default one
+        static INSTALLED: Mutex<()> = Mutex::new(());
+
+        const SECRETS: &[(&str, &str)] = &[
+            ("Authorization", "Bearer hunter2"),
+            ("cookie", "session=hunter2"),
+            ("set-cookie", "session=hunter2; HttpOnly"),
+            ("api-key", "hunter2"),
+            ("x-api-key", "hunter2"),
+            ("proxy-authorization", "Basic hunter2"),
+        ];
+
+        fn response() -> anyhow::Result<HttpResponse> {
+            let mut headers = HttpHeaders::new();
+            headers.append("content-type", "text/plain".parse()?);
+            headers.append("x-session", "hunter2".parse()?);
+            for (name, value) in SECRETS {
+                headers.append(*name, value.parse()?);
+            }
+            Ok(HttpResponse {
+                body: Bytes::from_static(TEST_BODY),
+                headers,
+                trailers: None,
+                status: 200,
+                reason: None,
+                url: Url::parse("http://example.com")?,
+                version: HttpVersion::Http11,
+                metadata: Default::default(),
+            })
+        }
+
+        // Formats the response with `redaction` installed, putting the
+        // default one back afterwards
+        fn formatted(redaction: Redaction) -> anyhow::Result<String> {
+            let _installed =
+                INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+            redaction.install();
+            let formatted = format!("{:?}", response()?);
+            Redaction::default().install();
+            Ok(formatted)
+        }
+
+        #[test]
+        fn sensitive_headers_are_redacted() -> anyhow::Result<()> {
+            let formatted = formatted(Redaction::default())?;
+            assert_eq!(formatted.matches("[redacted]").count(), SECRETS.len());
+            assert_eq!(formatted.matches("hunter2").count(), 1);
+            assert!(formatted.contains(r#""x-session": "hunter2""#));
+            assert!(formatted.contains(r#""Authorization": [redacted]"#));
+            assert!(formatted.contains(r#""content-type": "text/plain""#));
+            Ok(())
+        }
+
+        #[test]
+        fn redacted_headers_can_be_extended() -> anyhow::Result<()> {
+            let mut redaction = Redaction::default();
+            redaction.headers.push("X-Session".into());
+            let formatted = formatted(redaction)?;
+            assert!(!formatted.contains("hunter2"));
+            assert!(formatted.contains(r#""x-session": [redacted]"#));
+            Ok(())
+        }
+
+        #[test]
+        fn redaction_can_be_disabled() -> anyhow::Result<()> {
+            let formatted = formatted(Redaction::none())?;
+            assert!(!formatted.contains("[redacted]"));
+            assert!(formatted.contains(r#""Authorization": "Bearer hunter2""#));
+            Ok(())
+        }
+
+        #[test]
+        fn inspections_and_iterators_are_redacted() -> anyhow::Result<()> {
+            let _installed =
+                INSTALLED.lock().unwrap_or_else(|e| e.into_inner());
+            let res = response()?;
+            let req = http::Request::get("http://example.com")
+                .header("authorization", "Bearer hunter2")
+                .body(())?;
+            let policy = CachePolicy::new(&req, &res.parts()?);
+            let inspection = CacheInspection::new(res.headers.clone(), policy);
+            for formatted in [
+                format!("{:?}", inspection),
+                format!("{:?}", res.headers.iter()),
+            ] {
+                assert_eq!(formatted.matches("hunter2").count(), 1);
+                assert!(formatted.contains(r#""cookie": [redacted]"#));
+            }
+            Ok(())
+        }
+    }
+
+    mod run_http {
+        use crate::*;
+
+        // Answers every request with a cacheable response, counting them
+        fn respond(
+            calls: &AtomicUsize,
+            req: http::Request<Bytes>,
+        ) -> Result<http::Response<Bytes>> {
+            calls.fetch_add(1, Ordering::SeqCst);
+            assert!(req.uri().to_string().starts_with("http://example.com/"));
+            Ok(http::Response::builder()
+                .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                .body(Bytes::from_static(TEST_BODY))?)
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn caches_responses() -> anyhow::Result<()> {
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            let calls = AtomicUsize::new(0);
+            for expected in [HitOrMiss::MISS, HitOrMiss::HIT] {
+                let (parts, body) = http::Request::get("http://example.com/")
+                    .body(Bytes::new())?
+                    .into_parts();
+                let (res, status) =
+                    Box::pin(cache.run_http(parts, body, |req| async {
+                        respond(&calls, req)
+                    }))
+                    .await?;
+                assert_eq!(status.cache, expected);
+                assert_eq!(status.lookup, expected);
+                assert_eq!(res.headers()[XCACHE], expected.to_string());
+                assert_eq!(res.body(), TEST_BODY);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            Ok(())
+        }
+
+        // Stores a response fetched through `url`, then looks it up through
+        // its normalized form, the origin seeing `url` as it was given
+        async fn normalizes_key(url: &str) -> anyhow::Result<()> {
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            let calls = &AtomicUsize::new(0);
+            for (url, expected) in [
+                (url, HitOrMiss::MISS),
+                ("https://example.com/path", HitOrMiss::HIT),
+            ] {
+                let (parts, body) =
+                    http::Request::get(url).body(Bytes::new())?.into_parts();
+                let (_, status) =
+                    Box::pin(cache.run_http(parts, body, |req| async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        assert_eq!(req.uri(), url);
+                        Ok(http::Response::builder()
+                            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                            .body(Bytes::from_static(TEST_BODY))?)
+                    }))
+                    .await?;
+                assert_eq!(status.cache, expected, "{}", url);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 1);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_ignore_scheme_case() -> anyhow::Result<()> {
+            normalizes_key("HTTPS://example.com/path").await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_ignore_host_case() -> anyhow::Result<()> {
+            normalizes_key("https://Example.COM/path").await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_drop_default_port() -> anyhow::Result<()> {
+            normalizes_key("https://example.com:443/path").await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_resolve_dot_segments() -> anyhow::Result<()> {
+            normalizes_key("https://example.com/a/./b/../../path").await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_normalize_everything_at_once() -> anyhow::Result<()> {
+            normalizes_key("HTTPS://Example.COM:443/a/../path").await
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn keys_keep_other_ports() -> anyhow::Result<()> {
+            let cache =
+                HttpCache::new(CacheMode::Default, MokaManager::default());
+            let calls = AtomicUsize::new(0);
+            for url in
+                ["https://example.com:8443/path", "https://example.com/path"]
+            {
+                let (parts, body) =
+                    http::Request::get(url).body(Bytes::new())?.into_parts();
+                let (_, status) =
+                    Box::pin(cache.run_http(parts, body, |_| async {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        Ok(http::Response::builder()
+                            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+                            .body(Bytes::from_static(TEST_BODY))?)
+                    }))
+                    .await?;
+                assert_eq!(status.cache, HitOrMiss::MISS);
+            }
+            assert_eq!(calls.load(Ordering::SeqCst), 2);
+            Ok(())
+        }
+
+        #[cfg(feature = "manager-moka")]
+        #[async_std::test]
+        async fn only_if_cached_miss() -> anyhow::Result<()> {
+            let cache =
+                HttpCache::new(CacheMode::OnlyIfCached, MokaManager::default());
+            let calls = AtomicUsize::new(0);
+            let (parts, body) = http::Request::get("http://example.com/")
+                .body(Bytes::new())?
+                .into_parts();
+            let (res, status) =
+                Box::pin(cache.run_http(parts, body, |req| async {
+                    respond(&calls, req)
+                }))
+                .await?;
+            assert_eq!(res.status(), 504);
+            assert_eq!(status.lookup, HitOrMiss::MISS);
+            assert_eq!(calls.load(Ordering::SeqCst), 0);
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,399 @@
+use crate::*;
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use http_cache_memcached::MemcachedManager;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::{TcpListener, TcpStream},
+};
+
+// The largest value the fake server takes
+const ITEM_SIZE_LIMIT: usize = 2048;
+
+// The longest key memcached takes
+const KEY_MAX_LENGTH: usize = 250;
+
+// A stored value along with the expiration time it was set with
+type Stored = (Vec<u8>, u64);
+
+// A stand-in for a memcached server, answering the commands the manager
+// sends
+#[derive(Clone, Default)]
+struct FakeMemcached {
+    records: Arc<Mutex<HashMap<String, Stored>>>,
+    connections: Arc<AtomicUsize>,
+    // Closes every connection once it answered a command, as a server
+    // dropping idle connections would
+    closes_connections: Arc<AtomicBool>,
+}
+
+impl FakeMemcached {
+    // Starts the server, returning it along with its url
+    async fn start() -> anyhow::Result<(Self, String)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("memcache://{}", listener.local_addr()?);
+        let memcached = Self::default();
+        let server = memcached.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                server.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(server.clone().serve(stream));
+            }
+        });
+        Ok((memcached, url))
+    }
+
+    async fn serve(self, stream: TcpStream) {
+        let mut stream = BufStream::new(stream);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match stream.read_line(&mut line).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            let args = line.split_whitespace().collect::<Vec<_>>();
+            let reply = match args[..] {
+                ["get", ref keys @ ..] => {
+                    let records = self.records.lock().unwrap();
+                    let mut reply = Vec::new();
+                    for key in keys {
+                        if let Some((value, _)) = records.get(*key) {
+                            let header =
+                                format!("VALUE {key} 0 {}\r\n", value.len());
+                            reply.extend_from_slice(header.as_bytes());
+                            reply.extend_from_slice(value);
+                            reply.extend_from_slice(b"\r\n");
+                        }
+                    }
+                    reply.extend_from_slice(b"END\r\n");
+                    reply
+                }
+                // Rejected before the value is read, which memcached then
+                // reads as a command
+                ["set", key, ..] if key.len() > KEY_MAX_LENGTH => {
+                    b"CLIENT_ERROR bad command line format\r\n".to_vec()
+                }
+                ["set", key, _, exptime, len] => {
+                    let len: usize = len.parse().unwrap();
+                    let mut value = vec![0; len + 2];
+                    if stream.read_exact(&mut value).await.is_err() {
+                        return;
+                    }
+                    value.truncate(len);
+                    if len > ITEM_SIZE_LIMIT {
+                        b"SERVER_ERROR object too large for cache\r\n".to_vec()
+                    } else {
+                        let exptime = exptime.parse().unwrap();
+                        self.records
+                            .lock()
+                            .unwrap()
+                            .insert(key.to_string(), (value, exptime));
+                        b"STORED\r\n".to_vec()
+                    }
+                }
+                ["delete", key] => {
+                    match self.records.lock().unwrap().remove(key) {
+                        Some(_) => b"DELETED\r\n".to_vec(),
+                        None => b"NOT_FOUND\r\n".to_vec(),
+                    }
+                }
+                _ => b"ERROR\r\n".to_vec(),
+            };
+            if stream.write_all(&reply).await.is_err()
+                || stream.flush().await.is_err()
+                || self.closes_connections.load(Ordering::SeqCst)
+            {
+                return;
+            }
+        }
+    }
+}
+
+fn record(
+    url: &Url,
+    cache_control: &str,
+    body: Vec<u8>,
+) -> anyhow::Result<(HttpResponse, CachePolicy)> {
+    let res = HttpResponse {
+        body: body.into(),
+        headers: HttpHeaders::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(())?;
+    let parts = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, cache_control)
+        .body(())?;
+    Ok((res, CachePolicy::new(&req, &parts)))
+}
+
+#[tokio::test]
+async fn stores_records_with_a_prefix_and_ttl() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let mut manager = MemcachedManager::new(&url)?;
+    manager.prefix = "test:".to_string();
+    manager.stale_ttl = Duration::from_secs(30);
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let (res, policy) = record(&url, "max-age=60", TEST_BODY.to_vec())?;
+    manager.put(&key, res, policy).await?;
+
+    let stored = format!("test:{}", key.hash());
+    let (_, exptime) = memcached.records.lock().unwrap()[&stored].clone();
+    // The freshness lifetime along with `stale_ttl`
+    assert!((89..=90).contains(&exptime), "{exptime}");
+
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, TEST_BODY);
+    assert_eq!(res.url, url);
+
+    manager.delete(&key).await?;
+    assert!(memcached.records.lock().unwrap().is_empty());
+    assert!(manager.get(&key).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn sets_long_ttls_as_timestamps() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let mut manager = MemcachedManager::new(&url)?;
+    manager.stale_ttl = Duration::ZERO;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let max_age = 60 * 60 * 24 * 40;
+    let cache_control = format!("max-age={max_age}");
+    let (res, policy) = record(&url, &cache_control, TEST_BODY.to_vec())?;
+    manager.put(&key, res, policy).await?;
+
+    let (_, exptime) = memcached.records.lock().unwrap()
+        [&format!("http-cache:{}", key.hash())]
+        .clone();
+    let now = now().duration_since(UNIX_EPOCH)?.as_secs();
+    assert!((now + max_age - 2..=now + max_age + 1).contains(&exptime));
+    Ok(())
+}
+
+#[tokio::test]
+async fn splits_large_records() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let mut manager = MemcachedManager::new(&url)?;
+    manager.chunk_size = 1024;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let body = (0..5000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC, body.clone())?;
+    let encoded = encode_record(
+        &res,
+        &policy,
+        SerializationFormat::Bincode,
+        Some(BodyChecksum::Crc32),
+    )?;
+    manager.put(&key, res, policy).await?;
+
+    // The item standing for the record and the chunks of the record
+    let chunks = encoded.len().div_ceil(1024);
+    assert_eq!(memcached.records.lock().unwrap().len(), 1 + chunks);
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, body);
+
+    // A record is dropped along with the other chunks once one is gone
+    let stored = format!("http-cache:{}", key.hash());
+    {
+        let mut records = memcached.records.lock().unwrap();
+        let chunk = records
+            .keys()
+            .find(|key| key.starts_with(&format!("{stored}:")))
+            .cloned()
+            .unwrap();
+        records.remove(&chunk);
+    }
+    assert!(manager.get(&key).await?.is_none());
+    assert!(!memcached.records.lock().unwrap().contains_key(&stored));
+
+    // Records larger than the server takes fail without splitting them
+    manager.chunk_size = 4096;
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC, body)?;
+    assert!(manager.put(&key, res, policy).await.is_err());
+    // The connection is still in step
+    assert!(manager.get(&key).await?.is_none());
+    assert_eq!(memcached.connections.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn checks_bodies_put_back_together() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let mut manager = MemcachedManager::new(&url)?;
+    manager.chunk_size = 1024;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let body = (0..5000).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC, body.clone())?;
+    manager.put(&key, res, policy).await?;
+
+    // The last byte of the body, at the end of the last chunk, is flipped
+    {
+        let mut records = memcached.records.lock().unwrap();
+        let last = records
+            .keys()
+            .filter(|key| key.matches(':').count() == 3)
+            .max_by_key(|key| {
+                key.rsplit(':').next().unwrap().parse::<u32>().unwrap()
+            })
+            .cloned()
+            .unwrap();
+        let (chunk, _) = records.get_mut(&last).unwrap();
+        *chunk.last_mut().unwrap() ^= 0xff;
+    }
+    assert!(matches!(
+        manager.get(&key).await,
+        Err(CacheError::CorruptEntry(_))
+    ));
+
+    // Unless the check is skipped
+    manager.checksum = None;
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body.len(), body.len());
+    assert_ne!(res.body, body);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reopens_closed_connections() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    memcached.closes_connections.store(true, Ordering::SeqCst);
+    let manager = MemcachedManager::new(&url)?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC, TEST_BODY.to_vec())?;
+    manager.put(&key, res, policy).await?;
+    // Sent again on a new connection once the idle one turns out closed
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, TEST_BODY);
+    assert_eq!(memcached.connections.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_connections_out_of_step() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let manager = MemcachedManager::new(&url)?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC, TEST_BODY.to_vec())?;
+    manager.put(&key, res.clone(), policy.clone()).await?;
+
+    // The value of a rejected key is read by the server as a command, the
+    // connection isn't used again
+    let mut long = manager.clone();
+    long.prefix = "a".repeat(KEY_MAX_LENGTH);
+    assert!(long.put(&key, res, policy).await.is_err());
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, TEST_BODY);
+    assert_eq!(memcached.connections.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_records_of_other_versions() -> anyhow::Result<()> {
+    let (memcached, url) = FakeMemcached::start().await?;
+    let manager = MemcachedManager::new(&url)?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let stored = format!("http-cache:{}", key.hash());
+
+    let mut newer = b"httpcac\xff".to_vec();
+    newer.extend_from_slice(&[CACHE_RECORD_VERSION + 1, 1]);
+    memcached.records.lock().unwrap().insert(stored.clone(), (newer, 1));
+    assert!(manager.get(&key).await?.is_none());
+    assert!(memcached.records.lock().unwrap().is_empty());
+
+    let mut truncated = b"httpcac\xff".to_vec();
+    truncated.extend_from_slice(&[CACHE_RECORD_VERSION, 1]);
+    memcached.records.lock().unwrap().insert(stored.clone(), (truncated, 1));
+    assert!(matches!(
+        manager.get(&key).await,
+        Err(CacheError::CorruptEntry(_))
+    ));
+    memcached
+        .records
+        .lock()
+        .unwrap()
+        .insert(stored, (b"httpchk\xff\x01".to_vec(), 1));
+    assert!(matches!(
+        manager.get(&key).await,
+        Err(CacheError::CorruptEntry(_))
+    ));
+
+    assert!(MemcachedManager::new("redis://127.0.0.1:11211").is_err());
+    Ok(())
+}
+
+// The server `real_server` runs against, which is skipped unless
+// `MEMCACHED_URL` is set
+fn memcached_url() -> Option<String> {
+    std::env::var("MEMCACHED_URL").ok()
+}
+
+// Sends a command to the server on a connection of its own, returning the
+// first line of the reply
+async fn raw_command(url: &str, command: &str) -> anyhow::Result<String> {
+    let url = Url::parse(url)?;
+    let host = url.host_str().unwrap_or("127.0.0.1");
+    let stream =
+        TcpStream::connect((host, url.port().unwrap_or(11211))).await?;
+    let mut stream = BufStream::new(stream);
+    stream.write_all(format!("{command}\r\n").as_bytes()).await?;
+    stream.flush().await?;
+    let mut line = String::new();
+    stream.read_line(&mut line).await?;
+    Ok(line.trim_end().to_string())
+}
+
+#[tokio::test]
+async fn real_server() -> anyhow::Result<()> {
+    let Some(url) = memcached_url() else { return Ok(()) };
+    let mut manager = MemcachedManager::with_max_connections(&url, 1)?;
+    // Apart from the records of other test runs
+    let run = now().duration_since(UNIX_EPOCH)?.as_nanos();
+    manager.prefix = format!("http-cache-test:{run:x}:");
+    manager.chunk_size = 64 * 1024;
+    let record_url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &record_url);
+    let body = (0..1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+    let (res, policy) = record(&record_url, "max-age=60", body.clone())?;
+    manager.put(&key, res.clone(), policy.clone()).await?;
+
+    // The chunks are read back in a single multi-key get
+    let (stored, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(stored.body, body);
+    let stored = format!("{}{}", manager.prefix, key.hash());
+    let ttl = raw_command(&url, &format!("mg {stored} t")).await?;
+    let ttl: u64 = ttl.trim_start_matches("HD t").parse()?;
+    // The freshness lifetime along with a day of `stale_ttl`
+    assert!((86_000..=86_460).contains(&ttl), "{ttl}");
+
+    // A key too long is rejected with `CLIENT_ERROR`, which leaves the
+    // connection out of step, the next command goes on a new one
+    let mut long = manager.clone();
+    long.prefix = "a".repeat(KEY_MAX_LENGTH);
+    let (small, small_policy) =
+        record(&record_url, "max-age=60", TEST_BODY.to_vec())?;
+    assert!(long.put(&key, small, small_policy).await.is_err());
+    assert_eq!(manager.get(&key).await?.unwrap().0.body, body);
+
+    manager.delete(&key).await?;
+    assert!(manager.get(&key).await?.is_none());
+    Ok(())
+}
@@ -0,0 +1,414 @@
+use crate::*;
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use http_cache_redis::RedisManager;
+use http_cache_reqwest::Cache;
+use reqwest::Client;
+use reqwest_middleware::ClientBuilder;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+    net::{TcpListener, TcpStream},
+};
+
+// A stored value along with the `PX` it was set with
+type Stored = (Vec<u8>, u64);
+
+// A stand-in for a Redis server, answering the commands the manager sends
+#[derive(Clone, Default)]
+struct FakeRedis {
+    records: Arc<Mutex<HashMap<Vec<u8>, Stored>>>,
+    // Every command received, with binary arguments mangled
+    commands: Arc<Mutex<Vec<String>>>,
+    connections: Arc<AtomicUsize>,
+    // Closes every connection once it answered a command, as a server
+    // dropping idle connections would
+    closes_connections: Arc<AtomicBool>,
+}
+
+impl FakeRedis {
+    // Starts the server, returning it along with its url
+    async fn start() -> anyhow::Result<(Self, String)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let redis = Self::default();
+        let server = redis.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                server.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(server.clone().serve(stream));
+            }
+        });
+        Ok((redis, addr.to_string()))
+    }
+
+    async fn serve(self, stream: TcpStream) {
+        let mut stream = BufStream::new(stream);
+        while let Some(args) = read_command(&mut stream).await {
+            let reply = self.reply(&args);
+            if stream.write_all(&reply).await.is_err()
+                || stream.flush().await.is_err()
+                || self.closes_connections.load(Ordering::SeqCst)
+            {
+                return;
+            }
+        }
+    }
+
+    fn reply(&self, args: &[Vec<u8>]) -> Vec<u8> {
+        let command = args
+            .iter()
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect::<Vec<_>>()
+            .join(" ");
+        self.commands.lock().unwrap().push(command);
+        let mut records = self.records.lock().unwrap();
+        match args {
+            [name, _, ..] if name == b"AUTH" => b"+OK\r\n".to_vec(),
+            [name, _] if name == b"SELECT" => b"+OK\r\n".to_vec(),
+            [name, key] if name == b"GET" => match records.get(key) {
+                Some((value, _)) => {
+                    let mut reply =
+                        format!("${}\r\n", value.len()).into_bytes();
+                    reply.extend_from_slice(value);
+                    reply.extend_from_slice(b"\r\n");
+                    reply
+                }
+                None => b"$-1\r\n".to_vec(),
+            },
+            [name, key, value, px, ttl] if name == b"SET" && px == b"PX" => {
+                let ttl = String::from_utf8_lossy(ttl).parse().unwrap();
+                records.insert(key.clone(), (value.clone(), ttl));
+                b"+OK\r\n".to_vec()
+            }
+            [name, key] if name == b"DEL" => {
+                format!(":{}\r\n", u8::from(records.remove(key).is_some()))
+                    .into_bytes()
+            }
+            _ => b"-ERR unknown command\r\n".to_vec(),
+        }
+    }
+}
+
+async fn read_command(
+    stream: &mut BufStream<TcpStream>,
+) -> Option<Vec<Vec<u8>>> {
+    let mut line = String::new();
+    stream.read_line(&mut line).await.ok()?;
+    let count: usize = line.trim_end().strip_prefix('*')?.parse().ok()?;
+    let mut args = Vec::with_capacity(count);
+    for _ in 0..count {
+        line.clear();
+        stream.read_line(&mut line).await.ok()?;
+        let len: usize = line.trim_end().strip_prefix('$')?.parse().ok()?;
+        let mut arg = vec![0; len + 2];
+        stream.read_exact(&mut arg).await.ok()?;
+        arg.truncate(len);
+        args.push(arg);
+    }
+    Some(args)
+}
+
+fn record(
+    url: &Url,
+    cache_control: &str,
+) -> anyhow::Result<(HttpResponse, CachePolicy)> {
+    let res = HttpResponse {
+        body: Bytes::from_static(TEST_BODY),
+        headers: HttpHeaders::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(())?;
+    let parts = http::Response::builder()
+        .status(200)
+        .header(CACHE_CONTROL, cache_control)
+        .body(())?;
+    Ok((res, CachePolicy::new(&req, &parts)))
+}
+
+#[tokio::test]
+async fn stores_records_with_a_prefix_and_ttl() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    let mut manager = RedisManager::new(&format!("redis://{addr}"))?;
+    manager.prefix = "test:".to_string();
+    manager.stale_ttl = Duration::from_secs(30);
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let (res, policy) = record(&url, "max-age=60")?;
+    manager.put(&key, res, policy).await?;
+
+    let stored = format!("test:GET:{url}").into_bytes();
+    let (_, ttl) = redis.records.lock().unwrap()[&stored].clone();
+    // The freshness lifetime along with `stale_ttl`
+    assert!((88_000..=90_000).contains(&ttl), "{ttl}");
+
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, TEST_BODY);
+    assert_eq!(res.url, url);
+
+    manager.delete(&key).await?;
+    assert!(redis.records.lock().unwrap().is_empty());
+    assert!(manager.get(&key).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn stores_long_keys_as_digests() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    let manager = RedisManager::new(&format!("redis://{addr}"))?;
+    let url = Url::parse(&format!("http://example.com/{}", "a".repeat(4096)))?;
+    let key = CacheKey::new(GET, &url);
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC)?;
+    manager.put(&key, res, policy).await?;
+
+    let stored = format!("http-cache:{}", key.as_str()).into_bytes();
+    assert!(stored.len() < 100);
+    assert!(redis.records.lock().unwrap().contains_key(&stored));
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.url, url);
+    Ok(())
+}
+
+#[tokio::test]
+async fn drops_records_of_other_versions() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    let manager = RedisManager::new(&format!("redis://{addr}"))?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+    let stored = format!("http-cache:{}", key.as_str()).into_bytes();
+
+    let mut newer = b"httpcac\xff".to_vec();
+    newer.extend_from_slice(&[CACHE_RECORD_VERSION + 1, 1]);
+    redis.records.lock().unwrap().insert(stored.clone(), (newer, 1));
+    assert!(manager.get(&key).await?.is_none());
+    assert!(redis.records.lock().unwrap().is_empty());
+
+    let mut truncated = b"httpcac\xff".to_vec();
+    truncated.extend_from_slice(&[CACHE_RECORD_VERSION, 1]);
+    redis.records.lock().unwrap().insert(stored, (truncated, 1));
+    assert!(matches!(
+        manager.get(&key).await,
+        Err(CacheError::CorruptEntry(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn removes_records_with_damaged_bodies() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 2);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let (redis, addr) = FakeRedis::start().await?;
+    let manager = RedisManager::new(&format!("redis://{addr}"))?;
+    let client = ClientBuilder::new(Client::new())
+        .with(Cache(HttpCache {
+            mode: CacheMode::Default,
+            manager: manager.clone(),
+            options: HttpCacheOptions::default(),
+        }))
+        .build();
+    client.get(url.clone()).send().await?;
+
+    // The last byte of the body is flipped on the way to the server
+    let key = CacheKey::new(GET, &Url::parse(&url)?);
+    let stored = format!("http-cache:{}", key.as_str()).into_bytes();
+    let damaged = {
+        let mut records = redis.records.lock().unwrap();
+        let (record, _) = records.get_mut(&stored).unwrap();
+        *record.last_mut().unwrap() ^= 0xff;
+        record.clone()
+    };
+    assert!(matches!(
+        manager.get(&key).await,
+        Err(CacheError::CorruptEntry(_))
+    ));
+
+    // The cache removes it and fetches the response again
+    let res = client.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    let (record, _) = redis.records.lock().unwrap()[&stored].clone();
+    assert_ne!(record, damaged);
+    assert!(manager.get(&key).await?.is_some());
+    Ok(())
+}
+
+#[tokio::test]
+async fn shares_records_between_caches() -> anyhow::Result<()> {
+    let mock_server = MockServer::start().await;
+    let m = build_mock(CACHEABLE_PUBLIC, TEST_BODY, 200, 1);
+    let _mock_guard = mock_server.register_as_scoped(m).await;
+    let url = format!("{}/", &mock_server.uri());
+    let (_redis, addr) = FakeRedis::start().await?;
+    let client = || -> anyhow::Result<_> {
+        Ok(ClientBuilder::new(Client::new())
+            .with(Cache(HttpCache {
+                mode: CacheMode::Default,
+                manager: RedisManager::new(&format!("redis://{addr}"))?,
+                options: HttpCacheOptions::default(),
+            }))
+            .build())
+    };
+
+    let res = client()?.get(url.clone()).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), MISS);
+
+    // Another process with a cache of its own reads the same record
+    let res = client()?.get(url).send().await?;
+    assert_eq!(res.headers().get(XCACHE).unwrap(), HIT);
+    assert_eq!(res.bytes().await?, TEST_BODY);
+    Ok(())
+}
+
+#[tokio::test]
+async fn pools_connections() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    let manager =
+        RedisManager::with_max_connections(&format!("redis://{addr}"), 2)?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+
+    let gets = (0..20).map(|_| manager.get(&key));
+    for res in futures::future::join_all(gets).await {
+        assert!(res?.is_none());
+    }
+    let opened = redis.connections.load(Ordering::SeqCst);
+    assert!((1..=2).contains(&opened), "{opened}");
+
+    // Clones share the connections
+    manager.clone().get(&key).await?;
+    assert_eq!(redis.connections.load(Ordering::SeqCst), opened);
+    Ok(())
+}
+
+#[tokio::test]
+async fn reopens_closed_connections() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    redis.closes_connections.store(true, Ordering::SeqCst);
+    let manager = RedisManager::new(&format!("redis://{addr}"))?;
+    let url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &url);
+
+    let (res, policy) = record(&url, CACHEABLE_PUBLIC)?;
+    manager.put(&key, res, policy).await?;
+    // Sent again on a new connection once the idle one turns out closed
+    let (res, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(res.body, TEST_BODY);
+    assert_eq!(redis.connections.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn logs_in_with_the_url() -> anyhow::Result<()> {
+    let (redis, addr) = FakeRedis::start().await?;
+    let manager = RedisManager::new(&format!("redis://cache:p%40ss@{addr}/3"))?;
+    let debug = format!("{manager:?}");
+    assert!(!debug.contains("p@ss") && !debug.contains("p%40ss"));
+    let url = Url::parse("http://example.com/a")?;
+    manager.get(&CacheKey::new(GET, &url)).await?;
+    let commands = redis.commands.lock().unwrap().clone();
+    assert_eq!(commands[..2], ["AUTH cache p@ss", "SELECT 3"]);
+
+    assert!(RedisManager::new("http://127.0.0.1:6379").is_err());
+    assert!(RedisManager::new("redis://127.0.0.1:6379/cache").is_err());
+    Ok(())
+}
+
+// The server `real_server` runs against, which is skipped unless
+// `REDIS_URL` is set
+fn redis_url() -> Option<String> {
+    std::env::var("REDIS_URL").ok()
+}
+
+// A manager storing its records apart from the ones of other test runs
+fn real_manager(url: &str) -> anyhow::Result<RedisManager> {
+    let mut manager = RedisManager::with_max_connections(url, 1)?;
+    let run = now().duration_since(UNIX_EPOCH)?.as_nanos();
+    manager.prefix = format!("http-cache-test:{run}:");
+    Ok(manager)
+}
+
+// Sends a command to the server on a connection of its own, returning the
+// first line of the reply
+async fn raw_command(url: &str, args: &[&str]) -> anyhow::Result<String> {
+    let url = Url::parse(url)?;
+    let host = url.host_str().unwrap_or("127.0.0.1");
+    let stream = TcpStream::connect((host, url.port().unwrap_or(6379))).await?;
+    let mut stream = BufStream::new(stream);
+    let mut commands = Vec::new();
+    if let Some(password) = url.password() {
+        commands.push(match url.username() {
+            "" => vec!["AUTH", password],
+            username => vec!["AUTH", username, password],
+        });
+    }
+    let database = url.path().trim_start_matches('/');
+    if !database.is_empty() {
+        commands.push(vec!["SELECT", database]);
+    }
+    commands.push(args.to_vec());
+    let mut line = String::new();
+    for args in commands {
+        let mut command = format!("*{}\r\n", args.len());
+        for arg in args {
+            command.push_str(&format!("${}\r\n{arg}\r\n", arg.len()));
+        }
+        stream.write_all(command.as_bytes()).await?;
+        stream.flush().await?;
+        line.clear();
+        stream.read_line(&mut line).await?;
+    }
+    Ok(line.trim_end().to_string())
+}
+
+// A single test, as killing the connections of the manager kills the ones
+// of any other test running alongside
+#[tokio::test]
+async fn real_server() -> anyhow::Result<()> {
+    let Some(url) = redis_url() else { return Ok(()) };
+    let manager = real_manager(&url)?;
+    let record_url = Url::parse("http://example.com/a")?;
+    let key = CacheKey::new(GET, &record_url);
+    let (mut res, policy) = record(&record_url, "max-age=60")?;
+    res.body =
+        (0..1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<_>>().into();
+    manager.put(&key, res.clone(), policy).await?;
+
+    let (stored, _) = manager.get(&key).await?.unwrap();
+    assert_eq!(stored.body, res.body);
+    let stored = format!("{}{}", manager.prefix, key.as_str());
+    let ttl = raw_command(&url, &["PTTL", &stored]).await?;
+    let ttl: u64 = ttl.trim_start_matches(':').parse()?;
+    // The freshness lifetime along with a day of `stale_ttl`
+    assert!((86_000_000..=86_460_000).contains(&ttl), "{ttl}");
+
+    // The idle connection is found closed and the command sent again
+    let killed = raw_command(
+        &url,
+        &["CLIENT", "KILL", "TYPE", "normal", "SKIPME", "yes"],
+    )
+    .await?;
+    assert_ne!(killed, ":0");
+    assert!(manager.get(&key).await?.is_some());
+
+    manager.delete(&key).await?;
+    assert!(manager.get(&key).await?.is_none());
+
+    // `WRONGTYPE`, with the next command still answered in step
+    let list = CacheKey::new(GET, &Url::parse("http://example.com/list")?);
+    let stored = format!("{}{}", manager.prefix, list.as_str());
+    raw_command(&url, &["RPUSH", &stored, "item"]).await?;
+    assert!(manager.get(&list).await.is_err());
+    assert!(manager.get(&key).await?.is_none());
+    manager.delete(&list).await?;
+    Ok(())
+}
@@ -0,0 +1,209 @@
+use crate::*;
+use std::{
+    future::poll_fn,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use http::header::{ACCEPT_LANGUAGE, AGE, VARY};
+use http_cache_tower::{BoxError, ResponseBody, ResponseCacheLayer};
+use hyper::{
+    body::to_bytes, ext::ReasonPhrase, service::service_fn, Body, Request,
+    Response,
+};
+use tower_service::Service;
+
+const CACHE_STATUS: &str = "cache-status";
+
+// Wraps a handler answering with `cache_control` and the accept-language
+// of the request, along with a count of the times it ran
+fn server<T: CacheManager>(
+    cache: HttpCache<T>,
+    cache_control: &'static str,
+) -> (
+    impl Service<Request<Body>, Response = Response<ResponseBody>, Error = BoxError>,
+    Arc<AtomicUsize>,
+) {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counted = Arc::clone(&calls);
+    let handler = service_fn(move |req: Request<Body>| {
+        counted.fetch_add(1, Ordering::SeqCst);
+        let language = match req.headers().get(ACCEPT_LANGUAGE) {
+            Some(language) => Body::from(language.as_bytes().to_vec()),
+            None => Body::from(TEST_BODY),
+        };
+        async move {
+            Response::builder()
+                .header(CACHE_CONTROL, cache_control)
+                .header(VARY, "accept-language")
+                .body(language)
+        }
+    });
+    (ResponseCacheLayer::new(cache).layer(handler), calls)
+}
+
+// Sends the request once the service is ready, like `tower::ServiceExt::oneshot`
+async fn send<S>(
+    service: &mut S,
+    req: Request<Body>,
+) -> anyhow::Result<Response<ResponseBody>>
+where
+    S: Service<
+        Request<Body>,
+        Response = Response<ResponseBody>,
+        Error = BoxError,
+    >,
+{
+    poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    service.call(req).await.map_err(|e| anyhow::anyhow!(e))
+}
+
+fn get(path: &str, language: Option<&str>) -> anyhow::Result<Request<Body>> {
+    let mut req = Request::get(path);
+    if let Some(language) = language {
+        req = req.header(ACCEPT_LANGUAGE, language);
+    }
+    Ok(req.body(Body::empty())?)
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn caches_handler_responses() -> anyhow::Result<()> {
+    let manager = MokaManager::default();
+    let (mut server, calls) = server(
+        HttpCache::new(CacheMode::Default, manager.clone()),
+        CACHEABLE_PUBLIC,
+    );
+
+    // Cold pass runs the handler and stores its response
+    let res = send(&mut server, get("/page?id=1", None)?).await?;
+    assert_eq!(
+        res.headers().get(CACHE_STATUS).unwrap(),
+        "http-cache; fwd=uri-miss"
+    );
+    let url = Url::parse("http://localhost/page?id=1")?;
+    assert!(manager.get(&CacheKey::new(GET, &url)).await?.is_some());
+
+    // Hot pass is answered without the handler
+    let res = send(&mut server, get("/page?id=1", None)?).await?;
+    assert_eq!(res.headers().get(CACHE_STATUS).unwrap(), "http-cache; hit");
+    assert!(res.headers().get(AGE).is_some());
+    assert_eq!(to_bytes(res.into_body()).await.unwrap(), TEST_BODY);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // Another query is another record
+    let res = send(&mut server, get("/page?id=2", None)?).await?;
+    assert_eq!(
+        res.headers().get(CACHE_STATUS).unwrap(),
+        "http-cache; fwd=uri-miss"
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn varies_on_request_headers() -> anyhow::Result<()> {
+    let (mut server, calls) = server(
+        HttpCache::new(CacheMode::Default, MokaManager::default()),
+        CACHEABLE_PUBLIC,
+    );
+
+    send(&mut server, get("/", Some("en"))?).await?;
+    let res = send(&mut server, get("/", Some("en"))?).await?;
+    assert_eq!(res.headers().get(CACHE_STATUS).unwrap(), "http-cache; hit");
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+    // A different language doesn't match the stored response
+    let res = send(&mut server, get("/", Some("fr"))?).await?;
+    assert_ne!(res.headers().get(CACHE_STATUS).unwrap(), "http-cache; hit");
+    assert_eq!(to_bytes(res.into_body()).await.unwrap(), "fr");
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn private_responses_are_not_stored() -> anyhow::Result<()> {
+    let (mut server, calls) = server(
+        HttpCache::new(CacheMode::Default, MokaManager::default()),
+        CACHEABLE_PRIVATE,
+    );
+
+    for _ in 0..2 {
+        let res = send(&mut server, get("/", None)?).await?;
+        assert_eq!(
+            res.headers().get(CACHE_STATUS).unwrap(),
+            "http-cache; fwd=uri-miss"
+        );
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn passes_other_methods_through() -> anyhow::Result<()> {
+    let (mut server, calls) = server(
+        HttpCache::new(CacheMode::Default, MokaManager::default()),
+        CACHEABLE_PUBLIC,
+    );
+
+    for _ in 0..2 {
+        let req = Request::post("/").body(Body::from("form"))?;
+        let res = send(&mut server, req).await?;
+        assert!(res.headers().get(CACHE_STATUS).is_none());
+        assert_eq!(to_bytes(res.into_body()).await.unwrap(), TEST_BODY);
+    }
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_trailers() -> anyhow::Result<()> {
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    let handler = service_fn(|_req: Request<Body>| async {
+        let res = Response::builder()
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(trailed_body().await?)?;
+        Ok::<_, anyhow::Error>(res)
+    });
+    let mut server = ResponseCacheLayer::new(cache).layer(handler);
+
+    for status in ["http-cache; fwd=uri-miss", "http-cache; hit"] {
+        let res = send(&mut server, get("/page", None)?).await?;
+        assert_eq!(res.headers().get(CACHE_STATUS).unwrap(), status);
+        let (body, trailers) = read_trailed(res.into_body()).await?;
+        assert_eq!(body, TEST_BODY);
+        assert_eq!(trailers, Some(test_trailers()));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "manager-moka")]
+#[tokio::test]
+async fn keeps_the_reason_phrase() -> anyhow::Result<()> {
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    let handler = service_fn(|_req: Request<Body>| async {
+        let mut res = Response::builder()
+            .header(CACHE_CONTROL, CACHEABLE_PUBLIC)
+            .body(Body::from(TEST_BODY))?;
+        res.extensions_mut()
+            .insert(ReasonPhrase::from_static(REASON_PHRASE.as_bytes()));
+        Ok::<_, http::Error>(res)
+    });
+    let mut server = ResponseCacheLayer::new(cache).layer(handler);
+
+    for status in ["http-cache; fwd=uri-miss", "http-cache; hit"] {
+        let res = send(&mut server, get("/page", None)?).await?;
+        assert_eq!(res.headers().get(CACHE_STATUS).unwrap(), status);
+        let reason = res.extensions().get::<ReasonPhrase>().unwrap();
+        assert_eq!(reason.as_bytes(), REASON_PHRASE.as_bytes());
+    }
+    Ok(())
+}
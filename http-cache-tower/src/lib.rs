@@ -0,0 +1,515 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! The tower middleware implementation for http-cache, which lets the cache
+//! wrap any client [`Service`] such as a hyper `Client`.
+//! ```no_run
+//! use http_cache_tower::{
+//!     BoxError, CacheLayer, CacheMode, CACacheManager, HttpCache,
+//!     HttpCacheOptions,
+//! };
+//! use hyper::{Body, Client, Request};
+//! use tower_service::Service;
+//!
+//! #[tokio::main]
+//! async fn main() -> Result<(), BoxError> {
+//!     let layer = CacheLayer::new(HttpCache {
+//!         mode: CacheMode::Default,
+//!         manager: CACacheManager::default(),
+//!         options: HttpCacheOptions::default(),
+//!     });
+//!     let mut client = layer.layer(Client::new());
+//!     let req = Request::get("http://developer.mozilla.org/en-US/docs/Web/HTTP/Caching")
+//!         .body(Body::empty())?;
+//!     futures::future::poll_fn(|cx| client.poll_ready(cx)).await?;
+//!     client.call(req).await?;
+//!     Ok(())
+//! }
+//! ```
+//!
+//! ## Bodies
+//!
+//! Requests going through the cache have their body collected up front so
+//! it can be sent again, and responses are read in full before being stored
+//! or returned. Both body types are built back from [`Bytes`], which hyper's
+//! `Body` supports. Requests rejected by
+//! [`HttpCacheOptions::should_cache_request`] are passed on untouched.
+//!
+//! The trailers following the body of a response are stored along with it
+//! and restored on responses served from the cache, as long as the body
+//! type can carry them, see [`CachedBody`]. Server side responses always
+//! carry them.
+//!
+//! Requests need an absolute uri, as the cache keys records by url. Request
+//! extensions are only passed on with the first request sent to the inner
+//! service. Responses going through the cache are built back from what was
+//! stored, so the only extension they carry is the `hyper::ext::ReasonPhrase`
+//! hyper keeps for HTTP/1 responses that don't use the canonical one.
+//!
+//! ## Tower layers
+//!
+//! [`CacheLayer::layer`] has the signature of `tower::Layer::layer`, so the
+//! layer can be added to a `tower::ServiceBuilder` with
+//! `.layer_fn(|inner| cache_layer.layer(inner))`.
+//!
+//! ## Server side
+//!
+//! [`ResponseCacheLayer`] does the same for the services answering requests
+//! in a server, keeping the responses of expensive handlers around for as
+//! long as their `Cache-Control` headers allow.
+mod server;
+
+use std::{
+    convert::{TryFrom, TryInto},
+    fmt,
+    future::poll_fn,
+    marker::PhantomData,
+    str::FromStr,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use anyhow::anyhow;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::future::{BoxFuture, FutureExt};
+use http::{
+    header::{HeaderName, CACHE_CONTROL},
+    request::Parts,
+    HeaderMap, HeaderValue, Method,
+};
+use http_body::{Body, Full};
+use http_cache::{CacheError, CacheManager, Middleware, Result};
+use http_cache_semantics::CachePolicy;
+use hyper::ext::ReasonPhrase;
+use tower_service::Service;
+use url::Url;
+
+pub use http_cache::{
+    BreakerState, CacheDecision, CacheEvent, CacheMode, CacheOptions,
+    CacheStatus, CacheToggle, CircuitBreaker, Clock, Coalescing, ContentTypes,
+    DryRun, DryRunLookup, EntryMetadata, ErrorPolicy, EventBroadcast,
+    HealthReport, HitOrMiss, HttpCache, HttpCacheOptions, HttpHeaders,
+    HttpResponse, LeaderFailure, ManagerOverride, NotStoredReason, RecordMode,
+    Redaction, RequestPredicate, ResponsePredicate, RevalidationHook,
+    RevalidationRequest, Spawner, StoreHook, UrlFilter, WarmOutcome,
+    WarmReport, WriteBehind, WriteBehindStats,
+};
+
+#[cfg(feature = "manager-cacache")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
+pub use http_cache::CACacheManager;
+
+#[cfg(feature = "manager-moka")]
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
+pub use http_cache::{MokaCache, MokaCacheBuilder, MokaManager};
+
+pub use server::{ResponseBody, ResponseCacheLayer, ResponseCacheService};
+
+/// The error returned by a [`CacheService`], errors of the inner service
+/// are passed on as is.
+pub type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Wraps services in a [`CacheService`] that share the same [`HttpCache`].
+pub struct CacheLayer<T: CacheManager> {
+    cache: Arc<HttpCache<T>>,
+}
+
+impl<T: CacheManager + fmt::Debug> fmt::Debug for CacheLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheLayer").field("cache", &self.cache).finish()
+    }
+}
+
+impl<T: CacheManager> Clone for CacheLayer<T> {
+    fn clone(&self) -> Self {
+        Self { cache: Arc::clone(&self.cache) }
+    }
+}
+
+impl<T: CacheManager> CacheLayer<T> {
+    /// Creates a layer caching responses with `cache`.
+    pub fn new(cache: HttpCache<T>) -> Self {
+        Self { cache: Arc::new(cache) }
+    }
+
+    /// Wraps `inner` so its responses go through the cache.
+    pub fn layer<S>(&self, inner: S) -> CacheService<S, T> {
+        CacheService { inner, cache: Arc::clone(&self.cache) }
+    }
+
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.cache.manager()
+    }
+}
+
+/// A [`Service`] that runs requests through an [`HttpCache`] before handing
+/// them to the inner service, see [`CacheLayer`].
+///
+/// Requests the cache never stores the response to, in the
+/// [`CacheMode::NoStore`] mode or using methods other than `GET` and `HEAD`,
+/// are handed to the inner service with their body, and its response is
+/// returned without its body being read, see [`HttpCache::passes_through`].
+pub struct CacheService<S, T: CacheManager> {
+    inner: S,
+    cache: Arc<HttpCache<T>>,
+}
+
+impl<S: fmt::Debug, T: CacheManager + fmt::Debug> fmt::Debug
+    for CacheService<S, T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheService")
+            .field("inner", &self.inner)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: CacheManager> Clone for CacheService<S, T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), cache: Arc::clone(&self.cache) }
+    }
+}
+
+impl<S, T: CacheManager> CacheService<S, T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.cache.manager()
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<http::Request<ReqBody>>
+    for CacheService<S, T>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    T: CacheManager,
+    ReqBody: Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: Body + CachedBody + Send + 'static,
+    ResBody::Data: Send,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = http::Response<ResBody>;
+    type Error = BoxError;
+    type Future =
+        BoxFuture<'static, std::result::Result<Self::Response, BoxError>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        // The service that was polled ready goes along with the request,
+        // leaving a clone behind to be polled for the next one
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let cache = Arc::clone(&self.cache);
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let mut middleware = TowerMiddleware {
+                parts,
+                body: Bytes::new(),
+                inner,
+                url: absolute_url,
+                _body: PhantomData,
+            };
+            if !cache.can_cache_request(&middleware)? {
+                let TowerMiddleware { parts, mut inner, .. } = middleware;
+                let req = http::Request::from_parts(parts, body);
+                return inner.call(req).await.map_err(Into::into);
+            }
+            if cache.passes_through(&middleware) {
+                return Ok(pass_through(&cache, middleware, body).await?);
+            }
+            middleware.body = collect(body).await?;
+            let res = cache.run(middleware).await?;
+            Ok(convert_response(res, cache.options.trailers_as_headers)?)
+        })
+    }
+}
+
+/// Implements ['Middleware'] for tower
+pub(crate) struct TowerMiddleware<S, ReqBody> {
+    pub parts: Parts,
+    pub body: Bytes,
+    pub inner: S,
+    // Derives the url records are keyed by from the request
+    pub url: fn(&Parts) -> Result<Url>,
+    _body: PhantomData<fn() -> ReqBody>,
+}
+
+impl<S, ReqBody, ResBody> Middleware for TowerMiddleware<S, ReqBody>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>
+        + Send,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: From<Bytes> + Send,
+    ResBody: Body + Send,
+    ResBody::Data: Send,
+    ResBody::Error: Into<BoxError>,
+{
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == Method::GET || self.parts.method == Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            http_cache::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
+        Ok(())
+    }
+    fn parts(&self) -> Result<Parts> {
+        let mut converted = http::Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.url()?.as_str())
+            .version(self.parts.version)
+            .body(())?;
+        *converted.headers_mut() = self.parts.headers.clone();
+        Ok(converted.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        (self.url)(&self.parts)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.as_ref().to_string())
+    }
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        self.parts.extensions.get::<ManagerOverride>().cloned()
+    }
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        self.parts.extensions.get::<EntryMetadata>().cloned()
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let res = self.send(ReqBody::from(self.body.clone())).await?;
+        let (parts, body) = res.into_parts();
+        let (body, trailers) = collect_trailed(body).await.map_err(general)?;
+        Ok(HttpResponse {
+            body,
+            headers: HttpHeaders::from(&parts.headers),
+            trailers: trailers.as_ref().map(HttpHeaders::from),
+            status: parts.status.into(),
+            reason: reason(&parts.extensions),
+            url: self.url()?,
+            version: parts.version.try_into()?,
+            metadata: Default::default(),
+        })
+    }
+}
+
+impl<S, ReqBody, ResBody> TowerMiddleware<S, ReqBody>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>
+        + Send,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+{
+    // Sends the request to the inner service with `body`, carrying the
+    // headers set by the cache
+    async fn send(&mut self, body: ReqBody) -> Result<http::Response<ResBody>> {
+        poll_fn(|cx| self.inner.poll_ready(cx)).await.map_err(general)?;
+        let mut req = http::Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.parts.uri.clone())
+            .version(self.parts.version)
+            .body(body)?;
+        *req.headers_mut() = self.parts.headers.clone();
+        *req.extensions_mut() = std::mem::take(&mut self.parts.extensions);
+        self.inner.call(req).await.map_err(general)
+    }
+}
+
+// Sends a request the cache passes through along with its body, leaving the
+// body of the response to be read by the caller
+async fn pass_through<T, S, ReqBody, ResBody>(
+    cache: &HttpCache<T>,
+    mut middleware: TowerMiddleware<S, ReqBody>,
+    body: ReqBody,
+) -> Result<http::Response<ResBody>>
+where
+    T: CacheManager,
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>
+        + Send,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    ReqBody: From<Bytes> + Send,
+    ResBody: Body + Send,
+    ResBody::Data: Send,
+    ResBody::Error: Into<BoxError>,
+{
+    let mut upstream = middleware.send(body).await?;
+    let mut res = HttpResponse {
+        body: Bytes::new(),
+        headers: HttpHeaders::from(upstream.headers()),
+        trailers: None,
+        status: upstream.status().into(),
+        reason: reason(upstream.extensions()),
+        url: middleware.url()?,
+        version: upstream.version().try_into()?,
+        metadata: Default::default(),
+    };
+    cache.pass_through(middleware, &mut res).await?;
+    *upstream.headers_mut() = convert_headers(&res.headers)?;
+    Ok(upstream)
+}
+
+// Keys client requests by their absolute uri
+fn absolute_url(parts: &Parts) -> Result<Url> {
+    Ok(Url::parse(&parts.uri.to_string())?)
+}
+
+fn general(e: impl Into<BoxError>) -> CacheError {
+    CacheError::General(anyhow!(e.into()))
+}
+
+// Reads a body to the end
+async fn collect<B>(body: B) -> std::result::Result<Bytes, BoxError>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    read_data(std::pin::pin!(body)).await
+}
+
+// Reads a body to the end along with the trailers following it
+async fn collect_trailed<B>(
+    body: B,
+) -> std::result::Result<(Bytes, Option<HeaderMap>), BoxError>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    let mut body = std::pin::pin!(body);
+    let data = read_data(body.as_mut()).await?;
+    let trailers = body.trailers().await.map_err(Into::into)?;
+    Ok((data, trailers))
+}
+
+async fn read_data<B>(
+    mut body: std::pin::Pin<&mut B>,
+) -> std::result::Result<Bytes, BoxError>
+where
+    B: Body,
+    B::Error: Into<BoxError>,
+{
+    let mut collected = BytesMut::new();
+    while let Some(chunk) = body.data().await {
+        collected.put(chunk.map_err(Into::into)?);
+    }
+    Ok(collected.freeze())
+}
+
+/// The body of the responses handed back by a [`CacheService`], built back
+/// from the body and trailers that were stored.
+///
+/// hyper's `Body` carries the trailers. Bodies that can't, such as
+/// [`Full`](http_body::Full), drop them unless they are added to the headers
+/// with [`HttpCacheOptions::trailers_as_headers`].
+pub trait CachedBody: From<Bytes> {
+    /// Builds a body yielding `data` followed by `trailers`, `None` if the
+    /// body can't carry trailers.
+    fn with_trailers(data: Bytes, trailers: HeaderMap) -> Option<Self> {
+        let _ = (data, trailers);
+        None
+    }
+}
+
+impl CachedBody for hyper::Body {
+    fn with_trailers(data: Bytes, trailers: HeaderMap) -> Option<Self> {
+        let (mut sender, body) = hyper::Body::channel();
+        // The channel holds a chunk and the trailers until they are read,
+        // so neither send has to wait
+        if !data.is_empty() {
+            sender.try_send_data(data).ok()?;
+        }
+        sender.send_trailers(trailers).now_or_never()?.ok()?;
+        Some(body)
+    }
+}
+
+impl<D: Buf + From<Bytes>> CachedBody for Full<D> {}
+
+// Converts an [`HttpResponse`] to an http [`Response`](http::Response),
+// adding the trailers the body can't carry to the headers if
+// `trailers_as_headers` is set
+fn convert_response<B: CachedBody>(
+    mut response: HttpResponse,
+    trailers_as_headers: bool,
+) -> anyhow::Result<http::Response<B>> {
+    let mut body = None;
+    if let Some(trailers) = &response.trailers {
+        let trailers = convert_headers(trailers)?;
+        body = B::with_trailers(response.body.clone(), trailers);
+        if body.is_none() && trailers_as_headers {
+            response.trailers_into_headers();
+        }
+    }
+    let body = body.unwrap_or_else(|| B::from(response.body));
+    let mut ret_res = http::Response::builder()
+        .status(response.status)
+        .version(response.version.into())
+        .body(body)?;
+    *ret_res.headers_mut() = convert_headers(&response.headers)?;
+    if let Some(reason) =
+        response.reason.and_then(|r| ReasonPhrase::try_from(r).ok())
+    {
+        ret_res.extensions_mut().insert(reason);
+    }
+    Ok(ret_res)
+}
+
+// Reads the reason phrase hyper keeps for HTTP/1 responses that don't use
+// the canonical one
+fn reason(extensions: &http::Extensions) -> Option<String> {
+    let reason = extensions.get::<ReasonPhrase>()?;
+    Some(String::from_utf8_lossy(reason.as_bytes()).into_owned())
+}
+
+// Converts the headers of an [`HttpResponse`], keeping repeated values
+fn convert_headers(headers: &HttpHeaders) -> Result<HeaderMap> {
+    let mut converted = HeaderMap::with_capacity(headers.len());
+    for (name, value) in headers {
+        converted.append(HeaderName::from_str(name)?, value.clone());
+    }
+    Ok(converted)
+}
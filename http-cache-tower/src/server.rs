@@ -0,0 +1,214 @@
+use std::{
+    fmt,
+    marker::PhantomData,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use http::{request::Parts, HeaderValue};
+use http_body::{combinators::UnsyncBoxBody, Body};
+use http_cache::{
+    CacheManager, CacheStatus, HitOrMiss, HttpCache, Middleware, Result,
+};
+use tower_service::Service;
+use url::Url;
+
+use crate::{collect, convert_response, BoxError, TowerMiddleware};
+
+/// The name of the header telling how the cache handled a response, see
+/// [RFC 9211](https://www.rfc-editor.org/rfc/rfc9211).
+const CACHE_STATUS: &str = "cache-status";
+
+/// The body of the responses returned by a [`ResponseCacheService`].
+pub type ResponseBody = UnsyncBoxBody<Bytes, BoxError>;
+
+/// Wraps server side services, such as the handlers of a hyper or axum
+/// server, in a [`ResponseCacheService`] that shares the same [`HttpCache`].
+///
+/// Responses are stored and served following the same rules as on the
+/// client side, with the handler taking the place of the origin server. The
+/// cache is a shared one unless [`HttpCacheOptions::cache_options`](crate::HttpCacheOptions::cache_options)
+/// says otherwise, so responses marked `private` or answering requests with
+/// an `Authorization` header aren't stored.
+///
+/// ```no_run
+/// use http_cache_tower::{
+///     CacheMode, HttpCache, HttpCacheOptions, MokaManager, ResponseCacheLayer,
+/// };
+/// use hyper::{service::service_fn, Body, Request, Response};
+///
+/// let layer = ResponseCacheLayer::new(HttpCache {
+///     mode: CacheMode::Default,
+///     manager: MokaManager::default(),
+///     options: HttpCacheOptions::default(),
+/// });
+/// let service = layer.layer(service_fn(|_req: Request<Body>| async {
+///     Response::builder()
+///         .header("cache-control", "max-age=60")
+///         .body(Body::from("expensive"))
+/// }));
+/// ```
+///
+/// Only `GET` and `HEAD` requests go through the cache, others are handed
+/// to the service untouched and don't invalidate stored responses. Records
+/// are keyed by method, path and query, the `Host` of the request isn't
+/// part of the key, and headers listed in `Vary` by a stored response must
+/// match for it to be reused. As with the client side, only the last
+/// variant of a response is kept. Cached responses are buffered in full.
+///
+/// Responses going through the cache carry an `Age` header when served from
+/// it, and a `Cache-Status` header naming the cache `http-cache` along with
+/// whether it was a `hit`, a `fwd=stale` revalidation or a `fwd=uri-miss`.
+///
+/// Errors of the cache and the service are returned as a [`BoxError`], use
+/// axum's `HandleErrorLayer` to turn them into responses.
+pub struct ResponseCacheLayer<T: CacheManager> {
+    cache: Arc<HttpCache<T>>,
+}
+
+impl<T: CacheManager + fmt::Debug> fmt::Debug for ResponseCacheLayer<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseCacheLayer")
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl<T: CacheManager> Clone for ResponseCacheLayer<T> {
+    fn clone(&self) -> Self {
+        Self { cache: Arc::clone(&self.cache) }
+    }
+}
+
+impl<T: CacheManager> ResponseCacheLayer<T> {
+    /// Creates a layer caching responses with `cache`.
+    pub fn new(cache: HttpCache<T>) -> Self {
+        Self { cache: Arc::new(cache) }
+    }
+
+    /// Wraps `inner` so its responses go through the cache.
+    pub fn layer<S>(&self, inner: S) -> ResponseCacheService<S, T> {
+        ResponseCacheService { inner, cache: Arc::clone(&self.cache) }
+    }
+
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.cache.manager()
+    }
+}
+
+/// A [`Service`] that answers requests from an [`HttpCache`] when it can
+/// and stores the responses of the inner service otherwise, see
+/// [`ResponseCacheLayer`].
+pub struct ResponseCacheService<S, T: CacheManager> {
+    inner: S,
+    cache: Arc<HttpCache<T>>,
+}
+
+impl<S: fmt::Debug, T: CacheManager + fmt::Debug> fmt::Debug
+    for ResponseCacheService<S, T>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ResponseCacheService")
+            .field("inner", &self.inner)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+impl<S: Clone, T: CacheManager> Clone for ResponseCacheService<S, T> {
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), cache: Arc::clone(&self.cache) }
+    }
+}
+
+impl<S, T: CacheManager> ResponseCacheService<S, T> {
+    /// Returns the manager used by the wrapped [`HttpCache`].
+    pub fn manager(&self) -> &T {
+        self.cache.manager()
+    }
+}
+
+impl<S, T, ReqBody, ResBody> Service<http::Request<ReqBody>>
+    for ResponseCacheService<S, T>
+where
+    S: Service<http::Request<ReqBody>, Response = http::Response<ResBody>>
+        + Clone
+        + Send
+        + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    T: CacheManager,
+    ReqBody: Body + From<Bytes> + Send + 'static,
+    ReqBody::Data: Send,
+    ReqBody::Error: Into<BoxError>,
+    ResBody: Body<Data = Bytes> + Send + 'static,
+    ResBody::Error: Into<BoxError>,
+{
+    type Response = http::Response<ResponseBody>;
+    type Error = BoxError;
+    type Future =
+        BoxFuture<'static, std::result::Result<Self::Response, BoxError>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::result::Result<(), BoxError>> {
+        self.inner.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, req: http::Request<ReqBody>) -> Self::Future {
+        // The service that was polled ready goes along with the request,
+        // leaving a clone behind to be polled for the next one
+        let clone = self.inner.clone();
+        let inner = std::mem::replace(&mut self.inner, clone);
+        let cache = Arc::clone(&self.cache);
+        Box::pin(async move {
+            let (parts, body) = req.into_parts();
+            let mut middleware = TowerMiddleware {
+                parts,
+                body: Bytes::new(),
+                inner,
+                url: path_url,
+                _body: PhantomData,
+            };
+            if !middleware.is_method_get_head()
+                || !cache.can_cache_request(&middleware)?
+            {
+                let TowerMiddleware { parts, mut inner, .. } = middleware;
+                let req = http::Request::from_parts(parts, body);
+                let res = inner.call(req).await.map_err(Into::into)?;
+                return Ok(
+                    res.map(|body| body.map_err(Into::into).boxed_unsync())
+                );
+            }
+            middleware.body = collect(body).await?;
+            let res = cache.run(middleware).await?;
+            let status = CacheStatus::from(&res);
+            let mut res = convert_response::<hyper::Body>(res, false)?;
+            res.headers_mut().insert(
+                CACHE_STATUS,
+                HeaderValue::from_static(cache_status(status)),
+            );
+            Ok(res.map(|body| body.map_err(Into::into).boxed_unsync()))
+        })
+    }
+}
+
+// Keys server side requests by their path and query, requests to a server
+// only carry those in their uri
+fn path_url(parts: &Parts) -> Result<Url> {
+    let path = parts.uri.path_and_query().map_or("/", |p| p.as_str());
+    Ok(Url::parse(&format!("http://localhost{}", path))?)
+}
+
+// The value of the `Cache-Status` header for a response
+fn cache_status(status: CacheStatus) -> &'static str {
+    match (status.cache, status.lookup) {
+        (HitOrMiss::HIT, _) => "http-cache; hit",
+        (HitOrMiss::MISS, HitOrMiss::HIT) => "http-cache; fwd=stale",
+        (HitOrMiss::MISS, HitOrMiss::MISS) => "http-cache; fwd=uri-miss",
+    }
+}
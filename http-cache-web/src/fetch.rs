@@ -0,0 +1,239 @@
+use anyhow::anyhow;
+
+use http::{
+    header::{HeaderName, CACHE_CONTROL},
+    request::Parts,
+    HeaderMap, HeaderValue, Method,
+};
+use http_cache::{
+    CacheError, CacheManager, CacheOptions, HttpCache, HttpHeaders,
+    HttpResponse, HttpVersion, Middleware, Result,
+};
+use http_cache_semantics::CachePolicy;
+use js_sys::{Array, Promise, Uint8Array};
+use url::Url;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{
+    Headers, Request, RequestInit, Response, ResponseInit, Window,
+    WorkerGlobalScope,
+};
+
+/// Sends `request` through `cache`, using the `fetch` of the current window
+/// or worker when it needs to go upstream.
+///
+/// Errors thrown by `fetch` are passed on as they are, while cache errors
+/// are turned into a JavaScript `Error` holding their message. The
+/// returned future isn't `Send` and must be driven on the thread it was
+/// created on, which is what `wasm_bindgen_futures` does.
+pub async fn cached_fetch<T: CacheManager>(
+    cache: &HttpCache<T>,
+    request: Request,
+) -> std::result::Result<Response, JsValue> {
+    let parts = request_parts(&request).map_err(to_js)?;
+    let mut thrown = None;
+    let middleware =
+        FetchMiddleware { parts, request: &request, thrown: &mut thrown };
+    if !cache.can_cache_request(&middleware).map_err(to_js)? {
+        return JsFuture::from(fetch(&request)?).await?.dyn_into();
+    }
+    match cache.run(middleware).await {
+        Ok(mut res) => {
+            if cache.options.trailers_as_headers {
+                res.trailers_into_headers();
+            }
+            build_response(res).map_err(to_js)
+        }
+        Err(e) => Err(thrown.unwrap_or_else(|| to_js(e))),
+    }
+}
+
+/// Implements ['Middleware'] for the fetch API
+struct FetchMiddleware<'a> {
+    parts: Parts,
+    request: &'a Request,
+    // Receives what `fetch` threw, to hand it back to the caller as is
+    thrown: &'a mut Option<JsValue>,
+}
+
+impl Middleware for FetchMiddleware<'_> {
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == Method::GET || self.parts.method == Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            http_cache::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
+        Ok(())
+    }
+    fn parts(&self) -> Result<Parts> {
+        let mut converted = http::Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.url()?.as_str())
+            .body(())?;
+        *converted.headers_mut() = self.parts.headers.clone();
+        Ok(converted.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(Url::parse(&self.parts.uri.to_string())?)
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.as_ref().to_string())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        match self.send().await {
+            Ok(res) => Ok(res),
+            Err(e) => {
+                let message = format!("{:?}", e);
+                *self.thrown = Some(e);
+                Err(CacheError::General(anyhow!(message)))
+            }
+        }
+    }
+}
+
+impl FetchMiddleware<'_> {
+    // Sends a copy of the request carrying the headers set by the cache, so
+    // the body, mode and credentials of the original go along
+    async fn send(&self) -> std::result::Result<HttpResponse, JsValue> {
+        let init = RequestInit::new();
+        init.set_method(self.parts.method.as_str());
+        init.set_headers(&fetch_headers(&self.parts.headers)?.into());
+        let copied =
+            Request::new_with_request_and_init(&self.request.clone()?, &init)?;
+        let res: Response =
+            JsFuture::from(fetch(&copied)?).await?.dyn_into()?;
+        let body = JsFuture::from(res.array_buffer()?).await?;
+        let mut headers = HttpHeaders::new();
+        for entry in js_sys::try_iter(&res.headers())?.into_iter().flatten() {
+            let entry: Array = entry?.dyn_into()?;
+            if let (Some(name), Some(value)) =
+                (entry.get(0).as_string(), entry.get(1).as_string())
+            {
+                headers.append(name, from_byte_string(&value).map_err(to_js)?);
+            }
+        }
+        Ok(HttpResponse {
+            body: Uint8Array::new(&body).to_vec().into(),
+            headers,
+            trailers: None,
+            status: res.status(),
+            // Responses over HTTP/2 and later have an empty `statusText`
+            reason: Some(res.status_text()).filter(|text| !text.is_empty()),
+            url: self.url().map_err(to_js)?,
+            // The fetch API doesn't tell which version was used
+            version: HttpVersion::Http11,
+            metadata: Default::default(),
+        })
+    }
+}
+
+// Starts a fetch from the window or worker the code runs in
+fn fetch(request: &Request) -> std::result::Result<Promise, JsValue> {
+    let global = js_sys::global();
+    if let Some(window) = global.dyn_ref::<Window>() {
+        Ok(window.fetch_with_request(request))
+    } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+        Ok(worker.fetch_with_request(request))
+    } else {
+        Err(JsValue::from_str("fetch isn't available in this context"))
+    }
+}
+
+// Reads the method, url and headers of a fetch request
+fn request_parts(request: &Request) -> Result<Parts> {
+    let mut converted = http::Request::builder()
+        .method(Method::from_bytes(request.method().as_bytes())?)
+        .uri(request.url())
+        .body(())?;
+    let headers = converted.headers_mut();
+    let entries = js_sys::try_iter(&request.headers()).map_err(from_js)?;
+    for entry in entries.into_iter().flatten() {
+        let entry: Array =
+            entry.map_err(from_js)?.dyn_into().map_err(from_js)?;
+        if let (Some(name), Some(value)) =
+            (entry.get(0).as_string(), entry.get(1).as_string())
+        {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(&value)?,
+            );
+        }
+    }
+    Ok(converted.into_parts().0)
+}
+
+// Converts a header map to fetch headers, appending each value of repeated
+// headers
+fn fetch_headers(map: &HeaderMap) -> std::result::Result<Headers, JsValue> {
+    let headers = Headers::new()?;
+    for (name, value) in map {
+        let value = value.to_str().map_err(|e| to_js(e.into()))?;
+        headers.append(name.as_str(), value)?;
+    }
+    Ok(headers)
+}
+
+// Fetch header values are byte strings, each character holding one byte of
+// the value, which keeps values that aren't UTF-8 intact
+fn from_byte_string(value: &str) -> Result<HeaderValue> {
+    let bytes = value
+        .chars()
+        .map(|c| u8::try_from(c).map_err(|e| CacheError::General(e.into())))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(HeaderValue::from_bytes(&bytes)?)
+}
+
+fn to_byte_string(value: &HeaderValue) -> String {
+    value.as_bytes().iter().map(|&b| char::from(b)).collect()
+}
+
+// Converts an [`HttpResponse`] to a fetch [`Response`]
+fn build_response(response: HttpResponse) -> Result<Response> {
+    let headers = Headers::new().map_err(from_js)?;
+    for (name, value) in &response.headers {
+        headers.append(name, &to_byte_string(value)).map_err(from_js)?;
+    }
+    let init = ResponseInit::new();
+    init.set_status(response.status);
+    if let Some(reason) = &response.reason {
+        init.set_status_text(reason);
+    }
+    init.set_headers(&headers);
+    // The fetch API refuses a body, even an empty one, for these statuses
+    let mut body = Vec::from(response.body);
+    let body = match response.status {
+        101 | 204 | 205 | 304 => None,
+        _ => Some(body.as_mut_slice()),
+    };
+    Response::new_with_opt_u8_array_and_init(body, &init).map_err(from_js)
+}
+
+fn to_js(e: CacheError) -> JsValue {
+    js_sys::Error::new(&e.to_string()).into()
+}
+
+fn from_js(e: JsValue) -> CacheError {
+    CacheError::General(anyhow!("{:?}", e))
+}
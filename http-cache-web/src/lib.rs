@@ -0,0 +1,71 @@
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    missing_copy_implementations,
+    nonstandard_style,
+    unused_qualifications,
+    unused_import_braces,
+    unused_extern_crates,
+    trivial_casts,
+    trivial_numeric_casts
+)]
+#![cfg_attr(docsrs, feature(doc_cfg))]
+//! The http-cache integration for the fetch API of browsers, for use with
+//! [web-sys](https://docs.rs/web-sys) or clients built on it such as
+//! [gloo-net](https://docs.rs/gloo-net).
+//!
+//! [`cached_fetch`] takes the place of `fetch`, sending a `web_sys::Request`
+//! through an [`HttpCache`] and answering with a `web_sys::Response`:
+//!
+//! ```ignore
+//! use http_cache_web::{cached_fetch, HttpCache};
+//!
+//! async fn send(
+//!     cache: &HttpCache<MyManager>,
+//! ) -> Result<web_sys::Response, wasm_bindgen::JsValue> {
+//!     let request = web_sys::Request::new_with_str(
+//!         "https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching",
+//!     )?;
+//!     cached_fetch(cache, request).await
+//! }
+//! ```
+//!
+//! [`cached_fetch`] is only available when targeting `wasm32`. Requests are sent
+//! with the `fetch` of the window or worker the code runs in, and the cache
+//! runs on that same thread, none of the futures involved are `Send`.
+//!
+//! ## Managers
+//!
+//! The bundled managers don't build for `wasm32`, so the cache needs a
+//! [`CacheManager`](http_cache::CacheManager) of its own, such as one keeping
+//! records in memory or in IndexedDB.
+//!
+//! ## Headers
+//!
+//! Header values are exchanged as strings, as that's how the fetch API
+//! exposes them, and the browser joins the values of a repeated header into
+//! a single comma separated one. Headers are stored that way and appended
+//! back one by one to the responses served from the cache. `set-cookie`
+//! isn't visible to scripts at all.
+//!
+//! Responses built by the cache have an empty `url`, the fetch API doesn't
+//! allow setting it. Their `statusText` is the one of the stored response.
+//! The fetch API doesn't expose trailers, stored ones are only handed over
+//! as headers, see [`HttpCacheOptions::trailers_as_headers`].
+#[cfg(target_arch = "wasm32")]
+mod fetch;
+
+#[cfg(target_arch = "wasm32")]
+pub use fetch::cached_fetch;
+
+pub use http_cache::{
+    BreakerState, CacheDecision, CacheEvent, CacheMode, CacheOptions,
+    CacheStatus, CacheToggle, CircuitBreaker, Clock, Coalescing, ContentTypes,
+    DryRun, DryRunLookup, EntryMetadata, ErrorPolicy, EventBroadcast,
+    HealthReport, HitOrMiss, HttpCache, HttpCacheOptions, HttpResponse,
+    LeaderFailure, NotStoredReason, RecordMode, Redaction, RequestPredicate,
+    ResponsePredicate, RevalidationHook, RevalidationRequest, Spawner,
+    StoreHook, UrlFilter, WarmOutcome, WarmReport, WriteBehind,
+    WriteBehindStats,
+};
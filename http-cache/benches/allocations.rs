@@ -0,0 +1,133 @@
+// Counts the allocations made while serving a fresh response from the moka
+// manager, both with the manager used directly and behind the boxed futures
+// of `DynCacheManager`.
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use futures::executor::block_on;
+use http::request;
+use http_cache::{
+    CacheError, CacheKey, CacheManager, CacheMode, CacheOptions,
+    DynCacheManager, HttpCache, HttpResponse, HttpVersion, Middleware,
+    MokaManager, Result,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+const GET: &str = "GET";
+const ITERATIONS: usize = 10_000;
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+// Only ever asked for cached responses, so it never reaches the network
+struct HitMiddleware {
+    url: Url,
+}
+
+impl Middleware for HitMiddleware {
+    fn is_method_get_head(&self) -> bool {
+        true
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        Ok(CachePolicy::new(&self.parts()?, &response.parts()?))
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            std::time::SystemTime::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, _parts: &request::Parts) -> Result<()> {
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        Ok(())
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        Ok(http::Request::get(self.url.as_str()).body(())?.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(self.url.clone())
+    }
+    fn method(&self) -> Result<String> {
+        Ok(GET.to_string())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        Err(CacheError::BadRequest)
+    }
+}
+
+fn allocations_per_hit<T: CacheManager>(
+    cache: &HttpCache<T>,
+    url: &Url,
+) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..ITERATIONS {
+        block_on(cache.run(HitMiddleware { url: url.clone() })).unwrap();
+    }
+    let after = ALLOCATIONS.load(Ordering::Relaxed);
+    (after - before) as f64 / ITERATIONS as f64
+}
+
+fn main() {
+    let url = Url::parse("http://example.com/config").unwrap();
+    let response = HttpResponse {
+        body: b"test".to_vec().into(),
+        headers: Default::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(()).unwrap();
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", "max-age=86400, public")
+        .body(())
+        .unwrap();
+    let policy = CachePolicy::new(&req, &res);
+
+    let manager = MokaManager::default();
+    block_on(manager.put(&CacheKey::new(GET, &url), response, policy)).unwrap();
+    let native = HttpCache::new(CacheMode::Default, manager.clone());
+    let boxed: HttpCache<Arc<dyn DynCacheManager>> =
+        HttpCache::new(CacheMode::Default, Arc::new(manager));
+
+    println!(
+        "moka hit, native futures: {:.1} allocations per request",
+        allocations_per_hit(&native, &url)
+    );
+    println!(
+        "moka hit, boxed futures: {:.1} allocations per request",
+        allocations_per_hit(&boxed, &url)
+    );
+}
@@ -0,0 +1,66 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use futures::executor::block_on;
+use http_cache::{
+    CacheKey, CacheManager, HttpResponse, HttpVersion, MokaManager,
+    SerializationFormat,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+const GET: &str = "GET";
+
+fn put_get_1mb_binary_body(c: &mut Criterion) {
+    let url = Url::parse("http://example.com/artifact").unwrap();
+    let body: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    let response = HttpResponse {
+        body: body.clone().into(),
+        headers: Default::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(()).unwrap();
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", "max-age=86400, public")
+        .body(body)
+        .unwrap();
+    let policy = CachePolicy::new(&req, &res);
+
+    let mut group = c.benchmark_group("1mb_binary_body");
+    for format in [
+        SerializationFormat::Bincode,
+        SerializationFormat::Json,
+        SerializationFormat::Cbor,
+    ] {
+        let mut manager = MokaManager::default();
+        manager.format = format;
+        let name = format!("{:?}", format);
+        group.bench_function(BenchmarkId::new("put", &name), |b| {
+            b.iter(|| {
+                block_on(manager.put(
+                    &CacheKey::new(GET, &url),
+                    response.clone(),
+                    policy.clone(),
+                ))
+                .unwrap()
+            })
+        });
+        block_on(manager.put(
+            &CacheKey::new(GET, &url),
+            response.clone(),
+            policy.clone(),
+        ))
+        .unwrap();
+        group.bench_function(BenchmarkId::new("get", &name), |b| {
+            b.iter(|| block_on(manager.get(&CacheKey::new(GET, &url))).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, put_get_1mb_binary_body);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use http_cache::{
+    CacheKey, CacheManager, HttpResponse, HttpVersion, MokaManager,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+const GET: &str = "GET";
+
+fn hot_hits(c: &mut Criterion) {
+    let url = Url::parse("http://example.com/status").unwrap();
+    let body = br#"{"status":"ok"}"#.to_vec();
+    let response = HttpResponse {
+        body: body.clone().into(),
+        headers: Default::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(()).unwrap();
+    let res = http::Response::builder()
+        .status(200)
+        .header("cache-control", "max-age=86400, public")
+        .body(body)
+        .unwrap();
+    let policy = CachePolicy::new(&req, &res);
+
+    let manager = MokaManager::default();
+    block_on(manager.put(&CacheKey::new(GET, &url), response, policy)).unwrap();
+    c.bench_function("moka_hot_hit", |b| {
+        b.iter(|| {
+            block_on(manager.get(&CacheKey::new(GET, &url))).unwrap().unwrap()
+        })
+    });
+}
+
+criterion_group!(benches, hot_hits);
+criterion_main!(benches);
@@ -0,0 +1,147 @@
+//! An adapter hooking a client that isn't supported out of the box into
+//! [`HttpCache`] by implementing [`Middleware`].
+//!
+//! The client here is a fake that answers requests itself and counts them,
+//! the conversions are the part a real adapter needs.
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use http::{header::CACHE_CONTROL, request::Parts, HeaderValue, Method};
+use http_cache::{
+    CacheError, CacheMode, CacheOptions, HttpCache, HttpHeaders, HttpResponse,
+    HttpVersion, Middleware, MokaManager, Result,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+// The request and response types of the client
+struct FakeRequest {
+    method: String,
+    url: Url,
+    headers: Vec<(String, String)>,
+}
+
+struct FakeResponse {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct FakeClient {
+    sent: AtomicUsize,
+}
+
+impl FakeClient {
+    async fn send(&self, req: &FakeRequest) -> std::io::Result<FakeResponse> {
+        self.sent.fetch_add(1, Ordering::SeqCst);
+        Ok(FakeResponse {
+            status: 200,
+            headers: vec![("cache-control".into(), "max-age=60".into())],
+            body: format!("{} {}", req.method, req.url).into_bytes(),
+        })
+    }
+}
+
+// Wraps a single request along with the client sending it
+struct FakeMiddleware<'a> {
+    req: FakeRequest,
+    client: &'a FakeClient,
+}
+
+impl Middleware for FakeMiddleware<'_> {
+    fn is_method_get_head(&self) -> bool {
+        self.req.method == "GET" || self.req.method == "HEAD"
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            http_cache::now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+        for (name, value) in parts.headers.iter() {
+            self.req
+                .headers
+                .retain(|(n, _)| !name.as_str().eq_ignore_ascii_case(n));
+            self.req
+                .headers
+                .push((name.to_string(), value.to_str()?.to_string()));
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        let mut parts = self.parts()?;
+        parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        self.update_headers(&parts)
+    }
+    fn parts(&self) -> Result<Parts> {
+        let mut builder = http::Request::builder()
+            .method(Method::from_bytes(self.req.method.as_bytes())?)
+            .uri(self.req.url.as_str());
+        for (name, value) in &self.req.headers {
+            builder = builder.header(name, value);
+        }
+        Ok(builder.body(())?.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(self.req.url.clone())
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.req.method.clone())
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let res = match self.client.send(&self.req).await {
+            Ok(res) => res,
+            Err(e) => return Err(CacheError::General(e.into())),
+        };
+        let mut headers = HttpHeaders::new();
+        for (name, value) in res.headers {
+            headers.append(name, HeaderValue::from_str(&value)?);
+        }
+        Ok(HttpResponse {
+            body: res.body.into(),
+            headers,
+            trailers: None,
+            status: res.status,
+            reason: None,
+            url: self.req.url.clone(),
+            version: HttpVersion::Http11,
+            metadata: Default::default(),
+        })
+    }
+}
+
+fn main() -> Result<()> {
+    let client = FakeClient::default();
+    let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+    futures::executor::block_on(async {
+        for _ in 0..2 {
+            let req = FakeRequest {
+                method: "GET".into(),
+                url: Url::parse("http://example.com/")?,
+                headers: Vec::new(),
+            };
+            let res =
+                cache.run(FakeMiddleware { req, client: &client }).await?;
+            println!(
+                "{} {:?}",
+                res.headers.get_str("x-cache").unwrap_or_default(),
+                String::from_utf8_lossy(&res.body)
+            );
+        }
+        Ok::<_, CacheError>(())
+    })?;
+    assert_eq!(client.sent.load(Ordering::SeqCst), 1);
+    Ok(())
+}
@@ -0,0 +1,183 @@
+use std::{convert::TryInto, future::Future, str::FromStr};
+
+use bytes::Bytes;
+use http::{
+    header::{HeaderName, HeaderValue, CACHE_CONTROL},
+    request, Request, Response,
+};
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+use crate::{
+    now, CacheError, CacheManager, CacheOptions, EntryMetadata, HitOrMiss,
+    HttpCache, HttpHeaders, HttpResponse, ManagerOverride, MaybeSend,
+    Middleware, Result, XCACHE, XCACHELOOKUP, XCACHEOVERRIDE,
+};
+
+/// Whether a response came from the cache, the typed form of the `x-cache`
+/// and `x-cache-lookup` headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStatus {
+    /// Whether the response was served from the cache, see [`XCACHE`]
+    pub cache: HitOrMiss,
+    /// Whether a response was found in the cache, see [`XCACHELOOKUP`]
+    pub lookup: HitOrMiss,
+    /// Whether the response was stored in spite of its `no-store`
+    /// directive, see [`XCACHEOVERRIDE`]
+    pub overridden: bool,
+}
+
+impl From<&HttpResponse> for CacheStatus {
+    /// Reads the status from the headers set by the cache, a missing header
+    /// counts as a miss.
+    fn from(response: &HttpResponse) -> Self {
+        let read = |name: &str| match response.headers.get_str(name) {
+            Some("HIT") => HitOrMiss::HIT,
+            _ => HitOrMiss::MISS,
+        };
+        Self {
+            cache: read(XCACHE),
+            lookup: read(XCACHELOOKUP),
+            overridden: response.headers.contains_key(XCACHEOVERRIDE),
+        }
+    }
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    /// Runs a request through the cache for clients that don't have a
+    /// [`Middleware`] implementation, calling `fetch` to send the request
+    /// upstream when needed.
+    ///
+    /// `fetch` is called at most once, with the request the cache wants to
+    /// send, which may carry added revalidation headers. The request
+    /// extensions are passed along, and the request uri must be absolute as
+    /// records are keyed by url. Everything [`HttpCache::run`] does applies
+    /// here as well, it's what this calls into.
+    ///
+    /// ```no_run
+    /// # use http_cache::{Bytes, CacheMode, CACacheManager, HttpCache, Result};
+    /// # async fn send(req: http::Request<Bytes>) -> Result<http::Response<Bytes>> { todo!() }
+    /// # async fn example() -> Result<()> {
+    /// let cache = HttpCache::new(CacheMode::Default, CACacheManager::default());
+    /// let (parts, body) = http::Request::get("https://example.com/")
+    ///     .body(Bytes::new())?
+    ///     .into_parts();
+    /// let (res, status) = cache.run_http(parts, body, send).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn run_http<F, Fut>(
+        &self,
+        parts: request::Parts,
+        body: Bytes,
+        fetch: F,
+    ) -> Result<(Response<Bytes>, CacheStatus)>
+    where
+        F: FnOnce(Request<Bytes>) -> Fut + MaybeSend,
+        Fut: Future<Output = Result<Response<Bytes>>> + MaybeSend,
+    {
+        let url = Url::parse(&parts.uri.to_string())?;
+        let middleware =
+            FetchMiddleware { parts, body, url, fetch: Some(fetch) };
+        let mut res = self.run(middleware).await?;
+        let status = CacheStatus::from(&res);
+        let body = std::mem::take(&mut res.body);
+        let mut converted = Response::builder()
+            .status(res.status)
+            .version(res.version.into())
+            .body(body)?;
+        for (name, value) in res.headers {
+            converted.headers_mut().append(HeaderName::from_str(&name)?, value);
+        }
+        Ok((converted, status))
+    }
+}
+
+// Implements [`Middleware`] over the request and fetch closure handed to
+// [`HttpCache::run_http`]
+struct FetchMiddleware<F> {
+    parts: request::Parts,
+    body: Bytes,
+    url: Url,
+    fetch: Option<F>,
+}
+
+impl<F, Fut> Middleware for FetchMiddleware<F>
+where
+    F: FnOnce(Request<Bytes>) -> Fut + MaybeSend,
+    Fut: Future<Output = Result<Response<Bytes>>> + MaybeSend,
+{
+    fn is_method_get_head(&self) -> bool {
+        self.parts.method == http::Method::GET
+            || self.parts.method == http::Method::HEAD
+    }
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+        self.policy_with_options(response, CacheOptions::default())
+    }
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy> {
+        Ok(CachePolicy::new_options(
+            &self.parts()?,
+            &response.parts()?,
+            now(),
+            options,
+        ))
+    }
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()> {
+        for header in parts.headers.iter() {
+            self.parts.headers.insert(header.0.clone(), header.1.clone());
+        }
+        Ok(())
+    }
+    fn force_no_cache(&mut self) -> Result<()> {
+        self.parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_str("no-cache")?);
+        Ok(())
+    }
+    fn parts(&self) -> Result<request::Parts> {
+        let mut converted = Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.url.as_str())
+            .version(self.parts.version)
+            .body(())?;
+        *converted.headers_mut() = self.parts.headers.clone();
+        Ok(converted.into_parts().0)
+    }
+    fn url(&self) -> Result<Url> {
+        Ok(self.url.clone())
+    }
+    fn method(&self) -> Result<String> {
+        Ok(self.parts.method.as_ref().to_string())
+    }
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        self.parts.extensions.get::<ManagerOverride>().cloned()
+    }
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        self.parts.extensions.get::<EntryMetadata>().cloned()
+    }
+    async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+        let fetch = self.fetch.take().ok_or(CacheError::BadRequest)?;
+        let mut req = Request::builder()
+            .method(self.parts.method.clone())
+            .uri(self.parts.uri.clone())
+            .version(self.parts.version)
+            .body(std::mem::take(&mut self.body))?;
+        *req.headers_mut() = self.parts.headers.clone();
+        *req.extensions_mut() = std::mem::take(&mut self.parts.extensions);
+        let (parts, body) = fetch(req).await?.into_parts();
+        Ok(HttpResponse {
+            body,
+            headers: HttpHeaders::from(&parts.headers),
+            trailers: None,
+            status: parts.status.into(),
+            reason: None,
+            url: self.url.clone(),
+            version: parts.version.try_into()?,
+            metadata: Default::default(),
+        })
+    }
+}
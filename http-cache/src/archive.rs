@@ -0,0 +1,197 @@
+use std::convert::TryInto;
+
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use http_cache_semantics::CachePolicy;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    CacheError, CacheKey, CacheManager, EnumerableCacheManager, HttpResponse,
+    Result,
+};
+
+// An archive starts with the magic bytes and the format version, followed by
+// any number of frames. Each frame is the little endian payload length (u64),
+// the CRC32 checksum of the payload (u32), and the bincode encoded record.
+const MAGIC: &[u8; 8] = b"HTTPCACH";
+const VERSION: u32 = 1;
+const HEADER_LEN: usize = 12;
+
+#[derive(Debug, Deserialize, Serialize)]
+struct Record {
+    method: String,
+    url: Url,
+    response: HttpResponse,
+    policy: CachePolicy,
+}
+
+impl Record {
+    // Makes sure the record could have been produced by the cache
+    fn is_valid(&self) -> bool {
+        http::Method::from_bytes(self.method.as_bytes()).is_ok()
+            && matches!(self.url.scheme(), "http" | "https")
+            && self.response.parts().is_ok()
+    }
+}
+
+/// Determines what happens when an imported record already exists in the cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// Keep the existing record
+    SkipExisting,
+    /// Replace the existing record
+    Overwrite,
+    /// Keep whichever record has the longest remaining freshness lifetime
+    KeepFreshest,
+}
+
+/// A summary of an [`export`] run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportReport {
+    /// Number of records written to the archive
+    pub records: usize,
+    /// Total number of bytes written to the archive
+    pub bytes: u64,
+}
+
+/// A summary of an [`import`] run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ImportReport {
+    /// Number of records stored in the cache
+    pub imported: usize,
+    /// Number of records left out because of the [`ConflictPolicy`], or for
+    /// HAR files because the cache wouldn't store them
+    pub skipped: usize,
+    /// Number of records that failed validation and were not stored
+    pub rejected: usize,
+}
+
+/// Writes every record held by the manager to `writer` as a single archive
+/// that can later be loaded into any manager with [`import`].
+pub async fn export<M, W>(manager: &M, mut writer: W) -> Result<ExportReport>
+where
+    M: EnumerableCacheManager,
+    W: AsyncWrite + Unpin,
+{
+    let mut report = ExportReport::default();
+    writer.write_all(MAGIC).await?;
+    writer.write_all(&VERSION.to_le_bytes()).await?;
+    report.bytes += HEADER_LEN as u64;
+    for key in manager.keys().await? {
+        // The record may have been removed since the keys were listed
+        let (response, policy) = match manager.get(&key).await? {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let (method, url) = (key.method().to_string(), key.url().clone());
+        let payload =
+            bincode::serialize(&Record { method, url, response, policy })?;
+        writer.write_all(&(payload.len() as u64).to_le_bytes()).await?;
+        writer.write_all(&crc32fast::hash(&payload).to_le_bytes()).await?;
+        writer.write_all(&payload).await?;
+        report.records += 1;
+        report.bytes += (HEADER_LEN + payload.len()) as u64;
+    }
+    writer.flush().await?;
+    Ok(report)
+}
+
+/// Loads the records of an archive created by [`export`] into the manager.
+///
+/// Every record is checked against its checksum and validated before being
+/// stored, records that fail are counted as rejected. A truncated archive
+/// stops the import after the last complete record.
+pub async fn import<M, R>(
+    manager: &M,
+    mut reader: R,
+    conflict_policy: ConflictPolicy,
+) -> Result<ImportReport>
+where
+    M: CacheManager,
+    R: AsyncRead + Unpin,
+{
+    let mut header = [0; HEADER_LEN];
+    if !read_frame(&mut reader, &mut header).await? {
+        return Err(CacheError::BadArchive("missing header".into()));
+    }
+    if &header[..8] != MAGIC {
+        return Err(CacheError::BadArchive("unrecognized format".into()));
+    }
+    let version = u32::from_le_bytes(header[8..].try_into().unwrap());
+    if version != VERSION {
+        return Err(CacheError::BadArchive(format!(
+            "unsupported version {}",
+            version
+        )));
+    }
+    let mut report = ImportReport::default();
+    let mut frame = [0; HEADER_LEN];
+    loop {
+        match read_frame(&mut reader, &mut frame).await {
+            Ok(true) => {}
+            Ok(false) => break,
+            Err(_) => {
+                report.rejected += 1;
+                break;
+            }
+        }
+        let len = u64::from_le_bytes(frame[..8].try_into().unwrap());
+        let checksum = u32::from_le_bytes(frame[8..].try_into().unwrap());
+        let mut payload = Vec::new();
+        // Reading through `take` keeps a corrupt length from allocating
+        // more memory than the archive actually holds
+        (&mut reader).take(len).read_to_end(&mut payload).await?;
+        if (payload.len() as u64) < len {
+            report.rejected += 1;
+            break;
+        }
+        if crc32fast::hash(&payload) != checksum {
+            report.rejected += 1;
+            continue;
+        }
+        let record = match bincode::deserialize::<Record>(&payload) {
+            Ok(record) if record.is_valid() => record,
+            _ => {
+                report.rejected += 1;
+                continue;
+            }
+        };
+        let key = CacheKey::new(&record.method, &record.url);
+        if conflict_policy != ConflictPolicy::Overwrite {
+            if let Some((_, existing)) = manager.get(&key).await? {
+                let now = crate::now();
+                let keep_existing = conflict_policy
+                    == ConflictPolicy::SkipExisting
+                    || existing.time_to_live(now)
+                        >= record.policy.time_to_live(now);
+                if keep_existing {
+                    report.skipped += 1;
+                    continue;
+                }
+            }
+        }
+        manager.put(&key, record.response, record.policy).await?;
+        report.imported += 1;
+    }
+    Ok(report)
+}
+
+// Fills `buf` from the reader, returning false if the reader was already at
+// the end. Running out of data partway through is an error.
+async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    buf: &mut [u8],
+) -> Result<bool> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let read = reader.read(&mut buf[filled..]).await?;
+        if read == 0 {
+            if filled == 0 {
+                return Ok(false);
+            }
+            return Err(CacheError::BadArchive("truncated frame".into()));
+        }
+        filled += read;
+    }
+    Ok(true)
+}
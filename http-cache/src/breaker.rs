@@ -0,0 +1,166 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::now;
+
+/// Determines what happens to requests when the manager fails, set through
+/// [`HttpCacheOptions::error_policy`](crate::HttpCacheOptions::error_policy).
+///
+/// Records that can't be read back are handled separately, see
+/// [`HttpCacheOptions::propagate_corrupt_entries`](crate::HttpCacheOptions::propagate_corrupt_entries).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Lookups that fail are treated as misses and responses that fail to
+    /// be stored are returned anyway, reporting the error with
+    /// [`CacheEvent::BackendError`](crate::CacheEvent::BackendError)
+    ///
+    /// As storing a response hands it over to the manager, a copy of each
+    /// stored response is kept until the manager is done.
+    #[default]
+    FailOpen,
+    /// Errors of the manager fail the request
+    FailClosed,
+}
+
+/// Stops calling the manager for a while after it failed a number of times
+/// in a row, set through [`HttpCacheOptions::circuit_breaker`](crate::HttpCacheOptions::circuit_breaker).
+///
+/// While the breaker is open requests go on as if the manager failed,
+/// without paying for a call that would most likely fail too. Once the
+/// cool-down is over a single call goes through, closing the breaker again
+/// if it succeeds, the others are still skipped until it is done. A call
+/// that never reports back, for example because its request was dropped,
+/// stops holding the others back after another cool-down. Timed out calls
+/// count as failures, see
+/// [`HttpCacheOptions::backend_timeout`](crate::HttpCacheOptions::backend_timeout).
+///
+/// Clones share the same state, so the state can be inspected through a
+/// clone of the breaker handed to the cache.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    /// The number of consecutive failures opening the breaker
+    pub threshold: u32,
+    /// How long the breaker stays open
+    pub cool_down: Duration,
+    state: Arc<Mutex<Breaker>>,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    state: BreakerState,
+    // When the call let through while half-open started, until it is done
+    probe: Option<SystemTime>,
+}
+
+impl Default for CircuitBreaker {
+    /// Opens after 5 consecutive failures, for 30 seconds.
+    fn default() -> Self {
+        Self::new(5, Duration::from_secs(30))
+    }
+}
+
+/// The state of a [`CircuitBreaker`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// The manager is called as usual
+    Closed {
+        /// The number of calls that failed since the last one that didn't
+        consecutive_failures: u32,
+    },
+    /// The manager isn't called until the cool-down is over
+    Open {
+        /// When the cool-down is over
+        until: SystemTime,
+    },
+    /// The cool-down is over and a single call decides whether the breaker
+    /// closes or opens again
+    HalfOpen,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker opening after `threshold` consecutive
+    /// failures, for `cool_down`.
+    pub fn new(threshold: u32, cool_down: Duration) -> Self {
+        Self {
+            threshold,
+            cool_down,
+            state: Arc::new(Mutex::new(Breaker {
+                state: BreakerState::Closed { consecutive_failures: 0 },
+                probe: None,
+            })),
+        }
+    }
+
+    /// Returns the current state of the breaker.
+    pub fn state(&self) -> BreakerState {
+        let mut breaker = self.state.lock().unwrap();
+        breaker.cool_down(now());
+        breaker.state
+    }
+
+    // Whether the manager may be called, letting a single call through
+    // while half-open
+    pub(crate) fn allows(&self) -> bool {
+        let now = now();
+        let mut breaker = self.state.lock().unwrap();
+        breaker.cool_down(now);
+        match breaker.state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { .. } => false,
+            BreakerState::HalfOpen => match breaker.probe {
+                Some(since)
+                    if now.duration_since(since).unwrap_or_default()
+                        < self.cool_down =>
+                {
+                    false
+                }
+                _ => {
+                    breaker.probe = Some(now);
+                    true
+                }
+            },
+        }
+    }
+
+    pub(crate) fn succeeded(&self) {
+        *self.state.lock().unwrap() = Breaker {
+            state: BreakerState::Closed { consecutive_failures: 0 },
+            probe: None,
+        };
+    }
+
+    // Records a failure, returning when the cool-down ends if it opened the
+    // breaker
+    pub(crate) fn failed(&self) -> Option<SystemTime> {
+        let mut breaker = self.state.lock().unwrap();
+        let state = &mut breaker.state;
+        match *state {
+            BreakerState::Closed { consecutive_failures }
+                if consecutive_failures + 1 < self.threshold =>
+            {
+                *state = BreakerState::Closed {
+                    consecutive_failures: consecutive_failures + 1,
+                };
+                None
+            }
+            BreakerState::Open { .. } => None,
+            _ => {
+                let until = now() + self.cool_down;
+                *state = BreakerState::Open { until };
+                breaker.probe = None;
+                Some(until)
+            }
+        }
+    }
+}
+
+impl Breaker {
+    // Moves to half-open once the cool-down is over
+    fn cool_down(&mut self, now: SystemTime) {
+        if matches!(self.state, BreakerState::Open { until } if now >= until) {
+            self.state = BreakerState::HalfOpen;
+        }
+    }
+}
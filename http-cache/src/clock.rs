@@ -0,0 +1,73 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use crate::{now, Clock};
+
+/// A clock that only moves when told to, for testing time dependent caching
+/// without waiting, set through [`HttpCacheOptions::clock`](crate::HttpCacheOptions::clock)
+/// with [`MockClock::clock`].
+///
+/// Clones of a `MockClock` share the time they read.
+///
+/// ```
+/// use std::time::{Duration, UNIX_EPOCH};
+///
+/// use http_cache::{HttpCacheOptions, MockClock};
+///
+/// let clock = MockClock::new(UNIX_EPOCH + Duration::from_secs(1_700_000_000));
+/// let options = HttpCacheOptions {
+///     clock: Some(clock.clock()),
+///     ..Default::default()
+/// };
+/// clock.advance(Duration::from_secs(60));
+/// assert_eq!(clock.now(), UNIX_EPOCH + Duration::from_secs(1_700_000_060));
+/// ```
+#[cfg_attr(docsrs, doc(cfg(feature = "test-util")))]
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<SystemTime>>,
+}
+
+impl Default for MockClock {
+    /// Creates a clock reading the current time, see [`now`].
+    fn default() -> Self {
+        Self::new(now())
+    }
+}
+
+impl MockClock {
+    /// Creates a clock reading `now` until it is moved.
+    pub fn new(now: SystemTime) -> Self {
+        Self { now: Arc::new(Mutex::new(now)) }
+    }
+
+    /// Returns the time the clock reads.
+    pub fn now(&self) -> SystemTime {
+        *self.now.lock().unwrap()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+
+    /// Moves the clock back by `duration`, as a system clock being corrected
+    /// would.
+    pub fn rewind(&self, duration: Duration) {
+        *self.now.lock().unwrap() -= duration;
+    }
+
+    /// Sets the time the clock reads.
+    pub fn set(&self, now: SystemTime) {
+        *self.now.lock().unwrap() = now;
+    }
+
+    /// Returns a [`Clock`] reading the time of this one, for
+    /// [`HttpCacheOptions::clock`](crate::HttpCacheOptions::clock).
+    pub fn clock(&self) -> Clock {
+        let clock = self.clone();
+        Arc::new(move || clock.now())
+    }
+}
@@ -0,0 +1,199 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    future::{poll_fn, Future},
+    pin::{pin, Pin},
+    sync::{Arc, Mutex},
+    task::{Poll, Waker},
+};
+
+use futures::{
+    channel::oneshot,
+    future::{FutureExt, Shared},
+};
+use url::Url;
+
+use crate::CacheKey;
+
+// What the leader of a flight reports back, errors are passed on as their
+// message as they can't be cloned
+type Outcome = Result<(), String>;
+
+/// Determines what the requests waiting on a coalesced request do when it
+/// fails, see [`Coalescing`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LeaderFailure {
+    /// Every waiting request goes to the network on its own
+    #[default]
+    Refetch,
+    /// Every waiting request fails with
+    /// [`CacheError::CoalescedRequestFailed`](crate::CacheError::CoalescedRequestFailed)
+    Propagate,
+}
+
+/// Deduplicates concurrent requests for a record that isn't cached, set
+/// through [`HttpCacheOptions::coalescing`](crate::HttpCacheOptions::coalescing).
+///
+/// The first request goes to the network while the others wait for it to
+/// complete, and are then served from the response it stored. Requests are
+/// only coalesced with requests made through a cache sharing the same
+/// `Coalescing`, clones of it included.
+///
+/// Revalidating stale responses can be coalesced the same way by setting
+/// [`Coalescing::revalidations`]. Requests for a response still within its
+/// `stale-while-revalidate` window are then served the stale response right
+/// away instead of waiting.
+///
+/// A request awaited from the task that is already fetching the same record
+/// isn't coalesced, as waiting would never end. Responses streamed through
+/// [`HttpCache::run_streaming`](crate::HttpCache::run_streaming) are never
+/// coalesced.
+#[derive(Clone, Default)]
+pub struct Coalescing {
+    /// What the waiting requests do when the request they waited on fails
+    pub on_leader_failure: LeaderFailure,
+    /// Also coalesce requests revalidating a stale response, disabled by
+    /// default as it makes every request wait on the slowest revalidation
+    pub revalidations: bool,
+    in_flight: Arc<Mutex<HashMap<String, Flight>>>,
+}
+
+impl fmt::Debug for Coalescing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Coalescing")
+            .field("on_leader_failure", &self.on_leader_failure)
+            .field("revalidations", &self.revalidations)
+            .field("in_flight", &self.in_flight.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl Coalescing {
+    /// Creates a new `Coalescing` with nothing in flight.
+    pub fn new(on_leader_failure: LeaderFailure) -> Self {
+        Self { on_leader_failure, ..Default::default() }
+    }
+
+    // Makes the caller the leader of the flight for the record, unless
+    // another request already is
+    pub(crate) fn join(&self, method: &str, url: &Url) -> Role {
+        let key = CacheKey::new(method, url).to_string();
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if let Some(flight) = in_flight.get(&key) {
+            return Role::Follower(Follower {
+                done: flight.done.clone(),
+                leader: flight.leader.clone(),
+            });
+        }
+        let (sender, receiver) = oneshot::channel();
+        let leader = Arc::new(Mutex::new(None));
+        in_flight.insert(
+            key.clone(),
+            Flight { done: receiver.shared(), leader: leader.clone() },
+        );
+        Role::Leader(Leader {
+            key,
+            in_flight: self.in_flight.clone(),
+            leader,
+            sender: Some(sender),
+        })
+    }
+}
+
+struct Flight {
+    done: Shared<oneshot::Receiver<Outcome>>,
+    // Waker of the task driving the leader
+    leader: Arc<Mutex<Option<Waker>>>,
+}
+
+pub(crate) enum Role {
+    Leader(Leader),
+    Follower(Follower),
+}
+
+// Ends the flight when dropped, which lets the followers know the leader was
+// abandoned unless it finished first
+pub(crate) struct Leader {
+    key: String,
+    in_flight: Arc<Mutex<HashMap<String, Flight>>>,
+    leader: Arc<Mutex<Option<Waker>>>,
+    sender: Option<oneshot::Sender<Outcome>>,
+}
+
+impl Leader {
+    // Runs the leader's work, keeping track of the task it runs on
+    pub(crate) async fn track<F: Future>(&self, fut: F) -> F::Output {
+        let mut fut = pin!(fut);
+        poll_fn(|cx| {
+            let mut leader = self.leader.lock().unwrap();
+            if !leader.as_ref().is_some_and(|w| w.will_wake(cx.waker())) {
+                *leader = Some(cx.waker().clone());
+            }
+            drop(leader);
+            fut.as_mut().poll(cx)
+        })
+        .await
+    }
+
+    pub(crate) fn finish(mut self, outcome: Outcome) {
+        self.end();
+        if let Some(sender) = self.sender.take() {
+            sender.send(outcome).ok();
+        }
+    }
+
+    fn end(&self) {
+        let mut in_flight = self.in_flight.lock().unwrap();
+        // A new flight may have started once this one ended
+        if in_flight
+            .get(&self.key)
+            .is_some_and(|flight| Arc::ptr_eq(&flight.leader, &self.leader))
+        {
+            in_flight.remove(&self.key);
+        }
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.end();
+    }
+}
+
+pub(crate) struct Follower {
+    done: Shared<oneshot::Receiver<Outcome>>,
+    leader: Arc<Mutex<Option<Waker>>>,
+}
+
+pub(crate) enum Waited {
+    // The leader completed, storing the response if it could be stored
+    Done,
+    // The leader failed with the given error
+    Failed(String),
+    // The leader was dropped before it completed
+    Abandoned,
+    // The leader runs on the same task, so waiting for it would never end
+    SameTask,
+}
+
+impl Follower {
+    pub(crate) async fn wait(mut self) -> Waited {
+        poll_fn(|cx| {
+            let same_task = self
+                .leader
+                .lock()
+                .unwrap()
+                .as_ref()
+                .is_some_and(|w| w.will_wake(cx.waker()));
+            if same_task {
+                return Poll::Ready(Waited::SameTask);
+            }
+            Pin::new(&mut self.done).poll(cx).map(|outcome| match outcome {
+                Ok(Ok(())) => Waited::Done,
+                Ok(Err(e)) => Waited::Failed(e),
+                Err(oneshot::Canceled) => Waited::Abandoned,
+            })
+        })
+        .await
+    }
+}
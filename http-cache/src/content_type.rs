@@ -0,0 +1,69 @@
+/// The media types of the responses that may be stored, set through
+/// [`HttpCacheOptions::cacheable_content_types`](crate::HttpCacheOptions::cacheable_content_types).
+///
+/// Patterns are matched case insensitively against the media type of the
+/// `Content-Type` header, parameters such as `charset` left out. A pattern
+/// is either a full media type such as `application/json`, a type followed
+/// by `/*` such as `image/*` for any of its subtypes, or `*/*`.
+///
+/// ```
+/// use http_cache::ContentTypes;
+///
+/// let types = ContentTypes::new(["application/json", "image/*"]);
+/// assert!(types.matches(Some("application/json; charset=utf-8")));
+/// assert!(types.matches(Some("image/png")));
+/// assert!(!types.matches(Some("text/html")));
+/// assert!(!types.matches(None));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ContentTypes {
+    /// The patterns a response has to match one of
+    pub patterns: Vec<String>,
+    /// Whether responses without a `Content-Type` header may be stored
+    pub allow_missing: bool,
+}
+
+impl ContentTypes {
+    /// Allows the responses matching one of `patterns`, responses without a
+    /// `Content-Type` header aren't stored.
+    pub fn new(patterns: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        Self {
+            patterns: patterns.into_iter().map(Into::into).collect(),
+            allow_missing: false,
+        }
+    }
+
+    /// Sets whether responses without a `Content-Type` header may be stored.
+    pub fn allow_missing(mut self, allow_missing: bool) -> Self {
+        self.allow_missing = allow_missing;
+        self
+    }
+
+    /// Returns true if a response with the given `Content-Type` may be
+    /// stored.
+    pub fn matches(&self, content_type: Option<&str>) -> bool {
+        let content_type = match content_type {
+            Some(content_type) => content_type,
+            None => return self.allow_missing,
+        };
+        let media_type = content_type.split(';').next().unwrap_or("").trim();
+        let (kind, subtype) = match media_type.split_once('/') {
+            Some((kind, subtype)) => (kind.trim(), subtype.trim()),
+            // Not a media type, which nothing but `*/*` matches
+            None => (media_type, ""),
+        };
+        self.patterns.iter().any(|pattern| {
+            match pattern.trim().split_once('/') {
+                Some(("*", "*")) => true,
+                Some((pattern_kind, "*")) => {
+                    pattern_kind.eq_ignore_ascii_case(kind)
+                }
+                Some((pattern_kind, pattern_subtype)) => {
+                    pattern_kind.eq_ignore_ascii_case(kind)
+                        && pattern_subtype.eq_ignore_ascii_case(subtype)
+                }
+                None => false,
+            }
+        })
+    }
+}
@@ -0,0 +1,149 @@
+use http::{header::HeaderName, HeaderValue};
+
+use crate::{is_stored_status, HttpResponse};
+
+// The header fields left out of the stored copy of a response, as the field
+// names listed by its `private` and `no-cache` directives ask, and put back
+// on the response handed to the caller.
+//
+// RFC 9111 lets a shared cache store a response with `private="set-cookie"`
+// as long as the listed fields aren't stored, and any cache reuse one with
+// `no-cache="set-cookie"` without revalidation as long as the listed fields
+// aren't. The qualified directives are removed from the `Cache-Control` of
+// the stored copy, so the policy judges the rest of the response as usual.
+//
+// The same goes for `no-store` alongside `must-understand`, which only
+// applies to caches that don't understand the status of the response. The
+// cache only understands the statuses it stores, responses with any other
+// are only stored in the `Record` mode, which ignores their headers.
+#[derive(Debug, Default)]
+pub(crate) struct Withheld(Vec<(String, Vec<HeaderValue>)>);
+
+impl Withheld {
+    // Removes the listed fields and qualified directives from the response.
+    // A list that can't be parsed is taken as the unqualified directive,
+    // which keeps the whole response out of a shared cache for `private` and
+    // has it revalidated every time for `no-cache`. Private caches ignore
+    // `private` altogether and keep the fields it lists.
+    pub(crate) fn take(res: &mut HttpResponse, shared: bool) -> Self {
+        let mut values = Vec::new();
+        for value in res.headers.get_all("cache-control") {
+            match value.to_str() {
+                Ok(value) => values.push(value.to_string()),
+                // Left for the policy to make sense of
+                Err(_) => return Self::default(),
+            }
+        }
+        let joined = values.join(", ");
+        let directives = split_directives(&joined);
+        let understood = is_stored_status(res.status)
+            && directives.iter().any(|directive| {
+                directive.eq_ignore_ascii_case("must-understand")
+            });
+        let mut qualified = false;
+        let mut fields = Vec::new();
+        let mut kept = Vec::new();
+        for directive in directives {
+            if understood && directive.eq_ignore_ascii_case("no-store") {
+                qualified = true;
+                continue;
+            }
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), Some(value.trim())),
+                None => (directive, None),
+            };
+            let is_qualified = value.is_some()
+                && (name.eq_ignore_ascii_case("no-cache")
+                    || shared && name.eq_ignore_ascii_case("private"));
+            if !is_qualified {
+                kept.push(directive.to_string());
+                continue;
+            }
+            qualified = true;
+            match value.and_then(field_names) {
+                Some(names) => fields.extend(names),
+                None => kept.push(name.to_ascii_lowercase()),
+            }
+        }
+        if !qualified {
+            return Self::default();
+        }
+        let mut withheld = Vec::new();
+        let original = res.headers.get_all("cache-control").cloned().collect();
+        res.headers.remove("cache-control");
+        withheld.push(("cache-control".to_string(), original));
+        if !kept.is_empty() {
+            // Made of header values, so still a valid one
+            if let Ok(value) = HeaderValue::from_str(&kept.join(", ")) {
+                res.headers.insert("cache-control", value);
+            }
+        }
+        for field in fields {
+            if withheld.iter().any(|(name, _)| *name == field) {
+                continue;
+            }
+            let values =
+                res.headers.get_all(&field).cloned().collect::<Vec<_>>();
+            if values.is_empty() {
+                continue;
+            }
+            res.headers.remove(&field);
+            withheld.push((field, values));
+        }
+        Self(withheld)
+    }
+
+    // Puts the withheld fields back on the response
+    pub(crate) fn restore(self, res: &mut HttpResponse) {
+        for (name, values) in self.0 {
+            res.headers.remove(&name);
+            for value in values {
+                res.headers.append(name.clone(), value);
+            }
+        }
+    }
+}
+
+// Splits a `Cache-Control` value into its directives, leaving the commas in
+// quoted strings alone
+pub(crate) fn split_directives(value: &str) -> Vec<&str> {
+    let mut directives = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => {
+                directives.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    directives.push(&value[start..]);
+    directives
+        .into_iter()
+        .map(str::trim)
+        .filter(|directive| !directive.is_empty())
+        .collect()
+}
+
+// Parses the field names listed by a directive, a quoted list or a single
+// token, returning `None` if it isn't one
+fn field_names(value: &str) -> Option<Vec<String>> {
+    let list = match value.strip_prefix('"') {
+        Some(quoted) => quoted.strip_suffix('"')?,
+        None => value,
+    };
+    let mut names = Vec::new();
+    for name in list.split(',').map(str::trim) {
+        if name.is_empty() {
+            continue;
+        }
+        names.push(HeaderName::from_bytes(name.as_bytes()).ok()?.to_string());
+    }
+    (!names.is_empty()).then_some(names)
+}
@@ -0,0 +1,200 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use http::request;
+use http_cache_semantics::{BeforeRequest, CachePolicy};
+use url::Url;
+
+use crate::{
+    is_stored_status, CacheEvent, CacheKey, CacheManager, CacheMode, HitOrMiss,
+    HttpCache, HttpResponse, Middleware, Result,
+};
+
+/// Observes what the cache would do without ever storing or serving a
+/// response, set through [`HttpCacheOptions::dry_run`](crate::HttpCacheOptions::dry_run).
+///
+/// Every request goes to the network as if the cache wasn't installed, and
+/// a [`CacheEvent::DryRun`] describing the decisions the cache would have
+/// made is passed to [`HttpCacheOptions::on_event`](crate::HttpCacheOptions::on_event).
+/// The manager is never used. Instead, the policy of every response that
+/// would have been stored is kept in memory so later requests can report
+/// whether they would have been hits. Clones of a `DryRun` share the
+/// policies they keep.
+#[derive(Clone, Default)]
+pub struct DryRun {
+    policies: Arc<Mutex<HashMap<String, CachePolicy>>>,
+}
+
+impl fmt::Debug for DryRun {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DryRun")
+            .field("policies", &self.policies.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl DryRun {
+    /// Creates a new `DryRun` with no policies kept.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops every kept policy, so the next requests are reported as misses.
+    pub fn clear(&self) {
+        self.policies.lock().unwrap().clear();
+    }
+
+    fn lookup(
+        &self,
+        key: &str,
+        parts: &request::Parts,
+        now: SystemTime,
+    ) -> DryRunLookup {
+        match self.policies.lock().unwrap().get(key) {
+            Some(policy) => match policy.before_request(parts, now) {
+                BeforeRequest::Fresh(_) => DryRunLookup::Fresh,
+                BeforeRequest::Stale { .. } => DryRunLookup::Stale,
+            },
+            None => DryRunLookup::Miss,
+        }
+    }
+}
+
+/// What a dry run found for a request before it was sent, see
+/// [`CacheDecision::lookup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DryRunLookup {
+    /// Nothing would have been cached
+    Miss,
+    /// A cached response would have been served without going to the network
+    Fresh,
+    /// A cached response would have been revalidated
+    Stale,
+}
+
+/// Why a dry run would not have stored a response, see
+/// [`CacheDecision::not_stored`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotStoredReason {
+    /// The request method isn't `GET` or `HEAD`
+    Method,
+    /// The cache mode doesn't store responses
+    Mode,
+    /// Only responses with a `200` or `204` status are stored
+    Status(u16),
+    /// The cache policy doesn't allow the response to be stored
+    Policy,
+    /// The `Content-Type` of the response isn't one of
+    /// [`HttpCacheOptions::cacheable_content_types`](crate::HttpCacheOptions::cacheable_content_types)
+    ContentType,
+    /// [`HttpCacheOptions::should_store`](crate::HttpCacheOptions::should_store)
+    /// rejected the response
+    Predicate,
+}
+
+/// The decisions the cache would have made for a request, as reported by a
+/// [`DryRun`].
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct CacheDecision {
+    /// The request method
+    pub method: String,
+    /// The request url
+    pub url: Url,
+    /// What the cache would have found before sending the request
+    pub lookup: DryRunLookup,
+    /// Why the response would not have been stored, `None` if it would have
+    pub not_stored: Option<NotStoredReason>,
+    /// How long the response would have stayed fresh
+    pub time_to_live: Duration,
+    /// Whether the response sets a cookie, which is rarely meant to be cached
+    /// when it would have been stored
+    pub sets_cookie: bool,
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    // Looks up what the dry run would have found before the request is sent
+    pub(crate) fn dry_run_lookup(
+        &self,
+        dry_run: &DryRun,
+        middleware: &impl Middleware,
+    ) -> Result<DryRunLookup> {
+        if !middleware.is_method_get_head()
+            || self.mode == CacheMode::NoStore
+            || self.mode == CacheMode::Reload
+        {
+            return Ok(DryRunLookup::Miss);
+        }
+        let key =
+            dry_run_key(&middleware.method()?, &self.cache_url(middleware)?);
+        Ok(dry_run.lookup(&key, &middleware.parts()?, self.now()))
+    }
+
+    // Reports what the cache would have done with the fetched response,
+    // keeping its policy if it would have been stored
+    pub(crate) fn dry_run_decide(
+        &self,
+        dry_run: &DryRun,
+        middleware: &impl Middleware,
+        lookup: DryRunLookup,
+        res: &mut HttpResponse,
+    ) -> Result<()> {
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        let withheld = self.withhold(res);
+        let policy = self.response_policy(middleware, res);
+        withheld.restore(res);
+        let policy = policy?;
+        let is_get_head = middleware.is_method_get_head();
+        let not_stored = if !is_get_head {
+            Some(NotStoredReason::Method)
+        } else if self.mode == CacheMode::NoStore
+            || self.mode == CacheMode::Reload
+        {
+            Some(NotStoredReason::Mode)
+        } else if !is_stored_status(res.status) {
+            Some(NotStoredReason::Status(res.status))
+        } else if !policy.is_storable() {
+            Some(NotStoredReason::Policy)
+        } else if !self.stores_content_type(res) {
+            Some(NotStoredReason::ContentType)
+        } else if !self.passes_predicate(res) {
+            Some(NotStoredReason::Predicate)
+        } else {
+            None
+        };
+        let method = middleware.method()?.to_uppercase();
+        let url = middleware.url()?;
+        let key_url = self.cache_url(middleware)?;
+        let time_to_live = policy.time_to_live(self.now());
+        {
+            let mut policies = dry_run.policies.lock().unwrap();
+            if not_stored.is_none() {
+                policies.insert(dry_run_key(&method, &key_url), policy);
+            } else if !is_get_head {
+                // Like the cache, drop what was kept for the url
+                policies.remove(&dry_run_key("GET", &key_url));
+            }
+        }
+        let sets_cookie = res.headers.contains_key("set-cookie");
+        self.emit(|| {
+            CacheEvent::DryRun(CacheDecision {
+                method,
+                url,
+                lookup,
+                not_stored,
+                time_to_live,
+                sets_cookie,
+            })
+        });
+        Ok(())
+    }
+}
+
+fn dry_run_key(method: &str, url: &Url) -> String {
+    CacheKey::new(method, url).to_string()
+}
@@ -0,0 +1,151 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    CacheError, CacheEvent, EnumerableCacheManager, HttpCache, Result,
+};
+
+/// Application data stored along with a cached response, such as the tenant
+/// or the build it was fetched for.
+///
+/// The metadata of a response is taken from an `EntryMetadata` put in the
+/// extensions of its request, see [`Middleware::entry_metadata`](crate::Middleware::entry_metadata),
+/// and can then be changed by [`HttpCacheOptions::on_store`](crate::HttpCacheOptions::on_store)
+/// before the response is stored. It is kept by the bundled managers in
+/// [`HttpResponse::metadata`](crate::HttpResponse::metadata), left as is when
+/// a revalidation only refreshes the headers, and can be read back through
+/// [`HttpCache::inspect`] or used to pick the records removed by
+/// [`HttpCache::purge_where`].
+///
+/// Keys and values together are limited to [`EntryMetadata::MAX_SIZE`]
+/// bytes, so metadata can't grow a record unnoticed.
+#[derive(
+    Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct EntryMetadata(BTreeMap<String, String>);
+
+impl EntryMetadata {
+    /// The most bytes the keys and values of the metadata of a single
+    /// response can add up to.
+    pub const MAX_SIZE: usize = 4096;
+
+    /// The key [`HttpCache::soft_purge`] sets on the metadata of the response
+    /// it marks as stale. The response is then revalidated before it is
+    /// served again, which removes the key. Setting it never fails, even on
+    /// metadata already at [`EntryMetadata::MAX_SIZE`].
+    pub const SOFT_PURGED: &'static str = "http-cache-soft-purged";
+
+    /// Creates empty metadata.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets `key` to `value`, returning the value it replaced.
+    ///
+    /// Fails with [`CacheError::MetadataTooLarge`] and leaves the metadata
+    /// unchanged if that would take it past [`EntryMetadata::MAX_SIZE`].
+    pub fn insert(
+        &mut self,
+        key: impl Into<String>,
+        value: impl Into<String>,
+    ) -> Result<Option<String>> {
+        let (key, value) = (key.into(), value.into());
+        let replaced = self.0.get(&key).map_or(0, |old| key.len() + old.len());
+        let size = self.size() - replaced + key.len() + value.len();
+        if size > Self::MAX_SIZE {
+            return Err(CacheError::MetadataTooLarge(size));
+        }
+        Ok(self.0.insert(key, value))
+    }
+
+    /// Returns the value of `key`, if it is set.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    /// Removes `key`, returning its value if it was set.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        self.0.remove(key)
+    }
+
+    /// Iterates over the keys and values, ordered by key.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    /// Returns the number of keys set.
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns true if no key is set.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns how many bytes the keys and values add up to.
+    pub fn size(&self) -> usize {
+        self.0.iter().map(|(key, value)| key.len() + value.len()).sum()
+    }
+
+    // Marks the response as soft purged, see `EntryMetadata::SOFT_PURGED`
+    pub(crate) fn mark_soft_purged(&mut self) {
+        self.0.insert(Self::SOFT_PURGED.to_string(), "1".to_string());
+    }
+
+    pub(crate) fn is_soft_purged(&self) -> bool {
+        self.0.contains_key(Self::SOFT_PURGED)
+    }
+}
+
+/// Fails with [`CacheError::MetadataTooLarge`] if the keys and values add up
+/// to more than [`EntryMetadata::MAX_SIZE`] bytes.
+impl TryFrom<HashMap<String, String>> for EntryMetadata {
+    type Error = CacheError;
+
+    fn try_from(map: HashMap<String, String>) -> Result<Self> {
+        let mut metadata = Self::new();
+        for (key, value) in map {
+            metadata.insert(key, value)?;
+        }
+        Ok(metadata)
+    }
+}
+
+impl<T: EnumerableCacheManager> HttpCache<T> {
+    /// Removes every cached response whose [`EntryMetadata`] matches
+    /// `predicate`, returning how many were removed.
+    ///
+    /// Every record listed by [`EnumerableCacheManager::keys`] is read with
+    /// [`CacheManager::get_metadata`](crate::CacheManager::get_metadata).
+    /// Records that can't be read back are removed as corrupt, see
+    /// [`HttpCacheOptions::propagate_corrupt_entries`](crate::HttpCacheOptions::propagate_corrupt_entries),
+    /// and aren't counted.
+    pub async fn purge_where(
+        &self,
+        predicate: impl Fn(&EntryMetadata) -> bool,
+    ) -> Result<usize> {
+        let mut removed = 0;
+        for key in self.manager.keys().await? {
+            let (method, url) = (key.method(), key.url());
+            let res = self.manager.get_metadata(&key).await;
+            let metadata = match self.discard_corrupt(method, url, res).await? {
+                Some((_, _, metadata)) => metadata,
+                None => continue,
+            };
+            if !predicate(&metadata) {
+                continue;
+            }
+            if let Some(()) = self.remove(method, url).await? {
+                removed += 1;
+                self.emit(|| CacheEvent::Evicted {
+                    method: method.to_string(),
+                    url: url.clone(),
+                });
+            }
+        }
+        Ok(removed)
+    }
+}
@@ -49,10 +49,14 @@ pub enum CacheError {
     #[diagnostic(code(http_cache::cacache))]
     CaCache(#[from] cacache::Error),
     /// Error from bincode
-    #[cfg(any(feature = "manager-cacache", feature = "manager-moka"))]
     #[error(transparent)]
     #[diagnostic(code(http_cache::bincode))]
     Bincode(#[from] Box<bincode::ErrorKind>),
+    /// Error from serde_json
+    #[cfg(any(feature = "format-json", feature = "har"))]
+    #[error(transparent)]
+    #[diagnostic(code(http_cache::json))]
+    Json(#[from] serde_json::Error),
     /// There was an error parsing the HTTP request version
     #[error("Unknown HTTP version")]
     #[diagnostic(code(http_cache::bad_version))]
@@ -61,6 +65,49 @@ pub enum CacheError {
     #[error("Error parsing header value")]
     #[diagnostic(code(http_cache::bad_header))]
     BadHeader,
+    /// A stored record exists but could not be read back
+    #[error("Unreadable cache record: {0}")]
+    #[diagnostic(code(http_cache::corrupt_entry))]
+    CorruptEntry(#[source] Box<dyn std::error::Error + Send + Sync>),
+    /// An I/O error occurred while reading or writing an archive
+    #[error(transparent)]
+    #[diagnostic(code(http_cache::io))]
+    Io(#[from] std::io::Error),
+    /// The archive being imported is not in a format that can be read
+    #[error("Invalid cache archive: {0}")]
+    #[diagnostic(code(http_cache::bad_archive))]
+    BadArchive(String),
+    /// The request was coalesced with a request that failed, see
+    /// [`LeaderFailure::Propagate`](crate::LeaderFailure::Propagate)
+    #[error("Coalesced request failed: {0}")]
+    #[diagnostic(code(http_cache::coalesced_request_failed))]
+    CoalescedRequestFailed(String),
+    /// The manager wasn't called as the circuit breaker is open, see
+    /// [`ErrorPolicy::FailClosed`](crate::ErrorPolicy::FailClosed)
+    #[error("Cache backend unavailable, the circuit breaker is open")]
+    #[diagnostic(code(http_cache::backend_unavailable))]
+    BackendUnavailable,
+    /// The work has to run in the background but the [`Spawner`](crate::Spawner)
+    /// runs it inline
+    #[error("The work needs a spawner running it in the background")]
+    #[diagnostic(code(http_cache::inline_spawner))]
+    InlineSpawner,
+    /// Nothing was recorded for the request, which is listed by its key,
+    /// see [`RecordMode::Replay`](crate::RecordMode::Replay)
+    #[error("No recorded response for {0}")]
+    #[diagnostic(code(http_cache::not_recorded))]
+    NotRecorded(String),
+    /// The manager failed its health check for the given reason, see
+    /// [`CacheManager::health_check`](crate::CacheManager::health_check)
+    #[error("Cache backend unhealthy: {0}")]
+    #[diagnostic(code(http_cache::unhealthy))]
+    Unhealthy(String),
+    /// The keys and values of [`EntryMetadata`](crate::EntryMetadata) would
+    /// add up to the given number of bytes, more than
+    /// [`EntryMetadata::MAX_SIZE`](crate::EntryMetadata::MAX_SIZE)
+    #[error("Entry metadata too large: {0} bytes")]
+    #[diagnostic(code(http_cache::metadata_too_large))]
+    MetadataTooLarge(usize),
     /// There was an error parsing the HTTP request
     #[error(
         "Request object is not cloneable. Are you passing a streaming body?"
@@ -0,0 +1,343 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::{Duration, SystemTime},
+};
+
+use futures::Stream;
+use url::Url;
+
+use crate::{CacheDecision, PruneReport};
+
+/// Something noteworthy that happened while the cache handled a request.
+///
+/// Events are passed to the [`HttpCacheOptions::on_event`](crate::HttpCacheOptions::on_event)
+/// hook when one is configured, and to the subscribers of the
+/// [`HttpCacheOptions::event_broadcast`](crate::HttpCacheOptions::event_broadcast).
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum CacheEvent {
+    /// A stored record could not be read, it was removed and the request was
+    /// treated as a cache miss.
+    CorruptEntry {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// Description of the error encountered while reading the record
+        error: String,
+    },
+    /// A request went through a [`DryRun`](crate::DryRun), describing what
+    /// the cache would have done with it.
+    DryRun(CacheDecision),
+    /// A manager operation took longer than the configured
+    /// [`HttpCacheOptions::backend_timeout`](crate::HttpCacheOptions::backend_timeout)
+    /// and was given up on.
+    BackendTimeout {
+        /// The operation that timed out, one of `get`, `put`, `update` or
+        /// `delete`
+        operation: &'static str,
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+    },
+    /// A manager operation failed, see [`ErrorPolicy`](crate::ErrorPolicy).
+    BackendError {
+        /// The operation that failed, one of `get`, `put`, `update` or
+        /// `delete`
+        operation: &'static str,
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// Description of the error returned by the manager
+        error: String,
+    },
+    /// The manager failed enough times in a row to open the
+    /// [`CircuitBreaker`](crate::CircuitBreaker), and won't be called until
+    /// `until`.
+    CircuitOpened {
+        /// When the cool-down is over
+        until: SystemTime,
+    },
+    /// A prune cycle run by [`maintenance`](crate::maintenance) succeeded.
+    Pruned {
+        /// What the cycle removed
+        report: PruneReport,
+    },
+    /// A prune cycle run by [`maintenance`](crate::maintenance) failed.
+    PruneFailed {
+        /// Description of the error returned by the manager
+        error: String,
+        /// How long until the next cycle
+        retry_in: Duration,
+    },
+    /// A request was answered with a stored response, fresh or revalidated.
+    Hit {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// The status of the response
+        status: u16,
+        /// How long the cache took to answer the request
+        elapsed: Duration,
+    },
+    /// A request was answered with a response from the origin. Requests
+    /// that [pass through](crate::HttpCache::passes_through) the cache
+    /// aren't reported.
+    Miss {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// The status of the response
+        status: u16,
+        /// How long the cache took to answer the request
+        elapsed: Duration,
+    },
+    /// A response was stored.
+    Stored {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// The status of the response
+        status: u16,
+    },
+    /// A stale response was revalidated with the origin.
+    Revalidated {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+        /// The status the origin answered with, `304` if the stored
+        /// response is still valid
+        status: u16,
+        /// How long the origin took to answer
+        elapsed: Duration,
+    },
+    /// The revalidation hook dropped the validators of a conditional
+    /// request, which was sent without them, see
+    /// [`RevalidationRequest::drop_validators`](crate::RevalidationRequest::drop_validators).
+    ValidatorsDropped {
+        /// The request method
+        method: String,
+        /// The request url
+        url: Url,
+    },
+    /// The stored response was removed as a request with a method other than
+    /// `GET` or `HEAD` was sent to its url.
+    Evicted {
+        /// The method of the stored response
+        method: String,
+        /// The url of the stored response
+        url: Url,
+    },
+    /// The stored response was marked as stale by
+    /// [`HttpCache::soft_purge`](crate::HttpCache::soft_purge).
+    SoftPurged {
+        /// The method of the stored response
+        method: String,
+        /// The url of the stored response
+        url: Url,
+    },
+    /// The responses tagged with `tag` were removed by
+    /// [`HttpCache::purge_tag`](crate::HttpCache::purge_tag).
+    PurgedTag {
+        /// The purged tag
+        tag: String,
+        /// How many responses were removed
+        removed: usize,
+    },
+    /// The responses from an origin were removed as a response asked for it
+    /// with `Clear-Site-Data`, see
+    /// [`HttpCacheOptions::clear_site_data`](crate::HttpCacheOptions::clear_site_data).
+    PurgedOrigin {
+        /// The purged origin
+        origin: String,
+        /// How many responses were removed
+        removed: usize,
+    },
+    /// The subscriber fell behind and missed the oldest events, only handed
+    /// to the subscribers of an [`EventBroadcast`].
+    Lagged {
+        /// How many events were missed
+        missed: u64,
+    },
+}
+
+/// Hands the events of a cache to subscribers as a stream, set through
+/// [`HttpCacheOptions::event_broadcast`](crate::HttpCacheOptions::event_broadcast).
+///
+/// The last `capacity` events are kept for the subscribers to read, so
+/// sending an event never waits on them. A subscriber falling further behind
+/// misses the oldest events and is handed a [`CacheEvent::Lagged`] saying
+/// how many it missed. Events are only kept while someone is subscribed.
+///
+/// Clones share the same subscribers. The streams end once every clone is
+/// dropped, usually along with the cache.
+#[derive(Clone)]
+pub struct EventBroadcast {
+    sender: Arc<Sender>,
+}
+
+impl fmt::Debug for EventBroadcast {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let state = self.sender.shared.lock().unwrap();
+        f.debug_struct("EventBroadcast")
+            .field("capacity", &state.capacity)
+            .field("subscribers", &state.subscribers)
+            .finish()
+    }
+}
+
+// Closes the streams once the last clone of the broadcast is dropped
+struct Sender {
+    shared: Arc<Mutex<State>>,
+}
+
+impl Drop for Sender {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.closed = true;
+        state.wake();
+    }
+}
+
+struct State {
+    events: VecDeque<CacheEvent>,
+    capacity: usize,
+    // The position of the first kept event among every event sent
+    first: u64,
+    subscribers: usize,
+    next_id: u64,
+    wakers: HashMap<u64, Waker>,
+    closed: bool,
+}
+
+impl State {
+    fn end(&self) -> u64 {
+        self.first + self.events.len() as u64
+    }
+
+    fn wake(&mut self) {
+        for (_, waker) in self.wakers.drain() {
+            waker.wake();
+        }
+    }
+}
+
+impl EventBroadcast {
+    /// Creates a broadcast keeping the last `capacity` events, at least one.
+    pub fn new(capacity: usize) -> Self {
+        let state = State {
+            events: VecDeque::new(),
+            capacity: capacity.max(1),
+            first: 0,
+            subscribers: 0,
+            next_id: 0,
+            wakers: HashMap::new(),
+            closed: false,
+        };
+        let shared = Arc::new(Mutex::new(state));
+        Self { sender: Arc::new(Sender { shared }) }
+    }
+
+    /// Returns a stream of the events sent from now on.
+    pub fn subscribe(&self) -> EventStream {
+        let shared = self.sender.shared.clone();
+        let mut state = shared.lock().unwrap();
+        state.subscribers += 1;
+        state.next_id += 1;
+        let (id, next) = (state.next_id, state.end());
+        drop(state);
+        EventStream { shared, id, next }
+    }
+
+    /// Returns true if someone is subscribed.
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.shared.lock().unwrap().subscribers > 0
+    }
+
+    /// Hands `event` to the subscribers, dropping the oldest kept event if
+    /// there are `capacity` of them already.
+    pub fn send(&self, event: CacheEvent) {
+        let mut state = self.sender.shared.lock().unwrap();
+        if state.subscribers == 0 {
+            return;
+        }
+        state.events.push_back(event);
+        if state.events.len() > state.capacity {
+            state.events.pop_front();
+            state.first += 1;
+        }
+        state.wake();
+    }
+}
+
+/// The events of an [`EventBroadcast`], see [`EventBroadcast::subscribe`].
+pub struct EventStream {
+    shared: Arc<Mutex<State>>,
+    id: u64,
+    // The position of the next event to hand over
+    next: u64,
+}
+
+impl fmt::Debug for EventStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("EventStream").field("next", &self.next).finish()
+    }
+}
+
+impl EventStream {
+    // A stream that ended before handing over any event
+    pub(crate) fn closed() -> Self {
+        let broadcast = EventBroadcast::new(1);
+        broadcast.subscribe()
+    }
+}
+
+impl Stream for EventStream {
+    type Item = CacheEvent;
+
+    fn poll_next(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<CacheEvent>> {
+        let this = self.get_mut();
+        let mut state = this.shared.lock().unwrap();
+        if this.next < state.first {
+            let missed = state.first - this.next;
+            this.next = state.first;
+            return Poll::Ready(Some(CacheEvent::Lagged { missed }));
+        }
+        if this.next < state.end() {
+            let index = (this.next - state.first) as usize;
+            this.next += 1;
+            return Poll::Ready(Some(state.events[index].clone()));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.wakers.insert(this.id, cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        let mut state = self.shared.lock().unwrap();
+        state.subscribers -= 1;
+        state.wakers.remove(&self.id);
+        if state.subscribers == 0 {
+            let end = state.end();
+            state.events.clear();
+            state.first = end;
+        }
+    }
+}
@@ -0,0 +1,616 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use http::{header::HeaderName, HeaderValue, StatusCode};
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{
+    is_stored_status, CacheError, CacheKey, CacheManager, ConflictPolicy,
+    EnumerableCacheManager, ExportReport, HttpHeaders, HttpResponse,
+    HttpVersion, ImportReport, Redaction, Result, UrlFilter,
+};
+
+/// Determines how [`import_har`] stores the entries of a HAR file.
+#[cfg_attr(docsrs, doc(cfg(feature = "har")))]
+#[derive(Debug, Clone, Copy)]
+pub struct HarImportOptions {
+    /// What happens when an entry is already cached
+    pub conflict_policy: ConflictPolicy,
+    /// How long responses whose recorded headers don't allow them to be
+    /// stored stay fresh once imported, such responses are skipped when
+    /// `None`
+    pub fallback_ttl: Option<Duration>,
+    /// The options the policies of the imported responses are created with
+    pub cache_options: CacheOptions,
+    /// Create the policies as of when the entries were recorded, from their
+    /// `startedDateTime`, instead of when they are imported. Responses then
+    /// age from when they were recorded, as they do when a file written by
+    /// [`export_har`] is loaded back.
+    pub recorded_times: bool,
+}
+
+impl Default for HarImportOptions {
+    /// Overwrites cached entries, skips responses that can't be stored and
+    /// creates policies as of the import.
+    fn default() -> Self {
+        Self {
+            conflict_policy: ConflictPolicy::Overwrite,
+            fallback_ttl: None,
+            cache_options: CacheOptions::default(),
+            recorded_times: false,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct Har {
+    log: Log,
+}
+
+#[derive(Deserialize)]
+struct Log {
+    entries: Vec<Entry>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    started_date_time: Option<String>,
+    request: Request,
+    response: Response,
+}
+
+#[derive(Deserialize)]
+struct Request {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<Header>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Response {
+    status: u16,
+    #[serde(default)]
+    status_text: String,
+    #[serde(default)]
+    http_version: String,
+    #[serde(default)]
+    headers: Vec<Header>,
+    content: Content,
+}
+
+#[derive(Deserialize)]
+struct Content {
+    #[serde(default)]
+    size: i64,
+    text: Option<String>,
+    encoding: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Header {
+    name: String,
+    value: String,
+}
+
+/// Loads the `GET` responses recorded in a [HAR 1.2](http://www.softwareishard.com/blog/har-12-spec/)
+/// file, such as the ones browsers export, into the manager.
+///
+/// Each response is stored with the headers it was recorded with, under the
+/// url it was requested with, along with a policy created from the recorded
+/// request and response headers as if it was received now, see
+/// [`HarImportOptions::recorded_times`]. Recorded bodies have already been decoded, so the `Content-Encoding`
+/// header is left out.
+///
+/// Entries using another method, whose body wasn't recorded, which the
+/// cache never stores such as `404` responses, or whose headers don't allow
+/// them to be stored without [`HarImportOptions::fallback_ttl`], are
+/// counted as skipped along with the existing ones kept by the
+/// [`ConflictPolicy`]. Entries that can't be read, such as bodies that
+/// aren't valid base64, are counted as rejected.
+#[cfg_attr(docsrs, doc(cfg(feature = "har")))]
+pub async fn import_har<M, R>(
+    manager: &M,
+    mut reader: R,
+    options: HarImportOptions,
+) -> Result<ImportReport>
+where
+    M: CacheManager,
+    R: AsyncRead + Unpin,
+{
+    let mut data = Vec::new();
+    reader.read_to_end(&mut data).await?;
+    let har: Har = serde_json::from_slice(&data)
+        .map_err(|e| CacheError::BadArchive(format!("invalid HAR: {}", e)))?;
+    let mut report = ImportReport::default();
+    for entry in har.log.entries {
+        if !entry.request.method.eq_ignore_ascii_case("GET")
+            || !is_stored_status(entry.response.status)
+        {
+            report.skipped += 1;
+            continue;
+        }
+        let (response, policy) = match convert(&entry, &options) {
+            Ok(Some(record)) => record,
+            Ok(None) => {
+                report.skipped += 1;
+                continue;
+            }
+            Err(_) => {
+                report.rejected += 1;
+                continue;
+            }
+        };
+        let key = CacheKey::new("GET", &response.url);
+        if options.conflict_policy != ConflictPolicy::Overwrite {
+            if let Some((_, existing)) = manager.get(&key).await? {
+                let now = crate::now();
+                let keep_existing = options.conflict_policy
+                    == ConflictPolicy::SkipExisting
+                    || existing.time_to_live(now) >= policy.time_to_live(now);
+                if keep_existing {
+                    report.skipped += 1;
+                    continue;
+                }
+            }
+        }
+        manager.put(&key, response, policy).await?;
+        report.imported += 1;
+    }
+    Ok(report)
+}
+
+// Builds the response and policy of an entry, `None` if it should be skipped
+fn convert(
+    entry: &Entry,
+    options: &HarImportOptions,
+) -> Result<Option<(HttpResponse, CachePolicy)>> {
+    let content = &entry.response.content;
+    let body = match (&content.text, content.encoding.as_deref()) {
+        (Some(text), Some("base64")) => {
+            STANDARD.decode(text).map_err(|e| anyhow::anyhow!(e))?
+        }
+        (Some(text), _) => text.clone().into_bytes(),
+        // Empty bodies may be left out
+        (None, _) if content.size <= 0 => Vec::new(),
+        (None, _) => return Ok(None),
+    };
+    let url = Url::parse(&entry.request.url)?;
+    let mut headers = convert_headers(&entry.response.headers)?;
+    headers.remove("content-encoding");
+    if headers.remove("content-length").is_some() {
+        headers.insert("content-length", HeaderValue::from(body.len()));
+    }
+    let status_text = &entry.response.status_text;
+    let response = HttpResponse {
+        body: body.into(),
+        headers,
+        trailers: None,
+        status: entry.response.status,
+        reason: (!status_text.is_empty()).then(|| status_text.clone()),
+        url: url.clone(),
+        version: version(&entry.response.http_version),
+        metadata: Default::default(),
+    };
+    let mut request = http::Request::get(url.as_str()).body(())?;
+    for (name, value) in &convert_headers(&entry.request.headers)? {
+        request
+            .headers_mut()
+            .append(HeaderName::from_str(name)?, value.clone());
+    }
+    let received_at = match &entry.started_date_time {
+        Some(time) if options.recorded_times => {
+            parse_iso_8601(time).unwrap_or_else(crate::now)
+        }
+        _ => crate::now(),
+    };
+    let parts = response.parts()?;
+    let policy = CachePolicy::new_options(
+        &request,
+        &parts,
+        received_at,
+        options.cache_options,
+    );
+    if policy.is_storable() {
+        return Ok(Some((response, policy)));
+    }
+    let ttl = match options.fallback_ttl {
+        Some(ttl) => ttl,
+        None => return Ok(None),
+    };
+    // The policy is created as if the response allowed caching it for the
+    // fallback lifetime, the stored response keeps its recorded headers
+    let mut fallback = http::Response::builder().status(response.status);
+    for (name, value) in &response.headers {
+        if !matches!(
+            name.to_ascii_lowercase().as_str(),
+            "cache-control" | "expires" | "pragma" | "date" | "age"
+        ) {
+            fallback = fallback.header(name, value.clone());
+        }
+    }
+    let fallback = fallback
+        .header("cache-control", format!("public, max-age={}", ttl.as_secs()))
+        .body(())?;
+    let policy = CachePolicy::new_options(
+        &request,
+        &fallback,
+        received_at,
+        options.cache_options,
+    );
+    Ok(policy.is_storable().then_some((response, policy)))
+}
+
+// HTTP/2 pseudo headers recorded by some browsers are left out
+fn convert_headers(headers: &[Header]) -> Result<HttpHeaders> {
+    headers
+        .iter()
+        .filter(|header| !header.name.starts_with(':'))
+        .map(|header| {
+            Ok((header.name.clone(), HeaderValue::from_str(&header.value)?))
+        })
+        .collect()
+}
+
+fn version(version: &str) -> HttpVersion {
+    match version.to_ascii_uppercase().as_str() {
+        "HTTP/0.9" => HttpVersion::Http09,
+        "HTTP/1.0" => HttpVersion::Http10,
+        "HTTP/2" | "HTTP/2.0" | "H2" => HttpVersion::H2,
+        "HTTP/3" | "HTTP/3.0" | "H3" => HttpVersion::H3,
+        _ => HttpVersion::Http11,
+    }
+}
+
+/// Determines which records [`export_har`] writes and how.
+#[cfg_attr(docsrs, doc(cfg(feature = "har")))]
+#[derive(Debug, Clone)]
+pub struct HarExportOptions {
+    /// Only the records whose url the filter allows are written, every
+    /// record is when `None`
+    pub filter: Option<UrlFilter>,
+    /// Replace the values of sensitive headers with `[redacted]`, as the
+    /// current [`Redaction`] does in `Debug` output
+    pub redact: bool,
+}
+
+impl Default for HarExportOptions {
+    /// Writes every record, redacting sensitive headers.
+    fn default() -> Self {
+        Self { filter: None, redact: true }
+    }
+}
+
+#[derive(Serialize)]
+struct HarOut<'a> {
+    log: LogOut<'a>,
+}
+
+#[derive(Serialize)]
+struct LogOut<'a> {
+    version: &'a str,
+    creator: Creator<'a>,
+    entries: Vec<EntryOut<'a>>,
+}
+
+#[derive(Serialize)]
+struct Creator<'a> {
+    name: &'a str,
+    version: &'a str,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct EntryOut<'a> {
+    started_date_time: String,
+    time: u32,
+    request: RequestOut<'a>,
+    response: ResponseOut<'a>,
+    cache: Empty,
+    timings: Timings,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestOut<'a> {
+    method: &'a str,
+    url: &'a str,
+    http_version: &'a str,
+    cookies: [Empty; 0],
+    headers: [Empty; 0],
+    query_string: Vec<HeaderOut>,
+    headers_size: i8,
+    body_size: i8,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseOut<'a> {
+    status: u16,
+    status_text: String,
+    http_version: &'a str,
+    cookies: [Empty; 0],
+    headers: Vec<HeaderOut>,
+    content: ContentOut,
+    #[serde(rename = "redirectURL")]
+    redirect_url: &'a str,
+    headers_size: i8,
+    body_size: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ContentOut {
+    size: usize,
+    mime_type: String,
+    text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+struct HeaderOut {
+    name: String,
+    value: String,
+}
+
+#[derive(Serialize)]
+struct Empty {}
+
+#[derive(Serialize)]
+struct Timings {
+    send: u32,
+    wait: u32,
+    receive: u32,
+}
+
+/// Writes the records held by the manager to `writer` as a [HAR 1.2](http://www.softwareishard.com/blog/har-12-spec/)
+/// file, which browser developer tools can open and [`import_har`] can load
+/// back.
+///
+/// Each entry holds the stored url, status, headers and body, bodies that
+/// aren't valid UTF-8 encoded as base64. Entries are dated from the age of
+/// their stored policy, loading them back with
+/// [`HarImportOptions::recorded_times`] keeps their age. The request headers
+/// a response was stored for aren't kept, so entries are written without
+/// them.
+#[cfg_attr(docsrs, doc(cfg(feature = "har")))]
+pub async fn export_har<M, W>(
+    manager: &M,
+    mut writer: W,
+    options: HarExportOptions,
+) -> Result<ExportReport>
+where
+    M: EnumerableCacheManager,
+    W: AsyncWrite + Unpin,
+{
+    let redaction = options.redact.then(Redaction::current);
+    let mut records = Vec::new();
+    for key in manager.keys().await? {
+        if let Some(filter) = &options.filter {
+            if !filter.allows(key.url()) {
+                continue;
+            }
+        }
+        // The record may have been removed since the keys were listed
+        if let Some((res, policy)) = manager.get(&key).await? {
+            let (method, url) = (key.method().to_string(), key.url().clone());
+            records.push((method, url, res, policy));
+        }
+    }
+    let now = crate::now();
+    let entries = records
+        .iter()
+        .map(|(method, url, res, policy)| {
+            let stored_at = now.checked_sub(policy.age(now)).unwrap_or(now);
+            entry(method, url, res, stored_at, redaction.as_ref())
+        })
+        .collect();
+    let har = HarOut {
+        log: LogOut {
+            version: "1.2",
+            creator: Creator {
+                name: env!("CARGO_PKG_NAME"),
+                version: env!("CARGO_PKG_VERSION"),
+            },
+            entries,
+        },
+    };
+    let bytes = serde_json::to_vec_pretty(&har)?;
+    writer.write_all(&bytes).await?;
+    writer.flush().await?;
+    Ok(ExportReport { records: records.len(), bytes: bytes.len() as u64 })
+}
+
+fn entry<'a>(
+    method: &'a str,
+    url: &'a Url,
+    res: &'a HttpResponse,
+    stored_at: SystemTime,
+    redaction: Option<&Redaction>,
+) -> EntryOut<'a> {
+    let version = match res.version {
+        HttpVersion::Http09 => "HTTP/0.9",
+        HttpVersion::Http10 => "HTTP/1.0",
+        HttpVersion::Http11 => "HTTP/1.1",
+        HttpVersion::H2 => "HTTP/2.0",
+        HttpVersion::H3 => "HTTP/3.0",
+    };
+    let headers = res
+        .headers
+        .iter()
+        .map(|(name, value)| HeaderOut {
+            name: name.to_string(),
+            value: match redaction {
+                Some(redaction) if redaction.redacts(name) => {
+                    "[redacted]".to_string()
+                }
+                _ => String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            },
+        })
+        .collect();
+    let (text, encoding) = match std::str::from_utf8(&res.body) {
+        Ok(text) => (text.to_string(), None),
+        Err(_) => (STANDARD.encode(&res.body), Some("base64")),
+    };
+    let status_text = res.reason.clone().unwrap_or_else(|| {
+        StatusCode::from_u16(res.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or("")
+            .to_string()
+    });
+    // Responses stored after following a redirect keep the url they came from
+    let redirect_url = if &res.url == url { "" } else { res.url.as_str() };
+    EntryOut {
+        started_date_time: iso_8601(stored_at),
+        time: 0,
+        request: RequestOut {
+            method,
+            url: url.as_str(),
+            http_version: version,
+            cookies: [],
+            headers: [],
+            query_string: url
+                .query_pairs()
+                .map(|(name, value)| HeaderOut {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect(),
+            headers_size: -1,
+            body_size: -1,
+        },
+        response: ResponseOut {
+            status: res.status,
+            status_text,
+            http_version: version,
+            cookies: [],
+            headers,
+            content: ContentOut {
+                size: res.body.len(),
+                mime_type: res
+                    .headers
+                    .get_str("content-type")
+                    .unwrap_or("")
+                    .to_string(),
+                text,
+                encoding,
+            },
+            redirect_url,
+            headers_size: -1,
+            body_size: res.body.len(),
+        },
+        cache: Empty {},
+        timings: Timings { send: 0, wait: 0, receive: 0 },
+    }
+}
+
+// Formats a time in UTC as ISO 8601, such as `2024-01-01T00:00:00.000Z`
+fn iso_8601(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((secs / 86_400) as i64);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        secs % 86_400 / 3_600,
+        secs % 3_600 / 60,
+        secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// The date a number of days after the epoch falls on, following
+// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524
+        - day_of_era / 146_096)
+        / 365;
+    let day_of_year =
+        day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month =
+        if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month as u32, day as u32)
+}
+
+// Parses an ISO 8601 time such as `2024-01-01T00:00:00.000+02:00`, as HAR
+// files date entries
+fn parse_iso_8601(time: &str) -> Option<SystemTime> {
+    let number = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = time.get(range)?;
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        digits.parse().ok()
+    };
+    let (year, month, day) = (number(0..4)?, number(5..7)?, number(8..10)?);
+    let (hour, minute, second) =
+        (number(11..13)?, number(14..16)?, number(17..19)?);
+    if time.get(4..5)? != "-"
+        || time.get(7..8)? != "-"
+        || !matches!(time.get(10..11)?, "T" | "t" | " ")
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+    let mut rest = time.get(19..)?;
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let len = fraction.bytes().take_while(u8::is_ascii_digit).count();
+        let digits = &fraction[..len.min(9)];
+        nanos = format!("{:0<9}", digits).parse().ok()?;
+        rest = &fraction[len..];
+    }
+    let offset = match rest {
+        "Z" | "z" | "" => 0,
+        _ => {
+            let sign = match rest.get(..1)? {
+                "+" => 1,
+                "-" => -1,
+                _ => return None,
+            };
+            let offset = rest.get(1..)?.replace(':', "");
+            let hours: i64 = offset.get(..2)?.parse().ok()?;
+            let minutes: i64 = offset.get(2..)?.parse().ok()?;
+            sign * (hours * 3_600 + minutes * 60)
+        }
+    };
+    let secs = days_from_civil(year, month as u32, day as u32) * 86_400
+        + hour * 3_600
+        + minute * 60
+        + second
+        - offset;
+    let secs = u64::try_from(secs).ok()?;
+    Some(UNIX_EPOCH + Duration::new(secs, nanos))
+}
+
+// The number of days from the epoch to a date, the inverse of
+// `civil_from_days`
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let shifted_month = i64::from((month + 9) % 12);
+    let day_of_year = (153 * shifted_month + 2) / 5 + i64::from(day) - 1;
+    let day_of_era =
+        year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
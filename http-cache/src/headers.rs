@@ -0,0 +1,299 @@
+use std::{fmt, iter::FromIterator, slice};
+
+use bytes::Bytes;
+use http::{HeaderMap, HeaderValue};
+use serde::{
+    de::{Error, MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use crate::redact::RedactedEntries;
+
+/// The headers of an [`HttpResponse`](crate::HttpResponse), keeping every
+/// value of a repeated header such as `set-cookie`, in the order they were
+/// received.
+///
+/// Names are matched case insensitively. Values are kept byte for byte, so
+/// values holding commas are never split and values that aren't UTF-8 are
+/// kept too, use [`HttpHeaders::get_str`] to read them as text. Values of
+/// sensitive headers are left out of the `Debug` output, see
+/// [`Redaction`](crate::Redaction).
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct HttpHeaders {
+    entries: Vec<(String, HeaderValue)>,
+}
+
+impl HttpHeaders {
+    /// Creates an empty set of headers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the first value of the header.
+    pub fn get(&self, name: &str) -> Option<&HeaderValue> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns the first value of the header if it is valid UTF-8.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.get(name)
+            .and_then(|value| std::str::from_utf8(value.as_bytes()).ok())
+    }
+
+    /// Returns every value of the header, in order.
+    pub fn get_all<'a>(
+        &'a self,
+        name: &'a str,
+    ) -> impl Iterator<Item = &'a HeaderValue> + 'a {
+        self.entries
+            .iter()
+            .filter(move |(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+
+    /// Returns true if the header has at least one value.
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replaces every value of the header with `value`, keeping the place of
+    /// its first value.
+    pub fn insert(&mut self, name: impl Into<String>, value: HeaderValue) {
+        let name = name.into();
+        let mut value = Some(value);
+        self.entries.retain_mut(|(key, existing)| {
+            if !key.eq_ignore_ascii_case(&name) {
+                return true;
+            }
+            match value.take() {
+                Some(value) => {
+                    *existing = value;
+                    true
+                }
+                None => false,
+            }
+        });
+        if let Some(value) = value {
+            self.entries.push((name, value));
+        }
+    }
+
+    /// Adds a value to the header, after the values it already has.
+    pub fn append(&mut self, name: impl Into<String>, value: HeaderValue) {
+        self.entries.push((name.into(), value));
+    }
+
+    /// Removes every value of the header, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<HeaderValue> {
+        let mut removed = None;
+        self.entries.retain(|(key, value)| {
+            if !key.eq_ignore_ascii_case(name) {
+                return true;
+            }
+            if removed.is_none() {
+                removed = Some(value.clone());
+            }
+            false
+        });
+        removed
+    }
+
+    /// Returns every name and value, repeated headers once per value.
+    pub fn iter(&self) -> HttpHeadersIter<'_> {
+        HttpHeadersIter(self.entries.iter())
+    }
+
+    /// Returns the number of values.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if there aren't any headers.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+impl fmt::Debug for HttpHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        RedactedEntries(&self.entries).fmt(f)
+    }
+}
+
+/// Iterates over the names and values of [`HttpHeaders`].
+#[derive(Clone)]
+pub struct HttpHeadersIter<'a>(slice::Iter<'a, (String, HeaderValue)>);
+
+impl fmt::Debug for HttpHeadersIter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("HttpHeadersIter")
+            .field(&RedactedEntries(self.0.as_slice()))
+            .finish()
+    }
+}
+
+impl<'a> Iterator for HttpHeadersIter<'a> {
+    type Item = (&'a str, &'a HeaderValue);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next().map(|(name, value)| (name.as_str(), value))
+    }
+}
+
+impl<'a> IntoIterator for &'a HttpHeaders {
+    type Item = (&'a str, &'a HeaderValue);
+    type IntoIter = HttpHeadersIter<'a>;
+
+    fn into_iter(self) -> HttpHeadersIter<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for HttpHeaders {
+    type Item = (String, HeaderValue);
+    type IntoIter = std::vec::IntoIter<(String, HeaderValue)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<N: Into<String>> FromIterator<(N, HeaderValue)> for HttpHeaders {
+    fn from_iter<I: IntoIterator<Item = (N, HeaderValue)>>(iter: I) -> Self {
+        let mut headers = Self::new();
+        headers.extend(iter);
+        headers
+    }
+}
+
+impl<N: Into<String>> Extend<(N, HeaderValue)> for HttpHeaders {
+    fn extend<I: IntoIterator<Item = (N, HeaderValue)>>(&mut self, iter: I) {
+        for (name, value) in iter {
+            self.append(name, value);
+        }
+    }
+}
+
+impl From<&HeaderMap> for HttpHeaders {
+    fn from(map: &HeaderMap) -> Self {
+        map.iter().map(|(name, value)| (name.as_str(), value.clone())).collect()
+    }
+}
+
+// Serialized as a map holding a key per value, which reads the same as the
+// `HashMap` used by records stored before repeated headers were kept
+impl Serialize for HttpHeaders {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.entries.len()))?;
+        for (name, value) in &self.entries {
+            map.serialize_entry(name, &ValueRef(value))?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for HttpHeaders {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(HeadersVisitor)
+    }
+}
+
+struct HeadersVisitor;
+
+impl<'de> Visitor<'de> for HeadersVisitor {
+    type Value = HttpHeaders;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a map of header names to values")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(
+        self,
+        mut access: A,
+    ) -> Result<HttpHeaders, A::Error> {
+        let mut entries =
+            Vec::with_capacity(access.size_hint().unwrap_or(0).min(256));
+        while let Some((name, Value(value))) = access.next_entry()? {
+            entries.push((name, value));
+        }
+        Ok(HttpHeaders { entries })
+    }
+}
+
+// Human readable formats get values that are valid UTF-8 as strings, as they
+// always did, and the others as bytes. The other formats always get bytes,
+// which bincode lays out the same way as strings.
+struct ValueRef<'a>(&'a HeaderValue);
+
+impl Serialize for ValueRef<'_> {
+    fn serialize<S: Serializer>(
+        &self,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match std::str::from_utf8(self.0.as_bytes()) {
+            Ok(value) if serializer.is_human_readable() => {
+                serializer.serialize_str(value)
+            }
+            _ => serializer.serialize_bytes(self.0.as_bytes()),
+        }
+    }
+}
+
+struct Value(HeaderValue);
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(ValueVisitor)
+        } else {
+            deserializer.deserialize_byte_buf(ValueVisitor)
+        }
+    }
+}
+
+struct ValueVisitor;
+
+impl<'de> Visitor<'de> for ValueVisitor {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a header value as a string or bytes")
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Value, E> {
+        self.visit_bytes(value.as_bytes())
+    }
+
+    fn visit_bytes<E: Error>(self, value: &[u8]) -> Result<Value, E> {
+        HeaderValue::from_bytes(value).map(Value).map_err(E::custom)
+    }
+
+    fn visit_byte_buf<E: Error>(self, value: Vec<u8>) -> Result<Value, E> {
+        HeaderValue::from_maybe_shared(Bytes::from(value))
+            .map(Value)
+            .map_err(E::custom)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(
+        self,
+        mut access: A,
+    ) -> Result<Value, A::Error> {
+        let mut bytes =
+            Vec::with_capacity(access.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = access.next_element()? {
+            bytes.push(byte);
+        }
+        self.visit_byte_buf(bytes)
+    }
+}
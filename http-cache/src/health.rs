@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use http_cache_semantics::CachePolicy;
+use url::Url;
+
+use crate::{
+    now, CacheError, CacheKey, CacheManager, HttpHeaders, HttpResponse,
+    HttpVersion, Result,
+};
+
+/// The outcome of a health check that passed, see
+/// [`CacheManager::health_check`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct HealthReport {
+    /// How long the check took, from the first call to the backend to the
+    /// answer of the last one
+    pub latency: Duration,
+}
+
+// The url of the record stored by the default health check, under a domain
+// reserved to never resolve so it can't shadow a real response
+const SENTINEL_URL: &str = "http://http-cache.invalid/health-check";
+
+// The body of the record stored by the default health check
+const SENTINEL_BODY: &[u8] = b"http-cache health check";
+
+// Stores, reads back and removes a sentinel record, the default
+// implementation of `CacheManager::health_check`
+pub(crate) async fn round_trip<T: CacheManager + ?Sized>(
+    manager: &T,
+) -> Result<HealthReport> {
+    let url = Url::parse(SENTINEL_URL)?;
+    let key = CacheKey::new("GET", &url);
+    let res = HttpResponse {
+        body: Bytes::from_static(SENTINEL_BODY),
+        headers: HttpHeaders::default(),
+        trailers: None,
+        status: 200,
+        reason: None,
+        url: url.clone(),
+        version: HttpVersion::Http11,
+        metadata: Default::default(),
+    };
+    let req = http::Request::get(url.as_str()).body(())?;
+    let policy = CachePolicy::new(&req, &res.parts()?);
+    let started = now();
+    manager.put(&key, res, policy).await?;
+    let read = manager.get(&key).await;
+    // Removed even when it can't be read, so a failed check leaves nothing
+    // behind
+    manager.delete(&key).await?;
+    let latency = now().duration_since(started).unwrap_or_default();
+    match read? {
+        Some((res, _)) if res.body == SENTINEL_BODY => {
+            Ok(HealthReport { latency })
+        }
+        Some(_) => Err(unhealthy("the record read back doesn't match")),
+        None => Err(unhealthy("the record stored can't be read back")),
+    }
+}
+
+pub(crate) fn unhealthy(reason: &str) -> CacheError {
+    CacheError::Unhealthy(reason.to_string())
+}
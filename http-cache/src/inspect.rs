@@ -0,0 +1,303 @@
+use std::{
+    fmt,
+    time::{Duration, SystemTime},
+};
+
+use http_cache_semantics::CachePolicy;
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    now, CacheError, Clock, EntryMetadata, EnumerableCacheManager, HttpHeaders,
+    Result,
+};
+
+/// What the cache knows about a stored response, as returned by
+/// [`HttpCache::inspect`](crate::HttpCache::inspect).
+///
+/// The `Debug` output holds the headers redacted as set by [`Redaction`](crate::Redaction),
+/// but not the policy, which would show them as they are.
+#[derive(Clone)]
+pub struct CacheInspection {
+    headers: HttpHeaders,
+    policy: CachePolicy,
+    metadata: EntryMetadata,
+    clock: Option<Clock>,
+}
+
+impl fmt::Debug for CacheInspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CacheInspection")
+            .field("headers", &self.headers)
+            .field("metadata", &self.metadata)
+            .finish_non_exhaustive()
+    }
+}
+
+impl CacheInspection {
+    /// Creates an inspection from the stored response headers and policy.
+    pub fn new(headers: HttpHeaders, policy: CachePolicy) -> Self {
+        Self {
+            headers,
+            policy,
+            metadata: EntryMetadata::default(),
+            clock: None,
+        }
+    }
+
+    /// Sets the application data stored along with the response.
+    pub fn with_metadata(mut self, metadata: EntryMetadata) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    // Judges freshness against the clock of the cache the inspection comes
+    // from, see `HttpCacheOptions::clock`
+    pub(crate) fn with_clock(mut self, clock: Option<Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    fn now(&self) -> SystemTime {
+        match &self.clock {
+            Some(clock) => clock(),
+            None => now(),
+        }
+    }
+
+    /// Returns true if the stored response can be served without revalidation.
+    pub fn is_fresh_now(&self) -> bool {
+        !self.metadata.is_soft_purged() && !self.policy.is_stale(self.now())
+    }
+
+    /// Returns how much longer the stored response stays fresh, zero once it
+    /// was soft purged.
+    pub fn time_to_live(&self) -> Duration {
+        if self.metadata.is_soft_purged() {
+            return Duration::ZERO;
+        }
+        self.policy.time_to_live(self.now())
+    }
+
+    /// Returns true if the stored response was allowed to be cached.
+    pub fn is_storable(&self) -> bool {
+        self.policy.is_storable()
+    }
+
+    /// Returns true if the stored response has an `ETag` to revalidate with.
+    pub fn has_etag(&self) -> bool {
+        self.header("etag").is_some()
+    }
+
+    /// Returns true if the stored response has a `Last-Modified` date to
+    /// revalidate with.
+    pub fn has_last_modified(&self) -> bool {
+        self.header("last-modified").is_some()
+    }
+
+    /// Returns the lowercased names listed in the `Vary` header of the stored
+    /// response.
+    pub fn vary_headers(&self) -> Vec<String> {
+        self.header("vary")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|name| name.trim().to_ascii_lowercase())
+                    .filter(|name| !name.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the stored response headers.
+    pub fn headers(&self) -> &HttpHeaders {
+        &self.headers
+    }
+
+    /// Returns the stored policy.
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    /// Returns the application data stored along with the response, see
+    /// [`EntryMetadata`].
+    pub fn metadata(&self) -> &EntryMetadata {
+        &self.metadata
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get_str(name)
+    }
+}
+
+/// How long a stored response stays fresh, as returned by
+/// [`HttpCache::freshness`](crate::HttpCache::freshness).
+///
+/// The stale windows are the ones the response allows through its
+/// `Cache-Control` header, counted from when it stops being fresh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct FreshnessInfo {
+    /// How much longer the stored response stays fresh, zero once it is stale
+    pub time_to_live: Duration,
+    /// Whether the stored response has to be revalidated before being served
+    pub is_stale: bool,
+    /// Whether the stored response has an `ETag` or a `Last-Modified` date to
+    /// revalidate with
+    pub can_revalidate: bool,
+    /// How long the response may be served stale while it is revalidated,
+    /// from its `stale-while-revalidate` directive
+    pub stale_while_revalidate: Option<Duration>,
+    /// How long the response may be served stale when revalidating it fails,
+    /// from its `stale-if-error` directive
+    pub stale_if_error: Option<Duration>,
+}
+
+impl FreshnessInfo {
+    pub(crate) fn new(
+        headers: &HttpHeaders,
+        policy: &CachePolicy,
+        metadata: &EntryMetadata,
+        now: SystemTime,
+    ) -> Self {
+        let window = |name| {
+            cache_control_seconds(headers, name).map(Duration::from_secs)
+        };
+        let purged = metadata.is_soft_purged();
+        Self {
+            time_to_live: if purged {
+                Duration::ZERO
+            } else {
+                policy.time_to_live(now)
+            },
+            is_stale: purged || policy.is_stale(now),
+            can_revalidate: headers.contains_key("etag")
+                || headers.contains_key("last-modified"),
+            stale_while_revalidate: window("stale-while-revalidate"),
+            stale_if_error: window("stale-if-error"),
+        }
+    }
+}
+
+// Reads the seconds of a `Cache-Control` directive of the response, such as
+// `max-age=60`
+pub(crate) fn cache_control_seconds(
+    headers: &HttpHeaders,
+    name: &str,
+) -> Option<u64> {
+    let value = headers.get_str("cache-control")?;
+    value.split(',').find_map(|directive| {
+        let (key, seconds) = directive.trim().split_once('=')?;
+        if !key.trim().eq_ignore_ascii_case(name) {
+            return None;
+        }
+        seconds.trim().trim_matches('"').parse::<u64>().ok()
+    })
+}
+
+/// Lists every record of an enumerable manager along with what the cache
+/// knows about it.
+///
+/// Records that can't be read back are counted in
+/// [`CacheReportTotals::unreadable`] instead of failing the whole report.
+/// Every record is read in full to measure its body.
+pub async fn inspect<T: EnumerableCacheManager + ?Sized>(
+    manager: &T,
+) -> Result<CacheReport> {
+    let now = now();
+    let mut report = CacheReport::default();
+    for key in manager.keys().await? {
+        let (res, policy) = match manager.get(&key).await {
+            Ok(Some(record)) => record,
+            // Removed since the keys were listed
+            Ok(None) => continue,
+            Err(CacheError::CorruptEntry(_)) => {
+                report.totals.unreadable += 1;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let tags = manager.tags(&key).await?;
+        let inspection = CacheInspection::new(res.headers, policy)
+            .with_metadata(res.metadata);
+        let entry = CacheReportEntry {
+            method: key.method().to_string(),
+            url: key.url().clone(),
+            status: res.status,
+            stored_at: now
+                .checked_sub(inspection.policy.age(now))
+                .unwrap_or(now),
+            // Lifetimes too long to add to the current time are taken as
+            // 2^31 seconds, as RFC 9111 has caches do with delta-seconds
+            expires_at: now
+                .checked_add(inspection.time_to_live())
+                .unwrap_or(now + Duration::from_secs(1 << 31)),
+            is_stale: !inspection.is_fresh_now(),
+            body_size: res.body.len(),
+            has_etag: inspection.has_etag(),
+            has_last_modified: inspection.has_last_modified(),
+            tags,
+            metadata: inspection.metadata.clone(),
+        };
+        report.totals.entries += 1;
+        report.totals.body_bytes += entry.body_size;
+        if entry.is_stale {
+            report.totals.stale += 1;
+        }
+        report.entries.push(entry);
+    }
+    Ok(report)
+}
+
+/// Every record held by a manager, as returned by [`inspect`].
+#[derive(Debug, Clone, Default, Serialize)]
+#[non_exhaustive]
+pub struct CacheReport {
+    /// The readable records, in the order the manager listed them
+    pub entries: Vec<CacheReportEntry>,
+    /// Totals over every record
+    pub totals: CacheReportTotals,
+}
+
+/// A single record of a [`CacheReport`].
+#[derive(Debug, Clone, Serialize)]
+#[non_exhaustive]
+pub struct CacheReportEntry {
+    /// The request method
+    pub method: String,
+    /// The request url
+    pub url: Url,
+    /// The status of the stored response
+    pub status: u16,
+    /// When the stored response was generated, derived from its current age
+    pub stored_at: SystemTime,
+    /// When the stored response stops being fresh
+    pub expires_at: SystemTime,
+    /// Whether the stored response has to be revalidated before being served
+    pub is_stale: bool,
+    /// Size of the stored body in bytes
+    pub body_size: usize,
+    /// Whether the stored response has an `ETag` to revalidate with
+    pub has_etag: bool,
+    /// Whether the stored response has a `Last-Modified` date to revalidate
+    /// with
+    pub has_last_modified: bool,
+    /// The tags stored along with the record
+    pub tags: Vec<String>,
+    /// The application data stored along with the record
+    pub metadata: EntryMetadata,
+}
+
+/// The totals of a [`CacheReport`].
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[non_exhaustive]
+pub struct CacheReportTotals {
+    /// Number of readable records
+    pub entries: usize,
+    /// Number of readable records that are stale
+    pub stale: usize,
+    /// Combined size of the readable bodies in bytes
+    pub body_bytes: usize,
+    /// Number of records that couldn't be read back
+    pub unreadable: usize,
+}
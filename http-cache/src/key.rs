@@ -0,0 +1,117 @@
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use url::Url;
+
+// Keys longer than this are stored under a digest of the key instead, so
+// backends keeping keys in file names or size limited indexes can hold them
+const MAX_KEY_LEN: usize = 2048;
+
+// Marks a key replaced by its digest, which never parses as a url
+const HASHED_PREFIX: &str = "sha256-";
+
+/// The key a record is stored under, handed to every [`CacheManager`](crate::CacheManager)
+/// method in place of the method and url of the request.
+///
+/// [`HttpCache`](crate::HttpCache) builds the key from the uppercased method
+/// and the url of the request, normalized as [`Url`] parses it, which drops
+/// default ports among other things. The fragment of the request never
+/// reaches the origin and is left out, the fragment of the key holds the
+/// variant picked by
+/// [`HttpCacheOptions::key_headers`](crate::HttpCacheOptions::key_headers)
+/// instead.
+///
+/// Managers should store records under [`as_str`](Self::as_str) verbatim,
+/// so every backend keys records the same way. Keys too long for file names
+/// or size limited indexes are replaced by their digest there, see
+/// [`is_digest`](Self::is_digest).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    method: String,
+    url: Url,
+    key: String,
+}
+
+impl CacheKey {
+    /// Creates the key of the record for `method` and `url`, taking the
+    /// fragment of `url` as the variant of the record.
+    pub fn new(method: &str, url: &Url) -> Self {
+        let method = method.to_uppercase();
+        let full = format!("{}:{}", method, url);
+        let key = if full.len() <= MAX_KEY_LEN {
+            full
+        } else {
+            format!("{}:{}{}", method, HASHED_PREFIX, digest(&full))
+        };
+        Self { method, url: url.clone(), key }
+    }
+
+    /// Parses a key written by the [`Display`](fmt::Display) implementation,
+    /// returning `None` if it isn't one.
+    pub fn parse(key: &str) -> Option<Self> {
+        let (method, url) = key.split_once(':')?;
+        Some(Self::new(method, &Url::parse(url).ok()?))
+    }
+
+    /// Returns the uppercased method of the request.
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    /// Returns the url of the request, along with the variant as its
+    /// fragment.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Returns the variant of the record, if there is one.
+    pub fn variant(&self) -> Option<&str> {
+        self.url.fragment()
+    }
+
+    /// Returns the key the record should be stored under, `{method}:{url}`
+    /// or, past 2048 bytes, `{method}:sha256-{hash}`.
+    pub fn as_str(&self) -> &str {
+        &self.key
+    }
+
+    /// Returns the hex encoded sha256 digest of the whole key, for backends
+    /// that need keys of a fixed length.
+    pub fn hash(&self) -> String {
+        digest(&self.to_string())
+    }
+
+    /// Returns true if [`as_str`](Self::as_str) is the digest of the key
+    /// rather than the key itself. Managers that list their records then
+    /// need to keep the whole key, its [`Display`](fmt::Display) form, along
+    /// with the record to tell its method and url.
+    pub fn is_digest(&self) -> bool {
+        is_hashed_key(&self.key)
+    }
+
+    // A fraction in `0.0..1.0` picked from the digest of the key, the same
+    // for every request the key is built for
+    pub(crate) fn fraction(&self) -> f64 {
+        let digest = Sha256::digest(self.to_string().as_bytes());
+        let bytes = [digest[0], digest[1], digest[2], digest[3]];
+        f64::from(u32::from_be_bytes(bytes)) / (f64::from(u32::MAX) + 1.0)
+    }
+}
+
+/// Writes the whole key, `{method}:{url}`, which
+/// [`CacheKey::parse`] reads back.
+impl fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.method, self.url)
+    }
+}
+
+fn digest(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+// Whether a key read back from a backend is the digest of a key too long to
+// be used as is, the full key is then kept along with the record
+pub(crate) fn is_hashed_key(key: &str) -> bool {
+    key.split_once(':').is_some_and(|(_, rest)| rest.starts_with(HASHED_PREFIX))
+}
@@ -17,48 +17,165 @@
 //!
 //! ## Features
 //!
-//! The following features are available. By default `manager-cacache` is enabled.
+//! The following features are available. By default `manager-cacache` and
+//! `manager-moka` are enabled, disabling default features leaves just the
+//! traits and the caching logic for use with a custom manager.
 //!
 //! - `manager-cacache` (default): enable [cacache](https://github.com/zkat/cacache-rs),
-//! a high-performance disk cache, backend manager.
-//! - `manager-moka` (disabled): enable [moka](https://github.com/moka-rs/moka),
-//! a high-performance in-memory cache, backend manager.
+//!   a high-performance disk cache, backend manager.
+//! - `manager-moka` (default): enable [moka](https://github.com/moka-rs/moka),
+//!   a high-performance in-memory cache, backend manager.
 //! - `with-http-types` (disabled): enable [http-types](https://github.com/http-rs/http-types)
-//! type conversion support
+//!   type conversion support
+//! - `format-json` (disabled): allow the bundled managers to store records as JSON,
+//!   see [`SerializationFormat`].
+//! - `format-cbor` (disabled): allow the bundled managers to store records as CBOR,
+//!   see [`SerializationFormat`].
+//! - `har` (disabled): enable [`import_har`] and [`export_har`] to load the
+//!   responses recorded in a HAR file and write the cache out as one.
+//! - `test-util` (disabled): enable [`MockClock`], a clock that only moves
+//!   when told to, for testing time dependent caching.
+//! - `spawn-tokio` (disabled): enable [`Spawner::tokio`] to run background work
+//!   on tokio.
+//! - `spawn-async-std` (disabled): enable [`Spawner::async_std`] to run background
+//!   work on async-std.
+//!
+//! ## WebAssembly
+//!
+//! With default features disabled the crate builds for `wasm32-unknown-unknown`.
+//! Times are then read from the browser through [`now`], and the futures of
+//! the manager and middleware traits don't need to be `Send`, see
+//! [`MaybeSend`].
+mod adapter;
+mod archive;
+mod breaker;
+#[cfg(feature = "test-util")]
+mod clock;
+mod coalesce;
+mod content_type;
+mod directives;
+mod dry_run;
+mod entry_metadata;
 mod error;
+mod events;
+#[cfg(feature = "har")]
+mod har;
+mod headers;
+mod health;
+mod inspect;
+mod key;
+mod maintenance;
 mod managers;
+mod no_store;
+mod record;
+mod redact;
+mod revalidation;
+mod spawn;
+mod streaming;
+mod timeout;
+mod toggle;
+mod url_filter;
+mod write_behind;
 
 use std::{
-    collections::HashMap, convert::TryFrom, fmt, str::FromStr, time::SystemTime,
+    convert::TryFrom,
+    fmt,
+    future::{ready, Future},
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
 };
 
-use http::{header::CACHE_CONTROL, request, response, StatusCode};
+use coalesce::{Role, Waited};
+use directives::Withheld;
+use futures::{
+    future::{Either, FutureExt},
+    stream::{self, StreamExt},
+};
+use http::{
+    header::{CACHE_CONTROL, IF_MODIFIED_SINCE, IF_NONE_MATCH},
+    request, response, HeaderMap, HeaderName, HeaderValue, StatusCode,
+};
 use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy};
 use serde::{Deserialize, Serialize};
-use url::Url;
+use url::{Origin, Url};
 
+pub use adapter::CacheStatus;
+pub use archive::{export, import, ConflictPolicy, ExportReport, ImportReport};
+pub use breaker::{BreakerState, CircuitBreaker, ErrorPolicy};
+#[cfg(feature = "test-util")]
+pub use clock::MockClock;
+pub use coalesce::{Coalescing, LeaderFailure};
+pub use content_type::ContentTypes;
+pub use dry_run::{CacheDecision, DryRun, DryRunLookup, NotStoredReason};
+pub use entry_metadata::EntryMetadata;
 pub use error::{CacheError, Result};
+pub use events::{CacheEvent, EventBroadcast, EventStream};
+#[cfg(feature = "har")]
+pub use har::{export_har, import_har, HarExportOptions, HarImportOptions};
+pub use headers::{HttpHeaders, HttpHeadersIter};
+pub use health::HealthReport;
+pub use inspect::{
+    inspect, CacheInspection, CacheReport, CacheReportEntry, CacheReportTotals,
+    FreshnessInfo,
+};
+pub use key::CacheKey;
+pub use maintenance::{
+    maintenance, MaintenanceHandle, MaintenanceOptions, MaintenanceStats,
+    PrunableCacheManager, PruneReport, Timer,
+};
+pub use record::RecordMode;
+pub use redact::{Redaction, DEFAULT_REDACTED_HEADERS};
+pub use revalidation::RevalidationRequest;
+pub use spawn::{BackgroundTask, Spawner};
+pub use streaming::{
+    BodyStream, BodyWriter, StreamingCacheManager, StreamingMiddleware,
+};
+pub use toggle::CacheToggle;
+pub use url_filter::UrlFilter;
+pub use write_behind::{WriteBehind, WriteBehindStats};
 
 #[cfg(feature = "manager-cacache")]
-pub use managers::cacache::CACacheManager;
+pub use managers::cacache::{
+    BodyStats, CACacheManager, CACacheReader, CACacheWriter,
+};
 
 #[cfg(feature = "manager-moka")]
-pub use managers::moka::MokaManager;
+pub use managers::moka::{MokaManager, MokaWriter};
+
+pub use managers::store::{
+    decode_record, encode_record, BodyChecksum, DecodedRecord,
+    SerializationFormat,
+};
+
+pub use bytes::Bytes;
 
 // Exposing the moka cache for convenience, renaming to avoid naming conflicts
 #[cfg(feature = "manager-moka")]
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
 pub use moka::future::{Cache as MokaCache, CacheBuilder as MokaCacheBuilder};
 
+/// Version of the layout used by the bundled managers to store records.
+///
+/// Every stored record carries the version it was written with. Records from
+/// older versions are upgraded when read, while records from unknown (newer)
+/// versions are treated as misses and removed. Custom managers that persist
+/// records are encouraged to follow the same convention, which
+/// [`encode_record`] and [`decode_record`] implement.
+pub const CACHE_RECORD_VERSION: u8 = 6;
+
 // Custom headers used to indicate cache status (hit or miss)
 /// `x-cache` header: Value will be HIT if the response was served from cache, MISS if not
 pub const XCACHE: &str = "x-cache";
 /// `x-cache-lookup` header: Value will be HIT if a response existed in cache, MISS if not
 pub const XCACHELOOKUP: &str = "x-cache-lookup";
+/// `x-cache-override` header: Set to `no-store` on responses stored in spite of their
+/// `no-store` directive, see [`HttpCacheOptions::force_cache_overrides_no_store`]
+pub const XCACHEOVERRIDE: &str = "x-cache-override";
 
 /// Represents a basic cache status
 /// Used in the custom headers `x-cache` and `x-cache-lookup`
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum HitOrMiss {
     /// Yes, there was a hit
     HIT,
@@ -66,15 +183,21 @@ pub enum HitOrMiss {
     MISS,
 }
 
-impl fmt::Display for HitOrMiss {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+impl HitOrMiss {
+    const fn as_str(self) -> &'static str {
         match self {
-            HitOrMiss::HIT => write!(f, "HIT"),
-            HitOrMiss::MISS => write!(f, "MISS"),
+            HitOrMiss::HIT => "HIT",
+            HitOrMiss::MISS => "MISS",
         }
     }
 }
 
+impl fmt::Display for HitOrMiss {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// Represents an HTTP version
 #[derive(Debug, Copy, Clone, PartialEq, Deserialize, Serialize)]
 #[non_exhaustive]
@@ -99,16 +222,58 @@ pub enum HttpVersion {
 /// A basic generic type that represents an HTTP response
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct HttpResponse {
-    /// HTTP response body
-    pub body: Vec<u8>,
-    /// HTTP response headers
-    pub headers: HashMap<String, String>,
+    /// HTTP response body, which can be built from a `Vec<u8>` with `into`.
+    /// Clones share the same buffer.
+    ///
+    /// Bodies are stored the way the client handed them over. When a client
+    /// decoded a gzip body its `Content-Encoding` is dropped and its
+    /// `Content-Length` set to the decoded length before it is cached.
+    #[serde(
+        deserialize_with = "deserialize_body",
+        serialize_with = "serialize_body"
+    )]
+    pub body: Bytes,
+    /// HTTP response headers, keeping every value of repeated headers
+    pub headers: HttpHeaders,
+    /// The trailers sent after the body, when the client exposes them.
+    ///
+    /// Only the tower client reads and restores them, the other clients
+    /// drop trailers, see [`HttpCacheOptions::trailers_as_headers`] for
+    /// handing them stored trailers.
+    pub trailers: Option<HttpHeaders>,
     /// HTTP response status code
     pub status: u16,
+    /// The reason phrase of the status line, such as `Not Found`, when the
+    /// client exposes it.
+    ///
+    /// Clients built on hyper, such as reqwest and tower, only expose the
+    /// phrases of HTTP/1 responses that aren't the canonical one of their
+    /// status, the fetch API of browsers exposes every one as `statusText`.
+    /// The client crates restore it on responses served from the cache when
+    /// the client allows, see their documentation.
+    pub reason: Option<String>,
     /// HTTP response url
     pub url: Url,
     /// HTTP response version
     pub version: HttpVersion,
+    /// The application data stored along with the response, see
+    /// [`EntryMetadata`]. Empty for responses fetched without any.
+    pub metadata: EntryMetadata,
+}
+
+// Bodies are serialized as a sequence of bytes, the same way as a `Vec<u8>`,
+// which keeps records written before the body was `Bytes` readable
+fn serialize_body<S: serde::Serializer>(
+    body: &Bytes,
+    serializer: S,
+) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_seq(body.iter())
+}
+
+pub(crate) fn deserialize_body<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<Bytes, D::Error> {
+    Vec::<u8>::deserialize(deserializer).map(Bytes::from)
 }
 
 impl HttpResponse {
@@ -118,11 +283,8 @@ impl HttpResponse {
             response::Builder::new().status(self.status).body(())?;
         {
             let headers = converted.headers_mut();
-            for header in &self.headers {
-                headers.insert(
-                    http::header::HeaderName::from_str(header.0.as_str())?,
-                    http::HeaderValue::from_str(header.1.as_str())?,
-                );
+            for (name, value) in &self.headers {
+                headers.append(HeaderName::from_str(name)?, value.clone());
             }
         }
         Ok(converted.into_parts().0)
@@ -131,8 +293,8 @@ impl HttpResponse {
     /// Returns the status code of the warning header if present
     #[must_use]
     pub fn warning_code(&self) -> Option<usize> {
-        self.headers.get("warning").and_then(|hdr| {
-            hdr.as_str().chars().take(3).collect::<String>().parse().ok()
+        self.headers.get_str("warning").and_then(|hdr| {
+            hdr.chars().take(3).collect::<String>().parse().ok()
         })
     }
 
@@ -147,15 +309,16 @@ impl HttpResponse {
         // warn-text  = quoted-string
         // warn-date  = <"> HTTP-date <">
         // (https://tools.ietf.org/html/rfc2616#section-14.46)
+        let warning = format!(
+            "{} {} {:?} \"{}\"",
+            code,
+            url.host().expect("Invalid URL"),
+            message,
+            httpdate::fmt_http_date(now())
+        );
         self.headers.insert(
-            "warning".to_string(),
-            format!(
-                "{} {} {:?} \"{}\"",
-                code,
-                url.host().expect("Invalid URL"),
-                message,
-                httpdate::fmt_http_date(SystemTime::now())
-            ),
+            "warning",
+            HeaderValue::from_str(&warning).expect("Invalid warning"),
         );
     }
 
@@ -164,338 +327,2903 @@ impl HttpResponse {
         self.headers.remove("warning");
     }
 
-    /// Update the headers from `http::response::Parts`
+    /// Moves the trailers to the headers, each one as a `trailer-{name}`
+    /// header, see [`HttpCacheOptions::trailers_as_headers`].
+    pub fn trailers_into_headers(&mut self) {
+        for (name, value) in self.trailers.take().into_iter().flatten() {
+            self.headers.append(format!("trailer-{}", name), value);
+        }
+    }
+
+    /// Update the headers from `http::response::Parts`, the values of a
+    /// header present in `parts` replace the values it had.
+    ///
+    /// The parts handed out by a [`CachePolicy`] only hold the last value of
+    /// a repeated header, so a single value matching the last one stored
+    /// leaves the others in place.
     pub fn update_headers(&mut self, parts: &response::Parts) -> Result<()> {
-        for header in parts.headers.iter() {
-            self.headers.insert(
-                header.0.as_str().to_string(),
-                header.1.to_str()?.to_string(),
-            );
+        for name in parts.headers.keys() {
+            let mut values = parts.headers.get_all(name).iter();
+            if let (Some(value), None) = (values.next(), values.next()) {
+                if self.headers.get_all(name.as_str()).last() == Some(value) {
+                    continue;
+                }
+            }
+            self.headers.remove(name.as_str());
+            for value in parts.headers.get_all(name) {
+                self.headers.append(name.as_str(), value.clone());
+            }
         }
         Ok(())
     }
 
+    // Drops the `Content-Encoding` of a gzip body that the client already
+    // decoded, as the isahc and surf clients do, and sets its
+    // `Content-Length` to the length of the decoded body. A body that is
+    // still encoded starts with the gzip magic number and is left alone, as
+    // are empty bodies and the bodies of other encodings.
+    fn drop_decoded_encoding(&mut self) {
+        let gzip = self.headers.get_str("content-encoding").is_some_and(|e| {
+            e.trim().eq_ignore_ascii_case("gzip")
+                || e.trim().eq_ignore_ascii_case("x-gzip")
+        });
+        if !gzip || self.body.is_empty() || self.body.starts_with(&[0x1f, 0x8b])
+        {
+            return;
+        }
+        self.headers.remove("content-encoding");
+        if self.headers.contains_key("content-length") {
+            self.headers.insert("content-length", self.body.len().into());
+        }
+    }
+
+    // Replaces an `Expires` header that isn't text with `0`. A cache has to
+    // treat an invalid `Expires` as a time in the past, which the policy does
+    // for values it can't parse but not for values it can't read at all,
+    // falling back to a heuristic lifetime instead.
+    pub(crate) fn expire_unreadable_dates(&mut self) {
+        let unreadable = self
+            .headers
+            .get_all("expires")
+            .any(|value| std::str::from_utf8(value.as_bytes()).is_err());
+        if unreadable {
+            self.headers.insert("expires", HeaderValue::from_static("0"));
+        }
+    }
+
+    // Adds the time the request took to the `Age` of the response, which RFC
+    // 9111 counts in the initial age, so a response that waited in another
+    // cache or on the way isn't taken as younger than it is. The apparent age
+    // from the `Date` header is left out as it relies on the clocks of the
+    // client and the origin being in sync.
+    pub(crate) fn correct_age(
+        &mut self,
+        requested: SystemTime,
+        received: SystemTime,
+    ) {
+        let delay =
+            received.duration_since(requested).unwrap_or_default().as_secs();
+        let age = match self.headers.get_str("age") {
+            Some(age) => match age.trim().parse::<u64>() {
+                Ok(age) => age,
+                // Left for the policy to read as no age
+                Err(_) => return,
+            },
+            None if delay == 0 => return,
+            None => 0,
+        };
+        self.headers
+            .insert("age", HeaderValue::from(age.saturating_add(delay)));
+    }
+
     /// Checks if the Cache-Control header contains the must-revalidate directive
     #[must_use]
     pub fn must_revalidate(&self) -> bool {
-        self.headers.get(CACHE_CONTROL.as_str()).map_or(false, |val| {
-            val.as_str().to_lowercase().contains("must-revalidate")
-        })
+        matches!(
+            self.headers.get_str(CACHE_CONTROL.as_str()),
+            Some(val) if val.to_lowercase().contains("must-revalidate")
+        )
     }
 
     /// Adds the custom `x-cache` header to the response
     pub fn cache_status(&mut self, hit_or_miss: HitOrMiss) {
-        self.headers.insert(XCACHE.to_string(), hit_or_miss.to_string());
+        self.headers
+            .insert(XCACHE, HeaderValue::from_static(hit_or_miss.as_str()));
     }
 
     /// Adds the custom `x-cache-lookup` header to the response
     pub fn cache_lookup_status(&mut self, hit_or_miss: HitOrMiss) {
-        self.headers.insert(XCACHELOOKUP.to_string(), hit_or_miss.to_string());
+        self.headers.insert(
+            XCACHELOOKUP,
+            HeaderValue::from_static(hit_or_miss.as_str()),
+        );
     }
 }
 
-/// A trait providing methods for storing, reading, and removing cache records.
-#[async_trait::async_trait]
-pub trait CacheManager: Send + Sync + 'static {
-    /// Attempts to pull a cached response and related policy from cache.
-    async fn get(
-        &self,
-        method: &str,
-        url: &Url,
-    ) -> Result<Option<(HttpResponse, CachePolicy)>>;
-    /// Attempts to cache a response and related policy.
-    async fn put(
-        &self,
-        method: &str,
-        url: &Url,
-        res: HttpResponse,
-        policy: CachePolicy,
-    ) -> Result<HttpResponse>;
-    /// Attempts to remove a record from cache.
-    async fn delete(&self, method: &str, url: &Url) -> Result<()>;
-}
+/// Implemented for every type that is `Send`, or for every type at all when
+/// targeting `wasm32`, where futures driven by the browser aren't `Send`.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSend: Send {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Send + ?Sized> MaybeSend for T {}
+/// Implemented for every type that is `Send`, or for every type at all when
+/// targeting `wasm32`, where futures driven by the browser aren't `Send`.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSend {}
+#[cfg(target_arch = "wasm32")]
+impl<T: ?Sized> MaybeSend for T {}
 
-/// Describes the functionality required for interfacing with HTTP client middleware
-#[async_trait::async_trait]
-pub trait Middleware: Send {
-    /// Determines if the request method is either GET or HEAD
-    fn is_method_get_head(&self) -> bool;
-    /// Returns a new cache policy with default options
-    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy>;
-    /// Returns a new cache policy with custom options
-    fn policy_with_options(
-        &self,
-        response: &HttpResponse,
-        options: CacheOptions,
-    ) -> Result<CachePolicy>;
-    /// Attempts to update the request headers with the passed `http::request::Parts`
-    fn update_headers(&mut self, parts: &request::Parts) -> Result<()>;
-    /// Attempts to force the "no-cache" directive on the request
-    fn force_no_cache(&mut self) -> Result<()>;
-    /// Attempts to construct `http::request::Parts` from the request
-    fn parts(&self) -> Result<request::Parts>;
-    /// Attempts to determine the requested url
-    fn url(&self) -> Result<Url>;
-    /// Attempts to determine the request method
-    fn method(&self) -> Result<String>;
-    /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
-    async fn remote_fetch(&mut self) -> Result<HttpResponse>;
-}
+/// Implemented for every type that is `Sync`, or for every type at all when
+/// targeting `wasm32`.
+#[cfg(not(target_arch = "wasm32"))]
+pub trait MaybeSync: Sync {}
+#[cfg(not(target_arch = "wasm32"))]
+impl<T: Sync + ?Sized> MaybeSync for T {}
+/// Implemented for every type that is `Sync`, or for every type at all when
+/// targeting `wasm32`.
+#[cfg(target_arch = "wasm32")]
+pub trait MaybeSync {}
+#[cfg(target_arch = "wasm32")]
+impl<T: ?Sized> MaybeSync for T {}
 
-/// Similar to [make-fetch-happen cache options](https://github.com/npm/make-fetch-happen#--optscache).
-/// Passed in when the [`HttpCache`] struct is being built.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum CacheMode {
-    /// Will inspect the HTTP cache on the way to the network.
-    /// If there is a fresh response it will be used.
-    /// If there is a stale response a conditional request will be created,
-    /// and a normal request otherwise.
-    /// It then updates the HTTP cache with the response.
-    /// If the revalidation request fails (for example, on a 500 or if you're offline),
-    /// the stale response will be returned.
-    Default,
-    /// Behaves as if there is no HTTP cache at all.
-    NoStore,
-    /// Behaves as if there is no HTTP cache on the way to the network.
-    /// Ergo, it creates a normal request and updates the HTTP cache with the response.
-    Reload,
-    /// Creates a conditional request if there is a response in the HTTP cache
-    /// and a normal request otherwise. It then updates the HTTP cache with the response.
-    NoCache,
-    /// Uses any response in the HTTP cache matching the request,
-    /// not paying attention to staleness. If there was no response,
-    /// it creates a normal request and updates the HTTP cache with the response.
-    ForceCache,
-    /// Uses any response in the HTTP cache matching the request,
-    /// not paying attention to staleness. If there was no response,
-    /// it returns a network error.
-    OnlyIfCached,
-}
+/// The boxed future returned by [`DynCacheManager`], which is only `Send`
+/// when not targeting `wasm32`.
+#[cfg(not(target_arch = "wasm32"))]
+pub type ManagerFuture<'a, T> = futures::future::BoxFuture<'a, T>;
+/// The boxed future returned by [`DynCacheManager`], which is only `Send`
+/// when not targeting `wasm32`.
+#[cfg(target_arch = "wasm32")]
+pub type ManagerFuture<'a, T> = futures::future::LocalBoxFuture<'a, T>;
 
-impl TryFrom<http::Version> for HttpVersion {
-    type Error = CacheError;
+/// Returns the current time. Unlike `SystemTime::now` this also works on
+/// `wasm32-unknown-unknown`, where the time is read from the browser.
+pub fn now() -> SystemTime {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        SystemTime::now()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        std::time::UNIX_EPOCH
+            + Duration::from_millis(js_sys::Date::now() as u64)
+    }
+}
 
-    fn try_from(value: http::Version) -> Result<Self> {
-        Ok(match value {
-            http::Version::HTTP_09 => Self::Http09,
-            http::Version::HTTP_10 => Self::Http10,
-            http::Version::HTTP_11 => Self::Http11,
-            http::Version::HTTP_2 => Self::H2,
-            http::Version::HTTP_3 => Self::H3,
-            _ => return Err(CacheError::BadVersion),
-        })
+// Hands the event to the hook and the subscribers, building it only if
+// someone gets it
+fn emit_event(
+    on_event: Option<&EventHook>,
+    broadcast: Option<&EventBroadcast>,
+    event: impl FnOnce() -> CacheEvent,
+) {
+    let broadcast = broadcast.filter(|broadcast| broadcast.has_subscribers());
+    if on_event.is_none() && broadcast.is_none() {
+        return;
+    }
+    let event = event();
+    if let Some(on_event) = on_event {
+        on_event(&event);
+    }
+    if let Some(broadcast) = broadcast {
+        broadcast.send(event);
     }
 }
 
-impl From<HttpVersion> for http::Version {
-    fn from(value: HttpVersion) -> Self {
-        match value {
-            HttpVersion::Http09 => Self::HTTP_09,
-            HttpVersion::Http10 => Self::HTTP_10,
-            HttpVersion::Http11 => Self::HTTP_11,
-            HttpVersion::H2 => Self::HTTP_2,
-            HttpVersion::H3 => Self::HTTP_3,
+// Strips the whitespace around a header value, which may not be UTF-8
+fn trim_whitespace(mut bytes: &[u8]) -> &[u8] {
+    while let [first, rest @ ..] = bytes {
+        if !first.is_ascii_whitespace() {
+            break;
+        }
+        bytes = rest;
+    }
+    while let [rest @ .., last] = bytes {
+        if !last.is_ascii_whitespace() {
+            break;
         }
+        bytes = rest;
     }
+    bytes
 }
 
-#[cfg(feature = "http-types")]
-impl TryFrom<http_types::Version> for HttpVersion {
-    type Error = CacheError;
+// Returns true if responses with the status are stored, `200` responses and
+// `204` ones, which only have their headers to go wrong
+pub(crate) fn is_stored_status(status: u16) -> bool {
+    status == 200 || status == 204
+}
 
-    fn try_from(value: http_types::Version) -> Result<Self> {
-        Ok(match value {
-            http_types::Version::Http0_9 => Self::Http09,
-            http_types::Version::Http1_0 => Self::Http10,
-            http_types::Version::Http1_1 => Self::Http11,
-            http_types::Version::Http2_0 => Self::H2,
-            http_types::Version::Http3_0 => Self::H3,
-            _ => return Err(CacheError::BadVersion),
-        })
-    }
+// Returns true if the request carries validators of its own, revalidating
+// a response the caller holds rather than the one that was stored
+pub(crate) fn has_validators(parts: &request::Parts) -> bool {
+    parts.headers.contains_key(IF_NONE_MATCH)
+        || parts.headers.contains_key(IF_MODIFIED_SINCE)
 }
 
-#[cfg(feature = "http-types")]
-impl From<HttpVersion> for http_types::Version {
-    fn from(value: HttpVersion) -> Self {
-        match value {
-            HttpVersion::Http09 => Self::Http0_9,
-            HttpVersion::Http10 => Self::Http1_0,
-            HttpVersion::Http11 => Self::Http1_1,
-            HttpVersion::H2 => Self::Http2_0,
-            HttpVersion::H3 => Self::Http3_0,
+// Asks the policy about a soft purged response the way a request with
+// `Cache-Control: no-cache` would, which gives the conditional request the
+// policy builds for a stale response. The request keeps its own
+// `Cache-Control` headers.
+fn soft_purged_request(
+    policy: &CachePolicy,
+    parts: &request::Parts,
+    now: SystemTime,
+) -> Result<BeforeRequest> {
+    let mut no_cache = request::Builder::new()
+        .method(parts.method.clone())
+        .uri(parts.uri.clone())
+        .version(parts.version)
+        .body(())?
+        .into_parts()
+        .0;
+    no_cache.headers = parts.headers.clone();
+    no_cache
+        .headers
+        .append(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    Ok(match policy.before_request(&no_cache, now) {
+        BeforeRequest::Stale { mut request, matches } => {
+            request.headers.remove(CACHE_CONTROL);
+            for value in parts.headers.get_all(CACHE_CONTROL) {
+                request.headers.append(CACHE_CONTROL, value.clone());
+            }
+            BeforeRequest::Stale { request, matches }
         }
-    }
+        fresh => fresh,
+    })
 }
 
-/// Options struct provided by
-/// [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
-pub use http_cache_semantics::CacheOptions;
+// The headers of a cached response along with its policy and application
+// metadata
+type Metadata = (HttpHeaders, CachePolicy, EntryMetadata);
 
-/// Caches requests according to http spec.
-#[derive(Debug, Clone)]
-pub struct HttpCache<T: CacheManager> {
-    /// Determines the manager behavior.
-    pub mode: CacheMode,
-    /// Manager instance that implements the [`CacheManager`] trait.
-    /// By default, a manager implementation with [`cacache`](https://github.com/zkat/cacache-rs)
-    /// as the backend has been provided, see [`CACacheManager`].
-    pub manager: T,
-    /// Override the default cache options.
-    pub options: Option<CacheOptions>,
-}
+// A cached response along with its policy
+type Record = (HttpResponse, CachePolicy);
 
-#[allow(dead_code)]
-impl<T: CacheManager> HttpCache<T> {
-    /// Attempts to run the passed middleware along with the cache
-    pub async fn run(
+/// A trait providing methods for storing, reading, and removing cache records.
+///
+/// The methods can be implemented with `async fn`. As the trait can't be made
+/// into a trait object, managers picked at runtime go through
+/// [`DynCacheManager`] instead.
+pub trait CacheManager: MaybeSend + MaybeSync + 'static {
+    /// Attempts to pull a cached response and related policy from cache.
+    ///
+    /// Records that exist but can't be read back should be reported with
+    /// [`CacheError::CorruptEntry`], which lets [`HttpCache`] remove them and
+    /// treat the request as a cache miss.
+    fn get(
         &self,
-        mut middleware: impl Middleware,
-    ) -> Result<HttpResponse> {
-        let is_cacheable = middleware.is_method_get_head()
-            && self.mode != CacheMode::NoStore
-            && self.mode != CacheMode::Reload;
-        if !is_cacheable {
-            return self.remote_fetch(&mut middleware).await;
-        }
-        let method = middleware.method()?.to_uppercase();
-        let url = middleware.url()?;
-        if let Some(store) = self.manager.get(&method, &url).await? {
-            let (mut res, policy) = store;
-            res.cache_lookup_status(HitOrMiss::HIT);
-            if let Some(warning_code) = res.warning_code() {
-                // https://tools.ietf.org/html/rfc7234#section-4.3.4
-                //
-                // If a stored response is selected for update, the cache MUST:
-                //
-                // * delete any warning header fields in the stored response with
-                //   warn-code 1xx (see Section 5.5);
-                //
-                // * retain any warning header fields in the stored response with
-                //   warn-code 2xx;
-                //
-                if (100..200).contains(&warning_code) {
-                    res.remove_warning();
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<Option<(HttpResponse, CachePolicy)>>> + MaybeSend;
+    /// Attempts to cache a response and related policy.
+    fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<HttpResponse>> + MaybeSend;
+    /// Attempts to remove a record from cache.
+    fn delete(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend;
+    /// Attempts to cache a response and related policy along with its tags,
+    /// replacing any tags previously stored for the record.
+    ///
+    /// Managers without tag support can rely on the default implementation,
+    /// which ignores the tags.
+    fn put_tagged(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        _tags: &[String],
+    ) -> impl Future<Output = Result<HttpResponse>> + MaybeSend {
+        self.put(key, res, policy)
+    }
+    /// Attempts to remove every record tagged with `tag`, returning how many
+    /// were removed. Managers without tag support never remove anything.
+    fn purge_tag(
+        &self,
+        _tag: &str,
+    ) -> impl Future<Output = Result<usize>> + MaybeSend {
+        async { Ok(0) }
+    }
+    /// Attempts to remove every record whose url has the given origin,
+    /// returning how many were removed. The default implementation can't
+    /// list the records and never removes anything.
+    fn purge_origin(
+        &self,
+        _origin: &Origin,
+    ) -> impl Future<Output = Result<usize>> + MaybeSend {
+        async { Ok(0) }
+    }
+    /// Protects the record from eviction until it is unpinned, the key can be
+    /// pinned before anything is stored under it. Pinned records still expire
+    /// and get revalidated as usual, and can be removed with `delete`.
+    ///
+    /// The default implementation does nothing, which is only correct for
+    /// managers that never evict records.
+    fn pin(
+        &self,
+        _key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend {
+        async { Ok(()) }
+    }
+    /// Makes a pinned record subject to eviction again.
+    fn unpin(
+        &self,
+        _key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend {
+        async { Ok(()) }
+    }
+    /// Replaces the policy of a cached record while keeping its response,
+    /// returning false if there is no such record.
+    ///
+    /// The default implementation reads the record and stores it again with
+    /// [`CacheManager::put`].
+    fn update_policy(
+        &self,
+        key: &CacheKey,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        async move {
+            match self.get(key).await? {
+                Some((res, _)) => {
+                    self.put(key, res, policy).await?;
+                    Ok(true)
                 }
+                None => Ok(false),
             }
-
-            match self.mode {
-                CacheMode::Default => {
-                    self.conditional_fetch(middleware, res, policy).await
-                }
-                CacheMode::NoCache => {
-                    middleware.force_no_cache()?;
-                    let mut res = self.remote_fetch(&mut middleware).await?;
-                    res.cache_lookup_status(HitOrMiss::HIT);
-                    Ok(res)
-                }
-                CacheMode::ForceCache | CacheMode::OnlyIfCached => {
-                    //   112 Disconnected operation
-                    // SHOULD be included if the cache is intentionally disconnected from
-                    // the rest of the network for a period of time.
-                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    res.add_warning(
-                        &res.url.clone(),
-                        112,
-                        "Disconnected operation",
-                    );
-                    res.cache_status(HitOrMiss::HIT);
-                    Ok(res)
+        }
+    }
+    /// Replaces the response headers and policy of a cached record while
+    /// keeping its body, returning false if there is no such record. Used to
+    /// refresh a record after a `304 Not Modified`.
+    ///
+    /// The default implementation reads the record and stores it again with
+    /// [`CacheManager::put`], managers that store the body separately can
+    /// avoid rewriting it.
+    fn update(
+        &self,
+        key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        async move {
+            match self.get(key).await? {
+                Some((mut res, _)) => {
+                    res.headers = headers;
+                    self.put(key, res, policy).await?;
+                    Ok(true)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                None => Ok(false),
             }
-        } else {
-            match self.mode {
-                CacheMode::OnlyIfCached => {
-                    // ENOTCACHED
-                    let mut res = HttpResponse {
-                        body: b"GatewayTimeout".to_vec(),
-                        headers: HashMap::default(),
-                        status: 504,
-                        url: middleware.url()?,
-                        version: HttpVersion::Http11,
-                    };
-                    res.cache_status(HitOrMiss::MISS);
-                    res.cache_lookup_status(HitOrMiss::MISS);
-                    Ok(res)
+        }
+    }
+    /// Replaces the [`EntryMetadata`] of a cached record while keeping its
+    /// response and policy, returning false if there is no such record.
+    ///
+    /// The default implementation reads the record and stores it again with
+    /// [`CacheManager::put`].
+    fn update_metadata(
+        &self,
+        key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        async move {
+            match self.get(key).await? {
+                Some((mut res, policy)) => {
+                    res.metadata = metadata;
+                    self.put(key, res, policy).await?;
+                    Ok(true)
                 }
-                _ => self.remote_fetch(&mut middleware).await,
+                None => Ok(false),
             }
         }
     }
-
-    async fn remote_fetch(
+    /// Returns a handle to the same cache that background work can hold on
+    /// to, see [`HttpCache::spawn`].
+    ///
+    /// The default implementation returns `None`, which makes that work run
+    /// inline instead.
+    fn background_handle(&self) -> Option<Self>
+    where
+        Self: Sized,
+    {
+        None
+    }
+    /// Attempts to pull the headers of a cached response, related policy and
+    /// [`EntryMetadata`] from cache, leaving out the body.
+    ///
+    /// The default implementation reads the whole record with
+    /// [`CacheManager::get`], managers that store the body separately can
+    /// avoid loading it.
+    fn get_metadata(
         &self,
-        middleware: &mut impl Middleware,
-    ) -> Result<HttpResponse> {
-        let mut res = middleware.remote_fetch().await?;
-        res.cache_status(HitOrMiss::MISS);
-        res.cache_lookup_status(HitOrMiss::MISS);
-        let policy = match self.options {
-            Some(options) => middleware.policy_with_options(&res, options)?,
-            None => middleware.policy(&res)?,
-        };
-        let is_get_head = middleware.is_method_get_head();
-        let is_cacheable = is_get_head
-            && self.mode != CacheMode::NoStore
-            && self.mode != CacheMode::Reload
-            && res.status == 200
-            && policy.is_storable();
-        let url = middleware.url()?;
-        let method = middleware.method()?.to_uppercase();
-        if is_cacheable {
-            Ok(self.manager.put(&method, &url, res, policy).await?)
-        } else if !is_get_head {
-            self.manager.delete("GET", &url).await.ok();
-            Ok(res)
-        } else {
-            Ok(res)
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<Option<Metadata>>> + MaybeSend {
+        async move {
+            Ok(self
+                .get(key)
+                .await?
+                .map(|(res, policy)| (res.headers, policy, res.metadata)))
         }
     }
-
-    async fn conditional_fetch(
+    /// Attempts to pull several cached responses and related policies from
+    /// cache, returning one result per key in the order of `keys`.
+    ///
+    /// Errors are returned per record, so a record that can't be read back
+    /// doesn't fail the others. The default implementation calls
+    /// [`CacheManager::get`] for each record in turn, managers that can do
+    /// better, for example by looking records up concurrently or in a
+    /// single round trip, should override it.
+    fn get_many(
         &self,
-        mut middleware: impl Middleware,
-        mut cached_res: HttpResponse,
-        mut policy: CachePolicy,
+        keys: &[CacheKey],
+    ) -> impl Future<Output = Vec<Result<Option<Record>>>> + MaybeSend {
+        async move {
+            let mut records = Vec::with_capacity(keys.len());
+            for key in keys {
+                records.push(self.get(key).await);
+            }
+            records
+        }
+    }
+    /// Checks that the backend can be reached and written to, such as
+    /// before a service starts taking traffic, failing with
+    /// [`CacheError::Unhealthy`] or the error of the backend when it can't.
+    ///
+    /// The default implementation stores, reads back and removes a record
+    /// under the url `http://http-cache.invalid/health-check`, managers with
+    /// a cheaper way to tell, such as a ping, should override it.
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<HealthReport>> + MaybeSend {
+        health::round_trip(self)
+    }
+    /// Writes out whatever the manager buffered, so the records stored so
+    /// far survive the process. Dropping a manager isn't guaranteed to, see
+    /// [`HttpCache::shutdown`].
+    ///
+    /// The default implementation does nothing, which is only correct for
+    /// managers that are done with a record once `put` returns.
+    fn flush(&self) -> impl Future<Output = Result<()>> + MaybeSend {
+        async { Ok(()) }
+    }
+}
+
+/// A [`CacheManager`] that is able to list the records it holds.
+pub trait EnumerableCacheManager: CacheManager {
+    /// Attempts to list the key of every cached record.
+    fn keys(&self) -> impl Future<Output = Result<Vec<CacheKey>>> + MaybeSend;
+    /// Attempts to list the tags stored along with a record, see
+    /// [`CacheManager::put_tagged`]. Managers without tag support can rely
+    /// on the default implementation, which never lists any.
+    fn tags(
+        &self,
+        _key: &CacheKey,
+    ) -> impl Future<Output = Result<Vec<String>>> + MaybeSend {
+        async { Ok(Vec::new()) }
+    }
+}
+
+/// An object safe version of [`CacheManager`] with boxed futures, which lets
+/// a manager be picked at runtime as an `Arc<dyn DynCacheManager>`.
+///
+/// Every [`CacheManager`] implements this trait, and `dyn DynCacheManager`
+/// implements [`CacheManager`] in turn so it can be handed to [`HttpCache`].
+pub trait DynCacheManager: MaybeSend + MaybeSync + 'static {
+    /// See [`CacheManager::get`].
+    fn dyn_get<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<Option<(HttpResponse, CachePolicy)>>>;
+    /// See [`CacheManager::put`].
+    fn dyn_put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<HttpResponse>>;
+    /// See [`CacheManager::delete`].
+    fn dyn_delete<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>>;
+    /// See [`CacheManager::put_tagged`].
+    fn dyn_put_tagged<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &'a [String],
+    ) -> ManagerFuture<'a, Result<HttpResponse>>;
+    /// See [`CacheManager::purge_tag`].
+    fn dyn_purge_tag<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> ManagerFuture<'a, Result<usize>>;
+    /// See [`CacheManager::purge_origin`].
+    fn dyn_purge_origin<'a>(
+        &'a self,
+        origin: &'a Origin,
+    ) -> ManagerFuture<'a, Result<usize>>;
+    /// See [`CacheManager::pin`].
+    fn dyn_pin<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>>;
+    /// See [`CacheManager::unpin`].
+    fn dyn_unpin<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>>;
+    /// See [`CacheManager::update_policy`].
+    fn dyn_update_policy<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<bool>>;
+    /// See [`CacheManager::update`].
+    fn dyn_update<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<bool>>;
+    /// See [`CacheManager::update_metadata`].
+    fn dyn_update_metadata<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        metadata: EntryMetadata,
+    ) -> ManagerFuture<'a, Result<bool>>;
+    /// See [`CacheManager::get_metadata`].
+    fn dyn_get_metadata<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<Option<Metadata>>>;
+    /// See [`CacheManager::get_many`].
+    fn dyn_get_many<'a>(
+        &'a self,
+        keys: &'a [CacheKey],
+    ) -> ManagerFuture<'a, Vec<Result<Option<Record>>>>;
+    /// See [`CacheManager::health_check`].
+    fn dyn_health_check(&self) -> ManagerFuture<'_, Result<HealthReport>>;
+    /// See [`CacheManager::flush`].
+    fn dyn_flush(&self) -> ManagerFuture<'_, Result<()>>;
+}
+
+impl<T: CacheManager> DynCacheManager for T {
+    fn dyn_get<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<Option<(HttpResponse, CachePolicy)>>> {
+        Box::pin(self.get(key))
+    }
+
+    fn dyn_put<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<HttpResponse>> {
+        Box::pin(self.put(key, res, policy))
+    }
+
+    fn dyn_delete<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>> {
+        Box::pin(self.delete(key))
+    }
+
+    fn dyn_put_tagged<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &'a [String],
+    ) -> ManagerFuture<'a, Result<HttpResponse>> {
+        Box::pin(self.put_tagged(key, res, policy, tags))
+    }
+
+    fn dyn_purge_tag<'a>(
+        &'a self,
+        tag: &'a str,
+    ) -> ManagerFuture<'a, Result<usize>> {
+        Box::pin(self.purge_tag(tag))
+    }
+
+    fn dyn_purge_origin<'a>(
+        &'a self,
+        origin: &'a Origin,
+    ) -> ManagerFuture<'a, Result<usize>> {
+        Box::pin(self.purge_origin(origin))
+    }
+
+    fn dyn_pin<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>> {
+        Box::pin(self.pin(key))
+    }
+
+    fn dyn_unpin<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<()>> {
+        Box::pin(self.unpin(key))
+    }
+
+    fn dyn_update_policy<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<bool>> {
+        Box::pin(self.update_policy(key, policy))
+    }
+
+    fn dyn_update<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> ManagerFuture<'a, Result<bool>> {
+        Box::pin(self.update(key, headers, policy))
+    }
+
+    fn dyn_update_metadata<'a>(
+        &'a self,
+        key: &'a CacheKey,
+        metadata: EntryMetadata,
+    ) -> ManagerFuture<'a, Result<bool>> {
+        Box::pin(self.update_metadata(key, metadata))
+    }
+
+    fn dyn_get_metadata<'a>(
+        &'a self,
+        key: &'a CacheKey,
+    ) -> ManagerFuture<'a, Result<Option<Metadata>>> {
+        Box::pin(self.get_metadata(key))
+    }
+
+    fn dyn_get_many<'a>(
+        &'a self,
+        keys: &'a [CacheKey],
+    ) -> ManagerFuture<'a, Vec<Result<Option<Record>>>> {
+        Box::pin(self.get_many(keys))
+    }
+
+    fn dyn_health_check(&self) -> ManagerFuture<'_, Result<HealthReport>> {
+        Box::pin(self.health_check())
+    }
+
+    fn dyn_flush(&self) -> ManagerFuture<'_, Result<()>> {
+        Box::pin(self.flush())
+    }
+}
+
+impl CacheManager for dyn DynCacheManager {
+    async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.dyn_get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.dyn_put(key, res, policy).await
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        self.dyn_delete(key).await
+    }
+
+    async fn put_tagged(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<HttpResponse> {
+        self.dyn_put_tagged(key, res, policy, tags).await
+    }
+
+    async fn purge_tag(&self, tag: &str) -> Result<usize> {
+        self.dyn_purge_tag(tag).await
+    }
+
+    async fn purge_origin(&self, origin: &Origin) -> Result<usize> {
+        self.dyn_purge_origin(origin).await
+    }
+
+    async fn pin(&self, key: &CacheKey) -> Result<()> {
+        self.dyn_pin(key).await
+    }
+
+    async fn unpin(&self, key: &CacheKey) -> Result<()> {
+        self.dyn_unpin(key).await
+    }
+
+    async fn update_policy(
+        &self,
+        key: &CacheKey,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        self.dyn_update_policy(key, policy).await
+    }
+
+    async fn update(
+        &self,
+        key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        self.dyn_update(key, headers, policy).await
+    }
+
+    async fn update_metadata(
+        &self,
+        key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> Result<bool> {
+        self.dyn_update_metadata(key, metadata).await
+    }
+
+    async fn get_metadata(&self, key: &CacheKey) -> Result<Option<Metadata>> {
+        self.dyn_get_metadata(key).await
+    }
+
+    async fn get_many(&self, keys: &[CacheKey]) -> Vec<Result<Option<Record>>> {
+        self.dyn_get_many(keys).await
+    }
+
+    async fn health_check(&self) -> Result<HealthReport> {
+        self.dyn_health_check().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.dyn_flush().await
+    }
+}
+
+/// Sends the lookups, stores and invalidations of a request to another
+/// manager than the one of the [`HttpCache`], when added to the extensions
+/// of the request.
+///
+/// ```
+/// use http_cache::{ManagerOverride, MokaManager};
+///
+/// let artifacts = ManagerOverride::new(MokaManager::default());
+/// let mut req = http::Request::get("https://example.com/app.tar").body(())?;
+/// req.extensions_mut().insert(artifacts.clone());
+/// # Ok::<(), http::Error>(())
+/// ```
+///
+/// The mode and options of the cache still apply. The other methods of the
+/// cache, such as [`HttpCache::purge_tag`], can be run against the same
+/// manager through [`HttpCache::with_manager`], as the override is a
+/// [`CacheManager`] itself. Streaming caches need a
+/// [`StreamingCacheManager`] and keep using their own.
+#[derive(Clone)]
+pub struct ManagerOverride(pub Arc<dyn DynCacheManager>);
+
+impl ManagerOverride {
+    /// Creates an override sending requests to `manager`.
+    pub fn new(manager: impl CacheManager) -> Self {
+        Self(Arc::new(manager))
+    }
+}
+
+impl fmt::Debug for ManagerOverride {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ManagerOverride(..)")
+    }
+}
+
+impl CacheManager for ManagerOverride {
+    async fn get(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        self.0.dyn_get(key).await
+    }
+
+    async fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        self.0.dyn_put(key, res, policy).await
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        self.0.dyn_delete(key).await
+    }
+
+    async fn put_tagged(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<HttpResponse> {
+        self.0.dyn_put_tagged(key, res, policy, tags).await
+    }
+
+    async fn purge_tag(&self, tag: &str) -> Result<usize> {
+        self.0.dyn_purge_tag(tag).await
+    }
+
+    async fn purge_origin(&self, origin: &Origin) -> Result<usize> {
+        self.0.dyn_purge_origin(origin).await
+    }
+
+    async fn pin(&self, key: &CacheKey) -> Result<()> {
+        self.0.dyn_pin(key).await
+    }
+
+    async fn unpin(&self, key: &CacheKey) -> Result<()> {
+        self.0.dyn_unpin(key).await
+    }
+
+    async fn update_policy(
+        &self,
+        key: &CacheKey,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        self.0.dyn_update_policy(key, policy).await
+    }
+
+    async fn update(
+        &self,
+        key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        self.0.dyn_update(key, headers, policy).await
+    }
+
+    async fn update_metadata(
+        &self,
+        key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> Result<bool> {
+        self.0.dyn_update_metadata(key, metadata).await
+    }
+
+    async fn get_metadata(&self, key: &CacheKey) -> Result<Option<Metadata>> {
+        self.0.dyn_get_metadata(key).await
+    }
+
+    async fn get_many(&self, keys: &[CacheKey]) -> Vec<Result<Option<Record>>> {
+        self.0.dyn_get_many(keys).await
+    }
+
+    async fn health_check(&self) -> Result<HealthReport> {
+        self.0.dyn_health_check().await
+    }
+
+    async fn flush(&self) -> Result<()> {
+        self.0.dyn_flush().await
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+// Lets a shared manager be used anywhere a manager is expected
+impl<T: CacheManager + ?Sized> CacheManager for Arc<T> {
+    fn get(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<Option<(HttpResponse, CachePolicy)>>> + MaybeSend
+    {
+        (**self).get(key)
+    }
+
+    fn put(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<HttpResponse>> + MaybeSend {
+        (**self).put(key, res, policy)
+    }
+
+    fn delete(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend {
+        (**self).delete(key)
+    }
+
+    fn put_tagged(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> impl Future<Output = Result<HttpResponse>> + MaybeSend {
+        (**self).put_tagged(key, res, policy, tags)
+    }
+
+    fn purge_tag(
+        &self,
+        tag: &str,
+    ) -> impl Future<Output = Result<usize>> + MaybeSend {
+        (**self).purge_tag(tag)
+    }
+
+    fn purge_origin(
+        &self,
+        origin: &Origin,
+    ) -> impl Future<Output = Result<usize>> + MaybeSend {
+        (**self).purge_origin(origin)
+    }
+
+    fn pin(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend {
+        (**self).pin(key)
+    }
+
+    fn unpin(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<()>> + MaybeSend {
+        (**self).unpin(key)
+    }
+
+    fn update_policy(
+        &self,
+        key: &CacheKey,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        (**self).update_policy(key, policy)
+    }
+
+    fn update(
+        &self,
+        key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        (**self).update(key, headers, policy)
+    }
+
+    fn update_metadata(
+        &self,
+        key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> impl Future<Output = Result<bool>> + MaybeSend {
+        (**self).update_metadata(key, metadata)
+    }
+
+    fn get_metadata(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<Option<Metadata>>> + MaybeSend {
+        (**self).get_metadata(key)
+    }
+
+    fn get_many(
+        &self,
+        keys: &[CacheKey],
+    ) -> impl Future<Output = Vec<Result<Option<Record>>>> + MaybeSend {
+        (**self).get_many(keys)
+    }
+
+    fn health_check(
+        &self,
+    ) -> impl Future<Output = Result<HealthReport>> + MaybeSend {
+        (**self).health_check()
+    }
+
+    fn flush(&self) -> impl Future<Output = Result<()>> + MaybeSend {
+        (**self).flush()
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl<T: EnumerableCacheManager + ?Sized> EnumerableCacheManager for Arc<T> {
+    fn keys(&self) -> impl Future<Output = Result<Vec<CacheKey>>> + MaybeSend {
+        (**self).keys()
+    }
+
+    fn tags(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<Output = Result<Vec<String>>> + MaybeSend {
+        (**self).tags(key)
+    }
+}
+
+/// Describes the functionality required for interfacing with HTTP client middleware
+///
+/// This is what the surf and reqwest crates implement to hook a client into
+/// [`HttpCache::run`], and it can be implemented the same way for any other
+/// client. An implementation wraps a single request along with whatever it
+/// needs to send it:
+///
+/// - [`method`](Middleware::method), [`url`](Middleware::url) and
+///   [`parts`](Middleware::parts) describe the request, the method and url
+///   make up the cache key.
+/// - [`is_method_get_head`](Middleware::is_method_get_head) tells whether
+///   the response can be cached at all.
+/// - [`policy`](Middleware::policy) and
+///   [`policy_with_options`](Middleware::policy_with_options) build the
+///   [`CachePolicy`] of a response, usually with [`CachePolicy::new_options`]
+///   and [`now`].
+/// - [`update_headers`](Middleware::update_headers) and
+///   [`force_no_cache`](Middleware::force_no_cache) change the request
+///   before it's sent for revalidation.
+/// - [`remote_fetch`](Middleware::remote_fetch) sends the request and
+///   converts the response into an [`HttpResponse`]. It's called at most
+///   once per run.
+///
+/// Clients that can send an `http::Request<Vec<u8>>` can skip implementing
+/// this and use [`HttpCache::run_http`] instead. A complete adapter over a
+/// fake client lives in `examples/custom_client.rs`.
+///
+/// ```
+/// use http::request::Parts;
+/// use http_cache::{
+///     CacheMode, CacheOptions, HttpCache, HttpHeaders, HttpResponse,
+///     HttpVersion, Middleware, MokaManager, Result,
+/// };
+/// use http_cache_semantics::CachePolicy;
+/// use url::Url;
+///
+/// // An adapter over a client that answers every request itself
+/// struct EchoMiddleware {
+///     parts: Parts,
+/// }
+///
+/// impl Middleware for EchoMiddleware {
+///     fn is_method_get_head(&self) -> bool {
+///         self.parts.method == http::Method::GET
+///             || self.parts.method == http::Method::HEAD
+///     }
+///     fn policy(&self, response: &HttpResponse) -> Result<CachePolicy> {
+///         self.policy_with_options(response, CacheOptions::default())
+///     }
+///     fn policy_with_options(
+///         &self,
+///         response: &HttpResponse,
+///         options: CacheOptions,
+///     ) -> Result<CachePolicy> {
+///         Ok(CachePolicy::new_options(
+///             &self.parts()?,
+///             &response.parts()?,
+///             http_cache::now(),
+///             options,
+///         ))
+///     }
+///     fn update_headers(&mut self, parts: &Parts) -> Result<()> {
+///         for (name, value) in parts.headers.iter() {
+///             self.parts.headers.insert(name.clone(), value.clone());
+///         }
+///         Ok(())
+///     }
+///     fn force_no_cache(&mut self) -> Result<()> {
+///         self.parts.headers.insert(
+///             http::header::CACHE_CONTROL,
+///             http::HeaderValue::from_static("no-cache"),
+///         );
+///         Ok(())
+///     }
+///     fn parts(&self) -> Result<Parts> {
+///         let mut converted = http::Request::builder()
+///             .method(self.parts.method.clone())
+///             .uri(self.url()?.as_str())
+///             .body(())?;
+///         *converted.headers_mut() = self.parts.headers.clone();
+///         Ok(converted.into_parts().0)
+///     }
+///     fn url(&self) -> Result<Url> {
+///         Ok(Url::parse(&self.parts.uri.to_string())?)
+///     }
+///     fn method(&self) -> Result<String> {
+///         Ok(self.parts.method.to_string())
+///     }
+///     async fn remote_fetch(&mut self) -> Result<HttpResponse> {
+///         let headers = HttpHeaders::from_iter([(
+///             "cache-control",
+///             http::HeaderValue::from_static("max-age=60"),
+///         )]);
+///         Ok(HttpResponse {
+///             body: b"hello".to_vec().into(),
+///             headers,
+///             trailers: None,
+///             status: 200,
+///             reason: None,
+///             url: self.url()?,
+///             version: HttpVersion::Http11,
+///             metadata: Default::default(),
+///         })
+///     }
+/// }
+///
+/// # futures::executor::block_on(async {
+/// let cache = HttpCache::new(CacheMode::Default, MokaManager::default());
+/// let parts = http::Request::get("http://example.com/").body(())?.into_parts().0;
+/// let res = cache.run(EchoMiddleware { parts }).await?;
+/// assert_eq!(res.body, &b"hello"[..]);
+/// # Ok::<_, http_cache::CacheError>(())
+/// # }).unwrap();
+/// ```
+///
+/// # Stability
+///
+/// The trait is part of the public API. Adding a required method or
+/// changing the signature of one is a breaking change and only ships in a
+/// release that bumps the major version (the minor version while below
+/// 1.0). New methods arrive with default implementations so existing
+/// adapters keep compiling.
+pub trait Middleware: MaybeSend {
+    /// Determines if the request method is either GET or HEAD, the only
+    /// methods whose responses are cached
+    fn is_method_get_head(&self) -> bool;
+    /// Returns a new cache policy with default options
+    fn policy(&self, response: &HttpResponse) -> Result<CachePolicy>;
+    /// Returns a new cache policy with custom options, used when
+    /// [`HttpCacheOptions::cache_options`] is set
+    fn policy_with_options(
+        &self,
+        response: &HttpResponse,
+        options: CacheOptions,
+    ) -> Result<CachePolicy>;
+    /// Attempts to update the request headers with the passed `http::request::Parts`,
+    /// which carry the conditional headers of a revalidation request
+    fn update_headers(&mut self, parts: &request::Parts) -> Result<()>;
+    /// Attempts to force the "no-cache" directive on the request
+    fn force_no_cache(&mut self) -> Result<()>;
+    /// Attempts to construct `http::request::Parts` from the request, without
+    /// the body
+    ///
+    /// The uri of the parts should be the one returned by [`Middleware::url`],
+    /// so stored policies match requests for the same url however it was
+    /// written, the request sent to the origin keeps its own.
+    fn parts(&self) -> Result<request::Parts>;
+    /// Attempts to determine the requested url
+    fn url(&self) -> Result<Url>;
+    /// Attempts to determine the request method
+    fn method(&self) -> Result<String>;
+    /// Attempts to fetch an upstream resource and return an [`HttpResponse`]
+    /// with the whole body read
+    fn remote_fetch(
+        &mut self,
+    ) -> impl Future<Output = Result<HttpResponse>> + MaybeSend;
+    /// Returns the manager to use for this request instead of the one of the
+    /// cache, see [`ManagerOverride`]. The bundled clients read it from the
+    /// extensions of the request, the default implementation never
+    /// overrides the manager.
+    fn manager_override(&self) -> Option<ManagerOverride> {
+        None
+    }
+    /// Returns the application data to store along with the response, see
+    /// [`EntryMetadata`]. The bundled clients read it from the extensions of
+    /// the request, the default implementation stores none.
+    fn entry_metadata(&self) -> Option<EntryMetadata> {
+        None
+    }
+}
+
+/// Similar to [make-fetch-happen cache options](https://github.com/npm/make-fetch-happen#--optscache).
+/// Passed in when the [`HttpCache`] struct is being built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheMode {
+    /// Will inspect the HTTP cache on the way to the network.
+    /// If there is a fresh response it will be used.
+    /// If there is a stale response a conditional request will be created,
+    /// and a normal request otherwise.
+    /// It then updates the HTTP cache with the response.
+    /// If the revalidation request fails (for example, on a 500 or if you're offline),
+    /// the stale response will be returned.
+    Default,
+    /// Behaves as if there is no HTTP cache at all.
+    NoStore,
+    /// Behaves as if there is no HTTP cache on the way to the network.
+    /// Ergo, it creates a normal request and updates the HTTP cache with the response.
+    Reload,
+    /// Creates a conditional request if there is a response in the HTTP cache
+    /// and a normal request otherwise. It then updates the HTTP cache with the response.
+    NoCache,
+    /// Uses any response in the HTTP cache matching the request,
+    /// not paying attention to staleness. If there was no response,
+    /// it creates a normal request and updates the HTTP cache with the response.
+    ///
+    /// Responses with `no-store` are still not stored, unless
+    /// [`HttpCacheOptions::force_cache_overrides_no_store`] allows it.
+    ForceCache,
+    /// Uses any response in the HTTP cache matching the request,
+    /// not paying attention to staleness. If there was no response,
+    /// it returns a network error.
+    OnlyIfCached,
+}
+
+impl TryFrom<http::Version> for HttpVersion {
+    type Error = CacheError;
+
+    fn try_from(value: http::Version) -> Result<Self> {
+        Ok(match value {
+            http::Version::HTTP_09 => Self::Http09,
+            http::Version::HTTP_10 => Self::Http10,
+            http::Version::HTTP_11 => Self::Http11,
+            http::Version::HTTP_2 => Self::H2,
+            http::Version::HTTP_3 => Self::H3,
+            _ => return Err(CacheError::BadVersion),
+        })
+    }
+}
+
+impl From<HttpVersion> for http::Version {
+    fn from(value: HttpVersion) -> Self {
+        match value {
+            HttpVersion::Http09 => Self::HTTP_09,
+            HttpVersion::Http10 => Self::HTTP_10,
+            HttpVersion::Http11 => Self::HTTP_11,
+            HttpVersion::H2 => Self::HTTP_2,
+            HttpVersion::H3 => Self::HTTP_3,
+        }
+    }
+}
+
+#[cfg(feature = "http-types")]
+impl TryFrom<http_types::Version> for HttpVersion {
+    type Error = CacheError;
+
+    fn try_from(value: http_types::Version) -> Result<Self> {
+        Ok(match value {
+            http_types::Version::Http0_9 => Self::Http09,
+            http_types::Version::Http1_0 => Self::Http10,
+            http_types::Version::Http1_1 => Self::Http11,
+            http_types::Version::Http2_0 => Self::H2,
+            http_types::Version::Http3_0 => Self::H3,
+            _ => return Err(CacheError::BadVersion),
+        })
+    }
+}
+
+#[cfg(feature = "http-types")]
+impl From<HttpVersion> for http_types::Version {
+    fn from(value: HttpVersion) -> Self {
+        match value {
+            HttpVersion::Http09 => Self::Http0_9,
+            HttpVersion::Http10 => Self::Http1_0,
+            HttpVersion::Http11 => Self::Http1_1,
+            HttpVersion::H2 => Self::Http2_0,
+            HttpVersion::H3 => Self::Http3_0,
+        }
+    }
+}
+
+/// The outcome of warming a single url, see [`HttpCache::warm`].
+#[derive(Debug)]
+pub enum WarmOutcome {
+    /// The url was fetched through the cache
+    Fetched,
+    /// A fresh response for the url was already cached, so it was skipped
+    AlreadyFresh,
+    /// Fetching the url failed
+    Failed(CacheError),
+}
+
+/// A per-url report returned by [`HttpCache::warm`].
+#[derive(Debug, Default)]
+pub struct WarmReport {
+    /// The outcome for each url, in the order the urls were provided
+    pub entries: Vec<(Url, WarmOutcome)>,
+}
+
+impl WarmReport {
+    /// Returns the number of urls that were fetched
+    #[must_use]
+    pub fn fetched(&self) -> usize {
+        self.count(|outcome| matches!(outcome, WarmOutcome::Fetched))
+    }
+
+    /// Returns the number of urls that were skipped because they were fresh
+    #[must_use]
+    pub fn already_fresh(&self) -> usize {
+        self.count(|outcome| matches!(outcome, WarmOutcome::AlreadyFresh))
+    }
+
+    /// Returns the number of urls that failed to be fetched
+    #[must_use]
+    pub fn failed(&self) -> usize {
+        self.count(|outcome| matches!(outcome, WarmOutcome::Failed(_)))
+    }
+
+    fn count(&self, f: impl Fn(&WarmOutcome) -> bool) -> usize {
+        self.entries.iter().filter(|(_, outcome)| f(outcome)).count()
+    }
+}
+
+/// Options struct provided by
+/// [`http-cache-semantics`](https://github.com/kornelski/rusty-http-cache-semantics).
+pub use http_cache_semantics::CacheOptions;
+
+/// A closure that receives [`CacheEvent`]s, see [`HttpCacheOptions::on_event`].
+pub type EventHook = Arc<dyn Fn(&CacheEvent) + Send + Sync>;
+
+/// A closure that decides whether a response should be stored, see
+/// [`HttpCacheOptions::should_store`].
+pub type ResponsePredicate = Arc<dyn Fn(&HttpResponse) -> bool + Send + Sync>;
+
+/// A closure that can change the metadata of a response before it is stored,
+/// see [`HttpCacheOptions::on_store`].
+pub type StoreHook =
+    Arc<dyn Fn(&HttpResponse, &mut EntryMetadata) + Send + Sync>;
+
+/// A closure that decides whether a request should use the cache at all, see
+/// [`HttpCacheOptions::should_cache_request`].
+pub type RequestPredicate = Arc<dyn Fn(&request::Parts) -> bool + Send + Sync>;
+
+/// A closure that can change a revalidation request before it is sent, see
+/// [`HttpCacheOptions::on_revalidation_request`].
+pub type RevalidationHook =
+    Arc<dyn Fn(&mut RevalidationRequest<'_>) + Send + Sync>;
+
+/// A closure returning the current time, see [`HttpCacheOptions::clock`].
+pub type Clock = Arc<dyn Fn() -> SystemTime + Send + Sync>;
+
+/// Can be used to override the default caching behavior.
+#[derive(Clone, Default)]
+pub struct HttpCacheOptions {
+    /// Override the default cache options.
+    pub cache_options: Option<CacheOptions>,
+    /// Called with a [`CacheEvent`] whenever something noteworthy happens.
+    pub on_event: Option<EventHook>,
+    /// Hands every [`CacheEvent`] to the streams returned by
+    /// [`HttpCache::subscribe`], see [`EventBroadcast`].
+    pub event_broadcast: Option<EventBroadcast>,
+    /// Return an error instead of removing stored records that can't be read
+    /// and treating them as misses. Mostly useful for debugging.
+    pub propagate_corrupt_entries: bool,
+    /// Consulted once the cache policy allows a response to be stored,
+    /// returning false skips storing it. The response is still returned to
+    /// the caller as usual.
+    pub should_store: Option<ResponsePredicate>,
+    /// Called with every response about to be stored and its metadata, as
+    /// taken from the request, so it can be changed, see [`EntryMetadata`].
+    /// Responses only refreshed by a revalidation keep their metadata and
+    /// don't go through it.
+    pub on_store: Option<StoreHook>,
+    /// Consulted before anything else, returning false sends the request
+    /// straight through as if the cache wasn't installed. No cache lookup
+    /// happens and nothing is stored.
+    pub should_cache_request: Option<RequestPredicate>,
+    /// Called with the conditional requests the cache makes to revalidate
+    /// stale responses before they are sent, such as to sign them, see
+    /// [`RevalidationRequest`]. Requests the caller made conditional
+    /// themselves are sent as they are.
+    pub on_revalidation_request: Option<RevalidationHook>,
+    /// Turns the cache on and off at runtime, see [`CacheToggle`] and
+    /// [`HttpCache::set_enabled`]. Requests are sent straight through while
+    /// it is off.
+    pub toggle: CacheToggle,
+    /// Only the requests to urls the filter allows go through the cache,
+    /// the others are sent straight through like the ones
+    /// [`HttpCacheOptions::should_cache_request`] rejects, see
+    /// [`UrlFilter`].
+    pub url_filter: Option<UrlFilter>,
+    /// Only store the responses whose `Content-Type` matches, see
+    /// [`ContentTypes`]. Checked along with
+    /// [`HttpCacheOptions::should_store`], a response is only stored when
+    /// both allow it.
+    pub cacheable_content_types: Option<ContentTypes>,
+    /// Request headers the responses depend on whatever their `Vary` header
+    /// says, requests that differ in the value of one of them are stored
+    /// apart as if the response varied on it.
+    ///
+    /// The values are folded into the fragment of the url a response is
+    /// stored under, so the methods of [`HttpCache`] taking a url only find
+    /// the responses stored for requests without any of the headers when
+    /// handed the plain url. Values are trimmed and a missing header is
+    /// told apart from an empty one.
+    pub key_headers: Vec<HeaderName>,
+    /// Response header holding the cache tags of a response, such as
+    /// `surrogate-key` or `cache-tag`. Tags are separated by whitespace or
+    /// commas and can be purged with [`HttpCache::purge_tag`].
+    pub tag_header: Option<String>,
+    /// Lets only one of the concurrent requests for a response that isn't
+    /// cached go to the network, see [`Coalescing`].
+    pub coalescing: Option<Coalescing>,
+    /// Runs the work that doesn't need to happen before a response is
+    /// returned, inline by default.
+    pub spawner: Spawner,
+    /// Purge every cached response from the origin of a response carrying
+    /// `Clear-Site-Data: "cache"` or `"*"`, through the [`Spawner`] once the
+    /// response has been handled.
+    pub clear_site_data: bool,
+    /// Sends every request to the network without storing or serving
+    /// anything, reporting what the cache would have done instead, see
+    /// [`DryRun`].
+    pub dry_run: Option<DryRun>,
+    /// Records every response to replay it later without going to the
+    /// network, in place of the mode of the cache, see [`RecordMode`].
+    pub record_mode: Option<RecordMode>,
+    /// Gives up on manager operations taking longer than this, so a slow
+    /// or hanging backend doesn't hold requests up. A lookup that times out
+    /// is treated as a miss, and a response that isn't stored in time is
+    /// returned without being stored, see [`CacheEvent::BackendTimeout`].
+    ///
+    /// Storing a response hands it over to the manager, so when this is set
+    /// a copy of each stored response is kept until the manager is done.
+    pub backend_timeout: Option<Duration>,
+    /// Stores responses kept out of the cache only by their `no-store`
+    /// directive in the [`CacheMode::ForceCache`] mode, as if they were
+    /// fresh for this long, for apps that need something to show offline.
+    ///
+    /// Such responses are marked with the [`XCACHEOVERRIDE`] header, see
+    /// [`CacheStatus::overridden`], and are only served in the
+    /// `ForceCache` and [`CacheMode::OnlyIfCached`] modes with this set,
+    /// until they expire. Every other mode acts as if they weren't stored,
+    /// and requests with `no-store` of their own are never stored.
+    pub force_cache_overrides_no_store: Option<Duration>,
+    /// Let a server error answering the revalidation of a stored response
+    /// replace it, removing the record and returning the error. By default
+    /// the stored response is served in its place and kept for the next
+    /// requests, responses with an error status are never stored.
+    pub server_errors_replace_stored: bool,
+    /// What happens to requests when the manager fails, by default they go
+    /// on as if nothing was cached, see [`ErrorPolicy`].
+    pub error_policy: ErrorPolicy,
+    /// Stops calling the manager for a while after it failed a number of
+    /// times in a row, see [`CircuitBreaker`].
+    pub circuit_breaker: Option<CircuitBreaker>,
+    /// Also store responses the client followed a redirect to under the url
+    /// they were redirected to, when their headers allow caching them for
+    /// that url.
+    ///
+    /// Responses are always stored under the url that was requested, with
+    /// the url they were redirected to recorded in [`HttpResponse::url`].
+    /// Only clients following redirects on their own report that url, and
+    /// streamed responses are only stored under the url that was requested.
+    pub cache_redirect_targets: bool,
+    /// Add the stored trailers of a response to its headers, each one as a
+    /// `trailer-{name}` header, when handing it to a client that can't carry
+    /// trailers on the responses it builds. Such trailers are dropped
+    /// otherwise, see [`HttpResponse::trailers`].
+    pub trailers_as_headers: bool,
+    /// Reads the current time the freshness of stored responses is judged
+    /// against, [`now`] by default. Mostly useful for tests, see
+    /// `MockClock` with the `test-util` feature.
+    ///
+    /// When set, the policies of fetched responses are stamped with the time
+    /// it reads instead of being created by the [`Middleware`]. A clock
+    /// reading a time before the one a response was stored at gives it an
+    /// age of zero.
+    pub clock: Option<Clock>,
+    /// Revalidate fresh responses early, when no more than a part of their
+    /// freshness lifetime is left, so responses stored at the same time
+    /// aren't all revalidated at once. Each response is given a part between
+    /// none and this fraction of its lifetime, picked from its key so it
+    /// stays the same from one request to the next, `0.1` spreads the
+    /// revalidations over the last tenth of the lifetimes.
+    ///
+    /// Values are clamped to `0.0..=1.0`. Responses are never served past
+    /// the freshness their headers give them.
+    pub refresh_jitter: Option<f64>,
+    /// Hand fetched responses back right away and store them in the
+    /// background, through the [`Spawner`], see [`WriteBehind`].
+    pub write_behind: Option<WriteBehind>,
+}
+
+impl fmt::Debug for HttpCacheOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HttpCacheOptions")
+            .field("cache_options", &self.cache_options)
+            .field(
+                "on_event",
+                &self.on_event.as_ref().map(|_| "Fn(&CacheEvent)"),
+            )
+            .field("event_broadcast", &self.event_broadcast)
+            .field("propagate_corrupt_entries", &self.propagate_corrupt_entries)
+            .field(
+                "should_store",
+                &self
+                    .should_store
+                    .as_ref()
+                    .map(|_| "Fn(&HttpResponse) -> bool"),
+            )
+            .field(
+                "on_store",
+                &self
+                    .on_store
+                    .as_ref()
+                    .map(|_| "Fn(&HttpResponse, &mut EntryMetadata)"),
+            )
+            .field(
+                "should_cache_request",
+                &self
+                    .should_cache_request
+                    .as_ref()
+                    .map(|_| "Fn(&request::Parts) -> bool"),
+            )
+            .field(
+                "on_revalidation_request",
+                &self
+                    .on_revalidation_request
+                    .as_ref()
+                    .map(|_| "Fn(&mut RevalidationRequest)"),
+            )
+            .field("toggle", &self.toggle)
+            .field("url_filter", &self.url_filter)
+            .field("cacheable_content_types", &self.cacheable_content_types)
+            .field("key_headers", &self.key_headers)
+            .field("tag_header", &self.tag_header)
+            .field("coalescing", &self.coalescing)
+            .field("spawner", &self.spawner)
+            .field("clear_site_data", &self.clear_site_data)
+            .field("dry_run", &self.dry_run)
+            .field("record_mode", &self.record_mode)
+            .field("backend_timeout", &self.backend_timeout)
+            .field(
+                "force_cache_overrides_no_store",
+                &self.force_cache_overrides_no_store,
+            )
+            .field(
+                "server_errors_replace_stored",
+                &self.server_errors_replace_stored,
+            )
+            .field("error_policy", &self.error_policy)
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("cache_redirect_targets", &self.cache_redirect_targets)
+            .field("trailers_as_headers", &self.trailers_as_headers)
+            .field("clock", &self.clock.as_ref().map(|_| "Fn() -> SystemTime"))
+            .field("refresh_jitter", &self.refresh_jitter)
+            .field("write_behind", &self.write_behind)
+            .finish()
+    }
+}
+
+/// Caches requests according to http spec.
+#[derive(Debug, Clone)]
+pub struct HttpCache<T: CacheManager> {
+    /// Determines the manager behavior.
+    pub mode: CacheMode,
+    /// Manager instance that implements the [`CacheManager`] trait.
+    /// By default, a manager implementation with [`cacache`](https://github.com/zkat/cacache-rs)
+    /// as the backend has been provided, see [`CACacheManager`].
+    ///
+    /// Managers can be used directly, shared behind an `Arc`, or picked at
+    /// runtime as an `Arc<dyn CacheManager>`.
+    pub manager: T,
+    /// Override the default caching behavior.
+    pub options: HttpCacheOptions,
+}
+
+#[allow(dead_code)]
+impl<T: CacheManager> HttpCache<T> {
+    /// Creates a cache using the provided mode and manager with the default
+    /// options.
+    pub fn new(mode: CacheMode, manager: T) -> Self {
+        Self { mode, manager, options: HttpCacheOptions::default() }
+    }
+
+    // Returns the current time according to the clock of the cache
+    pub(crate) fn now(&self) -> SystemTime {
+        match &self.options.clock {
+            Some(clock) => clock(),
+            None => now(),
+        }
+    }
+
+    /// Returns the manager used by the cache.
+    ///
+    /// The managers provided by this crate share their storage between
+    /// clones, so a clone of the manager can be kept around to inspect the
+    /// cache after it was handed to a client.
+    pub fn manager(&self) -> &T {
+        &self.manager
+    }
+
+    /// Enables or disables the cache, along with every clone of it, see
+    /// [`CacheToggle`]. Stored responses are kept while it is disabled.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.options.toggle.set_enabled(enabled);
+    }
+
+    /// Returns true if the cache is enabled, see [`HttpCache::set_enabled`].
+    pub fn is_enabled(&self) -> bool {
+        self.options.toggle.is_enabled()
+    }
+
+    /// Returns a stream of the events sent from now on, through the
+    /// [`HttpCacheOptions::event_broadcast`]. Without one the stream ends
+    /// right away.
+    pub fn subscribe(&self) -> EventStream {
+        match &self.options.event_broadcast {
+            Some(broadcast) => broadcast.subscribe(),
+            None => EventStream::closed(),
+        }
+    }
+
+    /// Determines if the request should go through the cache at all, based on
+    /// whether it is enabled, [`HttpCacheOptions::url_filter`] and
+    /// [`HttpCacheOptions::should_cache_request`]. Requests that shouldn't
+    /// can be sent on without involving the cache.
+    ///
+    /// Requests to urls whose scheme isn't `http` or `https`, such as `data:`
+    /// urls or schemes resolved by another middleware, never go through the
+    /// cache and are left for the client to handle.
+    pub fn can_cache_request(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<bool> {
+        if !self.is_enabled() {
+            return Ok(false);
+        }
+        let url = middleware.url()?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Ok(false);
+        }
+        if let Some(filter) = &self.options.url_filter {
+            if !filter.allows(&url) {
+                return Ok(false);
+            }
+        }
+        match &self.options.should_cache_request {
+            Some(should_cache_request) => {
+                Ok(should_cache_request(&middleware.parts()?))
+            }
+            None => Ok(true),
+        }
+    }
+
+    // The url the response to the request is stored under, see
+    // [`HttpCacheOptions::key_headers`]
+    pub(crate) fn cache_url(
+        &self,
+        middleware: &impl Middleware,
+    ) -> Result<Url> {
+        let mut url = middleware.url()?;
+        // Fragments never reach the origin, the fragment of the key holds
+        // the variant picked by the key headers instead
+        url.set_fragment(None);
+        if self.options.key_headers.is_empty() {
+            return Ok(url);
+        }
+        Ok(self.keyed_url(url, &middleware.parts()?.headers))
+    }
+
+    fn keyed_url(&self, mut url: Url, headers: &HeaderMap) -> Url {
+        if self.options.key_headers.is_empty() {
+            return url;
+        }
+        // Values are percent encoded and a missing header has no `=`
+        let fragment = self
+            .options
+            .key_headers
+            .iter()
+            .map(|name| {
+                let mut values = headers.get_all(name).iter().peekable();
+                if values.peek().is_none() {
+                    return name.as_str().to_string();
+                }
+                let value = values
+                    .map(|value| trim_whitespace(value.as_bytes()))
+                    .collect::<Vec<_>>()
+                    .join(&b", "[..]);
+                let value = url::form_urlencoded::byte_serialize(&value)
+                    .collect::<String>();
+                format!("{}={}", name, value)
+            })
+            .collect::<Vec<_>>()
+            .join("&");
+        url.set_fragment(Some(&fragment));
+        url
+    }
+
+    /// Returns a cache using `manager` along with the mode and options of
+    /// this one, such as the one a [`ManagerOverride`] makes a request use.
+    pub fn with_manager<M: CacheManager>(&self, manager: M) -> HttpCache<M> {
+        HttpCache { mode: self.mode, manager, options: self.options.clone() }
+    }
+
+    /// Attempts to run the passed middleware along with the cache
+    pub async fn run(
+        &self,
+        middleware: impl Middleware,
+    ) -> Result<HttpResponse> {
+        match middleware.manager_override() {
+            Some(manager) => {
+                Box::pin(self.with_manager(manager).run_here(middleware)).await
+            }
+            None => self.run_here(middleware).await,
+        }
+    }
+
+    // Runs the middleware with the manager of this cache
+    async fn run_here(
+        &self,
+        mut middleware: impl Middleware,
+    ) -> Result<HttpResponse> {
+        if !self.can_cache_request(&middleware)? {
+            return middleware.remote_fetch().await;
+        }
+        if let Some(dry_run) = &self.options.dry_run {
+            let lookup = self.dry_run_lookup(dry_run, &middleware)?;
+            let mut res = middleware.remote_fetch().await?;
+            self.dry_run_decide(dry_run, &middleware, lookup, &mut res)?;
+            return Ok(res);
+        }
+        if !self.is_observed() {
+            return self.run_cached(middleware).await;
+        }
+        let started = now();
+        let method = middleware.method()?.to_uppercase();
+        let url = middleware.url()?;
+        let res = self.run_cached(middleware).await?;
+        self.emit_answered(method, url, &res, started);
+        Ok(res)
+    }
+
+    // Answers a request going through the cache from the cache or the
+    // origin, according to the mode
+    async fn run_cached(
+        &self,
+        mut middleware: impl Middleware,
+    ) -> Result<HttpResponse> {
+        match self.options.record_mode {
+            Some(RecordMode::Record) => {
+                return self.record(&mut middleware).await
+            }
+            Some(RecordMode::Replay) => {
+                return self.replay(&mut middleware).await
+            }
+            None => {}
+        }
+        let is_cacheable = middleware.is_method_get_head()
+            && self.mode != CacheMode::NoStore
+            && self.mode != CacheMode::Reload;
+        if !is_cacheable {
+            return self.remote_fetch(&mut middleware).await;
+        }
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(&middleware)?;
+        let stored = self
+            .lookup(&method, &url)
+            .await?
+            .filter(|(res, policy)| self.serves_overridden(res, policy));
+        if let Some((res, policy)) = stored {
+            self.serve_stored(middleware, res, policy).await
+        } else {
+            match self.mode {
+                CacheMode::OnlyIfCached => {
+                    // ENOTCACHED
+                    let mut res = HttpResponse {
+                        body: Bytes::from_static(b"GatewayTimeout"),
+                        headers: HttpHeaders::default(),
+                        trailers: None,
+                        status: 504,
+                        reason: None,
+                        url: middleware.url()?,
+                        version: HttpVersion::Http11,
+                        metadata: Default::default(),
+                    };
+                    res.cache_status(HitOrMiss::MISS);
+                    res.cache_lookup_status(HitOrMiss::MISS);
+                    Ok(res)
+                }
+                _ => self.fetch_missing(middleware, &method, &url).await,
+            }
+        }
+    }
+
+    /// Returns true if [`HttpCache::run`] would hand back the response to the
+    /// request without storing it, which is the case in the
+    /// [`CacheMode::NoStore`] mode and for methods other than `GET` and
+    /// `HEAD`, unless a dry run or a [`RecordMode`] is set up.
+    ///
+    /// Such requests can be sent by the caller, handing the response to
+    /// [`HttpCache::pass_through`] instead of reading its body into memory.
+    pub fn passes_through(&self, middleware: &impl Middleware) -> bool {
+        self.options.dry_run.is_none()
+            && self.options.record_mode.is_none()
+            && (self.mode == CacheMode::NoStore
+                || !middleware.is_method_get_head())
+    }
+
+    /// Handles the response to a request that [passes through](HttpCache::passes_through)
+    /// the cache the same way [`HttpCache::run`] would, given the middleware
+    /// the request was sent with. The body of the response isn't used and
+    /// can be left empty.
+    ///
+    /// The cache status headers are added, stored responses to the url are
+    /// invalidated by methods other than `GET` and `HEAD`, and the origin is
+    /// purged if the response asks for it with `Clear-Site-Data`.
+    pub async fn pass_through(
+        &self,
+        middleware: impl Middleware,
+        res: &mut HttpResponse,
+    ) -> Result<()> {
+        match middleware.manager_override() {
+            Some(manager) => {
+                let cache = self.with_manager(manager);
+                Box::pin(cache.pass_through_here(middleware, res)).await
+            }
+            None => self.pass_through_here(middleware, res).await,
+        }
+    }
+
+    async fn pass_through_here(
+        &self,
+        middleware: impl Middleware,
+        res: &mut HttpResponse,
+    ) -> Result<()> {
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        if !middleware.is_method_get_head() {
+            self.invalidate(&self.cache_url(&middleware)?).await;
+        }
+        if let Some(origin) = self.cleared_origin(res) {
+            self.purge_origin_later(origin).await;
+        }
+        Ok(())
+    }
+
+    // Handles a request that has a stored response according to the mode
+    async fn serve_stored(
+        &self,
+        mut middleware: impl Middleware,
+        mut res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        res.cache_lookup_status(HitOrMiss::HIT);
+        if let Some(warning_code) = res.warning_code() {
+            // https://tools.ietf.org/html/rfc7234#section-4.3.4
+            //
+            // If a stored response is selected for update, the cache MUST:
+            //
+            // * delete any warning header fields in the stored response with
+            //   warn-code 1xx (see Section 5.5);
+            //
+            // * retain any warning header fields in the stored response with
+            //   warn-code 2xx;
+            //
+            if (100..200).contains(&warning_code) {
+                res.remove_warning();
+            }
+        }
+
+        match self.mode {
+            CacheMode::Default => {
+                self.conditional_fetch(middleware, res, policy).await
+            }
+            CacheMode::NoCache => {
+                middleware.force_no_cache()?;
+                let mut res = self.remote_fetch(&mut middleware).await?;
+                res.cache_lookup_status(HitOrMiss::HIT);
+                Ok(res)
+            }
+            CacheMode::ForceCache | CacheMode::OnlyIfCached => {
+                //   112 Disconnected operation
+                // SHOULD be included if the cache is intentionally disconnected from
+                // the rest of the network for a period of time.
+                // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                res.add_warning(
+                    &res.url.clone(),
+                    112,
+                    "Disconnected operation",
+                );
+                res.cache_status(HitOrMiss::HIT);
+                Ok(res)
+            }
+            _ => self.remote_fetch(&mut middleware).await,
+        }
+    }
+
+    // Fetches a response that isn't cached. With coalescing enabled only one
+    // of the concurrent requests for it goes to the network, the others are
+    // served what it stored.
+    async fn fetch_missing(
+        &self,
+        mut middleware: impl Middleware,
+        method: &str,
+        url: &Url,
+    ) -> Result<HttpResponse> {
+        let coalescing = match &self.options.coalescing {
+            Some(coalescing) => coalescing,
+            None => return self.remote_fetch(&mut middleware).await,
+        };
+        loop {
+            let follower = match coalescing.join(method, url) {
+                Role::Leader(leader) => {
+                    let res =
+                        leader.track(self.remote_fetch(&mut middleware)).await;
+                    leader.finish(match &res {
+                        Ok(_) => Ok(()),
+                        Err(e) => Err(e.to_string()),
+                    });
+                    return res;
+                }
+                Role::Follower(follower) => follower,
+            };
+            match follower.wait().await {
+                Waited::Done => {
+                    // The leader may serve responses this cache can't
+                    if let Some((res, policy)) =
+                        self.lookup(method, url).await?.filter(
+                            |(res, policy)| self.serves_overridden(res, policy),
+                        )
+                    {
+                        return self
+                            .serve_stored(middleware, res, policy)
+                            .await;
+                    }
+                    // The response could not be stored
+                    return self.remote_fetch(&mut middleware).await;
+                }
+                Waited::Failed(e)
+                    if coalescing.on_leader_failure
+                        == LeaderFailure::Propagate =>
+                {
+                    return Err(CacheError::CoalescedRequestFailed(e));
+                }
+                Waited::Failed(_) | Waited::SameTask => {
+                    return self.remote_fetch(&mut middleware).await;
+                }
+                // Someone else gets to lead the next flight
+                Waited::Abandoned => continue,
+            }
+        }
+    }
+
+    /// Prefetches the provided urls so later requests can be served from cache.
+    ///
+    /// The `fetch` closure should issue a GET request for the url through a
+    /// client that has this cache installed. Urls that already have a fresh
+    /// response in the cache are skipped and at most `concurrency` fetches are
+    /// in flight at once. A failure for one url does not stop the others.
+    pub async fn warm<F, Fut>(
+        &self,
+        urls: impl IntoIterator<Item = Url>,
+        concurrency: usize,
+        fetch: F,
+    ) -> WarmReport
+    where
+        F: Fn(Url) -> Fut,
+        Fut: Future<Output = Result<()>>,
+    {
+        let fetch = &fetch;
+        let entries = stream::iter(urls)
+            .map(|url| async move {
+                let outcome = match self.is_fresh("GET", &url).await {
+                    Ok(true) => WarmOutcome::AlreadyFresh,
+                    _ => match fetch(url.clone()).await {
+                        Ok(()) => WarmOutcome::Fetched,
+                        Err(e) => WarmOutcome::Failed(e),
+                    },
+                };
+                (url, outcome)
+            })
+            .buffered(concurrency.max(1))
+            .collect()
+            .await;
+        WarmReport { entries }
+    }
+
+    async fn is_fresh(&self, method: &str, url: &Url) -> Result<bool> {
+        let policy = match self.lookup(method, url).await? {
+            Some((res, _)) if res.metadata.is_soft_purged() => {
+                return Ok(false)
+            }
+            Some((_, policy)) => policy,
+            None => return Ok(false),
+        };
+        let parts = request::Builder::new()
+            .method(method)
+            .uri(url.as_str())
+            .body(())?
+            .into_parts()
+            .0;
+        Ok(matches!(
+            policy.before_request(&parts, self.now()),
+            BeforeRequest::Fresh(_)
+        ))
+    }
+
+    // Reads a record from the manager, removing it if it can't be read
+    async fn lookup(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<(HttpResponse, CachePolicy)>> {
+        let key = CacheKey::new(method, url);
+        let get = self.manager.get(&key);
+        let res = self.call("get", method, url, get).await.map(Option::flatten);
+        self.discard_corrupt(method, url, res).await
+    }
+
+    // Removes the record if reading it failed because it is corrupt, unless
+    // corrupt entries are configured to be propagated
+    async fn discard_corrupt<R>(
+        &self,
+        method: &str,
+        url: &Url,
+        res: Result<Option<R>>,
+    ) -> Result<Option<R>> {
+        match res {
+            Err(CacheError::CorruptEntry(e))
+                if !self.options.propagate_corrupt_entries =>
+            {
+                self.delete(method, url).await;
+                self.emit(|| CacheEvent::CorruptEntry {
+                    method: method.to_string(),
+                    url: url.clone(),
+                    error: e.to_string(),
+                });
+                Ok(None)
+            }
+            res => res,
+        }
+    }
+
+    /// Describes the cached response for the request without loading its
+    /// body, if the manager supports that, see [`CacheManager::get_metadata`].
+    /// Returns `None` if nothing was cached.
+    ///
+    /// The inspection judges freshness with the clock of the cache, see
+    /// [`HttpCacheOptions::clock`].
+    pub async fn inspect(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<CacheInspection>> {
+        let method = method.to_uppercase();
+        let res = self.manager.get_metadata(&CacheKey::new(&method, url)).await;
+        Ok(self.discard_corrupt(&method, url, res).await?.map(
+            |(headers, policy, metadata)| {
+                CacheInspection::new(headers, policy)
+                    .with_metadata(metadata)
+                    .with_clock(self.options.clock.clone())
+            },
+        ))
+    }
+
+    /// Tells how long the cached response for the request stays fresh, from
+    /// its stored policy and without loading its body, if the manager
+    /// supports that, see [`CacheManager::get_metadata`]. Returns `None` if
+    /// nothing was cached.
+    ///
+    /// Freshness is measured with the clock of the cache, see
+    /// [`HttpCacheOptions::clock`].
+    pub async fn freshness(
+        &self,
+        method: &str,
+        url: &Url,
+    ) -> Result<Option<FreshnessInfo>> {
+        let method = method.to_uppercase();
+        let res = self.manager.get_metadata(&CacheKey::new(&method, url)).await;
+        Ok(self.discard_corrupt(&method, url, res).await?.map(
+            |(headers, policy, metadata)| {
+                FreshnessInfo::new(&headers, &policy, &metadata, self.now())
+            },
+        ))
+    }
+
+    /// Describes the cached responses for several urls requested with
+    /// `method` at once, see [`HttpCache::inspect`], returning one result per
+    /// url in the order of `urls`.
+    ///
+    /// The records are read with [`CacheManager::get_many`]. Records that
+    /// can't be read back are removed and reported as `None` like with
+    /// [`HttpCache::inspect`], other errors are returned for their url only.
+    pub async fn lookup_many(
+        &self,
+        method: &str,
+        urls: &[Url],
+    ) -> Vec<Result<Option<CacheInspection>>> {
+        let method = method.to_uppercase();
+        let keys = urls
+            .iter()
+            .map(|url| CacheKey::new(&method, url))
+            .collect::<Vec<_>>();
+        let records = self.manager.get_many(&keys).await;
+        let mut inspections = Vec::with_capacity(records.len());
+        for (url, res) in urls.iter().zip(records) {
+            inspections.push(
+                self.discard_corrupt(&method, url, res).await.map(|record| {
+                    record.map(|(res, policy)| {
+                        CacheInspection::new(res.headers, policy)
+                            .with_metadata(res.metadata)
+                            .with_clock(self.options.clock.clone())
+                    })
+                }),
+            );
+        }
+        inspections
+    }
+
+    /// Runs `work` with the manager through the configured [`Spawner`],
+    /// without waiting for it to complete.
+    ///
+    /// The work runs before this returns when the spawner runs work inline,
+    /// or when the manager has no [`CacheManager::background_handle`].
+    pub async fn spawn<F>(&self, work: F)
+    where
+        F: for<'a> FnOnce(&'a T) -> ManagerFuture<'a, ()> + MaybeSend + 'static,
+    {
+        if let Some(manager) = self.background_manager() {
+            self.options
+                .spawner
+                .spawn(async move { work(&manager).await })
+                .await;
+            return;
+        }
+        work(&self.manager).await
+    }
+
+    /// Removes every cached response tagged with `tag` through
+    /// [`HttpCacheOptions::tag_header`], returning how many were removed.
+    pub async fn purge_tag(&self, tag: &str) -> Result<usize> {
+        let removed = self.manager.purge_tag(tag).await?;
+        self.emit(|| CacheEvent::PurgedTag { tag: tag.to_string(), removed });
+        Ok(removed)
+    }
+
+    /// Checks that the manager can be reached and written to, see
+    /// [`CacheManager::health_check`].
+    pub async fn health_check(&self) -> Result<HealthReport> {
+        self.manager.health_check().await
+    }
+
+    /// Waits for the responses queued by [`HttpCacheOptions::write_behind`]
+    /// to be stored, then flushes the manager with [`CacheManager::flush`].
+    pub async fn flush(&self) -> Result<()> {
+        if let Some(write_behind) = &self.options.write_behind {
+            write_behind.drained().await;
+        }
+        self.manager.flush().await
+    }
+
+    /// Gets the cache ready for the process to exit, returning how many
+    /// background tasks were still running once `timeout` elapsed.
+    ///
+    /// The work the cache hands to its [`Spawner`] runs inline from then on,
+    /// for every cache sharing the spawner, while the tasks already handed
+    /// over are given up to `timeout` to finish. The manager is then flushed
+    /// with [`CacheManager::flush`]. Dropping the cache does neither, so
+    /// background work and writes the manager buffered can be lost when the
+    /// process exits without calling this.
+    ///
+    /// The responses queued by [`HttpCacheOptions::write_behind`] are stored
+    /// by a background task, and are lost when it doesn't finish in time.
+    pub async fn shutdown(&self, timeout: Duration) -> Result<usize> {
+        let running = self.options.spawner.shutdown(timeout).await;
+        self.manager.flush().await?;
+        Ok(running)
+    }
+
+    /// Keeps the cached response for `url` from being evicted, see
+    /// [`CacheManager::pin`].
+    pub async fn pin(&self, url: &Url) -> Result<()> {
+        self.manager.pin(&CacheKey::new("GET", url)).await
+    }
+
+    /// Allows the cached response for `url` to be evicted again.
+    pub async fn unpin(&self, url: &Url) -> Result<()> {
+        self.manager.unpin(&CacheKey::new("GET", url)).await
+    }
+
+    /// Marks the cached response as stale without removing it, so the next
+    /// request revalidates it using the stored validators instead of fetching
+    /// it from scratch. Returns false if nothing was cached.
+    ///
+    /// The response is marked with [`EntryMetadata::SOFT_PURGED`] through
+    /// [`CacheManager::update_metadata`], its policy is left as is.
+    pub async fn soft_purge(&self, method: &str, url: &Url) -> Result<bool> {
+        let method = method.to_uppercase();
+        let key = CacheKey::new(&method, url);
+        let res = self.manager.get_metadata(&key).await;
+        let mut metadata = match self.discard_corrupt(&method, url, res).await?
+        {
+            Some((_, _, metadata)) => metadata,
+            None => return Ok(false),
+        };
+        metadata.mark_soft_purged();
+        let purged = self.manager.update_metadata(&key, metadata).await?;
+        if purged {
+            self.emit(|| CacheEvent::SoftPurged { method, url: url.clone() });
+        }
+        Ok(purged)
+    }
+
+    // Refreshes a record after a `304 Not Modified`, which also takes back a
+    // soft purge, returning whether the record was still there unless the
+    // manager couldn't be reached
+    pub(crate) async fn refresh(
+        &self,
+        method: &str,
+        url: &Url,
+        cached_res: &mut HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<Option<bool>> {
+        let key = CacheKey::new(method, url);
+        let update =
+            self.manager.update(&key, cached_res.headers.clone(), policy);
+        let updated = self.call("update", method, url, update).await?;
+        let purged = cached_res.metadata.remove(EntryMetadata::SOFT_PURGED);
+        if purged.is_some() && updated == Some(true) {
+            let metadata = cached_res.metadata.clone();
+            let update = self.manager.update_metadata(&key, metadata);
+            self.call("update_metadata", method, url, update).await?;
+        }
+        Ok(updated)
+    }
+
+    // Sets the metadata of a response about to be stored, taken from the
+    // request and handed to `HttpCacheOptions::on_store`
+    pub(crate) fn attach_metadata(
+        &self,
+        middleware: &impl Middleware,
+        res: &mut HttpResponse,
+    ) {
+        let mut metadata = middleware.entry_metadata().unwrap_or_default();
+        if let Some(on_store) = &self.options.on_store {
+            on_store(res, &mut metadata);
+        }
+        res.metadata = metadata;
+    }
+
+    fn tags(&self, res: &HttpResponse) -> Vec<String> {
+        let header = match &self.options.tag_header {
+            Some(header) => header.to_ascii_lowercase(),
+            None => return Vec::new(),
+        };
+        let mut tags = Vec::new();
+        if let Some(value) = res.headers.get_str(&header) {
+            for tag in value.split(|c: char| c == ',' || c.is_whitespace()) {
+                if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                    tags.push(tag.to_string());
+                }
+            }
+        }
+        tags
+    }
+
+    fn should_store(&self, res: &HttpResponse) -> bool {
+        self.stores_content_type(res) && self.passes_predicate(res)
+    }
+
+    fn stores_content_type(&self, res: &HttpResponse) -> bool {
+        match &self.options.cacheable_content_types {
+            Some(types) => types.matches(res.headers.get_str("content-type")),
+            None => true,
+        }
+    }
+
+    fn passes_predicate(&self, res: &HttpResponse) -> bool {
+        match &self.options.should_store {
+            Some(should_store) => should_store(res),
+            None => true,
+        }
+    }
+
+    fn emit(&self, event: impl FnOnce() -> CacheEvent) {
+        emit_event(
+            self.options.on_event.as_ref(),
+            self.options.event_broadcast.as_ref(),
+            event,
+        );
+    }
+
+    // Returns true if events are handed to anyone
+    fn is_observed(&self) -> bool {
+        self.options.on_event.is_some()
+            || self
+                .options
+                .event_broadcast
+                .as_ref()
+                .is_some_and(EventBroadcast::has_subscribers)
+    }
+
+    // Reports whether a request was answered from the cache or the origin
+    pub(crate) fn emit_answered(
+        &self,
+        method: String,
+        url: Url,
+        res: &HttpResponse,
+        started: SystemTime,
+    ) {
+        let status = res.status;
+        let elapsed = now().duration_since(started).unwrap_or_default();
+        if res.headers.get_str(XCACHE) == Some(HitOrMiss::HIT.as_str()) {
+            self.emit(|| CacheEvent::Hit { method, url, status, elapsed });
+        } else {
+            self.emit(|| CacheEvent::Miss { method, url, status, elapsed });
+        }
+    }
+
+    // Runs a manager operation within the backend timeout, if one is
+    // configured. Resolves to `None` when it timed out, after reporting it.
+    fn timed<'a, F: Future + 'a>(
+        &'a self,
+        operation: &'static str,
+        method: &'a str,
+        url: &'a Url,
+        fut: F,
+    ) -> impl Future<Output = Option<F::Output>> + 'a {
+        let duration = match self.options.backend_timeout {
+            Some(duration) => duration,
+            None => return Either::Left(fut.map(Some)),
+        };
+        Either::Right(timeout::timeout(duration, fut).map(move |res| {
+            if res.is_none() {
+                self.emit(|| CacheEvent::BackendTimeout {
+                    operation,
+                    method: method.to_string(),
+                    url: url.clone(),
+                });
+            }
+            res
+        }))
+    }
+
+    // Runs a manager operation through the circuit breaker and within the
+    // backend timeout. Resolves to `None` when the operation was skipped,
+    // timed out or failed with the error swallowed by the error policy.
+    fn call<'a, R: 'a, F: Future<Output = Result<R>> + 'a>(
+        &'a self,
+        operation: &'static str,
+        method: &'a str,
+        url: &'a Url,
+        fut: F,
+    ) -> impl Future<Output = Result<Option<R>>> + 'a {
+        let breaker = self.options.circuit_breaker.as_ref();
+        if breaker.is_some_and(|breaker| !breaker.allows()) {
+            return Either::Left(ready(match self.options.error_policy {
+                ErrorPolicy::FailOpen => Ok(None),
+                ErrorPolicy::FailClosed => Err(CacheError::BackendUnavailable),
+            }));
+        }
+        Either::Right(self.timed(operation, method, url, fut).map(move |res| {
+            match res {
+                Some(Ok(res)) => {
+                    if let Some(breaker) = breaker {
+                        breaker.succeeded();
+                    }
+                    Ok(Some(res))
+                }
+                // The manager did its job, the record is what's broken
+                Some(Err(e @ CacheError::CorruptEntry(_))) => {
+                    if let Some(breaker) = breaker {
+                        breaker.succeeded();
+                    }
+                    Err(e)
+                }
+                Some(Err(e)) => {
+                    self.failed(breaker);
+                    self.emit(|| CacheEvent::BackendError {
+                        operation,
+                        method: method.to_string(),
+                        url: url.clone(),
+                        error: e.to_string(),
+                    });
+                    match self.options.error_policy {
+                        ErrorPolicy::FailOpen => Ok(None),
+                        ErrorPolicy::FailClosed => Err(e),
+                    }
+                }
+                None => {
+                    self.failed(breaker);
+                    Ok(None)
+                }
+            }
+        }))
+    }
+
+    fn failed(&self, breaker: Option<&CircuitBreaker>) {
+        if let Some(until) = breaker.and_then(CircuitBreaker::failed) {
+            self.emit(|| CacheEvent::CircuitOpened { until });
+        }
+    }
+
+    // Stores a response, returning it as is if the manager didn't store it
+    // and the request should go on anyway
+    async fn store(
+        &self,
+        method: &str,
+        url: &Url,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<HttpResponse> {
+        let key = CacheKey::new(method, url);
+        if let Some(write_behind) = &self.options.write_behind {
+            if let Some(manager) = self.background_manager() {
+                return Ok(self
+                    .store_behind(write_behind, manager, key, res, policy, tags)
+                    .await);
+            }
+        }
+        // The manager only hands the response back once it is done
+        let fallback = (self.options.error_policy == ErrorPolicy::FailOpen
+            || self.options.backend_timeout.is_some())
+        .then(|| res.clone());
+        let put = self.manager.put_tagged(&key, res, policy, tags);
+        match self.call("put", method, url, put).await? {
+            Some(res) => {
+                self.emit(|| CacheEvent::Stored {
+                    method: method.to_string(),
+                    url: url.clone(),
+                    status: res.status,
+                });
+                Ok(res)
+            }
+            None => fallback.ok_or(CacheError::BackendUnavailable),
+        }
+    }
+
+    // Takes the header fields the directives of a response keep out of the
+    // stored copy off it, to be put back once it is stored
+    pub(crate) fn withhold(&self, res: &mut HttpResponse) -> Withheld {
+        let shared = self.options.cache_options.unwrap_or_default().shared;
+        Withheld::take(res, shared)
+    }
+
+    // The policy of a response fetched for the request, stamped with the
+    // time of the clock when one is set, see [`HttpCacheOptions::clock`]
+    pub(crate) fn response_policy(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        if self.options.clock.is_some() {
+            return Ok(CachePolicy::new_options(
+                &middleware.parts()?,
+                &res.parts()?,
+                self.now(),
+                self.options.cache_options.unwrap_or_default(),
+            ));
+        }
+        match self.options.cache_options {
+            Some(options) => middleware.policy_with_options(res, options),
+            None => middleware.policy(res),
+        }
+    }
+
+    // Returns the url and policy to store a response the client was
+    // redirected to under as well, if it may be, see
+    // [`HttpCacheOptions::cache_redirect_targets`]
+    fn redirect_target_policy(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<Option<(Url, CachePolicy)>> {
+        if !self.options.cache_redirect_targets
+            || res.url == middleware.url()?
+        {
+            return Ok(None);
+        }
+        let mut parts = middleware.parts()?;
+        parts.uri = res.url.as_str().parse()?;
+        let policy = CachePolicy::new_options(
+            &parts,
+            &res.parts()?,
+            self.now(),
+            self.options.cache_options.unwrap_or_default(),
+        );
+        let url = self.keyed_url(res.url.clone(), &parts.headers);
+        Ok(policy.is_storable().then_some((url, policy)))
+    }
+
+    // Removes a record, ignoring failures
+    async fn delete(&self, method: &str, url: &Url) {
+        self.remove(method, url).await.ok();
+    }
+
+    // Removes a record along with the responses queued to be stored for it,
+    // or once the one being stored is, see `HttpCacheOptions::write_behind`
+    async fn remove(&self, method: &str, url: &Url) -> Result<Option<()>> {
+        let key = CacheKey::new(method, url);
+        if let Some(write_behind) = &self.options.write_behind {
+            if write_behind.remove(&key) {
+                return Ok(Some(()));
+            }
+        }
+        let delete = self.manager.delete(&key);
+        self.call("delete", method, url, delete).await
+    }
+
+    // Returns a handle to the manager to store responses in the background
+    // with, unless the spawner runs work inline
+    fn background_manager(&self) -> Option<T> {
+        if self.options.spawner.is_inline() {
+            return None;
+        }
+        self.manager.background_handle()
+    }
+
+    // Removes the record of a url a request with a method other than `GET`
+    // or `HEAD` was sent to
+    pub(crate) async fn invalidate(&self, url: &Url) {
+        if let Ok(Some(())) = self.remove("GET", url).await {
+            self.emit(|| CacheEvent::Evicted {
+                method: "GET".to_string(),
+                url: url.clone(),
+            });
+        }
+    }
+
+    async fn remote_fetch(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<HttpResponse> {
+        let requested = self.now();
+        let mut res = middleware.remote_fetch().await?;
+        res.correct_age(requested, self.now());
+        res.drop_decoded_encoding();
+        res.expire_unreadable_dates();
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        let withheld = self.withhold(&mut res);
+        let policy = self.response_policy(middleware, &res)?;
+        let policy = self.override_no_store(middleware, &mut res, policy)?;
+        let is_get_head = middleware.is_method_get_head();
+        let is_cacheable = is_get_head
+            && self.mode != CacheMode::NoStore
+            && self.mode != CacheMode::Reload
+            && is_stored_status(res.status)
+            && policy.is_storable()
+            && self.should_store(&res);
+        let url = self.cache_url(middleware)?;
+        let method = middleware.method()?.to_uppercase();
+        let cleared = self.cleared_origin(&res);
+        let mut res = if is_cacheable {
+            self.attach_metadata(middleware, &mut res);
+            let tags = self.tags(&res);
+            if let Some((target, policy)) =
+                self.redirect_target_policy(middleware, &res)?
+            {
+                self.store(&method, &target, res.clone(), policy, &tags)
+                    .await?;
+            }
+            self.store(&method, &url, res, policy, &tags).await?
+        } else {
+            if !is_get_head {
+                self.invalidate(&url).await;
+            }
+            res
+        };
+        withheld.restore(&mut res);
+        if let Some(origin) = cleared {
+            self.purge_origin_later(origin).await;
+        }
+        Ok(res)
+    }
+
+    // Returns the origin to purge if the response asks for the cache of its
+    // origin to be cleared
+    fn cleared_origin(&self, res: &HttpResponse) -> Option<Origin> {
+        if !self.options.clear_site_data {
+            return None;
+        }
+        let value = res.headers.get_str("clear-site-data")?;
+        let clears_cache = value.split(',').any(|directive| {
+            let directive = directive.trim().trim_matches('"');
+            directive == "cache" || directive == "*"
+        });
+        let origin = res.url.origin();
+        (clears_cache && origin.is_tuple()).then_some(origin)
+    }
+
+    // Purges the origin through the spawner, there is nobody left to report
+    // a failure to once the response has been returned
+    async fn purge_origin_later(&self, origin: Origin) {
+        let on_event = self.options.on_event.clone();
+        let broadcast = self.options.event_broadcast.clone();
+        self.spawn(move |manager| {
+            Box::pin(async move {
+                if let Ok(removed) = manager.purge_origin(&origin).await {
+                    emit_event(on_event.as_ref(), broadcast.as_ref(), || {
+                        CacheEvent::PurgedOrigin {
+                            origin: origin.ascii_serialization(),
+                            removed,
+                        }
+                    });
+                }
+            })
+        })
+        .await
+    }
+
+    async fn conditional_fetch(
+        &self,
+        mut middleware: impl Middleware,
+        mut cached_res: HttpResponse,
+        policy: CachePolicy,
     ) -> Result<HttpResponse> {
-        let before_req =
-            policy.before_request(&middleware.parts()?, SystemTime::now());
-        match before_req {
+        let parts = middleware.parts()?;
+        let forward = has_validators(&parts);
+        match self.before_request(&middleware, &cached_res, &policy, &parts)? {
             BeforeRequest::Fresh(parts) => {
                 cached_res.update_headers(&parts)?;
                 cached_res.cache_status(HitOrMiss::HIT);
                 cached_res.cache_lookup_status(HitOrMiss::HIT);
                 return Ok(cached_res);
             }
-            BeforeRequest::Stale { request: parts, matches } => {
-                if matches {
+            // The validators of the caller are sent as they are, so the
+            // response answers them
+            BeforeRequest::Stale { request: mut parts, matches } => {
+                if matches && !forward {
+                    self.before_revalidation(&middleware, &mut parts)?;
                     middleware.update_headers(&parts)?;
                 }
             }
         }
-        let req_url = middleware.url()?;
-        match middleware.remote_fetch().await {
+        if forward {
+            return self.revalidate(middleware, cached_res, policy, true).await;
+        }
+        match &self.options.coalescing {
+            Some(coalescing) if coalescing.revalidations => {
+                self.coalesced_revalidate(
+                    coalescing, middleware, cached_res, policy,
+                )
+                .await
+            }
+            _ => self.revalidate(middleware, cached_res, policy, false).await,
+        }
+    }
+
+    // Asks the policy whether the cached response can be served as is,
+    // taking it as stale once its share of the freshness lifetime is left
+    // when `HttpCacheOptions::refresh_jitter` is set, or once it was soft
+    // purged
+    pub(crate) fn before_request(
+        &self,
+        middleware: &impl Middleware,
+        cached_res: &HttpResponse,
+        policy: &CachePolicy,
+        parts: &request::Parts,
+    ) -> Result<BeforeRequest> {
+        let now = self.now();
+        if cached_res.metadata.is_soft_purged() {
+            return soft_purged_request(policy, parts, now);
+        }
+        let fresh = policy.before_request(parts, now);
+        let jitter = match self.options.refresh_jitter {
+            Some(jitter) if jitter > 0.0 && !policy.is_stale(now) => {
+                jitter.min(1.0)
+            }
+            _ => return Ok(fresh),
+        };
+        let key =
+            CacheKey::new(&middleware.method()?, &self.cache_url(middleware)?);
+        let lifetime = policy.age(now) + policy.time_to_live(now);
+        // Judged as if the share was already gone, which gives the
+        // conditional request the policy builds for a stale response
+        let early = now + lifetime.mul_f64(jitter * key.fraction());
+        match policy.before_request(parts, early) {
+            stale @ BeforeRequest::Stale { .. } => Ok(stale),
+            BeforeRequest::Fresh(_) => Ok(fresh),
+        }
+    }
+
+    // Hands a conditional request to the revalidation hook before it is
+    // sent, see [`HttpCacheOptions::on_revalidation_request`]
+    pub(crate) fn before_revalidation(
+        &self,
+        middleware: &impl Middleware,
+        parts: &mut request::Parts,
+    ) -> Result<()> {
+        let hook = match &self.options.on_revalidation_request {
+            Some(hook) => hook,
+            None => return Ok(()),
+        };
+        if !revalidation::apply(hook, parts) {
+            let method = middleware.method()?.to_uppercase();
+            let url = self.cache_url(middleware)?;
+            self.emit(|| CacheEvent::ValidatorsDropped { method, url });
+        }
+        Ok(())
+    }
+
+    // Lets only one of the concurrent requests revalidate a stale response,
+    // the others wait for it and are then served what it stored
+    async fn coalesced_revalidate(
+        &self,
+        coalescing: &Coalescing,
+        middleware: impl Middleware,
+        mut cached_res: HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<HttpResponse> {
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(&middleware)?;
+        let follower = match coalescing.join(&method, &url) {
+            Role::Leader(leader) => {
+                let res = leader
+                    .track(
+                        self.revalidate(middleware, cached_res, policy, false),
+                    )
+                    .await;
+                leader.finish(match &res {
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                });
+                return res;
+            }
+            Role::Follower(follower) => follower,
+        };
+        if self.may_serve_stale(&cached_res, &policy) {
+            if policy.is_stale(self.now())
+                || cached_res.metadata.is_soft_purged()
+            {
+                //   110 Response is Stale
+                //   MUST be included whenever the returned response is stale.
+                // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                cached_res.add_warning(&url, 110, "Response is Stale");
+            }
+            cached_res.cache_status(HitOrMiss::HIT);
+            return Ok(cached_res);
+        }
+        match follower.wait().await {
+            Waited::Done => {
+                if let Some((mut res, policy)) =
+                    self.lookup(&method, &url).await?.filter(|(res, policy)| {
+                        !res.metadata.is_soft_purged()
+                            && self.serves_overridden(res, policy)
+                    })
+                {
+                    let parts = middleware.parts()?;
+                    if let BeforeRequest::Fresh(parts) =
+                        policy.before_request(&parts, self.now())
+                    {
+                        res.update_headers(&parts)?;
+                        res.cache_status(HitOrMiss::HIT);
+                        res.cache_lookup_status(HitOrMiss::HIT);
+                        return Ok(res);
+                    }
+                }
+            }
+            Waited::Failed(e)
+                if coalescing.on_leader_failure == LeaderFailure::Propagate =>
+            {
+                return Err(CacheError::CoalescedRequestFailed(e));
+            }
+            Waited::Failed(_) | Waited::Abandoned | Waited::SameTask => {}
+        }
+        self.revalidate(middleware, cached_res, policy, false).await
+    }
+
+    // Reports the answer of the origin to a revalidation request
+    pub(crate) fn emit_revalidated(
+        &self,
+        middleware: &impl Middleware,
+        cond_res: &HttpResponse,
+        started: SystemTime,
+    ) -> Result<()> {
+        if !self.is_observed() {
+            return Ok(());
+        }
+        let method = middleware.method()?.to_uppercase();
+        let url = middleware.url()?;
+        let status = cond_res.status;
+        let elapsed = now().duration_since(started).unwrap_or_default();
+        self.emit(|| CacheEvent::Revalidated { method, url, status, elapsed });
+        Ok(())
+    }
+
+    // Returns true if the stale response is allowed to be served while it is
+    // being revalidated, according to its `stale-while-revalidate` directive
+    fn may_serve_stale(
+        &self,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> bool {
+        let directive =
+            |name: &str| inspect::cache_control_seconds(&res.headers, name);
+        let window = match directive("stale-while-revalidate") {
+            Some(window) => window,
+            None => return false,
+        };
+        let shared = self.options.cache_options.unwrap_or_default().shared;
+        let max_age = match shared.then(|| directive("s-maxage")).flatten() {
+            Some(max_age) => max_age,
+            None => match directive("max-age") {
+                Some(max_age) => max_age,
+                None => return false,
+            },
+        };
+        !res.must_revalidate()
+            && policy.age(self.now())
+                <= Duration::from_secs(max_age.saturating_add(window))
+    }
+
+    // Revalidates a stale response, handing a 304 over to the caller as is
+    // when `forward` is set as it answers validators of its own
+    async fn revalidate(
+        &self,
+        mut middleware: impl Middleware,
+        mut cached_res: HttpResponse,
+        mut policy: CachePolicy,
+        forward: bool,
+    ) -> Result<HttpResponse> {
+        let req_url = self.cache_url(&middleware)?;
+        let started = now();
+        let requested = self.now();
+        // Revalidated early as `HttpCacheOptions::refresh_jitter` asks, the
+        // response can still be served as is when that fails. Soft purged
+        // responses count as stale.
+        let stale =
+            policy.is_stale(requested) || cached_res.metadata.is_soft_purged();
+        let mut fetched = middleware.remote_fetch().await;
+        if let Ok(cond_res) = &mut fetched {
+            cond_res.correct_age(requested, self.now());
+            self.emit_revalidated(&middleware, cond_res, started)?;
+        }
+        let cleared =
+            fetched.as_ref().ok().and_then(|res| self.cleared_origin(res));
+        let res = match fetched {
             Ok(mut cond_res) => {
                 let status = StatusCode::from_u16(cond_res.status)?;
-                if status.is_server_error() && cached_res.must_revalidate() {
-                    //   111 Revalidation failed
-                    //   MUST be included if a cache returns a stale response
-                    //   because an attempt to revalidate the response failed,
-                    //   due to an inability to reach the server.
-                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
-                        &req_url,
-                        111,
-                        "Revalidation failed",
-                    );
+                if status.is_server_error()
+                    && self.options.server_errors_replace_stored
+                {
+                    let method = middleware.method()?.to_uppercase();
+                    self.remove(&method, &req_url).await?;
+                    cond_res.cache_status(HitOrMiss::MISS);
+                    cond_res.cache_lookup_status(HitOrMiss::HIT);
+                    Ok(cond_res)
+                } else if status.is_server_error()
+                    && cached_res.must_revalidate()
+                {
+                    if stale {
+                        //   111 Revalidation failed
+                        //   MUST be included if a cache returns a stale response
+                        //   because an attempt to revalidate the response failed,
+                        //   due to an inability to reach the server.
+                        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                        cached_res.add_warning(
+                            &req_url,
+                            111,
+                            "Revalidation failed",
+                        );
+                    }
                     cached_res.cache_status(HitOrMiss::HIT);
                     Ok(cached_res)
+                } else if cond_res.status == 304 && forward {
+                    // The stored response is only refreshed if it is one of
+                    // those the caller validated
+                    if let AfterResponse::NotModified(policy, parts) = policy
+                        .after_response(
+                            &middleware.parts()?,
+                            &cond_res.parts()?,
+                            self.now(),
+                        )
+                    {
+                        cached_res.update_headers(&parts)?;
+                        let method = middleware.method()?.to_uppercase();
+                        self.refresh(
+                            &method,
+                            &req_url,
+                            &mut cached_res,
+                            policy,
+                        )
+                        .await?;
+                    }
+                    cond_res.cache_status(HitOrMiss::MISS);
+                    cond_res.cache_lookup_status(HitOrMiss::HIT);
+                    Ok(cond_res)
                 } else if cond_res.status == 304 {
                     let after_res = policy.after_response(
                         &middleware.parts()?,
                         &cond_res.parts()?,
-                        SystemTime::now(),
+                        self.now(),
                     );
                     match after_res {
                         AfterResponse::Modified(new_policy, parts)
@@ -507,24 +3235,59 @@ impl<T: CacheManager> HttpCache<T> {
                     cached_res.cache_status(HitOrMiss::HIT);
                     cached_res.cache_lookup_status(HitOrMiss::HIT);
                     let method = middleware.method()?.to_uppercase();
-                    let res = self
-                        .manager
-                        .put(&method, &req_url, cached_res, policy)
-                        .await?;
-                    Ok(res)
-                } else if cond_res.status == 200 {
-                    let policy = match self.options {
-                        Some(options) => middleware
-                            .policy_with_options(&cond_res, options)?,
-                        None => middleware.policy(&cond_res)?,
-                    };
+                    // Serving the response matters more than storing it
+                    let updated = self
+                        .refresh(
+                            &method,
+                            &req_url,
+                            &mut cached_res,
+                            policy.clone(),
+                        )
+                        .await?
+                        .unwrap_or(true);
+                    if updated {
+                        Ok(cached_res)
+                    } else {
+                        // The record was removed since it was looked up
+                        let tags = self.tags(&cached_res);
+                        self.store(&method, &req_url, cached_res, policy, &tags)
+                            .await
+                    }
+                } else if is_stored_status(cond_res.status) {
+                    cond_res.drop_decoded_encoding();
+                    cond_res.expire_unreadable_dates();
+                    let withheld = self.withhold(&mut cond_res);
+                    let policy =
+                        self.response_policy(&middleware, &cond_res)?;
+                    let policy = self.override_no_store(
+                        &middleware,
+                        &mut cond_res,
+                        policy,
+                    )?;
                     cond_res.cache_status(HitOrMiss::MISS);
                     cond_res.cache_lookup_status(HitOrMiss::HIT);
                     let method = middleware.method()?.to_uppercase();
-                    let res = self
-                        .manager
-                        .put(&method, &req_url, cond_res, policy)
-                        .await?;
+                    let mut res = if !policy.is_storable() {
+                        // The stored response is outdated, and the one
+                        // replacing it can't be stored
+                        self.remove(&method, &req_url).await?;
+                        cond_res
+                    } else if self.should_store(&cond_res) {
+                        self.attach_metadata(&middleware, &mut cond_res);
+                        let tags = self.tags(&cond_res);
+                        if let Some((url, policy)) =
+                            self.redirect_target_policy(&middleware, &cond_res)?
+                        {
+                            let res = cond_res.clone();
+                            self.store(&method, &url, res, policy, &tags)
+                                .await?;
+                        }
+                        self.store(&method, &req_url, cond_res, policy, &tags)
+                            .await?
+                    } else {
+                        cond_res
+                    };
+                    withheld.restore(&mut res);
                     Ok(res)
                 } else {
                     cached_res.cache_status(HitOrMiss::HIT);
@@ -532,23 +3295,30 @@ impl<T: CacheManager> HttpCache<T> {
                 }
             }
             Err(e) => {
-                if cached_res.must_revalidate() {
+                if cached_res.must_revalidate() && stale {
                     Err(e)
                 } else {
-                    //   111 Revalidation failed
-                    //   MUST be included if a cache returns a stale response
-                    //   because an attempt to revalidate the response failed,
-                    //   due to an inability to reach the server.
-                    // (https://tools.ietf.org/html/rfc2616#section-14.46)
-                    cached_res.add_warning(
-                        &req_url,
-                        111,
-                        "Revalidation failed",
-                    );
+                    if stale {
+                        //   111 Revalidation failed
+                        //   MUST be included if a cache returns a stale
+                        //   response because an attempt to revalidate the
+                        //   response failed, due to an inability to reach the
+                        //   server.
+                        // (https://tools.ietf.org/html/rfc2616#section-14.46)
+                        cached_res.add_warning(
+                            &req_url,
+                            111,
+                            "Revalidation failed",
+                        );
+                    }
                     cached_res.cache_status(HitOrMiss::HIT);
                     Ok(cached_res)
                 }
             }
+        };
+        if let Some(origin) = cleared {
+            self.purge_origin_later(origin).await;
         }
+        res
     }
 }
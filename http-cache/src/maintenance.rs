@@ -0,0 +1,223 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use futures::{
+    channel::oneshot,
+    future::{self, Either},
+};
+
+use crate::{
+    now, BackgroundTask, CacheError, CacheEvent, CacheManager, EventHook,
+    MaybeSend, Result, Spawner,
+};
+
+/// A [`CacheManager`] holding content it doesn't need anymore, which
+/// [`maintenance`] removes on a schedule.
+pub trait PrunableCacheManager: CacheManager {
+    /// Attempts to remove the content the manager doesn't need anymore.
+    fn prune(&self) -> impl Future<Output = Result<PruneReport>> + MaybeSend;
+}
+
+/// What a prune cycle removed, see [`PrunableCacheManager::prune`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct PruneReport {
+    /// Number of bodies and other content removed
+    pub removed: usize,
+    /// Combined size of the removed content in bytes
+    pub removed_bytes: u64,
+}
+
+/// Waits for the given duration, see [`MaintenanceOptions::timer`].
+pub type Timer = Arc<dyn Fn(Duration) -> BackgroundTask + Send + Sync>;
+
+/// Configures the prune cycles run by [`maintenance`].
+#[derive(Clone)]
+pub struct MaintenanceOptions {
+    /// How long to wait between the end of a cycle and the start of the
+    /// next one
+    pub interval: Duration,
+    /// The longest wait after failed cycles. The wait doubles with each
+    /// cycle failing in a row, starting from twice the interval, and goes
+    /// back to the interval once a cycle succeeds.
+    pub max_backoff: Duration,
+    /// Called with [`CacheEvent::Pruned`] after each cycle that succeeded
+    /// and [`CacheEvent::PruneFailed`] after each one that failed.
+    pub on_event: Option<EventHook>,
+    /// Waits between cycles, a timer of the runtime by default. Mostly
+    /// useful for tests.
+    pub timer: Option<Timer>,
+}
+
+impl fmt::Debug for MaintenanceOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MaintenanceOptions")
+            .field("interval", &self.interval)
+            .field("max_backoff", &self.max_backoff)
+            .field(
+                "on_event",
+                &self.on_event.as_ref().map(|_| "Fn(&CacheEvent)"),
+            )
+            .field("timer", &self.timer.as_ref().map(|_| "Fn(Duration)"))
+            .finish()
+    }
+}
+
+impl MaintenanceOptions {
+    /// Runs a cycle every `interval`, backing off up to 8 times as long
+    /// after failures.
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            max_backoff: interval.saturating_mul(8),
+            on_event: None,
+            timer: None,
+        }
+    }
+}
+
+/// What the cycles run by [`maintenance`] did so far, see
+/// [`MaintenanceHandle::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MaintenanceStats {
+    /// Number of cycles that ran, including failed ones
+    pub cycles: u64,
+    /// Number of cycles that failed
+    pub failures: u64,
+    /// Number of cycles that failed since the last one that didn't
+    pub consecutive_failures: u32,
+    /// Combined number of bodies and other content removed
+    pub removed: u64,
+    /// Combined size of the removed content in bytes
+    pub removed_bytes: u64,
+    /// When the last cycle ended
+    pub last_cycle: Option<SystemTime>,
+}
+
+/// Controls the prune cycles started by [`maintenance`].
+///
+/// Dropping the handle stops the cycles as [`MaintenanceHandle::stop`]
+/// does.
+#[derive(Debug)]
+pub struct MaintenanceHandle {
+    stop: Mutex<Option<oneshot::Sender<()>>>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    stats: Mutex<MaintenanceStats>,
+    finished: AtomicBool,
+}
+
+impl Shared {
+    fn record(&self, event: &CacheEvent) {
+        let mut stats = self.stats.lock().unwrap();
+        stats.cycles += 1;
+        stats.last_cycle = Some(now());
+        match event {
+            CacheEvent::Pruned { report } => {
+                stats.consecutive_failures = 0;
+                stats.removed += report.removed as u64;
+                stats.removed_bytes += report.removed_bytes;
+            }
+            _ => {
+                stats.failures += 1;
+                stats.consecutive_failures += 1;
+            }
+        }
+    }
+}
+
+impl MaintenanceHandle {
+    /// Stops the cycles. A cycle that is running finishes first and no
+    /// other one starts.
+    pub fn stop(&self) {
+        self.stop.lock().unwrap().take();
+    }
+
+    /// Returns true once the cycles stopped and the manager was dropped.
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::Acquire)
+    }
+
+    /// Returns what the cycles did so far.
+    pub fn stats(&self) -> MaintenanceStats {
+        *self.shared.stats.lock().unwrap()
+    }
+}
+
+/// Prunes `manager` on a schedule, in a task handed to `spawner`.
+///
+/// The first cycle starts once the interval is over, and each one after
+/// the previous one ended, so cycles never overlap however long they run.
+/// Failed cycles are retried after a growing wait, see
+/// [`MaintenanceOptions::max_backoff`].
+///
+/// The task runs until the cycles are stopped, so the [`Spawner`] has to
+/// run it in the background, the inline spawner is refused with
+/// [`CacheError::InlineSpawner`].
+pub fn maintenance<M: PrunableCacheManager>(
+    manager: M,
+    options: MaintenanceOptions,
+    spawner: &Spawner,
+) -> Result<MaintenanceHandle> {
+    let (stop, stopped) = oneshot::channel();
+    let shared = Arc::new(Shared::default());
+    let task = run_cycles(manager, options, stopped, shared.clone());
+    if !spawner.spawn_background(task) {
+        return Err(CacheError::InlineSpawner);
+    }
+    Ok(MaintenanceHandle { stop: Mutex::new(Some(stop)), shared })
+}
+
+async fn run_cycles<M: PrunableCacheManager>(
+    manager: M,
+    options: MaintenanceOptions,
+    mut stopped: oneshot::Receiver<()>,
+    shared: Arc<Shared>,
+) {
+    let emit = |event: CacheEvent| {
+        if let Some(on_event) = &options.on_event {
+            on_event(&event);
+        }
+    };
+    let mut wait = options.interval;
+    loop {
+        let sleep = match &options.timer {
+            Some(timer) => timer(wait),
+            None => crate::spawn::boxed(crate::timeout::sleep(wait)),
+        };
+        // The sender is only ever dropped, by `stop` or with the handle
+        if let Either::Right(_) = future::select(sleep, &mut stopped).await {
+            break;
+        }
+        let event = match manager.prune().await {
+            Ok(report) => {
+                wait = options.interval;
+                CacheEvent::Pruned { report }
+            }
+            Err(e) => {
+                wait = wait
+                    .saturating_mul(2)
+                    .min(options.max_backoff.max(options.interval));
+                CacheEvent::PruneFailed { error: e.to_string(), retry_in: wait }
+            }
+        };
+        shared.record(&event);
+        emit(event);
+        if !matches!(stopped.try_recv(), Ok(None)) {
+            break;
+        }
+    }
+    drop(manager);
+    shared.finished.store(true, Ordering::Release);
+}
@@ -1,73 +1,804 @@
-use crate::{CacheManager, HttpResponse, Result};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Poll},
+    time::{Duration, SystemTime},
+};
 
+use crate::{
+    key::is_hashed_key,
+    managers::store::{
+        long_key, Decoded, SerializationFormat, Store, StoreRef,
+    },
+    now, BodyWriter, CacheError, CacheKey, CacheManager, EntryMetadata,
+    EnumerableCacheManager, HealthReport, HttpHeaders, HttpResponse,
+    PrunableCacheManager, PruneReport, Result, StreamingCacheManager,
+};
+
+use bytes::Bytes;
+use cacache::Value;
+use futures::{
+    io::{AsyncRead, AsyncWrite, AsyncWriteExt, Cursor},
+    stream::{self, StreamExt},
+};
 use http_cache_semantics::CachePolicy;
-use serde::{Deserialize, Serialize};
-use url::Url;
+use ssri::Integrity;
+use url::Origin;
 
 /// Implements [`CacheManager`] with [`cacache`](https://github.com/zkat/cacache-rs) as the backend.
+///
+/// Bodies are kept in the content store under their digest, apart from the
+/// rest of their record, so identical bodies stored for different urls take
+/// up the space of a single one, see [`CACacheManager::body_stats`].
+/// Removing a record leaves its body in place as other records may share
+/// it, [`CACacheManager::prune`] removes the bodies no record references.
+///
+/// cacache checks everything it reads against the digest it was stored
+/// under, so no [`BodyChecksum`](crate::BodyChecksum) is stored along with
+/// the records. Records and bodies that fail the check are reported as
+/// [`CacheError::CorruptEntry`]. Bodies streamed by
+/// [`StreamingCacheManager::get_stream`] are read without the check.
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
 #[derive(Debug, Clone)]
 pub struct CACacheManager {
     /// Directory where the cache will be stored.
     pub path: String,
+    /// Format used to serialize newly stored records.
+    pub format: SerializationFormat,
+    /// How long [`CACacheManager::prune`] leaves newly written content
+    /// alone, as a record is written after its body and may not reference
+    /// it yet. Defaults to an hour.
+    pub prune_grace: Duration,
 }
 
 impl Default for CACacheManager {
     fn default() -> Self {
-        Self { path: "./http-cacache".into() }
+        Self {
+            path: "./http-cacache".into(),
+            format: SerializationFormat::default(),
+            prune_grace: PRUNE_GRACE,
+        }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Store {
-    response: HttpResponse,
-    policy: CachePolicy,
-}
+// Pinned keys are stored one per line under this key, which never parses as
+// a request key
+const PINS_KEY: &str = "http-cache:pins";
 
-fn req_key(method: &str, url: &Url) -> String {
-    format!("{}:{}", method, url)
-}
+// Numbers the files written by concurrent health checks
+static HEALTH_PROBES: AtomicUsize = AtomicUsize::new(0);
+
+// How long pruning leaves newly written content alone by default
+const PRUNE_GRACE: Duration = Duration::from_secs(60 * 60);
+
+// How many records `get_many` reads at once
+const GET_MANY_CONCURRENCY: usize = 16;
+
+// Bodies are handed to cacache in chunks of at most this size, as its writer
+// copies each write into a buffer of its own before hashing and writing it
+const WRITE_CHUNK: usize = 64 * 1024;
 
 #[allow(dead_code)]
 impl CACacheManager {
-    /// Clears out the entire cache.
-    pub async fn clear(&self) -> Result<()> {
+    /// Clears out the entire cache. Pinned records are only removed, along
+    /// with their pins, when `include_pinned` is set.
+    pub async fn clear(&self, include_pinned: bool) -> Result<()> {
+        if include_pinned {
+            cacache::clear(&self.path).await?;
+            return Ok(());
+        }
+        let pins = self.pins().await?;
+        let mut kept = Vec::new();
+        for key in &pins {
+            if let Some(metadata) = cacache::metadata(&self.path, key).await? {
+                let bytes = cacache::read(&self.path, key).await?;
+                let body = match body_integrity(&metadata.metadata) {
+                    Some(sri) => {
+                        Some(cacache::read_hash(&self.path, &sri).await?)
+                    }
+                    None => None,
+                };
+                kept.push((key, bytes, body, metadata.metadata));
+            }
+        }
         cacache::clear(&self.path).await?;
+        self.write_pins(&pins).await?;
+        for (key, bytes, body, metadata) in kept {
+            if let Some(body) = body {
+                self.write_body(&body).await?;
+            }
+            self.write_record(key, &bytes, metadata).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes the content no record references anymore, such as the bodies
+    /// of removed records that no other record shares.
+    ///
+    /// Content written within [`CACacheManager::prune_grace`] is kept even
+    /// if nothing references it, so pruning can run while records are being
+    /// stored without removing the body of a record before the record is
+    /// written.
+    pub async fn prune(&self) -> Result<PruneReport> {
+        // Taken before listing the references, so content written since
+        // is inside the grace period
+        let cutoff = now().checked_sub(self.prune_grace);
+        let mut referenced = HashSet::new();
+        for key in self.live_keys().await? {
+            if let Some(entry) = cacache::metadata(&self.path, &key).await? {
+                referenced.insert(content_path(&self.path, &entry.integrity));
+                if let Some(sri) = body_integrity(&entry.metadata) {
+                    referenced.insert(content_path(&self.path, &sri));
+                }
+            }
+        }
+        let mut report = PruneReport::default();
+        for (path, size, modified) in content_files(&self.path)? {
+            let settled = match (cutoff, modified) {
+                (Some(cutoff), Some(modified)) => modified <= cutoff,
+                _ => false,
+            };
+            if settled && !referenced.contains(&path) {
+                fs::remove_file(&path)?;
+                report.removed += 1;
+                report.removed_bytes += size;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Measures how much space sharing bodies between records saves.
+    ///
+    /// Records stored in one piece by earlier versions keep their body
+    /// inline and aren't counted.
+    pub async fn body_stats(&self) -> Result<BodyStats> {
+        let mut stats = BodyStats::default();
+        let mut bodies = HashSet::new();
+        for key in self.live_keys().await? {
+            let sri = match cacache::metadata(&self.path, &key).await? {
+                Some(entry) => match body_integrity(&entry.metadata) {
+                    Some(sri) => sri,
+                    None => continue,
+                },
+                None => continue,
+            };
+            let path = content_path(&self.path, &sri);
+            // Bodies removed from under their record are read as corrupt
+            let size = match fs::metadata(&path) {
+                Ok(metadata) => metadata.len(),
+                Err(_) => continue,
+            };
+            stats.records += 1;
+            stats.referenced_bytes += size;
+            if bodies.insert(path) {
+                stats.bodies += 1;
+                stats.stored_bytes += size;
+            }
+        }
+        Ok(stats)
+    }
+
+    // Reads the record stored under `key`, rewriting it if it was written by
+    // an older version. The body of a streamed record is left empty.
+    async fn read_record(&self, key: &str) -> Result<Option<(Store, Value)>> {
+        let entry = match cacache::metadata(&self.path, key).await {
+            Ok(Some(entry)) => entry,
+            _ => return Ok(None),
+        };
+        let bytes = match cacache::read_hash(&self.path, &entry.integrity).await
+        {
+            Ok(bytes) => bytes,
+            Err(e @ cacache::Error::IntegrityError { .. }) => {
+                return Err(CacheError::CorruptEntry(Box::new(e)))
+            }
+            Err(_e) => return Ok(None),
+        };
+        let store = match Store::deserialize(&Bytes::from(bytes), false)? {
+            Decoded::Current(store) => store,
+            Decoded::Upgraded(store) => {
+                let bytes = store.serialize(self.format, None)?;
+                self.write_record(key, &bytes, entry.metadata.clone()).await?;
+                store
+            }
+            Decoded::UnknownFormat => return Ok(None),
+            Decoded::UnknownVersion => {
+                cacache::remove(&self.path, key).await?;
+                return Ok(None);
+            }
+        };
+        Ok(Some((store, entry.metadata)))
+    }
+
+    async fn write_record(
+        &self,
+        key: &str,
+        bytes: &[u8],
+        metadata: Value,
+    ) -> Result<()> {
+        let mut writer = cacache::WriteOpts::new()
+            .algorithm(cacache::Algorithm::Sha256)
+            .metadata(metadata)
+            .open(&self.path, key)
+            .await?;
+        write_chunked(&mut writer, bytes).await?;
+        writer.commit().await?;
         Ok(())
     }
+
+    // Writes a body to the content store without a key. `cacache::write_hash`
+    // isn't used as the memory mapped writer it picks for small bodies panics.
+    async fn write_body(&self, body: &[u8]) -> Result<Integrity> {
+        let mut writer = cacache::WriteOpts::new()
+            .algorithm(cacache::Algorithm::Sha256)
+            .open_hash(&self.path)
+            .await?;
+        write_chunked(&mut writer, body).await?;
+        Ok(writer.commit().await?)
+    }
+
+    async fn pins(&self) -> Result<HashSet<String>> {
+        match cacache::read(&self.path, PINS_KEY).await {
+            Ok(data) => Ok(String::from_utf8_lossy(&data)
+                .lines()
+                .map(String::from)
+                .collect()),
+            Err(_) => Ok(HashSet::new()),
+        }
+    }
+
+    async fn write_pins(&self, pins: &HashSet<String>) -> Result<()> {
+        let data = pins.iter().map(String::as_str).collect::<Vec<_>>();
+        cacache::write(&self.path, PINS_KEY, data.join("\n")).await?;
+        Ok(())
+    }
+
+    // Lists the keys of every record currently in the cache
+    async fn live_keys(&self) -> Result<Vec<String>> {
+        // The index is append-only, so the same key can be listed more than
+        // once and removed keys may linger until the cache is compacted.
+        let mut seen = HashSet::new();
+        let mut keys = Vec::new();
+        // Content can be written before the first record creates the index
+        if !Path::new(&self.path).join("index-v5").exists() {
+            return Ok(keys);
+        }
+        for metadata in cacache::list_sync(&self.path) {
+            let key = metadata?.key;
+            if !seen.insert(key.clone()) {
+                continue;
+            }
+            if cacache::metadata(&self.path, &key).await?.is_some() {
+                keys.push(key);
+            }
+        }
+        Ok(keys)
+    }
 }
 
-#[async_trait::async_trait]
 impl CacheManager for CACacheManager {
     async fn get(
         &self,
-        method: &str,
-        url: &Url,
+        key: &CacheKey,
     ) -> Result<Option<(HttpResponse, CachePolicy)>> {
-        let store: Store =
-            match cacache::read(&self.path, &req_key(method, url)).await {
-                Ok(d) => bincode::deserialize(&d)?,
-                Err(_e) => {
-                    return Ok(None);
-                }
-            };
+        let (mut store, metadata) = match self.read_record(key.as_str()).await?
+        {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        if let Some(sri) = body_integrity(&metadata) {
+            store.response.body = cacache::read_hash(&self.path, &sri)
+                .await
+                .map_err(|e| CacheError::CorruptEntry(Box::new(e)))?
+                .into();
+        }
         Ok(Some((store.response, store.policy)))
     }
 
     async fn put(
         &self,
-        method: &str,
-        url: &Url,
+        key: &CacheKey,
         response: HttpResponse,
         policy: CachePolicy,
     ) -> Result<HttpResponse> {
-        let data = Store { response: response.clone(), policy };
-        let bytes = bincode::serialize(&data)?;
-        cacache::write(&self.path, &req_key(method, url), bytes).await?;
+        self.put_tagged(key, response, policy, &[]).await
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        Ok(cacache::remove(&self.path, key.as_str()).await?)
+    }
+
+    async fn put_tagged(
+        &self,
+        key: &CacheKey,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<HttpResponse> {
+        // The body goes to the content store on its own, so refreshing the
+        // record after a 304 doesn't have to write it again
+        let sri = self.write_body(&response.body).await?;
+        let body = std::mem::take(&mut response.body);
+        let bytes = StoreRef { response: &response, policy: &policy }
+            .serialize(self.format, None)?;
+        response.body = body;
+        // The tags live in the index entry, so overwriting a record always
+        // replaces them along with it
+        let metadata = record_metadata(tags.to_vec(), &sri, long_key(key));
+        self.write_record(key.as_str(), &bytes, metadata).await?;
         Ok(response)
     }
 
-    async fn delete(&self, method: &str, url: &Url) -> Result<()> {
-        Ok(cacache::remove(&self.path, &req_key(method, url)).await?)
+    async fn purge_tag(&self, tag: &str) -> Result<usize> {
+        let mut purged = 0;
+        for key in self.live_keys().await? {
+            let tagged = match cacache::metadata(&self.path, &key).await? {
+                Some(metadata) => record_tags(&metadata.metadata)
+                    .into_iter()
+                    .flatten()
+                    .any(|t| t == tag),
+                None => false,
+            };
+            if tagged {
+                cacache::remove(&self.path, &key).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn purge_origin(&self, origin: &Origin) -> Result<usize> {
+        let mut purged = 0;
+        for key in self.keys().await? {
+            if &key.url().origin() == origin {
+                self.delete(&key).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn update_policy(
+        &self,
+        key: &CacheKey,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        let (mut store, metadata) = match self.read_record(key.as_str()).await?
+        {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        store.policy = policy;
+        // Writing with the old metadata keeps the tags and body of the record
+        self.write_record(
+            key.as_str(),
+            &store.serialize(self.format, None)?,
+            metadata,
+        )
+        .await?;
+        Ok(true)
+    }
+
+    async fn update(
+        &self,
+        key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        let (mut store, metadata) = match self.read_record(key.as_str()).await?
+        {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        store.response.headers = headers;
+        store.policy = policy;
+        let metadata = match body_integrity(&metadata) {
+            Some(_) => metadata,
+            // Records stored in one piece by earlier versions get their body
+            // moved to the content store once
+            None => {
+                let body = std::mem::take(&mut store.response.body);
+                let sri = self.write_body(&body).await?;
+                let tags = record_tags(&metadata).cloned().unwrap_or_default();
+                record_metadata(tags, &sri, long_key(key))
+            }
+        };
+        self.write_record(
+            key.as_str(),
+            &store.serialize(self.format, None)?,
+            metadata,
+        )
+        .await?;
+        Ok(true)
+    }
+
+    async fn update_metadata(
+        &self,
+        key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> Result<bool> {
+        let (mut store, index) = match self.read_record(key.as_str()).await? {
+            Some(record) => record,
+            None => return Ok(false),
+        };
+        store.response.metadata = metadata;
+        // Writing with the old index metadata keeps the tags and body of the
+        // record
+        self.write_record(
+            key.as_str(),
+            &store.serialize(self.format, None)?,
+            index,
+        )
+        .await?;
+        Ok(true)
+    }
+
+    async fn get_metadata(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpHeaders, CachePolicy, EntryMetadata)>> {
+        Ok(self.read_record(key.as_str()).await?.map(|(store, _)| {
+            (store.response.headers, store.policy, store.response.metadata)
+        }))
+    }
+
+    // Each record lives in a file of its own, so several are read at once
+    // instead of waiting on the disk for every one in turn
+    async fn get_many(
+        &self,
+        keys: &[CacheKey],
+    ) -> Vec<Result<Option<(HttpResponse, CachePolicy)>>> {
+        let reads = keys.iter().map(|key| self.get(key)).collect::<Vec<_>>();
+        stream::iter(reads).buffered(GET_MANY_CONCURRENCY).collect().await
+    }
+
+    // Writes and removes a file in the cache directory, which tells whether
+    // it can be written to without going through the index
+    async fn health_check(&self) -> Result<HealthReport> {
+        let started = now();
+        fs::create_dir_all(&self.path)?;
+        let probe = Path::new(&self.path).join(format!(
+            ".health-check-{}-{}",
+            std::process::id(),
+            HEALTH_PROBES.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&probe, b"ok")?;
+        fs::remove_file(&probe)?;
+        let latency = now().duration_since(started).unwrap_or_default();
+        Ok(HealthReport { latency })
+    }
+
+    // cacache writes records and bodies to their files before returning but
+    // leaves them to the operating system, this makes it write them to disk
+    async fn flush(&self) -> Result<()> {
+        sync_files(&self.path)
+    }
+
+    // Nothing is ever evicted from the disk cache, so pins only need to
+    // survive `clear`
+    async fn pin(&self, key: &CacheKey) -> Result<()> {
+        let mut pins = self.pins().await?;
+        if pins.insert(key.as_str().to_string()) {
+            self.write_pins(&pins).await?;
+        }
+        Ok(())
+    }
+
+    async fn unpin(&self, key: &CacheKey) -> Result<()> {
+        let mut pins = self.pins().await?;
+        if pins.remove(key.as_str()) {
+            self.write_pins(&pins).await?;
+        }
+        Ok(())
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl EnumerableCacheManager for CACacheManager {
+    async fn keys(&self) -> Result<Vec<CacheKey>> {
+        let mut keys = Vec::new();
+        for key in self.live_keys().await? {
+            // Records stored under a digest are listed under their full key
+            let key = if is_hashed_key(&key) {
+                let metadata = cacache::metadata(&self.path, &key).await?;
+                match metadata.as_ref().and_then(|m| m.metadata.get("key")) {
+                    Some(Value::String(key)) => key.clone(),
+                    _ => continue,
+                }
+            } else {
+                key
+            };
+            keys.extend(CacheKey::parse(&key));
+        }
+        Ok(keys)
+    }
+    async fn tags(&self, key: &CacheKey) -> Result<Vec<String>> {
+        let metadata = match cacache::metadata(&self.path, key.as_str()).await?
+        {
+            Some(metadata) => metadata.metadata,
+            None => return Ok(Vec::new()),
+        };
+        Ok(record_tags(&metadata)
+            .into_iter()
+            .flatten()
+            .filter_map(|tag| tag.as_str().map(String::from))
+            .collect())
+    }
+}
+
+impl PrunableCacheManager for CACacheManager {
+    async fn prune(&self) -> Result<PruneReport> {
+        CACacheManager::prune(self).await
+    }
+}
+
+impl StreamingCacheManager for CACacheManager {
+    type Reader = CACacheReader;
+    type Writer = CACacheWriter;
+
+    async fn get_stream(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy, CACacheReader)>> {
+        let (mut store, metadata) = match self.read_record(key.as_str()).await?
+        {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+        let reader = match body_integrity(&metadata) {
+            Some(sri) => CACacheReader::Content(
+                cacache::Reader::open_hash(&self.path, sri)
+                    .await
+                    .map_err(|e| CacheError::CorruptEntry(Box::new(e)))?,
+            ),
+            // Records stored in one piece carry their body inline
+            None => CACacheReader::Inline(Cursor::new(std::mem::take(
+                &mut store.response.body,
+            ))),
+        };
+        Ok(Some((store.response, store.policy, reader)))
+    }
+
+    async fn put_stream(
+        &self,
+        key: &CacheKey,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<CACacheWriter> {
+        response.body.clear();
+        let record = Store { response, policy }.serialize(self.format, None)?;
+        // Written without a key, the body only lands in the content store
+        // once committed and is only referenced once the record is written
+        let content = cacache::WriteOpts::new()
+            .algorithm(cacache::Algorithm::Sha256)
+            .open_hash(&self.path)
+            .await?;
+        Ok(CACacheWriter {
+            manager: self.clone(),
+            key: key.as_str().to_string(),
+            long_key: long_key(key),
+            record,
+            tags: tags.to_vec(),
+            content,
+        })
+    }
+}
+
+/// How the records of a [`CACacheManager`] share their bodies, as returned
+/// by [`CACacheManager::body_stats`].
+#[cfg_attr(docsrs, doc(cfg(feature = "manager-cacache")))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct BodyStats {
+    /// Number of records with a body in the content store
+    pub records: usize,
+    /// Number of distinct bodies they reference
+    pub bodies: usize,
+    /// Combined size of the body of every record, counting shared bodies
+    /// once per record
+    pub referenced_bytes: u64,
+    /// Combined size of the distinct bodies, as stored
+    pub stored_bytes: u64,
+}
+
+impl BodyStats {
+    /// Returns how many bytes sharing bodies saves.
+    pub fn saved_bytes(&self) -> u64 {
+        self.referenced_bytes - self.stored_bytes
+    }
+}
+
+/// Reads a response body from a [`CACacheManager`].
+pub enum CACacheReader {
+    /// Body stored separately in the content store
+    Content(cacache::Reader),
+    /// Body stored along with the rest of the record
+    Inline(Cursor<Bytes>),
+}
+
+impl fmt::Debug for CACacheReader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Content(_) => f.write_str("CACacheReader::Content"),
+            Self::Inline(_) => f.write_str("CACacheReader::Inline"),
+        }
+    }
+}
+
+impl AsyncRead for CACacheReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Self::Content(reader) => Pin::new(reader).poll_read(cx, buf),
+            Self::Inline(reader) => Pin::new(reader).poll_read(cx, buf),
+        }
+    }
+}
+
+/// Writes a response body into a [`CACacheManager`], see
+/// [`StreamingCacheManager::put_stream`].
+pub struct CACacheWriter {
+    manager: CACacheManager,
+    key: String,
+    long_key: Option<String>,
+    record: Vec<u8>,
+    tags: Vec<String>,
+    content: cacache::Writer,
+}
+
+impl fmt::Debug for CACacheWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CACacheWriter")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncWrite for CACacheWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let buf = &buf[..buf.len().min(WRITE_CHUNK)];
+        Pin::new(&mut self.get_mut().content).poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().content).poll_flush(cx)
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.get_mut().content).poll_close(cx)
+    }
+}
+
+impl BodyWriter for CACacheWriter {
+    async fn commit(self) -> Result<()> {
+        let sri = self.content.commit().await?;
+        let metadata = record_metadata(self.tags, &sri, self.long_key);
+        self.manager.write_record(&self.key, &self.record, metadata).await
+    }
+}
+
+async fn write_chunked(
+    writer: &mut cacache::Writer,
+    bytes: &[u8],
+) -> Result<()> {
+    for chunk in bytes.chunks(WRITE_CHUNK) {
+        writer.write_all(chunk).await?;
+    }
+    Ok(())
+}
+
+// Records stored in one piece by earlier versions only keep their tags in
+// the index metadata, others keep them next to the integrity of their body
+// Records stored under a digest of their key keep the full key, so they can
+// still be listed
+fn record_metadata(
+    tags: Vec<impl Into<Value>>,
+    body: &Integrity,
+    long_key: Option<String>,
+) -> Value {
+    let mut metadata = Value::from_iter([
+        ("tags", Value::from(tags)),
+        ("body", Value::from(body.to_string())),
+    ]);
+    if let (Some(key), Some(map)) = (long_key, metadata.as_object_mut()) {
+        map.insert("key".into(), Value::from(key));
+    }
+    metadata
+}
+
+fn record_tags(metadata: &Value) -> Option<&Vec<Value>> {
+    match metadata.get("tags") {
+        Some(tags) => tags.as_array(),
+        None => metadata.as_array(),
+    }
+}
+
+fn body_integrity(metadata: &Value) -> Option<Integrity> {
+    metadata.get("body")?.as_str()?.parse().ok()
+}
+
+// Where cacache keeps the content with the given integrity, laid out as
+// `content-v2/{algorithm}/{hex[..2]}/{hex[2..4]}/{hex[4..]}`
+fn content_path(cache: &str, sri: &Integrity) -> PathBuf {
+    let (algorithm, hex) = sri.to_hex();
+    [
+        cache,
+        "content-v2",
+        &algorithm.to_string(),
+        &hex[0..2],
+        &hex[2..4],
+        &hex[4..],
+    ]
+    .iter()
+    .collect()
+}
+
+// Writes every file of the cache, and on unix every directory, to disk
+fn sync_files(cache: &str) -> Result<()> {
+    let mut dirs = vec![PathBuf::from(cache)];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                fs::File::open(entry.path())?.sync_all()?;
+            }
+        }
+        // Makes the entries of the directory, such as renamed files, last
+        #[cfg(unix)]
+        fs::File::open(&dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+// Lists the path, size and modification time, where the filesystem records
+// it, of every file in the content store
+fn content_files(
+    cache: &str,
+) -> Result<Vec<(PathBuf, u64, Option<SystemTime>)>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![Path::new(cache).join("content-v2")];
+    while let Some(dir) = dirs.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                let modified = metadata.modified().ok();
+                files.push((entry.path(), metadata.len(), modified));
+            }
+        }
     }
+    Ok(files)
 }
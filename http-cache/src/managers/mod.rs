@@ -3,3 +3,5 @@ pub mod cacache;
 
 #[cfg(feature = "manager-moka")]
 pub mod moka;
+
+pub(crate) mod store;
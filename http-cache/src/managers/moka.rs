@@ -1,18 +1,138 @@
-use crate::{CacheManager, HttpResponse, Result};
+use crate::{
+    managers::store::{
+        long_key, BodyChecksum, Decoded, SerializationFormat, Store, StoreRef,
+    },
+    BodyWriter, CacheError, CacheKey, CacheManager, EntryMetadata,
+    EnumerableCacheManager, HttpHeaders, HttpResponse, Result,
+    StreamingCacheManager,
+};
 
-use std::{fmt, sync::Arc};
+use std::{
+    collections::{
+        hash_map::{DefaultHasher, Entry},
+        HashMap, HashSet,
+    },
+    fmt,
+    hash::{Hash, Hasher},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    task::{Context, Poll},
+};
 
+use bytes::Bytes;
+use futures::{
+    io::{AsyncWrite, Cursor},
+    lock::{Mutex as AsyncMutex, MutexGuard as AsyncMutexGuard},
+};
 use http_cache_semantics::CachePolicy;
 use moka::future::{Cache, ConcurrentCacheExt};
-use serde::{Deserialize, Serialize};
-use url::Url;
+use url::Origin;
+
+type Record = Bytes;
+
+// The number of locks the writes are spread over by default
+const WRITE_LOCKS: usize = 64;
+
+// Starts the records stored under a digest of a key too long to be used as
+// is, followed by the length of the full key as a little endian `u64` and
+// the full key, ahead of the record itself
+const LONG_KEY_PREFIX: &[u8] = b"httpkey\xff";
+
+// The number of tagged records the tag index holds before it is first swept
+// of the records moka evicted
+const TAG_SWEEP_MIN: usize = 64;
 
 /// Implements [`CacheManager`] with [`moka`](https://github.com/moka-rs/moka) as the backend.
+///
+/// The writes to each record are serialized behind one of a fixed set of
+/// locks, 64 unless set with [`MokaManager::with_write_locks`], so updating
+/// a record can't bring back a version another task replaced or removed
+/// meanwhile. Clones share the same records and locks.
 #[cfg_attr(docsrs, doc(cfg(feature = "manager-moka")))]
 #[derive(Clone)]
 pub struct MokaManager {
     /// The instance of `moka::future::Cache`
-    pub cache: Cache<String, Arc<Vec<u8>>>,
+    pub cache: Cache<String, Record>,
+    /// Format used to serialize newly stored records.
+    pub format: SerializationFormat,
+    /// Checksum stored along with the body of newly stored records and
+    /// checked when records are read back, `None` skips both. Defaults to
+    /// [`BodyChecksum::Crc32`].
+    pub checksum: Option<BodyChecksum>,
+    // Maps each cache tag to the keys of the records carrying it, and back
+    tags: Arc<Mutex<TagIndex>>,
+    // Pinned keys along with their records, which are kept out of `cache`
+    // so they can't be evicted. The record is `None` until it is stored.
+    pinned: Arc<Mutex<HashMap<String, Option<Record>>>>,
+    // Whether any key is pinned, so the lookups can skip locking `pinned`
+    // while nothing is. Only changed with `pinned` locked.
+    any_pinned: Arc<AtomicBool>,
+    // Serializes the writes to a record, each key mapping to one of the
+    // locks. Holds at least one lock.
+    write_locks: Arc<Vec<AsyncMutex<()>>>,
+}
+
+// The tags of the records, indexed both ways so retagging a record only goes
+// over its own tags. Moka evicts records without telling, so the index is
+// swept of them as it grows.
+#[derive(Debug)]
+struct TagIndex {
+    // The keys of the records carrying each tag
+    keys: HashMap<String, HashSet<String>>,
+    // The tags of each record
+    tags: HashMap<String, Vec<String>>,
+    // How many records can be tagged before the next sweep
+    sweep_at: usize,
+}
+
+impl Default for TagIndex {
+    fn default() -> Self {
+        Self {
+            keys: HashMap::new(),
+            tags: HashMap::new(),
+            sweep_at: TAG_SWEEP_MIN,
+        }
+    }
+}
+
+impl TagIndex {
+    // Replaces the tags recorded for `key`, an empty set just forgets them
+    fn retag(&mut self, key: &str, tags: &[String]) {
+        for tag in self.tags.remove(key).unwrap_or_default() {
+            if let Entry::Occupied(mut keys) = self.keys.entry(tag) {
+                keys.get_mut().remove(key);
+                if keys.get().is_empty() {
+                    keys.remove();
+                }
+            }
+        }
+        if tags.is_empty() {
+            return;
+        }
+        for tag in tags {
+            self.keys.entry(tag.clone()).or_default().insert(key.to_string());
+        }
+        self.tags.insert(key.to_string(), tags.to_vec());
+    }
+
+    // Forgets the records that aren't `live` anymore. The next sweep waits
+    // for the index to double, so sweeping costs a constant time per record
+    // tagged.
+    fn sweep(&mut self, live: impl Fn(&String) -> bool) {
+        let gone = self
+            .tags
+            .keys()
+            .filter(|key| !live(key))
+            .cloned()
+            .collect::<Vec<_>>();
+        for key in gone {
+            self.retag(&key, &[]);
+        }
+        self.sweep_at = (self.tags.len() * 2).max(TAG_SWEEP_MIN);
+    }
 }
 
 impl fmt::Debug for MokaManager {
@@ -24,38 +144,205 @@ impl fmt::Debug for MokaManager {
 
 impl Default for MokaManager {
     fn default() -> Self {
-        Self { cache: Cache::new(42) }
+        Self::new(Cache::new(42))
     }
 }
 
-#[derive(Debug, Deserialize, Serialize)]
-struct Store {
-    response: HttpResponse,
-    policy: CachePolicy,
-}
+impl MokaManager {
+    /// Creates a manager keeping its records in `cache`.
+    pub fn new(cache: Cache<String, Record>) -> Self {
+        Self::with_write_locks(cache, WRITE_LOCKS)
+    }
 
-fn req_key(method: &str, url: &Url) -> String {
-    format!("{}:{}", method, url)
-}
+    /// Creates a manager keeping its records in `cache`, with the writes
+    /// spread over `write_locks` locks, at least one. More locks let more
+    /// writes to different records run at once.
+    pub fn with_write_locks(
+        cache: Cache<String, Record>,
+        write_locks: usize,
+    ) -> Self {
+        Self {
+            cache,
+            format: SerializationFormat::default(),
+            checksum: Some(BodyChecksum::default()),
+            tags: Default::default(),
+            pinned: Default::default(),
+            any_pinned: Default::default(),
+            write_locks: Arc::new(
+                (0..write_locks.max(1)).map(|_| AsyncMutex::new(())).collect(),
+            ),
+        }
+    }
 
-impl MokaManager {
-    /// Clears out the entire cache.
-    pub async fn clear(&self) -> Result<()> {
+    /// Clears out the entire cache. Pinned records are only removed, along
+    /// with their pins, when `include_pinned` is set.
+    pub async fn clear(&self, include_pinned: bool) -> Result<()> {
         self.cache.invalidate_all();
         self.cache.sync();
+        let mut pinned = self.pinned.lock().unwrap();
+        if include_pinned {
+            pinned.clear();
+            self.any_pinned.store(false, Ordering::Release);
+        }
+        self.tags
+            .lock()
+            .unwrap()
+            .sweep(|key| matches!(pinned.get(key), Some(Some(_))));
         Ok(())
     }
+
+    // Replaces the tags recorded for `key`, an empty set just forgets them
+    fn retag(&self, key: &str, tags: &[String]) {
+        let mut index = self.tags.lock().unwrap();
+        index.retag(key, tags);
+        if index.tags.len() >= index.sweep_at {
+            drop(index);
+            self.sweep_tags();
+        }
+    }
+
+    // Forgets the tags of the records moka evicted
+    fn sweep_tags(&self) {
+        let pinned = self.pinned.lock().unwrap();
+        self.tags.lock().unwrap().sweep(|key| {
+            self.cache.contains_key(key)
+                || matches!(pinned.get(key), Some(Some(_)))
+        });
+    }
+
+    // Locks out the other writes to the record, which the helpers below
+    // don't do themselves so they can be combined under a single lock
+    async fn lock(&self, key: &str) -> AsyncMutexGuard<'_, ()> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let index = hasher.finish() as usize % self.write_locks.len();
+        self.write_locks[index].lock().await
+    }
+
+    // The pinned records, `None` without taking the lock while nothing is
+    // pinned. Pins only change under the write lock of their record, so a
+    // write holding it can't miss one.
+    fn pins(&self) -> Option<MutexGuard<'_, HashMap<String, Option<Record>>>> {
+        if !self.any_pinned.load(Ordering::Acquire) {
+            return None;
+        }
+        Some(self.pinned.lock().unwrap())
+    }
+
+    // Takes the key as a `String` as that's what moka looks records up by,
+    // which saves copying it on every lookup
+    fn load(&self, key: &String) -> Option<Record> {
+        if let Some(record) = self.pins().as_ref().and_then(|p| p.get(key)) {
+            return record.as_ref().map(|record| split_long_key(record).1);
+        }
+        self.cache.get(key).map(|record| split_long_key(&record).1)
+    }
+
+    async fn store(&self, key: &CacheKey, bytes: Vec<u8>) {
+        let record = with_long_key(key, bytes);
+        let key = key.as_str().to_string();
+        if let Some(pinned) = self.pins().as_mut().and_then(|p| p.get_mut(&key))
+        {
+            *pinned = Some(record);
+            return;
+        }
+        self.cache.insert(key, record).await;
+        self.cache.sync();
+    }
+
+    async fn remove(&self, key: &str) {
+        if let Some(record) = self.pins().as_mut().and_then(|p| p.get_mut(key))
+        {
+            *record = None;
+        }
+        self.cache.invalidate(&key.to_string()).await;
+        self.cache.sync();
+        self.retag(key, &[]);
+    }
+
+    // Reads a record back to rewrite it. Records that don't match their
+    // checksum are removed, rewriting them would give the damaged body a
+    // checksum of its own.
+    async fn load_store(&self, key: &String) -> Result<Option<Store>> {
+        let bytes = match self.load(key) {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        match Store::deserialize(&bytes, self.checksum.is_some()) {
+            Ok(Decoded::Current(store) | Decoded::Upgraded(store)) => {
+                Ok(Some(store))
+            }
+            Ok(Decoded::UnknownFormat | Decoded::UnknownVersion) => Ok(None),
+            Err(CacheError::CorruptEntry(_)) => {
+                self.remove(key).await;
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// Prefixes the record of a key stored under its digest with the full key,
+// which it is listed by and goes along with when moka evicts the record
+fn with_long_key(key: &CacheKey, bytes: Vec<u8>) -> Record {
+    let long_key = match long_key(key) {
+        Some(long_key) => long_key,
+        None => return bytes.into(),
+    };
+    let mut record = Vec::with_capacity(
+        LONG_KEY_PREFIX.len() + 8 + long_key.len() + bytes.len(),
+    );
+    record.extend_from_slice(LONG_KEY_PREFIX);
+    record.extend_from_slice(&(long_key.len() as u64).to_le_bytes());
+    record.extend_from_slice(long_key.as_bytes());
+    record.extend_from_slice(&bytes);
+    record.into()
 }
 
-#[async_trait::async_trait]
-impl CacheManager for Arc<MokaManager> {
+// Splits the full key off a record stored under a digest
+fn split_long_key(record: &Record) -> (Option<&str>, Record) {
+    let rest = match record.strip_prefix(LONG_KEY_PREFIX) {
+        Some(rest) if rest.len() >= 8 => rest,
+        _ => return (None, record.clone()),
+    };
+    let (len, rest) = rest.split_at(8);
+    let len = u64::from_le_bytes(len.try_into().unwrap());
+    match usize::try_from(len).ok().filter(|len| *len <= rest.len()) {
+        Some(len) => {
+            let start = record.len() - rest.len() + len;
+            (std::str::from_utf8(&rest[..len]).ok(), record.slice(start..))
+        }
+        None => (None, record.clone()),
+    }
+}
+
+impl CacheManager for MokaManager {
     async fn get(
         &self,
-        method: &str,
-        url: &Url,
+        cache_key: &CacheKey,
     ) -> Result<Option<(HttpResponse, CachePolicy)>> {
-        let store: Store = match self.cache.get(&req_key(method, url)) {
-            Some(d) => bincode::deserialize(&d)?,
+        let key = cache_key.as_str().to_string();
+        let store = match self.load(&key) {
+            Some(d) => match Store::deserialize(&d, self.checksum.is_some())? {
+                Decoded::Current(store) => store,
+                Decoded::Upgraded(store) => {
+                    let bytes = store.serialize(self.format, self.checksum)?;
+                    let _lock = self.lock(&key).await;
+                    // Unless it was replaced or removed since
+                    if self.load(&key).as_ref() == Some(&d) {
+                        self.store(cache_key, bytes).await;
+                    }
+                    store
+                }
+                Decoded::UnknownFormat => return Ok(None),
+                Decoded::UnknownVersion => {
+                    let _lock = self.lock(&key).await;
+                    if self.load(&key).as_ref() == Some(&d) {
+                        self.remove(&key).await;
+                    }
+                    return Ok(None);
+                }
+            },
             None => return Ok(None),
         };
         Ok(Some((store.response, store.policy)))
@@ -63,21 +350,279 @@ impl CacheManager for Arc<MokaManager> {
 
     async fn put(
         &self,
-        method: &str,
-        url: &Url,
+        key: &CacheKey,
         response: HttpResponse,
         policy: CachePolicy,
     ) -> Result<HttpResponse> {
-        let data = Store { response: response.clone(), policy };
-        let bytes = bincode::serialize(&data)?;
-        self.cache.insert(req_key(method, url), Arc::new(bytes)).await;
-        self.cache.sync();
+        self.put_tagged(key, response, policy, &[]).await
+    }
+
+    async fn delete(&self, key: &CacheKey) -> Result<()> {
+        let _lock = self.lock(key.as_str()).await;
+        self.remove(key.as_str()).await;
+        Ok(())
+    }
+
+    async fn put_tagged(
+        &self,
+        key: &CacheKey,
+        response: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<HttpResponse> {
+        let bytes = StoreRef { response: &response, policy: &policy }
+            .serialize(self.format, self.checksum)?;
+        let _lock = self.lock(key.as_str()).await;
+        self.store(key, bytes).await;
+        self.retag(key.as_str(), tags);
         Ok(response)
     }
 
-    async fn delete(&self, method: &str, url: &Url) -> Result<()> {
-        self.cache.invalidate(&req_key(method, url)).await;
+    async fn purge_tag(&self, tag: &str) -> Result<usize> {
+        let keys =
+            self.tags.lock().unwrap().keys.remove(tag).unwrap_or_default();
+        let mut purged = 0;
+        for key in keys {
+            let _lock = self.lock(&key).await;
+            // Records evicted by moka can still be listed under the tag
+            if self.load(&key).is_some() {
+                purged += 1;
+            }
+            self.remove(&key).await;
+        }
+        Ok(purged)
+    }
+
+    async fn purge_origin(&self, origin: &Origin) -> Result<usize> {
+        let mut purged = 0;
+        for key in self.keys().await? {
+            if &key.url().origin() == origin {
+                self.delete(&key).await?;
+                purged += 1;
+            }
+        }
+        Ok(purged)
+    }
+
+    async fn update_policy(
+        &self,
+        cache_key: &CacheKey,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        let key = cache_key.as_str().to_string();
+        let _lock = self.lock(&key).await;
+        let mut store = match self.load_store(&key).await? {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        store.policy = policy;
+        // Storing directly keeps the tags of the record
+        self.store(cache_key, store.serialize(self.format, self.checksum)?)
+            .await;
+        Ok(true)
+    }
+
+    // The body is serialized along with the rest of the record, so it is
+    // copied over, but the record is stored without going through the tags
+    async fn update(
+        &self,
+        cache_key: &CacheKey,
+        headers: HttpHeaders,
+        policy: CachePolicy,
+    ) -> Result<bool> {
+        let key = cache_key.as_str().to_string();
+        let _lock = self.lock(&key).await;
+        let mut store = match self.load_store(&key).await? {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        store.response.headers = headers;
+        store.policy = policy;
+        self.store(cache_key, store.serialize(self.format, self.checksum)?)
+            .await;
+        Ok(true)
+    }
+
+    async fn update_metadata(
+        &self,
+        cache_key: &CacheKey,
+        metadata: EntryMetadata,
+    ) -> Result<bool> {
+        let key = cache_key.as_str().to_string();
+        let _lock = self.lock(&key).await;
+        let mut store = match self.load_store(&key).await? {
+            Some(store) => store,
+            None => return Ok(false),
+        };
+        store.response.metadata = metadata;
+        self.store(cache_key, store.serialize(self.format, self.checksum)?)
+            .await;
+        Ok(true)
+    }
+
+    async fn pin(&self, key: &CacheKey) -> Result<()> {
+        let key = key.as_str().to_string();
+        let _lock = self.lock(&key).await;
+        let record = self.cache.get(&key);
+        {
+            let mut pinned = self.pinned.lock().unwrap();
+            pinned.entry(key.clone()).or_insert(record);
+            self.any_pinned.store(true, Ordering::Release);
+        }
+        self.cache.invalidate(&key).await;
         self.cache.sync();
         Ok(())
     }
+
+    async fn unpin(&self, key: &CacheKey) -> Result<()> {
+        let key = key.as_str().to_string();
+        let _lock = self.lock(&key).await;
+        let record = {
+            let mut pinned = self.pinned.lock().unwrap();
+            let record = pinned.remove(&key);
+            self.any_pinned.store(!pinned.is_empty(), Ordering::Release);
+            record
+        };
+        if let Some(Some(bytes)) = record {
+            self.cache.insert(key, bytes).await;
+            self.cache.sync();
+        }
+        Ok(())
+    }
+
+    // Records only ever live in memory, this settles the evictions moka
+    // still has pending and forgets the tags of the evicted records
+    async fn flush(&self) -> Result<()> {
+        self.cache.sync();
+        self.sweep_tags();
+        Ok(())
+    }
+
+    fn background_handle(&self) -> Option<Self> {
+        Some(self.clone())
+    }
+}
+
+impl EnumerableCacheManager for MokaManager {
+    async fn keys(&self) -> Result<Vec<CacheKey>> {
+        let pinned = self
+            .pinned
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(key, record)| Some((key.clone(), record.clone()?)))
+            .collect::<Vec<_>>();
+        Ok(pinned
+            .into_iter()
+            .chain(
+                self.cache
+                    .iter()
+                    .map(|(key, record)| (key.to_string(), record)),
+            )
+            .filter_map(|(key, record)| match split_long_key(&record).0 {
+                Some(long_key) => CacheKey::parse(long_key),
+                None => CacheKey::parse(&key),
+            })
+            .collect())
+    }
+    async fn tags(&self, key: &CacheKey) -> Result<Vec<String>> {
+        let mut tags = self
+            .tags
+            .lock()
+            .unwrap()
+            .tags
+            .get(key.as_str())
+            .cloned()
+            .unwrap_or_default();
+        tags.sort();
+        Ok(tags)
+    }
+}
+
+impl StreamingCacheManager for MokaManager {
+    type Reader = Cursor<Bytes>;
+    type Writer = MokaWriter;
+
+    async fn get_stream(
+        &self,
+        key: &CacheKey,
+    ) -> Result<Option<(HttpResponse, CachePolicy, Cursor<Bytes>)>> {
+        Ok(self.get(key).await?.map(|(mut response, policy)| {
+            let body = std::mem::take(&mut response.body);
+            (response, policy, Cursor::new(body))
+        }))
+    }
+
+    async fn put_stream(
+        &self,
+        key: &CacheKey,
+        mut response: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> Result<MokaWriter> {
+        response.body.clear();
+        Ok(MokaWriter {
+            manager: self.clone(),
+            key: key.clone(),
+            response,
+            policy,
+            tags: tags.to_vec(),
+            body: Vec::new(),
+        })
+    }
+}
+
+/// Collects a response body for a [`MokaManager`], see
+/// [`StreamingCacheManager::put_stream`]. Records are kept in memory, so the
+/// body is too until the record is stored on commit.
+pub struct MokaWriter {
+    manager: MokaManager,
+    key: CacheKey,
+    response: HttpResponse,
+    policy: CachePolicy,
+    tags: Vec<String>,
+    body: Vec<u8>,
+}
+
+impl fmt::Debug for MokaWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MokaWriter")
+            .field("key", &self.key)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncWrite for MokaWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.get_mut().body.extend_from_slice(buf);
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl BodyWriter for MokaWriter {
+    async fn commit(mut self) -> Result<()> {
+        self.response.body = self.body.into();
+        self.manager
+            .put_tagged(&self.key, self.response, self.policy, &self.tags)
+            .await?;
+        Ok(())
+    }
 }
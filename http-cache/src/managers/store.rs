@@ -0,0 +1,494 @@
+use bytes::Bytes;
+use http_cache_semantics::CachePolicy;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::{
+    deserialize_body, CacheError, EntryMetadata, HttpHeaders, HttpResponse,
+    HttpVersion, Result, CACHE_RECORD_VERSION,
+};
+
+// Prefix marking a record that starts with a header holding the record
+// version and format. Untagged records written by earlier versions start
+// with the bincode encoded body length, which can never have its most
+// significant byte set, so the two can't be confused.
+const TAG_PREFIX: &[u8; 8] = b"httpcac\xff";
+
+/// The format used by the bundled managers to serialize stored records.
+///
+/// Each record is tagged with the format it was written in, along with
+/// the [`CACHE_RECORD_VERSION`](crate::CACHE_RECORD_VERSION) of its layout,
+/// so changing the
+/// format of an existing cache keeps older records readable. Records written
+/// in a format that isn't enabled are treated as cache misses.
+///
+/// Only the headers, policy and other metadata of a record go through the
+/// format, bodies are stored as raw bytes after them whatever the format.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SerializationFormat {
+    /// Compact binary encoding using [bincode](https://github.com/bincode-org/bincode),
+    /// the default and the most compact
+    #[default]
+    Bincode,
+    /// [JSON](https://github.com/serde-rs/json), human readable but larger
+    #[cfg(feature = "format-json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "format-json")))]
+    Json,
+    /// [CBOR](https://github.com/enarx/ciborium)
+    #[cfg(feature = "format-cbor")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "format-cbor")))]
+    Cbor,
+}
+
+impl SerializationFormat {
+    const fn tag(self) -> u8 {
+        match self {
+            Self::Bincode => 1,
+            #[cfg(feature = "format-json")]
+            Self::Json => 2,
+            #[cfg(feature = "format-cbor")]
+            Self::Cbor => 3,
+        }
+    }
+
+    const fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            1 => Some(Self::Bincode),
+            #[cfg(feature = "format-json")]
+            2 => Some(Self::Json),
+            #[cfg(feature = "format-cbor")]
+            3 => Some(Self::Cbor),
+            _ => None,
+        }
+    }
+}
+
+/// The checksum the bundled managers store along with the body of a record
+/// and check when reading it back, so a body damaged or truncated on the way
+/// to the backend is never served.
+///
+/// A record whose body doesn't match its checksum is reported as
+/// [`CacheError::CorruptEntry`], which has [`HttpCache`](crate::HttpCache)
+/// remove it and fetch the response again.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BodyChecksum {
+    /// CRC-32, the default and cheap enough for large bodies, which catches
+    /// accidental damage but not deliberate tampering
+    #[default]
+    Crc32,
+    /// SHA-256, slower but collision resistant
+    Sha256,
+}
+
+impl BodyChecksum {
+    const fn tag(self) -> u8 {
+        match self {
+            Self::Crc32 => 1,
+            Self::Sha256 => 2,
+        }
+    }
+
+    // The checksum picked by a tag along with its length, `None` for no
+    // checksum
+    fn from_tag(tag: u8) -> Result<Option<(Self, usize)>> {
+        match tag {
+            0 => Ok(None),
+            1 => Ok(Some((Self::Crc32, 4))),
+            2 => Ok(Some((Self::Sha256, 32))),
+            _ => Err(corrupt(checksum_mismatch())),
+        }
+    }
+
+    fn compute(self, body: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Crc32 => crc32fast::hash(body).to_le_bytes().to_vec(),
+            Self::Sha256 => Sha256::digest(body).to_vec(),
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+pub(crate) struct Store {
+    pub(crate) response: HttpResponse,
+    pub(crate) policy: CachePolicy,
+}
+
+// A record borrowing its parts, its metadata is serialized the same way as a
+// [`Store`] holding a response without a body, and its body is appended as
+// is so it neither gets copied nor inflated by the structured formats
+pub(crate) struct StoreRef<'a> {
+    pub(crate) response: &'a HttpResponse,
+    pub(crate) policy: &'a CachePolicy,
+}
+
+// The metadata of a record, a response with an empty body and its policy
+#[derive(Serialize)]
+struct MetadataRef<'a> {
+    response: ResponseRef<'a>,
+    policy: &'a CachePolicy,
+}
+
+// Mirrors the fields of an [`HttpResponse`], in the same order
+#[derive(Serialize)]
+struct ResponseRef<'a> {
+    body: &'a [u8],
+    headers: &'a HttpHeaders,
+    trailers: &'a Option<HttpHeaders>,
+    status: u16,
+    reason: &'a Option<String>,
+    url: &'a Url,
+    version: HttpVersion,
+    metadata: &'a EntryMetadata,
+}
+
+impl StoreRef<'_> {
+    // Records from version 2 on are laid out as the tag header, the length of
+    // the metadata as a little endian `u64`, the metadata and then the body.
+    // From version 5 on, the tag header is followed by the tag of the body
+    // checksum, zero for none, and the checksum itself.
+    pub(crate) fn serialize(
+        &self,
+        format: SerializationFormat,
+        checksum: Option<BodyChecksum>,
+    ) -> Result<Vec<u8>> {
+        let metadata = encode(
+            format,
+            &MetadataRef {
+                response: ResponseRef {
+                    body: &[],
+                    headers: &self.response.headers,
+                    trailers: &self.response.trailers,
+                    status: self.response.status,
+                    reason: &self.response.reason,
+                    url: &self.response.url,
+                    version: self.response.version,
+                    metadata: &self.response.metadata,
+                },
+                policy: self.policy,
+            },
+        )?;
+        let body = &self.response.body;
+        let sum = checksum.map(|checksum| checksum.compute(body));
+        let sum_len = sum.as_ref().map_or(0, Vec::len);
+        let mut bytes = Vec::with_capacity(
+            TAG_PREFIX.len() + 3 + sum_len + 8 + metadata.len() + body.len(),
+        );
+        bytes.extend_from_slice(TAG_PREFIX);
+        bytes.push(CACHE_RECORD_VERSION);
+        bytes.push(format.tag());
+        bytes.push(checksum.map_or(0, BodyChecksum::tag));
+        bytes.extend_from_slice(sum.as_deref().unwrap_or_default());
+        bytes.extend_from_slice(&(metadata.len() as u64).to_le_bytes());
+        bytes.extend_from_slice(&metadata);
+        bytes.extend_from_slice(body);
+        Ok(bytes)
+    }
+}
+
+impl Store {
+    #[cfg(any(feature = "manager-cacache", feature = "manager-moka"))]
+    pub(crate) fn serialize(
+        &self,
+        format: SerializationFormat,
+        checksum: Option<BodyChecksum>,
+    ) -> Result<Vec<u8>> {
+        StoreRef { response: &self.response, policy: &self.policy }
+            .serialize(format, checksum)
+    }
+
+    // The body of a current record is a slice of `bytes`, sharing its buffer.
+    // Its checksum, if the record has one, is only checked with `verify` set.
+    pub(crate) fn deserialize(bytes: &Bytes, verify: bool) -> Result<Decoded> {
+        let tagged = match bytes.strip_prefix(TAG_PREFIX) {
+            Some(tagged) => tagged,
+            // Version 0 records have no header and were always bincode
+            None => {
+                let store: LegacyStore<ResponseV2> =
+                    bincode::deserialize(bytes).map_err(corrupt)?;
+                return Ok(Decoded::Upgraded(store.into()));
+            }
+        };
+        let (version, format, data) = match tagged {
+            [version, format, data @ ..] => (*version, *format, data),
+            _ => return Ok(Decoded::UnknownVersion),
+        };
+        if version == 0 || version > CACHE_RECORD_VERSION {
+            return Ok(Decoded::UnknownVersion);
+        }
+        let format = match SerializationFormat::from_tag(format) {
+            Some(format) => format,
+            None => return Ok(Decoded::UnknownFormat),
+        };
+        // Version 1 records serialized the body along with the rest
+        if version == 1 {
+            let store: LegacyStore<ResponseV2> = decode(format, data)?;
+            return Ok(Decoded::Upgraded(store.into()));
+        }
+        // Records from before version 5 have no checksum
+        let (checksum, data) = match data {
+            [tag, data @ ..] if version >= 5 => {
+                match BodyChecksum::from_tag(*tag)? {
+                    Some((checksum, len)) if data.len() >= len => {
+                        let (sum, data) = data.split_at(len);
+                        (Some((checksum, sum)), data)
+                    }
+                    Some(_) => return Err(corrupt(truncated())),
+                    None => (None, data),
+                }
+            }
+            [] if version >= 5 => return Err(corrupt(truncated())),
+            data => (None, data),
+        };
+        if data.len() < 8 {
+            return Err(corrupt(truncated()));
+        }
+        let (len, data) = data.split_at(8);
+        let mut len_bytes = [0; 8];
+        len_bytes.copy_from_slice(len);
+        let len = u64::from_le_bytes(len_bytes);
+        if len > data.len() as u64 {
+            return Err(corrupt(truncated()));
+        }
+        let (metadata, body) = data.split_at(len as usize);
+        if let (true, Some((checksum, sum))) = (verify, checksum) {
+            if checksum.compute(body) != sum {
+                return Err(corrupt(checksum_mismatch()));
+            }
+        }
+        let mut store: Store = match version {
+            // Version 2 records were written before the reason phrase was
+            // kept, version 3 ones before the trailers were, and versions 4
+            // and 5 before the entry metadata was
+            2 => decode::<LegacyStore<ResponseV2>>(format, metadata)?.into(),
+            3 => decode::<LegacyStore<ResponseV3>>(format, metadata)?.into(),
+            4 | 5 => {
+                decode::<LegacyStore<ResponseV5>>(format, metadata)?.into()
+            }
+            _ => decode(format, metadata)?,
+        };
+        store.response.body = bytes.slice_ref(body);
+        if version < CACHE_RECORD_VERSION {
+            return Ok(Decoded::Upgraded(store));
+        }
+        Ok(Decoded::Current(store))
+    }
+}
+
+// A record written by versions up to 5, before the entry metadata was kept
+#[derive(Deserialize)]
+struct LegacyStore<R> {
+    response: R,
+    policy: CachePolicy,
+}
+
+// The response of a record written before the reason phrase was kept, by
+// versions up to 2
+#[derive(Deserialize)]
+struct ResponseV2 {
+    #[serde(deserialize_with = "deserialize_body")]
+    body: Bytes,
+    headers: HttpHeaders,
+    status: u16,
+    url: Url,
+    version: HttpVersion,
+}
+
+// The response of a record written by version 3
+#[derive(Deserialize)]
+struct ResponseV3 {
+    #[serde(deserialize_with = "deserialize_body")]
+    body: Bytes,
+    headers: HttpHeaders,
+    status: u16,
+    reason: Option<String>,
+    url: Url,
+    version: HttpVersion,
+}
+
+// The response of a record written by versions 4 and 5
+#[derive(Deserialize)]
+struct ResponseV5 {
+    #[serde(deserialize_with = "deserialize_body")]
+    body: Bytes,
+    headers: HttpHeaders,
+    trailers: Option<HttpHeaders>,
+    status: u16,
+    reason: Option<String>,
+    url: Url,
+    version: HttpVersion,
+}
+
+impl From<ResponseV2> for ResponseV3 {
+    fn from(res: ResponseV2) -> Self {
+        let ResponseV2 { body, headers, status, url, version } = res;
+        Self { body, headers, status, reason: None, url, version }
+    }
+}
+
+impl From<ResponseV2> for ResponseV5 {
+    fn from(res: ResponseV2) -> Self {
+        ResponseV3::from(res).into()
+    }
+}
+
+impl From<ResponseV3> for ResponseV5 {
+    fn from(res: ResponseV3) -> Self {
+        let ResponseV3 { body, headers, status, reason, url, version } = res;
+        Self { body, headers, trailers: None, status, reason, url, version }
+    }
+}
+
+impl From<ResponseV5> for HttpResponse {
+    fn from(res: ResponseV5) -> Self {
+        let ResponseV5 {
+            body,
+            headers,
+            trailers,
+            status,
+            reason,
+            url,
+            version,
+        } = res;
+        Self {
+            body,
+            headers,
+            trailers,
+            status,
+            reason,
+            url,
+            version,
+            metadata: EntryMetadata::default(),
+        }
+    }
+}
+
+impl<R: Into<ResponseV5>> From<LegacyStore<R>> for Store {
+    fn from(store: LegacyStore<R>) -> Self {
+        Self { response: store.response.into().into(), policy: store.policy }
+    }
+}
+
+fn encode(
+    format: SerializationFormat,
+    value: &impl Serialize,
+) -> Result<Vec<u8>> {
+    Ok(match format {
+        SerializationFormat::Bincode => bincode::serialize(value)?,
+        #[cfg(feature = "format-json")]
+        SerializationFormat::Json => serde_json::to_vec(value)?,
+        #[cfg(feature = "format-cbor")]
+        SerializationFormat::Cbor => {
+            let mut bytes = Vec::new();
+            ciborium::ser::into_writer(value, &mut bytes)
+                .map_err(|e| anyhow::anyhow!(e))?;
+            bytes
+        }
+    })
+}
+
+fn decode<T: DeserializeOwned>(
+    format: SerializationFormat,
+    data: &[u8],
+) -> Result<T> {
+    Ok(match format {
+        SerializationFormat::Bincode => {
+            bincode::deserialize(data).map_err(corrupt)?
+        }
+        #[cfg(feature = "format-json")]
+        SerializationFormat::Json => {
+            serde_json::from_slice(data).map_err(corrupt)?
+        }
+        #[cfg(feature = "format-cbor")]
+        SerializationFormat::Cbor => {
+            ciborium::de::from_reader(data).map_err(corrupt)?
+        }
+    })
+}
+
+fn truncated() -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "truncated record")
+}
+
+fn checksum_mismatch() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "body doesn't match its checksum",
+    )
+}
+
+fn corrupt(e: impl std::error::Error + Send + Sync + 'static) -> CacheError {
+    CacheError::CorruptEntry(Box::new(e))
+}
+
+/// Serializes a record the way the bundled managers store it, so managers
+/// for other backends can share their layout and upgrades.
+///
+/// The record is tagged with its format and the
+/// [`CACHE_RECORD_VERSION`](crate::CACHE_RECORD_VERSION) of its layout, and
+/// the body is appended as is along with its `checksum`, if any. Read it back
+/// with [`decode_record`].
+pub fn encode_record(
+    response: &HttpResponse,
+    policy: &CachePolicy,
+    format: SerializationFormat,
+    checksum: Option<BodyChecksum>,
+) -> Result<Vec<u8>> {
+    StoreRef { response, policy }.serialize(format, checksum)
+}
+
+/// Reads back a record serialized by [`encode_record`], in this or an earlier
+/// version. The body of the response is a slice of `bytes`.
+///
+/// The checksum of the body, if the record has one, is only checked with
+/// `verify` set. Records that don't match it, or that are truncated, are
+/// reported as [`CacheError::CorruptEntry`].
+pub fn decode_record(bytes: &Bytes, verify: bool) -> Result<DecodedRecord> {
+    Ok(match Store::deserialize(bytes, verify)? {
+        Decoded::Current(store) => {
+            DecodedRecord::Current(store.response, store.policy)
+        }
+        Decoded::Upgraded(store) => {
+            DecodedRecord::Upgraded(store.response, store.policy)
+        }
+        Decoded::UnknownFormat => DecodedRecord::UnknownFormat,
+        Decoded::UnknownVersion => DecodedRecord::UnknownVersion,
+    })
+}
+
+/// A record read back by [`decode_record`].
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DecodedRecord {
+    /// The record uses the current layout
+    Current(HttpResponse, CachePolicy),
+    /// The record was written by an earlier version, storing it again with
+    /// [`encode_record`] saves upgrading it on every read
+    Upgraded(HttpResponse, CachePolicy),
+    /// The record was written in a format that isn't enabled, which another
+    /// build may still read
+    UnknownFormat,
+    /// The record was written by a newer version and should be removed
+    UnknownVersion,
+}
+
+// The result of reading a stored record
+pub(crate) enum Decoded {
+    // The record uses the current version
+    Current(Store),
+    // The record used an older version and should be rewritten
+    Upgraded(Store),
+    // The record was written in a format that isn't enabled
+    UnknownFormat,
+    // The record was written by a newer version and should be removed
+    UnknownVersion,
+}
+
+// The full key of a record stored under a digest, `None` if the key is short
+// enough to be used as is
+#[cfg(any(feature = "manager-cacache", feature = "manager-moka"))]
+pub(crate) fn long_key(key: &crate::CacheKey) -> Option<String> {
+    key.is_digest().then(|| key.to_string())
+}
@@ -0,0 +1,96 @@
+use http::{header::CACHE_CONTROL, HeaderValue};
+use http_cache_semantics::CachePolicy;
+
+use crate::{
+    directives::split_directives, CacheManager, CacheMode, HttpCache,
+    HttpResponse, Middleware, Result, XCACHEOVERRIDE,
+};
+
+// The value of `XCACHEOVERRIDE` on responses stored in spite of their
+// `no-store` directive
+const OVERRIDDEN_NO_STORE: &str = "no-store";
+
+// The directives replaced on the stored copy of an overridden response
+const REPLACED_DIRECTIVES: [&str; 3] = ["no-store", "max-age", "s-maxage"];
+
+impl<T: CacheManager> HttpCache<T> {
+    // The policy to store a response fetched in the `ForceCache` mode with,
+    // which for a response only kept out by its `no-store` directive expires
+    // after `HttpCacheOptions::force_cache_overrides_no_store` once allowed.
+    // The response is then marked with `XCACHEOVERRIDE`.
+    pub(crate) fn override_no_store(
+        &self,
+        middleware: &impl Middleware,
+        res: &mut HttpResponse,
+        policy: CachePolicy,
+    ) -> Result<CachePolicy> {
+        let ttl = match self.options.force_cache_overrides_no_store {
+            Some(ttl) if self.mode == CacheMode::ForceCache => ttl,
+            _ => return Ok(policy),
+        };
+        if policy.is_storable() {
+            return Ok(policy);
+        }
+        let mut values = Vec::new();
+        for value in res.headers.get_all("cache-control") {
+            values.push(value.to_str()?.to_string());
+        }
+        let joined = values.join(", ");
+        let directives = split_directives(&joined);
+        if !directives.iter().any(|name| name.eq_ignore_ascii_case("no-store"))
+        {
+            return Ok(policy);
+        }
+        let mut kept = directives
+            .into_iter()
+            .filter(|directive| {
+                let name = directive.split('=').next().unwrap_or_default();
+                !REPLACED_DIRECTIVES
+                    .iter()
+                    .any(|replaced| name.trim().eq_ignore_ascii_case(replaced))
+            })
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        kept.push(format!("max-age={}", ttl.as_secs()));
+        let mut parts = res.parts()?;
+        parts
+            .headers
+            .insert(CACHE_CONTROL, HeaderValue::from_str(&kept.join(", "))?);
+        let overridden = CachePolicy::new_options(
+            &middleware.parts()?,
+            &parts,
+            self.now(),
+            self.options.cache_options.unwrap_or_default(),
+        );
+        // Kept out for other reasons, such as a request with `no-store` or a
+        // `private` response in a shared cache
+        if !overridden.is_storable() {
+            return Ok(policy);
+        }
+        res.headers.insert(
+            XCACHEOVERRIDE,
+            HeaderValue::from_static(OVERRIDDEN_NO_STORE),
+        );
+        Ok(overridden)
+    }
+
+    // Returns false for a stored response the cache must act as if it didn't
+    // have, one stored in spite of its `no-store` directive when the cache
+    // isn't in the `ForceCache` or `OnlyIfCached` mode with the override
+    // allowed, or once it expired
+    pub(crate) fn serves_overridden(
+        &self,
+        res: &HttpResponse,
+        policy: &CachePolicy,
+    ) -> bool {
+        if !res.headers.contains_key(XCACHEOVERRIDE) {
+            return true;
+        }
+        self.options.force_cache_overrides_no_store.is_some()
+            && matches!(
+                self.mode,
+                CacheMode::ForceCache | CacheMode::OnlyIfCached
+            )
+            && !policy.is_stale(self.now())
+    }
+}
@@ -0,0 +1,142 @@
+use http::{
+    header::{CACHE_CONTROL, EXPIRES, PRAGMA, VARY},
+    HeaderValue,
+};
+use http_cache_semantics::{CacheOptions, CachePolicy};
+use url::Url;
+
+use crate::{
+    streaming::{read_body, BodyStream},
+    CacheError, CacheKey, CacheManager, HitOrMiss, HttpCache, HttpResponse,
+    Middleware, Result, StreamingCacheManager, StreamingMiddleware,
+};
+
+/// Turns the cache into a store of test fixtures, set through
+/// [`HttpCacheOptions::record_mode`](crate::HttpCacheOptions::record_mode).
+///
+/// A test suite is run once against the real services in the `Record` mode,
+/// and from then on in the `Replay` mode, where it never touches the
+/// network. Responses are stored under the method and the url of their
+/// request, along with the headers of
+/// [`HttpCacheOptions::key_headers`](crate::HttpCacheOptions::key_headers),
+/// the request body is left out. Both modes take the place of the
+/// [`CacheMode`](crate::CacheMode) of the cache, requests the cache is
+/// disabled for still go to the network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordMode {
+    /// Sends every request to the network and stores every response,
+    /// whatever its method, status and headers. Stored responses never
+    /// expire.
+    Record,
+    /// Serves the stored response to every request without paying attention
+    /// to its headers, failing with [`CacheError::NotRecorded`] when nothing
+    /// was stored instead of going to the network.
+    Replay,
+}
+
+// Recorded responses stay fresh for a hundred years
+const RECORDED_CACHE_CONTROL: &str = "public, max-age=3153600000";
+
+// The key listed by `CacheError::NotRecorded`
+fn not_recorded(method: &str, url: &Url) -> CacheError {
+    CacheError::NotRecorded(format!("{}:{}", method, url))
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    // Fetches and stores the response to a request in the `Record` mode
+    pub(crate) async fn record(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<HttpResponse> {
+        let mut res = middleware.remote_fetch().await?;
+        res.drop_decoded_encoding();
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        let policy = self.recorded_policy(middleware, &res)?;
+        self.attach_metadata(middleware, &mut res);
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(middleware)?;
+        let tags = self.tags(&res);
+        self.store(&method, &url, res, policy, &tags).await
+    }
+
+    // Serves the stored response to a request in the `Replay` mode
+    pub(crate) async fn replay(
+        &self,
+        middleware: &mut impl Middleware,
+    ) -> Result<HttpResponse> {
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(middleware)?;
+        match self.lookup(&method, &url).await? {
+            Some((mut res, _)) => {
+                res.cache_status(HitOrMiss::HIT);
+                res.cache_lookup_status(HitOrMiss::HIT);
+                Ok(res)
+            }
+            None => Err(not_recorded(&method, &url)),
+        }
+    }
+
+    // The policy of a recorded response, built from its headers with the
+    // ones limiting its freshness replaced so it never expires
+    fn recorded_policy(
+        &self,
+        middleware: &impl Middleware,
+        res: &HttpResponse,
+    ) -> Result<CachePolicy> {
+        let mut parts = res.parts()?;
+        for name in [EXPIRES, PRAGMA, VARY] {
+            parts.headers.remove(name);
+        }
+        parts.headers.insert(
+            CACHE_CONTROL,
+            HeaderValue::from_static(RECORDED_CACHE_CONTROL),
+        );
+        Ok(CachePolicy::new_options(
+            &middleware.parts()?,
+            &parts,
+            self.now(),
+            CacheOptions { shared: false, ..Default::default() },
+        ))
+    }
+}
+
+impl<T: StreamingCacheManager> HttpCache<T> {
+    // Fetches and stores the response to a request in the `Record` mode,
+    // see `HttpCache::record`
+    pub(crate) async fn record_stream(
+        &self,
+        middleware: &mut impl StreamingMiddleware,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let (mut res, body) = middleware.remote_fetch_stream().await?;
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        let policy = self.recorded_policy(middleware, &res)?;
+        self.attach_metadata(middleware, &mut res);
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(middleware)?;
+        self.store_stream(&method, &url, res, policy, body).await
+    }
+
+    // Serves the stored response to a request in the `Replay` mode, see
+    // `HttpCache::replay`
+    pub(crate) async fn replay_stream(
+        &self,
+        middleware: &mut impl StreamingMiddleware,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(middleware)?;
+        let key = CacheKey::new(&method, &url);
+        let get = self.manager.get_stream(&key);
+        let res =
+            self.call("get", &method, &url, get).await.map(Option::flatten);
+        match self.discard_corrupt(&method, &url, res).await? {
+            Some((mut res, _, reader)) => {
+                res.cache_status(HitOrMiss::HIT);
+                res.cache_lookup_status(HitOrMiss::HIT);
+                Ok((res, read_body(reader)))
+            }
+            None => Err(not_recorded(&method, &url)),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::{fmt, sync::RwLock};
+
+use http::HeaderValue;
+
+/// The headers whose values are redacted unless told otherwise, see
+/// [`Redaction`].
+pub const DEFAULT_REDACTED_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+    "api-key",
+    "x-api-key",
+];
+
+// `None` until a redaction is installed, standing for the default one
+static REDACTION: RwLock<Option<Redaction>> = RwLock::new(None);
+
+/// Which header values are replaced with `[redacted]` in the `Debug` output
+/// of [`HttpHeaders`](crate::HttpHeaders), and so of the types holding them
+/// such as [`HttpResponse`](crate::HttpResponse) and
+/// [`CacheInspection`](crate::CacheInspection).
+///
+/// Formatting a value has no way of reaching the options of the cache it
+/// came from, so the redaction applies to the whole process and is set with
+/// [`Redaction::install`]. Until then the headers listed in
+/// [`DEFAULT_REDACTED_HEADERS`] are redacted.
+///
+/// ```
+/// use http_cache::Redaction;
+///
+/// let mut redaction = Redaction::default();
+/// redaction.headers.push("x-session-token".into());
+/// redaction.install();
+/// ```
+///
+/// The `Debug` output of a [`CachePolicy`](http_cache_semantics::CachePolicy)
+/// comes from `http-cache-semantics` and holds headers as they are, the
+/// types of this crate leave policies out of theirs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redaction {
+    /// The names of the headers whose values are redacted, matched case
+    /// insensitively
+    pub headers: Vec<String>,
+}
+
+impl Default for Redaction {
+    /// Redacts the headers listed in [`DEFAULT_REDACTED_HEADERS`].
+    fn default() -> Self {
+        Self {
+            headers: DEFAULT_REDACTED_HEADERS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+        }
+    }
+}
+
+impl Redaction {
+    /// Creates a redaction that doesn't redact anything, leaving every value
+    /// in plain text once installed.
+    pub fn none() -> Self {
+        Self { headers: Vec::new() }
+    }
+
+    /// Returns the redaction currently used.
+    pub fn current() -> Self {
+        REDACTION.read().unwrap().clone().unwrap_or_default()
+    }
+
+    /// Uses this redaction from now on, for the whole process.
+    pub fn install(self) {
+        *REDACTION.write().unwrap() = Some(self);
+    }
+
+    /// Returns true if the values of the header are redacted.
+    pub fn redacts(&self, name: &str) -> bool {
+        self.headers.iter().any(|header| header.eq_ignore_ascii_case(name))
+    }
+}
+
+// Formats header names and values as a map, redacting the values of the
+// headers the current redaction lists
+pub(crate) struct RedactedEntries<'a>(pub(crate) &'a [(String, HeaderValue)]);
+
+impl fmt::Debug for RedactedEntries<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let redaction = Redaction::current();
+        let mut map = f.debug_map();
+        for (name, value) in self.0 {
+            if redaction.redacts(name) {
+                map.entry(name, &format_args!("[redacted]"));
+            } else {
+                map.entry(name, value);
+            }
+        }
+        map.finish()
+    }
+}
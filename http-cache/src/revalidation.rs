@@ -0,0 +1,79 @@
+use http::{
+    header::{HeaderName, IF_MODIFIED_SINCE, IF_NONE_MATCH},
+    request, HeaderMap, Method, Uri,
+};
+
+use crate::RevalidationHook;
+
+// The headers the cache sets to make a revalidation request conditional
+const VALIDATORS: [HeaderName; 2] = [IF_NONE_MATCH, IF_MODIFIED_SINCE];
+
+/// The conditional request the cache is about to send to revalidate a
+/// stale response, handed to [`HttpCacheOptions::on_revalidation_request`](crate::HttpCacheOptions::on_revalidation_request).
+///
+/// Headers can be added or replaced, such as to sign the request, but not
+/// removed from the request. The validators the cache set, `If-None-Match`
+/// and `If-Modified-Since`, are put back as they were when they are changed
+/// or removed through [`headers_mut`](Self::headers_mut). Sending the
+/// request without them takes a call to
+/// [`drop_validators`](Self::drop_validators).
+#[derive(Debug)]
+pub struct RevalidationRequest<'a> {
+    parts: &'a mut request::Parts,
+    drop_validators: bool,
+}
+
+impl RevalidationRequest<'_> {
+    /// Returns the request method.
+    pub fn method(&self) -> &Method {
+        &self.parts.method
+    }
+
+    /// Returns the request uri.
+    pub fn uri(&self) -> &Uri {
+        &self.parts.uri
+    }
+
+    /// Returns the request headers, along with the validators.
+    pub fn headers(&self) -> &HeaderMap {
+        &self.parts.headers
+    }
+
+    /// Returns the request headers to add or replace some.
+    pub fn headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.parts.headers
+    }
+
+    /// Sends the request without the validators, so the origin answers
+    /// with the whole response. This is reported with
+    /// [`CacheEvent::ValidatorsDropped`](crate::CacheEvent::ValidatorsDropped).
+    pub fn drop_validators(&mut self) {
+        self.drop_validators = true;
+    }
+}
+
+// Hands the parts of a conditional request to the hook, returning false if
+// it dropped the validators
+pub(crate) fn apply(
+    hook: &RevalidationHook,
+    parts: &mut request::Parts,
+) -> bool {
+    let validators = VALIDATORS.map(|name| {
+        let values =
+            parts.headers.get_all(&name).iter().cloned().collect::<Vec<_>>();
+        (name, values)
+    });
+    let mut request = RevalidationRequest { parts, drop_validators: false };
+    hook(&mut request);
+    let dropped = request.drop_validators;
+    for (name, values) in validators {
+        parts.headers.remove(&name);
+        if dropped {
+            continue;
+        }
+        for value in values {
+            parts.headers.append(&name, value);
+        }
+    }
+    !dropped
+}
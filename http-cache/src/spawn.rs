@@ -0,0 +1,176 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use futures::{channel::oneshot, FutureExt};
+
+use crate::{timeout::timeout, MaybeSend};
+
+/// Work handed to a [`Spawner`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type BackgroundTask = futures::future::BoxFuture<'static, ()>;
+
+/// Work handed to a [`Spawner`].
+#[cfg(target_arch = "wasm32")]
+pub type BackgroundTask = futures::future::LocalBoxFuture<'static, ()>;
+
+/// Runs work the cache doesn't need to wait for in the background, set
+/// through [`HttpCacheOptions::spawner`](crate::HttpCacheOptions::spawner).
+///
+/// The default spawner runs the work inline before returning, which is
+/// always correct but adds the work to the latency of the request.
+///
+/// Clones of a spawner keep track of the same tasks, which
+/// [`HttpCache::shutdown`](crate::HttpCache::shutdown) waits for.
+#[derive(Clone, Default)]
+pub struct Spawner {
+    spawn: Option<Arc<dyn Fn(BackgroundTask) + Send + Sync>>,
+    tasks: Arc<Mutex<Tasks>>,
+}
+
+// The tasks handed to the executor that haven't finished yet
+#[derive(Default)]
+struct Tasks {
+    running: usize,
+    // Set once the spawner is shut down, the work runs inline from then on
+    closed: bool,
+    // Told once no task is running
+    idle: Vec<oneshot::Sender<()>>,
+}
+
+// Held by a task until it finishes or is dropped by the executor
+struct Running(Arc<Mutex<Tasks>>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        let mut tasks = self.0.lock().unwrap();
+        tasks.running -= 1;
+        if tasks.running == 0 {
+            for idle in tasks.idle.drain(..) {
+                idle.send(()).ok();
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Spawner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let spawn = self.spawn.as_ref().map(|_| "Fn(BackgroundTask)");
+        f.debug_struct("Spawner").field("spawn", &spawn).finish()
+    }
+}
+
+impl Spawner {
+    /// Creates a spawner that hands every task to `spawn`, which should run
+    /// it to completion on an executor.
+    pub fn new(spawn: impl Fn(BackgroundTask) + Send + Sync + 'static) -> Self {
+        Self { spawn: Some(Arc::new(spawn)), tasks: Default::default() }
+    }
+
+    /// Creates a spawner that runs the work inline, the default.
+    pub fn inline() -> Self {
+        Self::default()
+    }
+
+    /// Creates a spawner that runs the work on the current tokio runtime.
+    #[cfg(feature = "spawn-tokio")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "spawn-tokio")))]
+    pub fn tokio() -> Self {
+        Self::new(|task| {
+            tokio::spawn(task);
+        })
+    }
+
+    /// Creates a spawner that runs the work on the async-std executor.
+    #[cfg(feature = "spawn-async-std")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "spawn-async-std")))]
+    pub fn async_std() -> Self {
+        Self::new(|task| {
+            async_std::task::spawn(task);
+        })
+    }
+
+    /// Returns true if the work runs inline.
+    pub fn is_inline(&self) -> bool {
+        self.spawn.is_none()
+    }
+
+    // Hands the task to the executor, or runs it right away when inline or
+    // shut down
+    pub(crate) async fn spawn(
+        &self,
+        task: impl Future<Output = ()> + MaybeSend + 'static,
+    ) {
+        let spawn = match &self.spawn {
+            Some(spawn) => spawn,
+            None => return task.await,
+        };
+        let running = {
+            let mut tasks = self.tasks.lock().unwrap();
+            if tasks.closed {
+                None
+            } else {
+                tasks.running += 1;
+                Some(Running(self.tasks.clone()))
+            }
+        };
+        match running {
+            Some(running) => spawn(boxed(async move {
+                task.await;
+                drop(running);
+            })),
+            None => task.await,
+        }
+    }
+
+    // Runs the work handed to `spawn` inline from now on and waits up to
+    // `duration` for the tasks already running, returning how many are
+    // still running
+    pub(crate) async fn shutdown(&self, duration: Duration) -> usize {
+        let idle = {
+            let mut tasks = self.tasks.lock().unwrap();
+            tasks.closed = true;
+            if tasks.running == 0 {
+                return 0;
+            }
+            let (tell, idle) = oneshot::channel();
+            tasks.idle.push(tell);
+            idle
+        };
+        timeout(duration, idle).await;
+        self.tasks.lock().unwrap().running
+    }
+
+    // Hands the task to the executor, returning false without running it
+    // when inline. Such tasks run until they are stopped and aren't waited
+    // for on shutdown.
+    pub(crate) fn spawn_background(
+        &self,
+        task: impl Future<Output = ()> + MaybeSend + 'static,
+    ) -> bool {
+        match &self.spawn {
+            Some(spawn) => {
+                spawn(boxed(task));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn boxed(
+    task: impl Future<Output = ()> + Send + 'static,
+) -> BackgroundTask {
+    task.boxed()
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn boxed(
+    task: impl Future<Output = ()> + 'static,
+) -> BackgroundTask {
+    task.boxed_local()
+}
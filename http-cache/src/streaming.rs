@@ -0,0 +1,447 @@
+use std::future::Future;
+
+use bytes::{Bytes, BytesMut};
+use futures::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    stream::{self, Stream, StreamExt},
+};
+use http::StatusCode;
+use http_cache_semantics::{AfterResponse, BeforeRequest, CachePolicy};
+use url::Url;
+
+use crate::{
+    has_validators, is_stored_status, now, CacheEvent, CacheKey, CacheManager,
+    CacheMode, HitOrMiss, HttpCache, HttpHeaders, HttpResponse, HttpVersion,
+    MaybeSend, Middleware, RecordMode, Result,
+};
+
+// Size of the chunks a cached body is read in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A response body delivered in chunks, as returned by
+/// [`HttpCache::run_streaming`].
+#[cfg(not(target_arch = "wasm32"))]
+pub type BodyStream = stream::BoxStream<'static, Result<Bytes>>;
+
+/// A response body delivered in chunks, as returned by
+/// [`HttpCache::run_streaming`].
+#[cfg(target_arch = "wasm32")]
+pub type BodyStream = stream::LocalBoxStream<'static, Result<Bytes>>;
+
+/// Writes the body of a record being stored by
+/// [`StreamingCacheManager::put_stream`].
+pub trait BodyWriter: AsyncWrite + Unpin + MaybeSend + 'static {
+    /// Stores the record once the whole body has been written.
+    ///
+    /// A writer that is dropped without being committed must discard
+    /// everything written to it, leaving any previously stored record as is.
+    fn commit(self) -> impl Future<Output = Result<()>> + MaybeSend;
+}
+
+/// A [`CacheManager`] that can store and serve response bodies as streams,
+/// so they never have to be held in memory as a whole.
+pub trait StreamingCacheManager: CacheManager {
+    /// Reads the body of a cached response.
+    type Reader: AsyncRead + Unpin + MaybeSend + 'static;
+    /// Writes the body of a response being cached.
+    type Writer: BodyWriter;
+
+    /// Attempts to pull a cached response and related policy from cache,
+    /// along with a reader for the body. The body of the returned response is
+    /// left empty.
+    fn get_stream(
+        &self,
+        key: &CacheKey,
+    ) -> impl Future<
+        Output = Result<Option<(HttpResponse, CachePolicy, Self::Reader)>>,
+    > + MaybeSend;
+    /// Starts caching a response and related policy along with its tags, see
+    /// [`CacheManager::put_tagged`]. The body of `res` is ignored, it gets
+    /// written to the returned writer instead and the record is only stored
+    /// once the writer is committed.
+    fn put_stream(
+        &self,
+        key: &CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> impl Future<Output = Result<Self::Writer>> + MaybeSend;
+}
+
+/// A [`Middleware`] that can fetch responses without reading their body.
+pub trait StreamingMiddleware: Middleware {
+    /// Attempts to fetch an upstream resource, returning the response with
+    /// an empty body along with the body as a stream.
+    fn remote_fetch_stream(
+        &mut self,
+    ) -> impl Future<Output = Result<(HttpResponse, BodyStream)>> + MaybeSend;
+}
+
+impl<T: StreamingCacheManager> HttpCache<T> {
+    /// Attempts to run the passed middleware along with the cache like
+    /// [`HttpCache::run`], without ever holding the whole response body in
+    /// memory.
+    ///
+    /// Cached bodies are read from the manager in chunks. Bodies fetched from
+    /// the network are written to the manager as they are passed on, and are
+    /// only stored once they have been read to the end, so an aborted
+    /// download is never served.
+    pub async fn run_streaming(
+        &self,
+        mut middleware: impl StreamingMiddleware,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        if !self.can_cache_request(&middleware)? {
+            return middleware.remote_fetch_stream().await;
+        }
+        if let Some(dry_run) = &self.options.dry_run {
+            let lookup = self.dry_run_lookup(dry_run, &middleware)?;
+            let (mut res, body) = middleware.remote_fetch_stream().await?;
+            self.dry_run_decide(dry_run, &middleware, lookup, &mut res)?;
+            return Ok((res, body));
+        }
+        if !self.is_observed() {
+            return self.run_streaming_cached(middleware).await;
+        }
+        let started = now();
+        let method = middleware.method()?.to_uppercase();
+        let url = middleware.url()?;
+        let (res, body) = self.run_streaming_cached(middleware).await?;
+        self.emit_answered(method, url, &res, started);
+        Ok((res, body))
+    }
+
+    async fn run_streaming_cached(
+        &self,
+        mut middleware: impl StreamingMiddleware,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        match self.options.record_mode {
+            Some(RecordMode::Record) => {
+                return self.record_stream(&mut middleware).await
+            }
+            Some(RecordMode::Replay) => {
+                return self.replay_stream(&mut middleware).await
+            }
+            None => {}
+        }
+        let is_cacheable = middleware.is_method_get_head()
+            && self.mode != CacheMode::NoStore
+            && self.mode != CacheMode::Reload;
+        if !is_cacheable {
+            return self.remote_fetch_stream(&mut middleware).await;
+        }
+        let method = middleware.method()?.to_uppercase();
+        let url = self.cache_url(&middleware)?;
+        let key = CacheKey::new(&method, &url);
+        let get = self.manager.get_stream(&key);
+        let res =
+            self.call("get", &method, &url, get).await.map(Option::flatten);
+        let stored = self
+            .discard_corrupt(&method, &url, res)
+            .await?
+            .filter(|(res, policy, _)| self.serves_overridden(res, policy));
+        if let Some((mut res, policy, reader)) = stored {
+            res.cache_lookup_status(HitOrMiss::HIT);
+            if let Some(warning_code) = res.warning_code() {
+                // See `HttpCache::run`
+                if (100..200).contains(&warning_code) {
+                    res.remove_warning();
+                }
+            }
+            match self.mode {
+                CacheMode::Default => {
+                    self.conditional_fetch_stream(
+                        middleware, res, policy, reader,
+                    )
+                    .await
+                }
+                CacheMode::NoCache => {
+                    middleware.force_no_cache()?;
+                    let (mut res, body) =
+                        self.remote_fetch_stream(&mut middleware).await?;
+                    res.cache_lookup_status(HitOrMiss::HIT);
+                    Ok((res, body))
+                }
+                CacheMode::ForceCache | CacheMode::OnlyIfCached => {
+                    res.add_warning(
+                        &res.url.clone(),
+                        112,
+                        "Disconnected operation",
+                    );
+                    res.cache_status(HitOrMiss::HIT);
+                    Ok((res, read_body(reader)))
+                }
+                _ => self.remote_fetch_stream(&mut middleware).await,
+            }
+        } else {
+            match self.mode {
+                CacheMode::OnlyIfCached => {
+                    // ENOTCACHED
+                    let mut res = HttpResponse {
+                        body: Bytes::new(),
+                        headers: HttpHeaders::default(),
+                        trailers: None,
+                        status: 504,
+                        reason: None,
+                        url: middleware.url()?,
+                        version: HttpVersion::Http11,
+                        metadata: Default::default(),
+                    };
+                    res.cache_status(HitOrMiss::MISS);
+                    res.cache_lookup_status(HitOrMiss::MISS);
+                    let body = Bytes::from_static(b"GatewayTimeout");
+                    Ok((res, boxed(stream::once(async { Ok(body) }))))
+                }
+                _ => self.remote_fetch_stream(&mut middleware).await,
+            }
+        }
+    }
+
+    async fn remote_fetch_stream(
+        &self,
+        middleware: &mut impl StreamingMiddleware,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let requested = self.now();
+        let (mut res, body) = middleware.remote_fetch_stream().await?;
+        res.correct_age(requested, self.now());
+        if let Some(origin) = self.cleared_origin(&res) {
+            self.purge_origin_later(origin).await;
+        }
+        res.expire_unreadable_dates();
+        res.cache_status(HitOrMiss::MISS);
+        res.cache_lookup_status(HitOrMiss::MISS);
+        let withheld = self.withhold(&mut res);
+        let policy = self.response_policy(middleware, &res)?;
+        let policy = self.override_no_store(middleware, &mut res, policy)?;
+        let is_get_head = middleware.is_method_get_head();
+        let is_cacheable = is_get_head
+            && self.mode != CacheMode::NoStore
+            && self.mode != CacheMode::Reload
+            && is_stored_status(res.status)
+            && policy.is_storable()
+            && self.should_store(&res);
+        let url = self.cache_url(middleware)?;
+        let method = middleware.method()?.to_uppercase();
+        let (mut res, body) = if is_cacheable {
+            self.attach_metadata(middleware, &mut res);
+            self.store_stream(&method, &url, res, policy, body).await?
+        } else {
+            if !is_get_head {
+                self.invalidate(&url).await;
+            }
+            (res, body)
+        };
+        withheld.restore(&mut res);
+        Ok((res, body))
+    }
+
+    async fn conditional_fetch_stream(
+        &self,
+        mut middleware: impl StreamingMiddleware,
+        mut cached_res: HttpResponse,
+        policy: CachePolicy,
+        reader: T::Reader,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let parts = middleware.parts()?;
+        let forward = has_validators(&parts);
+        match self.before_request(&middleware, &cached_res, &policy, &parts)? {
+            BeforeRequest::Fresh(parts) => {
+                cached_res.update_headers(&parts)?;
+                cached_res.cache_status(HitOrMiss::HIT);
+                cached_res.cache_lookup_status(HitOrMiss::HIT);
+                return Ok((cached_res, read_body(reader)));
+            }
+            BeforeRequest::Stale { request: mut parts, matches } => {
+                if matches && !forward {
+                    self.before_revalidation(&middleware, &mut parts)?;
+                    middleware.update_headers(&parts)?;
+                }
+            }
+        }
+        let req_url = self.cache_url(&middleware)?;
+        let started = now();
+        let requested = self.now();
+        // See `HttpCache::revalidate`
+        let stale =
+            policy.is_stale(requested) || cached_res.metadata.is_soft_purged();
+        let (mut cond_res, body) = match middleware.remote_fetch_stream().await
+        {
+            Ok(mut fetched) => {
+                fetched.0.correct_age(requested, self.now());
+                self.emit_revalidated(&middleware, &fetched.0, started)?;
+                fetched
+            }
+            Err(e) if cached_res.must_revalidate() && stale => return Err(e),
+            Err(_) => {
+                if stale {
+                    cached_res.add_warning(
+                        &req_url,
+                        111,
+                        "Revalidation failed",
+                    );
+                }
+                cached_res.cache_status(HitOrMiss::HIT);
+                return Ok((cached_res, read_body(reader)));
+            }
+        };
+        if let Some(origin) = self.cleared_origin(&cond_res) {
+            self.purge_origin_later(origin).await;
+        }
+        let status = StatusCode::from_u16(cond_res.status)?;
+        let method = middleware.method()?.to_uppercase();
+        if status.is_server_error() && self.options.server_errors_replace_stored
+        {
+            self.remove(&method, &req_url).await?;
+            cond_res.cache_status(HitOrMiss::MISS);
+            cond_res.cache_lookup_status(HitOrMiss::HIT);
+            Ok((cond_res, body))
+        } else if status.is_server_error() && cached_res.must_revalidate() {
+            if stale {
+                cached_res.add_warning(&req_url, 111, "Revalidation failed");
+            }
+            cached_res.cache_status(HitOrMiss::HIT);
+            Ok((cached_res, read_body(reader)))
+        } else if cond_res.status == 304 && forward {
+            if let AfterResponse::NotModified(policy, parts) = policy
+                .after_response(
+                    &middleware.parts()?,
+                    &cond_res.parts()?,
+                    self.now(),
+                )
+            {
+                cached_res.update_headers(&parts)?;
+                self.refresh(&method, &req_url, &mut cached_res, policy)
+                    .await?;
+            }
+            cond_res.cache_status(HitOrMiss::MISS);
+            cond_res.cache_lookup_status(HitOrMiss::HIT);
+            Ok((cond_res, body))
+        } else if cond_res.status == 304 {
+            let after_res = policy.after_response(
+                &middleware.parts()?,
+                &cond_res.parts()?,
+                self.now(),
+            );
+            let (AfterResponse::Modified(policy, parts)
+            | AfterResponse::NotModified(policy, parts)) = after_res;
+            cached_res.update_headers(&parts)?;
+            cached_res.cache_status(HitOrMiss::HIT);
+            cached_res.cache_lookup_status(HitOrMiss::HIT);
+            // The body is kept as is, rewriting the record would mean reading
+            // it back from the manager
+            self.refresh(&method, &req_url, &mut cached_res, policy).await?;
+            Ok((cached_res, read_body(reader)))
+        } else if is_stored_status(cond_res.status) {
+            cond_res.expire_unreadable_dates();
+            let withheld = self.withhold(&mut cond_res);
+            let policy = self.response_policy(&middleware, &cond_res)?;
+            let policy =
+                self.override_no_store(&middleware, &mut cond_res, policy)?;
+            cond_res.cache_status(HitOrMiss::MISS);
+            cond_res.cache_lookup_status(HitOrMiss::HIT);
+            let (mut res, body) = if !policy.is_storable() {
+                // See `HttpCache::revalidate`
+                self.remove(&method, &req_url).await?;
+                (cond_res, body)
+            } else if self.should_store(&cond_res) {
+                self.attach_metadata(&middleware, &mut cond_res);
+                self.store_stream(&method, &req_url, cond_res, policy, body)
+                    .await?
+            } else {
+                (cond_res, body)
+            };
+            withheld.restore(&mut res);
+            Ok((res, body))
+        } else {
+            cached_res.cache_status(HitOrMiss::HIT);
+            Ok((cached_res, read_body(reader)))
+        }
+    }
+
+    pub(crate) async fn store_stream(
+        &self,
+        method: &str,
+        url: &Url,
+        res: HttpResponse,
+        policy: CachePolicy,
+        body: BodyStream,
+    ) -> Result<(HttpResponse, BodyStream)> {
+        let tags = self.tags(&res);
+        let key = CacheKey::new(method, url);
+        let put = self.manager.put_stream(&key, res.clone(), policy, &tags);
+        match self.call("put", method, url, put).await? {
+            Some(writer) => {
+                self.emit(|| CacheEvent::Stored {
+                    method: method.to_string(),
+                    url: url.clone(),
+                    status: res.status,
+                });
+                Ok((res, tee(body, writer)))
+            }
+            None => Ok((res, body)),
+        }
+    }
+}
+
+// Reads a cached body in chunks of at most `CHUNK_SIZE` bytes, reusing
+// what's left of the buffer once a chunk is split off it
+pub(crate) fn read_body<R: AsyncRead + Unpin + MaybeSend + 'static>(
+    reader: R,
+) -> BodyStream {
+    boxed(stream::unfold(
+        (reader, BytesMut::new()),
+        |(mut reader, mut buf)| async move {
+            buf.resize(CHUNK_SIZE, 0);
+            match reader.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(read) => {
+                    buf.truncate(read);
+                    Some((Ok(buf.split().freeze()), (reader, buf)))
+                }
+                Err(e) => Some((Err(e.into()), (reader, buf))),
+            }
+        },
+    ))
+}
+
+// Passes the body on while writing every chunk to `writer`, committing it
+// once the body has been read to the end. Failing to write only stops the
+// body from being stored, while an error reading it or dropping the stream
+// early drops the writer, which discards the partially written record.
+fn tee<W: BodyWriter>(body: BodyStream, writer: W) -> BodyStream {
+    boxed(stream::unfold(
+        (body, Some(writer)),
+        |(mut body, mut writer)| async move {
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    if let Some(w) = writer.as_mut() {
+                        if w.write_all(&chunk).await.is_err() {
+                            writer = None;
+                        }
+                    }
+                    Some((Ok(chunk), (body, writer)))
+                }
+                Some(Err(e)) => Some((Err(e), (body, None))),
+                None => {
+                    if let Some(writer) = writer {
+                        // The body was passed on in full, so there is nobody
+                        // left to report a failure to store it to
+                        writer.commit().await.ok();
+                    }
+                    None
+                }
+            }
+        },
+    ))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn boxed(
+    body: impl Stream<Item = Result<Bytes>> + Send + 'static,
+) -> BodyStream {
+    body.boxed()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn boxed(body: impl Stream<Item = Result<Bytes>> + 'static) -> BodyStream {
+    body.boxed_local()
+}
@@ -0,0 +1,49 @@
+use std::{future::Future, time::Duration};
+
+use futures::future::{self, Either, FutureExt};
+
+// Runs `fut` for at most `duration`, resolving to `None` if it didn't finish
+// in time. The future is dropped at that point, canceling it.
+pub(crate) fn timeout<F: Future>(
+    duration: Duration,
+    fut: F,
+) -> impl Future<Output = Option<F::Output>> {
+    // Both are boxed so the future of every manager call doesn't grow for
+    // caches that don't use a timeout
+    future::select(Box::pin(fut), Box::pin(sleep(duration))).map(
+        |res| match res {
+            Either::Left((output, _)) => Some(output),
+            Either::Right(_) => None,
+        },
+    )
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) async fn sleep(duration: Duration) {
+    futures_timer::Delay::new(duration).await;
+}
+
+// Waits on a `setTimeout` of the window or worker the code runs in, the
+// operation never times out if there is none
+#[cfg(target_arch = "wasm32")]
+pub(crate) async fn sleep(duration: Duration) {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let global = js_sys::global();
+    let promise = js_sys::Promise::new(&mut |resolve, _| {
+        let set_timeout =
+            js_sys::Reflect::get(&global, &JsValue::from_str("setTimeout"))
+                .ok()
+                .and_then(|f| f.dyn_into::<js_sys::Function>().ok());
+        if let Some(set_timeout) = set_timeout {
+            set_timeout
+                .call2(
+                    &global,
+                    &resolve,
+                    &JsValue::from_f64(duration.as_millis() as f64),
+                )
+                .ok();
+        }
+    });
+    wasm_bindgen_futures::JsFuture::from(promise).await.ok();
+}
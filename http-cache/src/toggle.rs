@@ -0,0 +1,43 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+/// Turns a cache on and off while it runs, set through
+/// [`HttpCacheOptions::toggle`](crate::HttpCacheOptions::toggle).
+///
+/// While the cache is disabled requests are sent straight through as if it
+/// wasn't installed, without looking anything up or storing anything, and
+/// the stored responses are left as they are for when it is enabled again.
+///
+/// Clones share the same state, as do the clones of a cache, so the cache
+/// can be switched through any of them, or through a clone of the toggle
+/// kept before handing the cache to a client.
+#[derive(Debug, Clone)]
+pub struct CacheToggle {
+    enabled: Arc<AtomicBool>,
+}
+
+impl Default for CacheToggle {
+    /// Starts enabled.
+    fn default() -> Self {
+        Self::new(true)
+    }
+}
+
+impl CacheToggle {
+    /// Creates a toggle starting enabled or not.
+    pub fn new(enabled: bool) -> Self {
+        Self { enabled: Arc::new(AtomicBool::new(enabled)) }
+    }
+
+    /// Enables or disables the caches using this toggle.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    /// Returns true if the caches using this toggle are enabled.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+}
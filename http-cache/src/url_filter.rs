@@ -0,0 +1,199 @@
+use url::Url;
+
+/// Limits the cache to the urls matching a set of patterns, set through
+/// [`HttpCacheOptions::url_filter`](crate::HttpCacheOptions::url_filter).
+///
+/// A url goes through the cache when it matches none of the denied
+/// patterns, and one of the allowed patterns if there are any. Patterns are
+/// parsed once when added, so checking a url is cheap.
+///
+/// A pattern is a host optionally preceded by a scheme and followed by a
+/// port and a path, such as `https://api.example.com/catalog/**`.
+///
+/// - The host is matched case insensitively. `*` matches any host and
+///   `*.example.com` any subdomain of `example.com`.
+/// - Without a scheme or port, urls with any scheme or port match.
+/// - The path is matched segment by segment. `*` matches any part of a
+///   segment, so `*.json` matches `items.json`, and a `**` segment matches
+///   any number of segments. Without a path, any path matches.
+/// - The query of the url is left out.
+///
+/// ```
+/// use http_cache::UrlFilter;
+/// use url::Url;
+///
+/// let filter = UrlFilter::new()
+///     .allow("https://api.example.com/catalog/**")
+///     .allow("*.cdn.example.com/*.png")
+///     .deny("api.example.com/catalog/drafts/**");
+/// let allows = |url| filter.allows(&Url::parse(url).unwrap());
+/// assert!(allows("https://api.example.com/catalog/items/1?page=2"));
+/// assert!(allows("http://eu.cdn.example.com/logo.png"));
+/// assert!(!allows("http://api.example.com/catalog/items/1"));
+/// assert!(!allows("https://api.example.com/catalog/drafts/1"));
+/// assert!(!allows("https://api.example.com/account"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UrlFilter {
+    allowed: Vec<UrlPattern>,
+    denied: Vec<UrlPattern>,
+}
+
+impl UrlFilter {
+    /// Creates a filter allowing every url.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allows the urls matching `pattern`, once a pattern is allowed the
+    /// urls matching none of the allowed patterns are left out.
+    pub fn allow(mut self, pattern: &str) -> Self {
+        self.allowed.push(UrlPattern::parse(pattern));
+        self
+    }
+
+    /// Leaves out the urls matching `pattern`, even when they match an
+    /// allowed pattern.
+    pub fn deny(mut self, pattern: &str) -> Self {
+        self.denied.push(UrlPattern::parse(pattern));
+        self
+    }
+
+    /// Returns true if requests to `url` go through the cache.
+    pub fn allows(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or("");
+        let path = url.path().strip_prefix('/').unwrap_or(url.path());
+        let segments = path.split('/').collect::<Vec<_>>();
+        let matches =
+            |pattern: &UrlPattern| pattern.matches(url, host, &segments);
+        !self.denied.iter().any(matches)
+            && (self.allowed.is_empty() || self.allowed.iter().any(matches))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct UrlPattern {
+    scheme: Option<String>,
+    host: HostPattern,
+    port: Option<u16>,
+    path: Vec<Segment>,
+}
+
+#[derive(Debug, Clone)]
+enum HostPattern {
+    Any,
+    Exact(String),
+    // Matches the subdomains of the domain, which starts with its dot
+    Subdomains(String),
+}
+
+#[derive(Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Glob(String),
+    // `**`, any number of segments
+    Any,
+}
+
+impl UrlPattern {
+    fn parse(pattern: &str) -> Self {
+        let (scheme, rest) = match pattern.split_once("://") {
+            Some((scheme, rest)) => (Some(scheme.to_ascii_lowercase()), rest),
+            None => (None, pattern),
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(index) => rest.split_at(index),
+            None => (rest, ""),
+        };
+        // A port follows the last colon, unless it closes an IPv6 address
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => {
+                (host, port.parse().ok())
+            }
+            _ => (authority, None),
+        };
+        let host = host.to_ascii_lowercase();
+        let host = if host == "*" {
+            HostPattern::Any
+        } else if let Some(domain) = host.strip_prefix('*') {
+            HostPattern::Subdomains(domain.to_string())
+        } else {
+            HostPattern::Exact(host)
+        };
+        let path = match path.strip_prefix('/') {
+            Some(path) => path.split('/').map(Segment::parse).collect(),
+            None => vec![Segment::Any],
+        };
+        Self { scheme, host, port, path }
+    }
+
+    fn matches(&self, url: &Url, host: &str, segments: &[&str]) -> bool {
+        // Hosts are lowercased when urls are parsed
+        let host_matches = match &self.host {
+            HostPattern::Any => true,
+            HostPattern::Exact(pattern) => pattern == host,
+            HostPattern::Subdomains(domain) => host.ends_with(domain.as_str()),
+        };
+        host_matches
+            && self
+                .scheme
+                .as_deref()
+                .map_or(true, |scheme| scheme == url.scheme())
+            && self
+                .port
+                .map_or(true, |port| url.port_or_known_default() == Some(port))
+            && matches_segments(&self.path, segments)
+    }
+}
+
+impl Segment {
+    fn parse(segment: &str) -> Self {
+        if segment == "**" {
+            Self::Any
+        } else if segment.contains('*') {
+            Self::Glob(segment.to_string())
+        } else {
+            Self::Literal(segment.to_string())
+        }
+    }
+
+    fn matches(&self, segment: &str) -> bool {
+        match self {
+            Self::Literal(literal) => literal == segment,
+            Self::Glob(glob) => matches_glob(glob, segment),
+            Self::Any => true,
+        }
+    }
+}
+
+fn matches_segments(pattern: &[Segment], segments: &[&str]) -> bool {
+    match (pattern.split_first(), segments.split_first()) {
+        (None, _) => segments.is_empty(),
+        (Some((Segment::Any, rest)), _) => (0..=segments.len())
+            .any(|skipped| matches_segments(rest, &segments[skipped..])),
+        (Some((segment, rest)), Some((first, others))) => {
+            segment.matches(first) && matches_segments(rest, others)
+        }
+        (Some(_), None) => false,
+    }
+}
+
+// Matches `text` against `glob`, where `*` stands for any run of characters
+fn matches_glob(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    // The glob holds a `*`, so there are at least two parts
+    let first = parts.next().unwrap_or("");
+    let mut rest = match text.strip_prefix(first) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut parts = parts.collect::<Vec<_>>();
+    let last = parts.pop().unwrap_or("");
+    for part in parts {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    sync::{Arc, Mutex},
+};
+
+use crate::{
+    emit_event, CacheEvent, CacheKey, CacheManager, EventBroadcast, EventHook,
+    HttpCache, HttpResponse,
+};
+use futures::channel::oneshot;
+use http_cache_semantics::CachePolicy;
+
+/// Stores responses in the background instead of making the caller wait
+/// for the manager, set through [`HttpCacheOptions::write_behind`](crate::HttpCacheOptions::write_behind).
+///
+/// Responses are handed back as soon as they are fetched, and queued to be
+/// stored by a task handed to the [`Spawner`](crate::Spawner) of the cache,
+/// one at a time in the order they were queued. Responses queued while the
+/// queue is full aren't stored, they are counted in
+/// [`WriteBehindStats::dropped`]. Removing a record, such as after an
+/// unsafe request, drops the responses queued for it, and waits behind the
+/// one being stored if there is one.
+///
+/// Responses are stored as usual when the spawner runs work inline or the
+/// manager has no [`CacheManager::background_handle`], and streamed
+/// responses always are. Stores running in the background aren't subject
+/// to [`HttpCacheOptions::backend_timeout`](crate::HttpCacheOptions::backend_timeout)
+/// nor the [`CircuitBreaker`](crate::CircuitBreaker), and a response isn't
+/// served from the cache until it is stored, so concurrent requests for it
+/// may still go to the network. [`HttpCache::flush`] and
+/// [`HttpCache::shutdown`] wait for the queue to drain.
+///
+/// Clones share the same queue, so the stats can be read through a clone of
+/// the one handed to the cache.
+#[derive(Clone)]
+pub struct WriteBehind {
+    /// The most responses waiting to be stored at once, not counting the
+    /// one being stored
+    pub capacity: usize,
+    queue: Arc<Mutex<Queue>>,
+}
+
+/// What a [`WriteBehind`] queue holds and has done, see
+/// [`WriteBehind::stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct WriteBehindStats {
+    /// Number of responses waiting to be stored
+    pub queued: usize,
+    /// Number of responses that weren't stored as the queue was full
+    pub dropped: u64,
+    /// Number of responses the manager failed to store
+    pub failed: u64,
+}
+
+// A response waiting to be stored
+struct Put {
+    res: HttpResponse,
+    policy: CachePolicy,
+    tags: Vec<String>,
+}
+
+// What is queued for a record
+enum Write {
+    Put(Box<Put>),
+    // Queued behind the response being stored for the same record
+    Delete,
+}
+
+#[derive(Default)]
+struct Queue {
+    writes: VecDeque<(CacheKey, Write)>,
+    // The record being written, if any
+    writing: Option<CacheKey>,
+    // Set while a task is draining the queue
+    draining: bool,
+    // Told once the queue is drained
+    drained: Vec<oneshot::Sender<()>>,
+    stats: WriteBehindStats,
+}
+
+impl Queue {
+    fn queued(&self) -> usize {
+        self.writes
+            .iter()
+            .filter(|(_, write)| matches!(write, Write::Put(_)))
+            .count()
+    }
+}
+
+// Whether a response was queued, and the queue needs a task to drain it
+enum Pushed {
+    Drain,
+    Waiting,
+    Dropped,
+}
+
+impl Default for WriteBehind {
+    /// Holds up to 1024 responses waiting to be stored.
+    fn default() -> Self {
+        Self::new(1024)
+    }
+}
+
+impl fmt::Debug for WriteBehind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("WriteBehind")
+            .field("capacity", &self.capacity)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl WriteBehind {
+    /// Creates an empty queue holding up to `capacity` responses waiting to
+    /// be stored.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, queue: Default::default() }
+    }
+
+    /// Returns how many responses are queued, and how many weren't stored.
+    pub fn stats(&self) -> WriteBehindStats {
+        let queue = self.queue.lock().unwrap();
+        WriteBehindStats { queued: queue.queued(), ..queue.stats }
+    }
+
+    // Queues a response to be stored, in place of an older one queued for
+    // the same record
+    fn push(&self, key: CacheKey, put: Write) -> Pushed {
+        let mut queue = self.queue.lock().unwrap();
+        let older = queue.writes.iter_mut().find(|(queued, write)| {
+            *queued == key && matches!(write, Write::Put(_))
+        });
+        if let Some((_, older)) = older {
+            *older = put;
+            return Pushed::Waiting;
+        }
+        if queue.queued() >= self.capacity {
+            queue.stats.dropped += 1;
+            return Pushed::Dropped;
+        }
+        queue.writes.push_back((key, put));
+        if queue.draining {
+            return Pushed::Waiting;
+        }
+        queue.draining = true;
+        Pushed::Drain
+    }
+
+    // Drops the responses queued for the record before it is removed,
+    // returning true if the removal was queued behind the response being
+    // stored for it
+    pub(crate) fn remove(&self, key: &CacheKey) -> bool {
+        let mut queue = self.queue.lock().unwrap();
+        queue.writes.retain(|(queued, _)| queued != key);
+        if queue.writing.as_ref() != Some(key) {
+            return false;
+        }
+        queue.writes.push_back((key.clone(), Write::Delete));
+        true
+    }
+
+    // Takes the next write off the queue, ending the drain once it is empty
+    fn next(&self) -> Option<(CacheKey, Write)> {
+        let mut queue = self.queue.lock().unwrap();
+        let next = queue.writes.pop_front();
+        queue.writing = next.as_ref().map(|(key, _)| key.clone());
+        if next.is_none() {
+            queue.draining = false;
+            for drained in queue.drained.drain(..) {
+                drained.send(()).ok();
+            }
+        }
+        next
+    }
+
+    fn failed(&self) {
+        self.queue.lock().unwrap().stats.failed += 1;
+    }
+
+    // Waits until nothing is queued nor being stored
+    pub(crate) async fn drained(&self) {
+        let drained = {
+            let mut queue = self.queue.lock().unwrap();
+            if !queue.draining {
+                return;
+            }
+            let (tell, drained) = oneshot::channel();
+            queue.drained.push(tell);
+            drained
+        };
+        drained.await.ok();
+    }
+}
+
+// Ends the drain when the task draining the queue is dropped before it is
+// done, even before it ran, so the next response queued starts another one
+struct Draining {
+    write_behind: WriteBehind,
+    done: bool,
+}
+
+impl Drop for Draining {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let mut queue = self.write_behind.queue.lock().unwrap();
+        queue.writing = None;
+        queue.draining = false;
+        for drained in queue.drained.drain(..) {
+            drained.send(()).ok();
+        }
+    }
+}
+
+impl<T: CacheManager> HttpCache<T> {
+    // Queues the response to be stored in the background by `manager`, a
+    // handle to the manager of the cache
+    pub(crate) async fn store_behind(
+        &self,
+        write_behind: &WriteBehind,
+        manager: T,
+        key: CacheKey,
+        res: HttpResponse,
+        policy: CachePolicy,
+        tags: &[String],
+    ) -> HttpResponse {
+        let put = Write::Put(Box::new(Put {
+            res: res.clone(),
+            policy,
+            tags: tags.to_vec(),
+        }));
+        if let Pushed::Drain = write_behind.push(key, put) {
+            let draining =
+                Draining { write_behind: write_behind.clone(), done: false };
+            let task = drain(
+                manager,
+                draining,
+                self.options.on_event.clone(),
+                self.options.event_broadcast.clone(),
+            );
+            self.options.spawner.spawn(task).await;
+        }
+        res
+    }
+}
+
+// Stores what is queued until the queue is empty
+async fn drain<T: CacheManager>(
+    manager: T,
+    mut draining: Draining,
+    on_event: Option<EventHook>,
+    broadcast: Option<EventBroadcast>,
+) {
+    let write_behind = draining.write_behind.clone();
+    while let Some((key, write)) = write_behind.next() {
+        match write {
+            Write::Put(put) => {
+                let Put { res, policy, tags } = *put;
+                match manager.put_tagged(&key, res, policy, &tags).await {
+                    Ok(res) => emit_event(
+                        on_event.as_ref(),
+                        broadcast.as_ref(),
+                        || CacheEvent::Stored {
+                            method: key.method().to_string(),
+                            url: key.url().clone(),
+                            status: res.status,
+                        },
+                    ),
+                    Err(_) => write_behind.failed(),
+                }
+            }
+            Write::Delete => {
+                manager.delete(&key).await.ok();
+            }
+        }
+    }
+    // The queue was found empty, which ended the drain
+    draining.done = true;
+}